@@ -6,7 +6,7 @@ import jadx.core.dex.visitors.IDexTreeVisitor
 import jadx.core.dex.visitors.ReSugarCode
 import java.io.File
 
-class ApkAnalyzer(private val apkFile: File) {
+class ApkAnalyzer(private val apkFile: File, private val includeNonExported: Boolean = false) {
 
     private val _intentTemplates: MutableList<IntentTemplate> = mutableListOf()
     val intentTemplates: List<IntentTemplate>
@@ -30,7 +30,7 @@ class ApkAnalyzer(private val apkFile: File) {
 
             val contents = manifestResource.get().loadContent();
             val intentReceivers =
-                parseIntentReceiversFromManifest(contents.text.codeStr, decompiler)
+                parseIntentReceiversFromManifest(contents.text.codeStr, decompiler, includeNonExported)
 
             // Invoke the tree visitor by decompiling all the classes in the apk.
             for (cls in decompiler.classes) {
@@ -50,6 +50,8 @@ class ApkAnalyzer(private val apkFile: File) {
                     // For now we assign all extras to every intent receiver. In the future maybe with some more
                     // advanced static analysis we could figure out which extras correspond to which intent receiver.
                     intentExtraUsageVisitor.extras,
+                    intentReceiver.dataFilters,
+                    intentReceiver.aliases,
                 ))
             }
         }
@@ -2,7 +2,9 @@ package com.ammaraskar.intent.fuzz
 
 import jadx.core.dex.instructions.BaseInvokeNode
 import jadx.core.dex.instructions.ConstStringNode
+import jadx.core.dex.instructions.args.InsnArg
 import jadx.core.dex.instructions.args.InsnWrapArg
+import jadx.core.dex.instructions.args.RegisterArg
 import jadx.core.dex.nodes.MethodNode
 import jadx.core.dex.visitors.AbstractVisitor
 
@@ -27,34 +29,56 @@ class IntentExtraUsageTreeVisitor : AbstractVisitor() {
         }
     }
 
+    // Only the extra types the fuzzer can create, which it checks when loading
+    // the templates.
     private val gettersToExtraTypes = hashMapOf(
         "getStringExtra" to "String",
         "getBooleanExtra" to "Boolean",
-        "getByteExtra" to "Byte",
-        "getCharExtra" to "Char",
-        "getShortExtra" to "Short",
         "getIntExtra" to "Int",
         "getLongExtra" to "Long",
         "getFloatExtra" to "Float",
-        "getDoubleExtra" to "Double",
         "getStringArrayExtra" to "StringArray",
-        "getBooleanArrayExtra" to "BooleanArray",
-        "getByteArrayExtra" to "ByteArray",
-        "getCharArrayExtra" to "CharArray",
-        "getShortArrayExtra" to "ShortArray",
         "getIntArrayExtra" to "IntArray",
         "getLongArrayExtra" to "LongArray",
-        "getDoubleArrayExtra" to "DoubleArray",
         "getIntegerArrayListExtra" to "IntArrayList",
         "getStringArrayListExtra" to "StringArrayList",
     )
 
+    // Getters on the extras Bundle, e.g. getIntent().getExtras().getString(key).
+    private val bundleGettersToExtraTypes = hashMapOf(
+        "getString" to "String",
+        "getBoolean" to "Boolean",
+        "getInt" to "Int",
+        "getLong" to "Long",
+        "getFloat" to "Float",
+        "getStringArray" to "StringArray",
+        "getIntArray" to "IntArray",
+        "getLongArray" to "LongArray",
+        "getFloatArray" to "FloatArray",
+        "getIntegerArrayList" to "IntArrayList",
+        "getStringArrayList" to "StringArrayList",
+        "getParcelable" to "URI",
+    )
+
+    // Methods that only check for the presence of a key, without telling its type.
+    private val presenceChecks = setOf("hasExtra", "containsKey")
+
     private fun visitInvokeNode(node: BaseInvokeNode) {
-        if (node.callMth.declClass.fullName != "android.content.Intent") {
-            return;
+        val extraType = when (node.callMth.declClass.fullName) {
+            "android.content.Intent" -> when (node.callMth.name) {
+                // Most parcelable extras the fuzzer can send are URIs.
+                "getParcelableExtra" -> "URI"
+                in presenceChecks -> null
+                else -> gettersToExtraTypes[node.callMth.name] ?: return
+            }
+            "android.os.Bundle", "android.os.BaseBundle" -> when {
+                // Other bundles, e.g. savedInstanceState, are not sent with the intent.
+                !isIntentExtrasBundle(node.instanceArg) -> return
+                node.callMth.name in presenceChecks -> null
+                else -> bundleGettersToExtraTypes[node.callMth.name] ?: return
+            }
+            else -> return
         }
-
-        val extraType = gettersToExtraTypes[node.callMth.name] ?: return
         var key: String? = null;
 
         for (argument in node.arguments) {
@@ -71,6 +95,26 @@ class IntentExtraUsageTreeVisitor : AbstractVisitor() {
         if (key == null) {
             return
         }
-        extras[key] = extraType
+        if (extraType != null) {
+            extras[key] = extraType
+        } else {
+            // Don't override the type found at a getter of the key.
+            extras.putIfAbsent(key, "String")
+        }
+    }
+
+    // Whether the bundle comes from Intent.getExtras() or Intent.getBundleExtra(), either directly or through a local
+    // variable.
+    private fun isIntentExtrasBundle(bundle: InsnArg?): Boolean {
+        val source = when (bundle) {
+            is InsnWrapArg -> bundle.wrapInsn
+            is RegisterArg -> bundle.assignInsn
+            else -> null
+        }
+        if (source !is BaseInvokeNode) {
+            return false
+        }
+        return source.callMth.declClass.fullName == "android.content.Intent" &&
+                source.callMth.name in setOf("getExtras", "getBundleExtra")
     }
 }
\ No newline at end of file
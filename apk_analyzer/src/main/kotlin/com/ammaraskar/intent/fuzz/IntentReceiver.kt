@@ -8,8 +8,12 @@ class IntentReceiver(
     val componentName: String,
     val actions: Collection<String>,
     val categories: Collection<String>,
-    val aliasTarget: String?
+    val aliasTarget: String?,
+    val dataFilters: MutableList<DataFilter> = mutableListOf()
 ) {
+    // The other activity-aliases routing to the same activity as this receiver.
+    var aliases: Collection<String> = emptyList()
+
     override fun equals(other: Any?): Boolean {
         if (other !is IntentReceiver) {
             return false
@@ -22,8 +26,14 @@ class IntentReceiver(
     }
 }
 
-fun parseIntentReceiversFromManifest(manifestXML: String, decompiler: JadxDecompiler): List<IntentReceiver> {
+fun parseIntentReceiversFromManifest(
+    manifestXML: String,
+    decompiler: JadxDecompiler,
+    includeNonExported: Boolean = false
+): List<IntentReceiver> {
     val targets = mutableListOf<IntentReceiver>()
+    // The components of the activity and its aliases, keyed by the activity they route to.
+    val aliasSets = mutableMapOf<String, MutableSet<String>>()
 
     val documentBuilder = DocumentBuilderFactory.newInstance().newDocumentBuilder()
     val document = documentBuilder.parse(manifestXML.byteInputStream())
@@ -56,8 +66,9 @@ fun parseIntentReceiversFromManifest(manifestXML: String, decompiler: JadxDecomp
                 ?: "true"
         val isExported = exportedAttribute == "true"
 
-        // Only add to list of targets if it is exported.
-        if (!isExported) {
+        // Only add to list of targets if it is exported, unless non-exported components are wanted as well (they
+        // can only be reached as root).
+        if (!isExported && !includeNonExported) {
             continue
         }
 
@@ -69,8 +80,16 @@ fun parseIntentReceiversFromManifest(manifestXML: String, decompiler: JadxDecomp
             null
         }
 
+        // Remember the exported members of the alias set, even the aliases skipped below.
+        if (containingComponent.nodeName == "activity" || aliasTargetActivity != null) {
+            val routedTo = qualifiedClassName(packageName, aliasTargetActivity ?: intentClass)
+            aliasSets.getOrPut(routedTo) { mutableSetOf() }
+                .add(qualifiedClassName(packageName, intentClass))
+        }
+
         val actionNames = mutableListOf<String>()
         val categoryNames = mutableListOf<String>()
+        val dataFilters = mutableListOf<DataFilter>()
         // Gather all the action tags.
         for (j in 0 until node.childNodes.length) {
             val intentFilterChild = node.childNodes.item(j)
@@ -80,6 +99,18 @@ fun parseIntentReceiversFromManifest(manifestXML: String, decompiler: JadxDecomp
                 actionNames.add(intentFilterChild.attributes.getNamedItem("android:name").nodeValue)
             } else if (intentFilterChild.nodeName == "category") {
                 categoryNames.add(intentFilterChild.attributes.getNamedItem("android:name").nodeValue)
+            } else if (intentFilterChild.nodeName == "data") {
+                val attributes = intentFilterChild.attributes
+                dataFilters.add(DataFilter(
+                    scheme = attributes.getNamedItem("android:scheme")?.nodeValue,
+                    host = attributes.getNamedItem("android:host")?.nodeValue,
+                    port = attributes.getNamedItem("android:port")?.nodeValue,
+                    path = attributes.getNamedItem("android:path")?.nodeValue,
+                    path_prefix = attributes.getNamedItem("android:pathPrefix")?.nodeValue,
+                    path_pattern = attributes.getNamedItem("android:pathPattern")?.nodeValue,
+                    mime_type = attributes.getNamedItem("android:mimeType")?.nodeValue,
+                    intent_filter = i,
+                ))
             }
         }
 
@@ -98,7 +129,8 @@ fun parseIntentReceiversFromManifest(manifestXML: String, decompiler: JadxDecomp
             componentName = "$packageName/$intentClass",
             actions = actionNames,
             categories = categoryNames,
-            aliasTarget = aliasTargetActivity
+            aliasTarget = aliasTargetActivity,
+            dataFilters = dataFilters
         )
 
         // Only add alias if it has new actions or categories.
@@ -118,10 +150,11 @@ fun parseIntentReceiversFromManifest(manifestXML: String, decompiler: JadxDecomp
 
         // Skip duplicate intent receivers (i.e., all properties are the same).
         // Duplicates happen because an activity can declare multiple intent filters.
-        // They may have different <data> tags, which we do not parse (yet?).
-        // Might be helpful to use them since they hint at the scheme and host of the URI.
-        if (targets.contains(intentReceiver)) {
+        // They may have different <data> tags, which are merged into the existing receiver.
+        val duplicate = targets.find { it == intentReceiver }
+        if (duplicate != null) {
             println("Skipping duplicate intent receiver: ${intentReceiver.componentName}")
+            duplicate.dataFilters.addAll(dataFilters)
             continue
         }
 
@@ -129,5 +162,18 @@ fun parseIntentReceiversFromManifest(manifestXML: String, decompiler: JadxDecomp
         targets.add(intentReceiver)
     }
 
+    for (target in targets) {
+        val className = qualifiedClassName(packageName, target.componentName.substringAfter("/"))
+        val routedTo = qualifiedClassName(packageName, target.aliasTarget ?: className)
+        target.aliases = aliasSets[routedTo].orEmpty()
+            .filter { it != className }
+            .map { "$packageName/$it" }
+    }
+
     return targets
 }
+
+// Resolve class names relative to the package (e.g. ".MainActivity").
+private fun qualifiedClassName(packageName: String, className: String): String {
+    return if (className.startsWith(".")) "$packageName$className" else className
+}
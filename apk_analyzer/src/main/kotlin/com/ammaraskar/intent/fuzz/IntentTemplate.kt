@@ -12,6 +12,8 @@ class IntentTemplate(
     val actions: Collection<String>,
     val categories: Collection<String>,
     val known_extras_keys: Map<String, String>,
+    val data_filters: Collection<DataFilter> = emptyList(),
+    val aliases: Collection<String> = emptyList(),
 ) {
     // Save this template to a json file in a given directory
     fun saveToFile(outputDir: File) {
@@ -24,3 +26,17 @@ class IntentTemplate(
         return component.substringAfterLast("/")
     }
 }
+
+// The constraints of a single <data> element of an intent filter. The fuzzer combines the schemes, hosts and types of
+// the elements with the same intent_filter, like the framework does.
+@Serializable
+class DataFilter(
+    val scheme: String? = null,
+    val host: String? = null,
+    val port: String? = null,
+    val path: String? = null,
+    val path_prefix: String? = null,
+    val path_pattern: String? = null,
+    val mime_type: String? = null,
+    val intent_filter: Int? = null,
+)
@@ -5,11 +5,14 @@ import kotlinx.serialization.json.Json
 import java.io.File
 
 fun main(args: Array<String>) {
-    val analyzer = ApkAnalyzer(File(args[0]))
+    // Non-exported components can only be fuzzed in the root mode of the fuzzer.
+    val includeNonExported = "--include-non-exported" in args
+    val paths = args.filter { it != "--include-non-exported" }
+    val analyzer = ApkAnalyzer(File(paths[0]), includeNonExported)
 
     val jsonFormat = Json { prettyPrint = true }
     println(jsonFormat.encodeToString(analyzer.intentTemplates))
 
     // Save all intent templates to a given output directory
-    analyzer.intentTemplates.forEach { it.saveToFile(File(args[1])) }
+    analyzer.intentTemplates.forEach { it.saveToFile(File(paths[1])) }
 }
\ No newline at end of file
@@ -0,0 +1,54 @@
+package org.gts3.jnifuzz.contentprovider
+
+import android.app.Activity
+import android.content.BroadcastReceiver
+import android.content.Context
+import android.content.Intent
+import android.util.Log
+import java.util.concurrent.CountDownLatch
+import java.util.concurrent.TimeUnit
+import kotlin.concurrent.thread
+
+/**
+ * Sends the fuzzer's broadcasts to receivers requiring a permission the shell
+ * does not hold.
+ *
+ * The app is built to hold the permission (see `broadcastPermission` in
+ * build.gradle). A FORWARD_BROADCAST broadcast carries the intent URI, which
+ * is sent as an ordered broadcast with the permissions of this app. Its final
+ * result is returned as the result of the FORWARD_BROADCAST broadcast, so
+ * `am broadcast` prints it, and the data holds the exception if it could not
+ * be sent.
+ *
+ * Only senders holding DUMP, i.e. the shell and root, may use the forwarder,
+ * so other apps cannot borrow the permission of this app.
+ */
+class BroadcastForwarder : BroadcastReceiver() {
+    override fun onReceive(context: Context, forward: Intent) {
+        val pending = goAsync()
+        thread {
+            try {
+                val intent = parseFuzzerIntentUri(forward.getStringExtra(EXTRA_INTENT) ?: "")
+                val done = CountDownLatch(1)
+                context.sendOrderedBroadcast(intent, null, object : BroadcastReceiver() {
+                    override fun onReceive(context: Context, result: Intent) {
+                        pending.setResult(resultCode, resultData, getResultExtras(false))
+                        done.countDown()
+                    }
+                }, null, Activity.RESULT_OK, null, null)
+                done.await(BROADCAST_TIMEOUT_SECONDS, TimeUnit.SECONDS)
+            } catch (e: Exception) {
+                Log.w(TAG, "Failed to forward broadcast", e)
+                pending.setResult(Activity.RESULT_CANCELED, e.toString(), null)
+            } finally {
+                pending.finish()
+            }
+        }
+    }
+
+    companion object {
+        const val TAG = "BroadcastForwarder"
+        const val EXTRA_INTENT = "org.gts3.jnifuzz.contentprovider.INTENT"
+        const val BROADCAST_TIMEOUT_SECONDS = 8L
+    }
+}
@@ -0,0 +1,100 @@
+package org.gts3.jnifuzz.contentprovider
+
+import android.app.Activity
+import android.app.Instrumentation
+import android.content.BroadcastReceiver
+import android.content.Context
+import android.content.Intent
+import android.content.IntentFilter
+import android.os.Build
+import android.os.Bundle
+import android.os.Handler
+import android.os.HandlerThread
+import android.util.Log
+import java.util.concurrent.CountDownLatch
+import java.util.concurrent.TimeUnit
+import kotlin.concurrent.thread
+
+/**
+ * Delivers the fuzzer's intents from inside the process of the target app.
+ *
+ * Started once with `am instrument` (which also starts the app), it launches
+ * the app's main activity and then waits for DELIVER broadcasts carrying an
+ * intent URI. Activities are started with startActivitySync, which returns
+ * once the activity is idle, and broadcasts are sent as ordered broadcasts.
+ * The outcome is returned as the result of the DELIVER broadcast, so
+ * `am broadcast` prints it: the result code is RESULT_OK if the intent was
+ * delivered, and the data holds the exception otherwise (or the result data
+ * of the target broadcast receiver).
+ */
+class IntentDeliveryInstrumentation : Instrumentation() {
+    private lateinit var handlerThread: HandlerThread
+
+    override fun onCreate(arguments: Bundle?) {
+        super.onCreate(arguments)
+        start()
+    }
+
+    override fun onStart() {
+        super.onStart()
+
+        handlerThread = HandlerThread("IntentDelivery").apply { start() }
+        val filter = IntentFilter(ACTION_DELIVER)
+        if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.TIRAMISU) {
+            targetContext.registerReceiver(
+                deliveryReceiver, filter, null, Handler(handlerThread.looper), Context.RECEIVER_EXPORTED
+            )
+        } else {
+            targetContext.registerReceiver(deliveryReceiver, filter, null, Handler(handlerThread.looper))
+        }
+
+        targetContext.packageManager.getLaunchIntentForPackage(targetContext.packageName)?.let {
+            it.addFlags(Intent.FLAG_ACTIVITY_NEW_TASK)
+            startActivitySync(it)
+        }
+        Log.i(TAG, "Ready to deliver intents to ${targetContext.packageName}")
+    }
+
+    private val deliveryReceiver = object : BroadcastReceiver() {
+        override fun onReceive(context: Context, deliver: Intent) {
+            val pending = goAsync()
+            thread {
+                try {
+                    val intent = parseFuzzerIntentUri(deliver.getStringExtra(EXTRA_INTENT) ?: "")
+                    val receiverType = deliver.getStringExtra(EXTRA_RECEIVER_TYPE)
+                    if (receiverType == "broadcast") {
+                        sendOrdered(intent, pending)
+                    } else {
+                        intent.addFlags(Intent.FLAG_ACTIVITY_NEW_TASK)
+                        startActivitySync(intent)
+                        pending.setResult(Activity.RESULT_OK, null, null)
+                    }
+                } catch (e: Exception) {
+                    pending.setResult(Activity.RESULT_CANCELED, e.toString(), null)
+                } finally {
+                    pending.finish()
+                }
+            }
+        }
+    }
+
+    /** Sends the intent as an ordered broadcast and forwards its final result. */
+    private fun sendOrdered(intent: Intent, pending: BroadcastReceiver.PendingResult) {
+        val done = CountDownLatch(1)
+        targetContext.sendOrderedBroadcast(intent, null, object : BroadcastReceiver() {
+            override fun onReceive(context: Context, result: Intent) {
+                pending.setResult(resultCode, resultData, getResultExtras(false))
+                done.countDown()
+            }
+        }, Handler(handlerThread.looper), Activity.RESULT_OK, null, null)
+        done.await(BROADCAST_TIMEOUT_SECONDS, TimeUnit.SECONDS)
+    }
+
+    companion object {
+        const val TAG = "IntentDelivery"
+        const val ACTION_DELIVER = "org.gts3.jnifuzz.contentprovider.DELIVER_INTENT"
+        const val EXTRA_INTENT = "org.gts3.jnifuzz.contentprovider.INTENT"
+        const val EXTRA_RECEIVER_TYPE = "org.gts3.jnifuzz.contentprovider.RECEIVER_TYPE"
+        const val BROADCAST_TIMEOUT_SECONDS = 8L
+    }
+}
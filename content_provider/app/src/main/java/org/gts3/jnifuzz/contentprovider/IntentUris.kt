@@ -0,0 +1,65 @@
+package org.gts3.jnifuzz.contentprovider
+
+import android.content.ComponentName
+import android.content.Intent
+import android.net.Uri
+
+/** Key prefix of the URI extras in the intent URIs of the fuzzer. */
+const val URI_PREFIX = "malintent.uri:"
+
+/** Key prefix of the ComponentName extras in the intent URIs of the fuzzer. */
+const val COMPONENT_PREFIX = "malintent.component:"
+
+/**
+ * Key prefix of the array extras in the intent URIs of the fuzzer, with the
+ * `am` option of their type, e.g. `malintent.ia:` for `--eia`.
+ */
+private val ARRAY_PREFIX = Regex("""malintent\.(ia|ial|la|lal|fa|fal|sa|sal):(.*)""", RegexOption.DOT_MATCHES_ALL)
+
+/**
+ * Parses an intent URI of the fuzzer, turning the extras the intent URI
+ * format has no type for (marked with a key prefix) into their proper types.
+ */
+fun parseFuzzerIntentUri(uri: String): Intent {
+    val intent = Intent.parseUri(uri, Intent.URI_INTENT_SCHEME)
+    val extras = intent.extras ?: return intent
+    for (key in extras.keySet()) {
+        val value = extras.getString(key) ?: continue
+        when {
+            key.startsWith(URI_PREFIX) -> {
+                intent.removeExtra(key)
+                intent.putExtra(key.removePrefix(URI_PREFIX), Uri.parse(value))
+            }
+            key.startsWith(COMPONENT_PREFIX) -> {
+                intent.removeExtra(key)
+                intent.putExtra(key.removePrefix(COMPONENT_PREFIX), ComponentName.unflattenFromString(value))
+            }
+            else -> {
+                val match = ARRAY_PREFIX.matchEntire(key) ?: continue
+                intent.removeExtra(key)
+                putArrayExtra(intent, match.groupValues[1], match.groupValues[2], value)
+            }
+        }
+    }
+    return intent
+}
+
+/**
+ * Puts the comma-separated array value into the intent the way `am` does for
+ * the option of the given type.
+ */
+private fun putArrayExtra(intent: Intent, type: String, key: String, value: String) {
+    val numbers = value.split(",")
+    // Like am, only commas not preceded by a backslash separate Strings.
+    val strings = value.split(Regex("""(?<!\\),"""))
+    when (type) {
+        "ia" -> intent.putExtra(key, numbers.map { it.toInt() }.toIntArray())
+        "ial" -> intent.putIntegerArrayListExtra(key, ArrayList(numbers.map { it.toInt() }))
+        "la" -> intent.putExtra(key, numbers.map { it.toLong() }.toLongArray())
+        "lal" -> intent.putExtra(key, ArrayList(numbers.map { it.toLong() }))
+        "fa" -> intent.putExtra(key, numbers.map { it.toFloat() }.toFloatArray())
+        "fal" -> intent.putExtra(key, ArrayList(numbers.map { it.toFloat() }))
+        "sa" -> intent.putExtra(key, strings.toTypedArray())
+        "sal" -> intent.putStringArrayListExtra(key, ArrayList(strings))
+    }
+}
@@ -0,0 +1,133 @@
+package org.gts3.jnifuzz.contentprovider
+
+import android.app.Activity
+import android.app.PendingIntent
+import android.content.BroadcastReceiver
+import android.content.Context
+import android.content.Intent
+import android.content.IntentFilter
+import android.net.Uri
+import android.os.Build
+import android.os.Handler
+import android.os.HandlerThread
+import android.service.notification.NotificationListenerService
+import android.util.Log
+import java.util.concurrent.CountDownLatch
+import java.util.concurrent.TimeUnit
+import kotlin.concurrent.thread
+
+/**
+ * Exposes the PendingIntents of the notifications posted by the target app.
+ *
+ * Once enabled with `cmd notification allow_listener`, it answers two
+ * broadcasts through their result:
+ * - LIST_NOTIFICATIONS returns the PendingIntents of the notifications of a
+ *   package as `kind,action,key` entries separated by `;`, where the action is
+ *   -1 for the content intent and the key is URI-encoded.
+ * - FIRE_PENDING_INTENT sends a PendingIntent with the given intent URI as
+ *   fill-in intent, so mutable PendingIntents receive the fuzzed extras and
+ *   data. The result code is RESULT_OK if it was sent, and the data holds the
+ *   exception otherwise (or the result data of a broadcast PendingIntent).
+ */
+class PendingIntentListener : NotificationListenerService() {
+    private lateinit var handlerThread: HandlerThread
+
+    override fun onListenerConnected() {
+        super.onListenerConnected()
+
+        handlerThread = HandlerThread("PendingIntentListener").apply { start() }
+        val filter = IntentFilter().apply {
+            addAction(ACTION_LIST_NOTIFICATIONS)
+            addAction(ACTION_FIRE_PENDING_INTENT)
+        }
+        if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.TIRAMISU) {
+            registerReceiver(commandReceiver, filter, null, Handler(handlerThread.looper), Context.RECEIVER_EXPORTED)
+        } else {
+            registerReceiver(commandReceiver, filter, null, Handler(handlerThread.looper))
+        }
+        Log.i(TAG, "Notification listener connected")
+    }
+
+    override fun onListenerDisconnected() {
+        unregisterReceiver(commandReceiver)
+        handlerThread.quitSafely()
+        super.onListenerDisconnected()
+    }
+
+    private val commandReceiver = object : BroadcastReceiver() {
+        override fun onReceive(context: Context, command: Intent) {
+            val pending = goAsync()
+            thread {
+                try {
+                    when (command.action) {
+                        ACTION_LIST_NOTIFICATIONS ->
+                            pending.setResult(Activity.RESULT_OK, listPendingIntents(command.getStringExtra(EXTRA_PACKAGE)), null)
+                        ACTION_FIRE_PENDING_INTENT -> fire(command, pending)
+                    }
+                } catch (e: Exception) {
+                    pending.setResult(Activity.RESULT_CANCELED, e.toString(), null)
+                } finally {
+                    pending.finish()
+                }
+            }
+        }
+    }
+
+    private fun listPendingIntents(packageName: String?): String {
+        val entries = mutableListOf<String>()
+        for (notification in activeNotifications.filter { it.packageName == packageName }) {
+            val key = Uri.encode(notification.key)
+            notification.notification.contentIntent?.let { entries.add("${kindOf(it)},-1,$key") }
+            notification.notification.actions?.forEachIndexed { index, action ->
+                action.actionIntent?.let { entries.add("${kindOf(it)},$index,$key") }
+            }
+        }
+        return entries.joinToString(";")
+    }
+
+    /** Sends the PendingIntent with the fill-in intent and forwards its result. */
+    private fun fire(command: Intent, pending: BroadcastReceiver.PendingResult) {
+        val key = Uri.decode(command.getStringExtra(EXTRA_NOTIFICATION_KEY) ?: "")
+        val action = command.getIntExtra(EXTRA_ACTION_INDEX, -1)
+        val notification = activeNotifications.find { it.key == key }?.notification
+            ?: throw IllegalStateException("No notification with key $key")
+        val pendingIntent = if (action < 0) {
+            notification.contentIntent
+        } else {
+            notification.actions?.getOrNull(action)?.actionIntent
+        } ?: throw IllegalStateException("No PendingIntent for action $action")
+
+        val fillIn = parseFuzzerIntentUri(command.getStringExtra(EXTRA_INTENT) ?: "")
+        // The fill-in intent can never change the component anyway.
+        fillIn.component = null
+
+        val done = CountDownLatch(1)
+        pendingIntent.send(this, Activity.RESULT_OK, fillIn, { _, _, resultCode, resultData, _ ->
+            pending.setResult(resultCode, resultData, null)
+            done.countDown()
+        }, Handler(handlerThread.looper))
+        done.await(SEND_TIMEOUT_SECONDS, TimeUnit.SECONDS)
+    }
+
+    private fun kindOf(pendingIntent: PendingIntent): String {
+        if (Build.VERSION.SDK_INT < Build.VERSION_CODES.S) {
+            return "activity"
+        }
+        return when {
+            pendingIntent.isBroadcast -> "broadcast"
+            pendingIntent.isService || pendingIntent.isForegroundService -> "service"
+            else -> "activity"
+        }
+    }
+
+    companion object {
+        const val TAG = "PendingIntentListener"
+        const val ACTION_LIST_NOTIFICATIONS = "org.gts3.jnifuzz.contentprovider.LIST_NOTIFICATIONS"
+        const val ACTION_FIRE_PENDING_INTENT = "org.gts3.jnifuzz.contentprovider.FIRE_PENDING_INTENT"
+        const val EXTRA_PACKAGE = "org.gts3.jnifuzz.contentprovider.PACKAGE"
+        const val EXTRA_NOTIFICATION_KEY = "org.gts3.jnifuzz.contentprovider.NOTIFICATION_KEY"
+        const val EXTRA_ACTION_INDEX = "org.gts3.jnifuzz.contentprovider.ACTION_INDEX"
+        const val EXTRA_INTENT = "org.gts3.jnifuzz.contentprovider.INTENT"
+        const val SEND_TIMEOUT_SECONDS = 8L
+    }
+}
@@ -0,0 +1,28 @@
+package org.gts3.jnifuzz.contentprovider
+
+import android.app.Activity
+import android.content.Intent
+import android.os.Bundle
+import android.util.Log
+
+/**
+ * Logs the intent this activity was started with, including its extras, and
+ * finishes right away.
+ *
+ * The activity manager only logs "(has extras)" for an activity start. The
+ * fuzzer offers this component as a value for the extras of its inputs and
+ * reads the intents an app redirected here from logcat (tag
+ * "IntentFuzzerProbe") to see which of the extras it passed on.
+ */
+class RedirectionProbeActivity : Activity() {
+    override fun onCreate(savedInstanceState: Bundle?) {
+        super.onCreate(savedInstanceState)
+
+        Log.i(TAG, "referrer=$referrer intent=${intent.toUri(Intent.URI_INTENT_SCHEME)}")
+        finish()
+    }
+
+    companion object {
+        const val TAG = "IntentFuzzerProbe"
+    }
+}
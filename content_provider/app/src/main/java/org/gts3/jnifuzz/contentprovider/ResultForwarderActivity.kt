@@ -0,0 +1,63 @@
+package org.gts3.jnifuzz.contentprovider
+
+import android.app.Activity
+import android.content.ComponentName
+import android.content.Intent
+import android.os.Bundle
+import android.util.Log
+
+/**
+ * Starts the activity given in the TARGET_COMPONENT extra with the intent this
+ * activity was started with, and logs the result the activity returns.
+ *
+ * The fuzzer reads the result from logcat (tag "IntentFuzzerResult") since
+ * result codes differentiate handling paths coverage alone may not.
+ */
+class ResultForwarderActivity : Activity() {
+    private var target: String? = null
+
+    override fun onCreate(savedInstanceState: Bundle?) {
+        super.onCreate(savedInstanceState)
+
+        target = intent.getStringExtra(EXTRA_TARGET_COMPONENT)
+        val component = target?.let { ComponentName.unflattenFromString(it) }
+        if (component == null) {
+            Log.i(TAG, "No valid target component given")
+            finish()
+            return
+        }
+
+        // Forward a copy of the intent we were started with to the target.
+        // am start always sets FLAG_ACTIVITY_NEW_TASK, with which (like with
+        // the other flags starting a new task) the result would be canceled
+        // right away instead of being delivered.
+        val forwarded = Intent(intent)
+        forwarded.removeExtra(EXTRA_TARGET_COMPONENT)
+        forwarded.component = component
+        forwarded.removeFlags(
+            Intent.FLAG_ACTIVITY_NEW_TASK or
+                Intent.FLAG_ACTIVITY_NEW_DOCUMENT or
+                Intent.FLAG_ACTIVITY_MULTIPLE_TASK
+        )
+
+        try {
+            startActivityForResult(forwarded, REQUEST_CODE)
+        } catch (e: Exception) {
+            Log.i(TAG, "Failed to start $target: $e")
+            finish()
+        }
+    }
+
+    override fun onActivityResult(requestCode: Int, resultCode: Int, data: Intent?) {
+        super.onActivityResult(requestCode, resultCode, data)
+
+        Log.i(TAG, "component=$target resultCode=$resultCode data=${data?.toUri(0)}")
+        finish()
+    }
+
+    companion object {
+        const val TAG = "IntentFuzzerResult"
+        const val EXTRA_TARGET_COMPONENT = "org.gts3.jnifuzz.contentprovider.TARGET_COMPONENT"
+        const val REQUEST_CODE = 1
+    }
+}
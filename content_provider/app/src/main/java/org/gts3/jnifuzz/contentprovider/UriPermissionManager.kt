@@ -45,6 +45,11 @@ class UriPermissionManager : BroadcastReceiver() {
                 return
             }
 
+            if (p1?.action == ACTION_REVOKE_PERMISSION) {
+                revokeUriPermissions(p0)
+                return
+            }
+
             // Grant permissions to the package
             grantUriPermissionsForPackage(p0!!, packageName!!);
         } else {
@@ -73,7 +78,26 @@ class UriPermissionManager : BroadcastReceiver() {
         }
     }
 
+    /**
+     * Revokes the permissions granted for the staged files from all packages,
+     * so only the grants sent with each intent apply.
+     */
+    fun revokeUriPermissions(context: Context) {
+        for (suffix in suffixes) {
+            for (i in 0..10) {
+                val uri =
+                    Uri.parse("content://" + context.packageName + ".provider/external_files/extra_input_" + i + "." + suffix);
+                context.revokeUriPermission(
+                    uri,
+                    Intent.FLAG_GRANT_WRITE_URI_PERMISSION or Intent.FLAG_GRANT_READ_URI_PERMISSION
+                );
+            }
+        }
+    }
+
     companion object {
+        const val ACTION_REVOKE_PERMISSION = "org.gts3.jnifuzz.sampleintent.REVOKE_PERMISSION"
+
         fun grantUriPermissionsForPackage(context: Context, packageName: String) {
             UriPermissionManager().grantUriPermissionsForPackage(context, packageName)
         }
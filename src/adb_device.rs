@@ -5,17 +5,25 @@
 use core::panic;
 use std::{
     fs,
-    io::{self, BufRead, BufReader, Read, Write},
-    path::PathBuf,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
     process::{Child, Command},
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, OnceLock},
     thread,
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use serde::{Deserialize, Serialize};
 
-use crate::util::encode_hex;
+use crate::{
+    adb_shell::{AdbShell, ShellOutput},
+    app_links::AppLinks,
+    campaign_stats,
+    intent_result::IntentResult,
+    native_crash::NativeCrash,
+    notifier, results_db,
+    util::encode_hex,
+};
 
 use tempfile::tempdir;
 
@@ -24,21 +32,594 @@ use subprocess::Popen;
 use subprocess::PopenConfig;
 use subprocess::Redirection;
 
+/// The package of the content provider app.
+const CONTENT_PROVIDER_PACKAGE: &str = "org.gts3.jnifuzz.contentprovider";
+/// The notification listener of the content provider app.
+const PENDING_INTENT_LISTENER: &str = "org.gts3.jnifuzz.contentprovider/.PendingIntentListener";
+/// `Activity.RESULT_OK`
+const RESULT_OK: i32 = -1;
+/// The activity of the content provider app logging the intents it is
+/// started with, including their extras.
+pub const REDIRECTION_PROBE: &str = "org.gts3.jnifuzz.contentprovider/.RedirectionProbeActivity";
+
+/// The longest wait between two attempts to deliver an intent, however much
+/// the backoff grew.
+const MAX_DELIVERY_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How long to wait for the activity started by the result forwarder to
+/// return a result. Activities returning one usually finish right away, the
+/// others never do within an execution.
+const ACTIVITY_RESULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often the log of the result forwarder is read while waiting.
+const ACTIVITY_RESULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long a command may run in the persistent shell before the shell is
+/// considered hung. Commands with a tighter limit enforce it themselves.
+const SHELL_COMMAND_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Commands that only read the state of the device, which are run again in a
+/// new shell if the persistent shell failed while running them.
+const READ_ONLY_COMMANDS: &[&str] = &[
+    "cat",
+    "date",
+    "df",
+    "dumpsys",
+    "getprop",
+    "id",
+    "logcat -d",
+    "logcat -g",
+    "ls",
+    "pidof",
+    "pm list",
+    "pm path",
+    "stat",
+];
+
+/// Whether the command only reads the state of the device, see
+/// [READ_ONLY_COMMANDS].
+fn is_read_only(command: &str) -> bool {
+    !command.contains([';', '&', '|', '>', '`', '$'])
+        && READ_ONLY_COMMANDS.iter().any(|read_only| {
+            command
+                .strip_prefix(read_only)
+                .map_or(false, |rest| rest.is_empty() || rest.starts_with(' '))
+        })
+}
+
+/// The directory in the sandbox of an app file URIs are staged in, in root
+/// mode.
+pub fn sandbox_staging_dir(package: &str) -> String {
+    format!("/data/user/0/{}/files/malintent", package)
+}
+
+/// A (remote) adb server, e.g. of a device farm, the devices are attached
+/// to.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AdbServer {
+    pub host: String,
+    pub port: u16,
+}
+
+impl AdbServer {
+    /// Parses `host:port`, or `tcp:host:port` as in `ADB_SERVER_SOCKET`.
+    pub fn parse(address: &str) -> Result<Self, libafl::Error> {
+        address
+            .trim_start_matches("tcp:")
+            .rsplit_once(':')
+            .and_then(|(host, port)| {
+                Some(Self {
+                    host: host.to_owned(),
+                    port: port.parse().ok()?,
+                })
+            })
+            .ok_or_else(|| {
+                libafl::Error::illegal_argument(format!(
+                    "Invalid adb server address {:?}, expected host:port",
+                    address
+                ))
+            })
+    }
+
+    /// The arguments selecting this server on the adb command line.
+    pub fn args(&self) -> [String; 4] {
+        [
+            "-H".to_owned(),
+            self.host.clone(),
+            "-P".to_owned(),
+            self.port.to_string(),
+        ]
+    }
+
+    /// The address of a local port forwarded with `adb forward`, which the
+    /// server listens on, e.g. `localhost:6249` becomes `<host>:6249`. The
+    /// server only binds its forwards to its loopback interface unless it was
+    /// started with `adb -a`.
+    pub fn forwarded_address(&self, address: &str) -> String {
+        match address.rsplit_once(':') {
+            Some(("localhost" | "127.0.0.1", port)) => format!("{}:{}", self.host, port),
+            _ => address.to_owned(),
+        }
+    }
+}
+
+/// Version information of an installed package.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PackageVersion {
+    pub version_name: String,
+    pub version_code: String,
+}
+
+/// A crash or ANR entry of the DropBoxManager.
+#[derive(Clone, Debug)]
+pub struct DropBoxEntry {
+    /// When the entry was added, to the second.
+    pub time: SystemTime,
+    pub text: String,
+}
+
+/// An `OutOfMemoryError` the app threw.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OutOfMemoryError {
+    /// The process with the pid caught the error and is still running.
+    Caught(String),
+    /// The process died of the error, so its heap is gone.
+    Fatal,
+}
+
+/// Why an intent could not be delivered with `am start` or `am broadcast`.
+#[derive(Debug)]
+pub enum AmStartError {
+    /// The command timed out.
+    Timeout,
+    /// The intent can never be delivered, e.g. because the target component
+    /// does not exist or is not exported.
+    SetupError(String),
+    /// The command kept failing for other reasons.
+    Failed(String),
+}
+
+/// How the app under test is prepared for the campaign, again after every
+/// restart of the framework or reboot of the device.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AppSetup {
+    pub package: String,
+    /// Whether the standing URI grants are revoked instead of granted, as the
+    /// inputs carry their own grants.
+    pub revoke_uri_grants: bool,
+}
+
+/// The failures of a delivery which always restart the app.
+pub const DEFAULT_APP_RESTART_PATTERNS: &[&str] = &[
+    "OutOfResourcesException",
+    "Activity not started, its current task has been brought to the front",
+];
+
+/// How often and when to retry delivering an intent, and which failures
+/// trigger an app or device restart.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeliveryPolicy {
+    /// Number of attempts to deliver an intent.
+    pub max_attempts: u32,
+    /// Time to wait after the first failed attempt.
+    pub initial_backoff: Duration,
+    /// Factor by which the time to wait grows after every failed attempt.
+    pub backoff_factor: f64,
+    /// Failures with any of these on stderr restart the app.
+    pub app_restart_patterns: Vec<String>,
+    /// Failures with any of these on stderr restart the device right away.
+    pub device_restart_patterns: Vec<String>,
+}
+
+impl Default for DeliveryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(2),
+            backoff_factor: 1.0,
+            app_restart_patterns: DEFAULT_APP_RESTART_PATTERNS
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            device_restart_patterns: Vec::new(),
+        }
+    }
+}
+
+/// How far to escalate the recovery after consecutive failures to reach the
+/// app: restarting the app, re-initializing the coverage socket, restarting
+/// the Android framework and finally rebooting the device.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EscalationPolicy {
+    /// Number of coverage socket reconnects before restarting the app.
+    pub socket_reconnects: u64,
+    /// Number of consecutive failures after which the framework is
+    /// restarted (in addition to the app).
+    pub framework_restart_after: u32,
+    /// Number of consecutive failures after which the device is rebooted
+    /// instead, None to never reboot.
+    pub reboot_after: Option<u32>,
+}
+
+impl Default for EscalationPolicy {
+    fn default() -> Self {
+        Self {
+            socket_reconnects: 1,
+            framework_restart_after: 2,
+            reboot_after: None,
+        }
+    }
+}
+
+/// How to detect that a (re-)started app is ready to receive intents.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IdleStrategy {
+    /// Wait for the "Reporting idle of ActivityRecord" message in the logcat
+    /// of the app
+    ActivityIdle,
+    /// Wait until a window of the app has the input focus
+    WindowFocus,
+    /// Wait until the broadcast queues are idle
+    BroadcastIdle,
+    /// Only wait for the settle delay
+    FixedDelay,
+    /// Only wait until the app process is running, for targets without UI
+    ProcessStarted,
+}
+
+/// Configuration of the idle detection after starting an app.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IdleDetection {
+    pub strategy: IdleStrategy,
+    /// Time to wait after the app process started before detecting idle.
+    pub settle_delay: Duration,
+    /// How long to wait for the app to become idle.
+    pub timeout: Duration,
+}
+
+impl IdleDetection {
+    /// The idle detection for fuzzing the given kind of targets. Targets
+    /// without activities (e.g. broadcast receivers) don't need to wait for an
+    /// idle activity, only for the app process.
+    pub fn for_targets(has_activities: bool) -> Self {
+        if has_activities {
+            Self::default()
+        } else {
+            Self {
+                strategy: IdleStrategy::ProcessStarted,
+                settle_delay: Duration::ZERO,
+                timeout: Duration::from_secs(20),
+            }
+        }
+    }
+}
+
+impl Default for IdleDetection {
+    fn default() -> Self {
+        Self {
+            strategy: IdleStrategy::ActivityIdle,
+            settle_delay: Duration::from_secs(5),
+            timeout: Duration::from_secs(20),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct AdbDevice {
     adb_command: String,
+    /// The adb server to use instead of the local one.
+    server: Option<AdbServer>,
+    /// The serial of the device, if not the one of `ANDROID_SERIAL`.
+    serial: Option<String>,
+    /// Further options of every adb command, e.g. `-e` or `-t 3`.
+    extra_args: Vec<String>,
+    idle_detection: IdleDetection,
+    delivery_policy: DeliveryPolicy,
+    escalation_policy: EscalationPolicy,
+    use_persistent_shell: bool,
+    /// The instrumentation delivering intents from inside the app, if used.
+    instrumentation_runner: Option<String>,
+    /// Whether the intents are delivered as root (root mode), see
+    /// [AdbDevice::with_root].
+    root: bool,
+    /// Whether the intents are delivered with `su`, as adbd does not run as
+    /// root.
+    use_su: bool,
+    /// The uid of the app owning the staged files in root mode.
+    sandbox_uid: Option<u32>,
+    /// The (debuggable) app whose private files are accessed with `run-as`.
+    run_as: Option<String>,
+    /// The Android user whose app instance the intents are delivered to, the
+    /// current user if None, see [AdbDevice::for_user].
+    user: Option<u32>,
+    app_setup: Option<AppSetup>,
+    /// The long-lived shell shared by all clones of the device, once opened.
+    #[serde(skip)]
+    shell: Arc<Mutex<Option<AdbShell>>>,
+    /// Whether adbd supports the persistent shell, once checked.
+    #[serde(skip)]
+    shell_v2: Arc<OnceLock<bool>>,
 }
 
 impl AdbDevice {
     pub fn new(adb_command: &str) -> Self {
         Self {
             adb_command: adb_command.to_owned(),
+            server: None,
+            serial: None,
+            extra_args: Vec::new(),
+            idle_detection: IdleDetection::default(),
+            delivery_policy: DeliveryPolicy::default(),
+            escalation_policy: EscalationPolicy::default(),
+            use_persistent_shell: true,
+            instrumentation_runner: None,
+            root: false,
+            use_su: false,
+            sandbox_uid: None,
+            run_as: None,
+            user: None,
+            app_setup: None,
+            shell: Arc::new(Mutex::new(None)),
+            shell_v2: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// Sets the adb server the device is attached to, if not the local one.
+    pub fn with_server(mut self, server: Option<AdbServer>) -> Self {
+        self.server = server;
+        self
+    }
+
+    /// Sets the serial of the device, if not the one of `ANDROID_SERIAL`.
+    pub fn with_serial(mut self, serial: Option<String>) -> Self {
+        self.serial = serial;
+        self
+    }
+
+    /// Sets further options passed to adb before every command, e.g. `-e` or
+    /// `-t 3`, for what the other settings cannot express.
+    pub fn with_extra_args(mut self, args: Vec<String>) -> Self {
+        self.extra_args = args;
+        self
+    }
+
+    /// The arguments of every adb command, selecting the server and device.
+    fn adb_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = self.server.iter().flat_map(AdbServer::args).collect();
+        if let Some(serial) = &self.serial {
+            args.extend(["-s".to_owned(), serial.clone()]);
+        }
+        args.extend(self.extra_args.iter().cloned());
+        args
+    }
+
+    /// The adb command, talking to the configured server and device.
+    fn adb(&self) -> Command {
+        let mut command = Command::new(&self.adb_command);
+        command.args(self.adb_args());
+        command
+    }
+
+    /// Sets whether to run the device commands in a long-lived shell instead
+    /// of a new `adb shell` process each.
+    pub fn with_persistent_shell(mut self, enabled: bool) -> Self {
+        self.use_persistent_shell = enabled;
+        self
+    }
+
+    /// Sets the instrumentation (e.g.
+    /// `org.gts3.jnifuzz.contentprovider/.IntentDeliveryInstrumentation`) to
+    /// start the app with, which then delivers the intents in-process.
+    pub fn with_instrumentation_runner(mut self, runner: Option<String>) -> Self {
+        self.instrumentation_runner = runner;
+        self
+    }
+
+    /// Delivers the intents as root, which reaches non-exported components,
+    /// and stages the files in the sandbox of the app, if enabled. Prefers
+    /// restarting adbd as root (userdebug builds and emulators) over `su`.
+    pub fn with_root(mut self, enabled: bool, package: &str) -> Result<Self, libafl::Error> {
+        if !enabled {
+            return Ok(self);
+        }
+
+        let is_root =
+            |output: Result<String, libafl::Error>| output.is_ok_and(|id| id.trim() == "0");
+        self.adb().arg("root").output()?;
+        self.adb().arg("wait-for-device").output()?;
+        // The persistent shell did not survive the restart of adbd.
+        *self.shell.lock().unwrap() = None;
+        if !is_root(self.run_command("id -u")) {
+            if !is_root(self.run_command("su 0 id -u")) {
+                return Err(libafl::Error::illegal_state(
+                    "Root mode needs adb root or su on the device",
+                ));
+            }
+            println!("adbd does not run as root, delivering intents with su");
+            self.use_su = true;
+        }
+        self.root = true;
+
+        // Every directory created (`files` may not exist yet) has to belong to
+        // the app, or it cannot write to its own files directory anymore.
+        let uid = self.app_uid(package)?;
+        let dir = sandbox_staging_dir(package);
+        let files_dir = dir
+            .rsplit_once('/')
+            .map_or(dir.as_str(), |(parent, _)| parent);
+        self.run_command(&self.as_root(&format!(
+            "mkdir -p {dir} && chown {uid}:{uid} {files_dir} {dir} && restorecon {files_dir} {dir}"
+        )))?;
+        self.sandbox_uid = Some(uid);
+        Ok(self)
+    }
+
+    /// Accesses the private files of the app with `run-as` and stages the
+    /// files in its sandbox, if the app is debuggable. Root mode takes
+    /// precedence.
+    pub fn with_run_as(mut self, package: &str) -> Self {
+        if self.sandbox_uid.is_some() {
+            return self;
+        }
+
+        let dir = sandbox_staging_dir(package);
+        match self.run_command(&format!("run-as {} mkdir -p {}", package, dir)) {
+            Ok(_) => {
+                println!("Accessing the files of {} with run-as", package);
+                self.run_as = Some(package.to_owned());
+            }
+            Err(err) => println!(
+                "run-as is not available for {}, accessing its files directly: {}",
+                package,
+                access_problem(&err.to_string()).unwrap_or("unknown reason")
+            ),
         }
+        self
+    }
+
+    /// The device delivering the intents to the app instance of the given
+    /// Android user (e.g. a work profile) and staging the content URIs of
+    /// that user. It has a persistent shell of its own, so it can stage while
+    /// the intent of another instance is delivered.
+    pub fn for_user(&self, user: u32) -> Self {
+        Self {
+            user: Some(user),
+            shell: Arc::new(Mutex::new(None)),
+            ..self.clone()
+        }
+    }
+
+    /// The given `am` or `content` command for the app instance of the user
+    /// of the device, e.g. `am start --user 10 -n ...`.
+    fn user_command(&self, command: &str) -> String {
+        let Some(user) = self.user else {
+            return command.to_owned();
+        };
+        for tool in ["am ", "content "] {
+            if let Some((subcommand, rest)) = command
+                .strip_prefix(tool)
+                .and_then(|rest| rest.split_once(' '))
+            {
+                return format!("{tool}{subcommand} --user {user} {rest}");
+            }
+        }
+        command.to_owned()
+    }
+
+    /// Whether the files of URIs are staged in the sandbox of the app.
+    pub fn stages_in_sandbox(&self) -> bool {
+        self.sandbox_uid.is_some() || self.run_as.is_some()
+    }
+
+    /// The command accessing the private files of the app, run as the app if
+    /// possible.
+    fn app_command(&self, package: &str, command: &str) -> String {
+        match (&self.run_as, shlex::try_quote(command)) {
+            (Some(run_as), Ok(quoted)) if run_as == package => {
+                format!("run-as {} sh -c {}", package, quoted)
+            }
+            _ => self.as_root(command),
+        }
+    }
+
+    /// Runs a command accessing the private files of the app, explaining
+    /// why it failed if the files were not accessible.
+    fn run_app_command(
+        &self,
+        package: &str,
+        path: &str,
+        command: &str,
+    ) -> Result<String, libafl::Error> {
+        self.run_command(&self.app_command(package, command))
+            .map_err(|err| {
+                let message = err.to_string();
+                match access_problem(&message) {
+                    Some(problem) => libafl::Error::illegal_state(format!(
+                        "Cannot access {}: {}{}\n{}",
+                        path,
+                        problem,
+                        if self.run_as.is_none() && self.sandbox_uid.is_none() {
+                            " (make the app debuggable to use run-as, or use --root)"
+                        } else {
+                            ""
+                        },
+                        message
+                    )),
+                    None => err,
+                }
+            })
+    }
+
+    /// The command running the given command as root in root mode, i.e.
+    /// with `su` if adbd does not run as root.
+    fn as_root(&self, command: &str) -> String {
+        if !self.use_su {
+            return command.to_owned();
+        }
+        su_command(command)
+    }
+
+    /// The intent delivery command running the given command as root, also
+    /// outside of root mode. In root mode, [AdbDevice::run_am_start] already
+    /// runs every command as root, so it is left as it is.
+    pub fn root_delivery_command(&self, command: &str) -> String {
+        if self.root {
+            return command.to_owned();
+        }
+        su_command(&self.user_command(command))
+    }
+
+    /// Sets how to retry delivering intents.
+    pub fn with_delivery_policy(mut self, delivery_policy: DeliveryPolicy) -> Self {
+        self.delivery_policy = delivery_policy;
+        self
+    }
+
+    /// Sets how to prepare the app under test, see [AdbDevice::set_up_app].
+    pub fn with_app_setup(mut self, app_setup: AppSetup) -> Self {
+        self.app_setup = Some(app_setup);
+        self
+    }
+
+    /// Sets how far to escalate the recovery from failures.
+    pub fn with_escalation_policy(mut self, escalation_policy: EscalationPolicy) -> Self {
+        self.escalation_policy = escalation_policy;
+        self
+    }
+
+    /// How far to escalate the recovery from failures.
+    pub fn escalation_policy(&self) -> &EscalationPolicy {
+        &self.escalation_policy
+    }
+
+    /// Sets how to detect that a started app is ready.
+    pub fn with_idle_detection(mut self, idle_detection: IdleDetection) -> Self {
+        self.idle_detection = idle_detection;
+        self
     }
 
     /// Runs a command on the device and returns the stdout.
     fn run_command(&self, command: &str) -> Result<String, libafl::Error> {
-        let mut adb_command = Command::new(&self.adb_command);
+        if self.use_persistent_shell {
+            match self.run_in_persistent_shell(command, SHELL_COMMAND_TIMEOUT) {
+                Some(Ok(output)) if output.exit_code != 0 => {
+                    return Err(libafl::Error::unknown(format!(
+                        "Command failed: {}\nStdout: {}\nStderr: {}",
+                        command, output.stdout, output.stderr
+                    )));
+                }
+                Some(Ok(output)) => return Ok(output.stdout),
+                Some(Err(err)) => {
+                    return Err(libafl::Error::unknown(format!(
+                        "Command failed in the persistent adb shell: {}\n{}",
+                        command, err
+                    )));
+                }
+                None => {}
+            }
+        }
+
+        let mut adb_command = self.adb();
         adb_command.arg("shell").arg(command);
         println!("Running command: {:?}", adb_command);
         let output = adb_command
@@ -60,9 +641,125 @@ impl AdbDevice {
         Ok(stdout)
     }
 
+    /// Runs a command in the persistent shell, (re-)establishing it if needed.
+    /// Returns None if the command was not run, as the shell can't be
+    /// established or adbd does not support it. If the shell fails while
+    /// running the command, it is only run again in a new shell if it is
+    /// read-only, as others may have taken effect already.
+    fn run_in_persistent_shell(
+        &self,
+        command: &str,
+        timeout: Duration,
+    ) -> Option<io::Result<ShellOutput>> {
+        if !self.supports_shell_v2() {
+            return None;
+        }
+        let mut shell = self.shell.lock().unwrap();
+
+        let mut result = None;
+        for _ in 0..2 {
+            if shell.is_none() {
+                match AdbShell::spawn(self.adb()) {
+                    Ok(new_shell) => *shell = Some(new_shell),
+                    Err(err) => {
+                        println!("Failed to open persistent adb shell: {}", err);
+                        return result;
+                    }
+                }
+            }
+
+            println!("Running command (persistent shell): {:?}", command);
+            match shell.as_mut().unwrap().run(command, timeout) {
+                Ok(output) => return Some(Ok(output)),
+                Err(err) => {
+                    println!("Persistent adb shell failed, re-establishing: {}", err);
+                    *shell = None;
+                    let retry = err.kind() == io::ErrorKind::BrokenPipe || is_read_only(command);
+                    result = Some(Err(err));
+                    if !retry {
+                        break;
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Whether adbd supports the `shell_v2` feature the persistent shell
+    /// needs. Without it, adbd merges stderr into stdout and runs the shell
+    /// in a terminal, so every command is run in an `adb shell` of its own.
+    fn supports_shell_v2(&self) -> bool {
+        *self.shell_v2.get_or_init(|| {
+            let supported = self.adb().arg("features").output().map_or(false, |output| {
+                String::from_utf8_lossy(&output.stdout)
+                    .split([',', '\n'])
+                    .any(|feature| feature.trim() == "shell_v2")
+            });
+            if !supported {
+                println!(
+                    "adbd does not support shell_v2, running every command in a new adb shell"
+                );
+            }
+            supported
+        })
+    }
+
+    /// Runs a command on the device, in the persistent shell if possible, and
+    /// waits for it to finish for at most the timeout. A command that did not
+    /// finish in time is killed and fails with [io::ErrorKind::TimedOut].
+    fn run_with_timeout(&self, command: &str, timeout: Duration) -> io::Result<ShellOutput> {
+        if self.use_persistent_shell {
+            if let Some(result) = self.run_in_persistent_shell(command, timeout) {
+                return result;
+            }
+        }
+
+        let mut argv = vec![self.adb_command.clone()];
+        argv.extend(self.adb_args());
+        argv.extend(["shell".to_owned(), command.to_owned()]);
+        println!("Running command: {:?}", argv);
+        let mut p = Popen::create(
+            &argv,
+            PopenConfig {
+                stdout: Redirection::Pipe,
+                stderr: Redirection::Pipe,
+                ..Default::default()
+            },
+        )
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+
+        // Wait for the command to finish
+        if let Ok(None) = p.wait_timeout(timeout) {
+            if p.kill().is_err() {
+                println!("Failed to kill");
+            }
+            p.wait()
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "Command timed out"));
+        }
+
+        // Capture stdout and stderr
+        let (stdout, stderr) = p
+            .communicate(None)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+
+        // Capture the exit code
+        let exit_code = match p.poll() {
+            Some(ExitStatus::Exited(code)) => code as i32,
+            _ => -1,
+        };
+
+        Ok(ShellOutput {
+            exit_code,
+            stdout: stdout.unwrap_or_default(),
+            stderr: stderr.unwrap_or_default(),
+        })
+    }
+
     /// Runs a command on the device and returns the stdout as a reader.
     fn run_command_io(&self, command: &str) -> Result<Child, libafl::Error> {
-        let mut adb_command = Command::new(&self.adb_command);
+        let mut adb_command = self.adb();
         adb_command.arg("shell").arg(command);
 
         let child = adb_command
@@ -75,115 +772,107 @@ impl AdbDevice {
         Ok(child)
     }
 
-    /// Runs an "am start" command on the device
-    pub fn run_am_start(&self, command: &str, app_name: &str, timeout: Duration) -> Result<(), io::Error> {
-        let mut adb_command = Command::new(&self.adb_command);
-        adb_command.arg("shell").arg(command);
-
-        for i in 0..5 {
-            let mut restart = false;
-
-            println!("Running command: {:?}", adb_command);
-
-            let mut p = Popen::create(
-                &[
-                    &self.adb_command.to_owned(),
-                    &"shell".to_owned(),
-                    &command.to_owned(),
-                ],
-                PopenConfig {
-                    stdout: Redirection::Pipe,
-                    stderr: Redirection::Pipe,
-                    ..Default::default()
-                },
-            )
-            .unwrap();
+    /// Runs an "am start" command on the device and returns its output
+    pub fn run_am_start(
+        &self,
+        command: &str,
+        app_name: &str,
+        timeout: Duration,
+    ) -> Result<String, AmStartError> {
+        let command = &self.as_root(&self.user_command(command));
 
-            // Wait for the command to finish
-            let result = p.wait_timeout(timeout);
-            if let Ok(None) = result {
-                println!("Command timed out");
+        let policy = &self.delivery_policy;
+        let mut last_error = AmStartError::Failed("Maximum retries reached".to_owned());
+        let mut backoff = policy.initial_backoff;
 
-                // A timeout indicates a lack of resources
-                restart = true;
+        for i in 0..policy.max_attempts {
+            let mut restart = false;
+            let mut restart_device = false;
 
-                if p.kill().is_err() {
-                    println!("Failed to kill");
+            let (exit_code, stdout, stderr) = match self.run_with_timeout(command, timeout) {
+                Ok(output) => (Some(output.exit_code), output.stdout, output.stderr),
+                Err(err) => {
+                    if err.kind() == io::ErrorKind::TimedOut {
+                        println!("Command timed out");
+
+                        // A timeout indicates a lack of resources
+                        restart = true;
+                        last_error = AmStartError::Timeout;
+                    } else {
+                        println!("Command failed: {}", err);
+                        last_error = AmStartError::Failed(err.to_string());
+                    }
+                    (None, String::new(), String::new())
                 }
-
-                // Wait for the command to finish
-                println!("Waiting for command to finish");
-                p.wait().unwrap();
-            }
-
-            // Capture stdout
-            let mut stdout = String::new();
-            BufReader::new(p.stdout.take().unwrap())
-                .read_to_string(&mut stdout)
-                .unwrap();
-
-            // Capture stderr
-            let mut stderr = String::new();
-            BufReader::new(p.stderr.take().unwrap())
-                .read_to_string(&mut stderr)
-                .unwrap();
-
-            // Capture the exit code
-            let exit_code = p.poll().unwrap();
+            };
 
             // The command failed when there is either a non-zero exit code or
             // output on stderr.
             // Thus, we return Ok only if the command succeeded.
             if stderr.contains("intent has been delivered to currently running top-most instance.")
             {
-                return Ok(());
+                return Ok(stdout);
             }
 
-            if let ExitStatus::Exited(0) = exit_code {
+            if exit_code == Some(0) {
                 // Now, we need to check the output on stderr.
                 // Successfull, if stderr is empty or contains "has been delivered"
                 if stderr.is_empty() {
-                    return Ok(());
+                    return Ok(stdout);
                 }
 
                 if stderr.contains("Activity class") && stderr.contains("does not exist") {
-                    // This should be handled like a timeout
                     println!("Activity does not exist");
-                    return Err(io::Error::new(io::ErrorKind::TimedOut, "Activity does not exist"));
+                    return Err(AmStartError::SetupError(stderr));
+                }
+
+                if stderr.contains("Permission Denial") {
+                    println!("Target component is not accessible");
+                    return Err(AmStartError::SetupError(stderr));
                 }
 
                 println!("Command failed (stderr)");
-            } else {
-                println!("Command failed (exit code): {:?}", exit_code);
+                last_error = AmStartError::Failed(stderr.clone());
+            } else if let Some(exit_code) = exit_code {
+                println!("Command failed (exit code): {}", exit_code);
+                last_error = AmStartError::Failed(format!("exit code {}", exit_code));
             }
 
-            // If the device is low on resources, we restart it
-            if stderr.contains("OutOfResourcesException")
-                || stderr.contains(
-                    "Activity not started, its current task has been brought to the front",
-                )
+            // E.g. if the device is low on resources, we restart it
+            if policy
+                .app_restart_patterns
+                .iter()
+                .any(|p| stderr.contains(p.as_str()))
             {
                 restart = true;
             }
+            if policy
+                .device_restart_patterns
+                .iter()
+                .any(|p| stderr.contains(p.as_str()))
+            {
+                restart = true;
+                restart_device = true;
+            }
 
             println!("Stdout: {}", stdout);
             println!("Stderr: {}", stderr);
 
             if restart {
-                if i > 1 {
+                if restart_device {
                     self.restart_device();
+                    self.restart_app(app_name);
+                } else {
+                    self.recover(app_name, i + 1);
                 }
-
-                self.restart_app(app_name);
             }
 
-            std::thread::sleep(std::time::Duration::from_secs(2));
+            std::thread::sleep(backoff);
+            backoff = Duration::try_from_secs_f64(backoff.as_secs_f64() * policy.backoff_factor)
+                .map_or(MAX_DELIVERY_BACKOFF, |next| next.min(MAX_DELIVERY_BACKOFF));
         }
 
-        Err(io::Error::new(
-            io::ErrorKind::Other,
-            "Maximum retries reached",
-        ))
+        Err(last_error)
     }
 
     #[allow(dead_code)]
@@ -229,21 +918,76 @@ impl AdbDevice {
         }
     }
 
+    /// Starts the app under the instrumentation, which launches its main
+    /// activity itself.
+    fn start_instrumentation(&self, runner: &str) -> Result<(), libafl::Error> {
+        self.run_command(&format!("am instrument {}", runner))
+            .map(|_| ())
+            .map_err(|err| {
+                libafl::Error::unknown(format!("Failed to start instrumentation: {}", err))
+            })
+    }
+
     /// Tries to start the app with the given name.
     pub fn start_app(&self, app_name: &str) -> Result<(), libafl::Error> {
         println!("Starting app: {}", app_name);
         //self.start_app_monkey(app_name);
-        self.start_app_explicit(app_name)?;
+        match &self.instrumentation_runner {
+            Some(runner) => self.start_instrumentation(runner)?,
+            None => self.start_app_explicit(app_name)?,
+        }
+
+        let wait_start = Instant::now();
+        let result = self.wait_for_idle(app_name);
+        campaign_stats::add_time(&campaign_stats::IDLE_WAIT_TIME, wait_start.elapsed());
+        result
+    }
+
+    /// Waits until the just started app is idle, as the idle detection says.
+    fn wait_for_idle(&self, app_name: &str) -> Result<(), libafl::Error> {
+        if self.idle_detection.strategy == IdleStrategy::ProcessStarted {
+            return self.wait_for_process(app_name);
+        }
 
         // Get the pid of the app
         std::thread::sleep(std::time::Duration::from_secs(2));
         let pid = self.pid_of(app_name)?;
 
-        println!("App started (pid {}), waiting for idle", pid);
+        println!(
+            "App started (pid {}), waiting for idle ({:?})",
+            pid, self.idle_detection.strategy
+        );
+
+        std::thread::sleep(self.idle_detection.settle_delay);
 
-        std::thread::sleep(std::time::Duration::from_secs(5));
+        match self.idle_detection.strategy {
+            IdleStrategy::ActivityIdle => self.wait_for_activity_idle(&pid),
+            IdleStrategy::WindowFocus => self.wait_for_window_focus(app_name),
+            IdleStrategy::BroadcastIdle => self.wait_for_broadcast_idle(),
+            IdleStrategy::FixedDelay | IdleStrategy::ProcessStarted => Ok(()),
+        }
+    }
+
+    /// Waits until the process of the app is running.
+    fn wait_for_process(&self, app_name: &str) -> Result<(), libafl::Error> {
+        let start = Instant::now();
+        loop {
+            match self.pid_of(app_name) {
+                Ok(pid) => {
+                    println!("App started (pid {})", pid);
+                    std::thread::sleep(self.idle_detection.settle_delay);
+                    return Ok(());
+                }
+                Err(err) if start.elapsed() >= self.idle_detection.timeout => return Err(err),
+                Err(_) => std::thread::sleep(Duration::from_millis(200)),
+            }
+        }
+    }
 
-        // Wait for the app to start
+    /// Waits for the app with the given pid to report an idle activity. The
+    /// timeout is counted from the last line the app logged.
+    fn wait_for_activity_idle(&self, pid: &str) -> Result<(), libafl::Error> {
+        let timeout = self.idle_detection.timeout;
         let shell_command = format!("logcat --pid={}", pid,);
 
         let mut logcat_child = self
@@ -262,7 +1006,7 @@ impl AdbDevice {
             loop {
                 match *last_update_clone.lock().unwrap() {
                     Some(my_time) => {
-                        if my_time.elapsed() > Duration::from_secs(20) {
+                        if my_time.elapsed() > timeout {
                             break;
                         }
                     }
@@ -314,6 +1058,34 @@ impl AdbDevice {
         ));
     }
 
+    /// Waits until a window of the app has the input focus.
+    fn wait_for_window_focus(&self, app_name: &str) -> Result<(), libafl::Error> {
+        let start = Instant::now();
+        while start.elapsed() < self.idle_detection.timeout {
+            let focus = self.run_command("dumpsys window | grep mCurrentFocus")?;
+            if focus.contains(&format!("{}/", app_name)) {
+                println!("Found focused window: {}", focus.trim());
+                return Ok(());
+            }
+
+            std::thread::sleep(Duration::from_millis(500));
+        }
+
+        Err(libafl::Error::unknown(format!(
+            "No window of {} got the focus",
+            app_name
+        )))
+    }
+
+    /// Waits until the broadcast queues are idle.
+    fn wait_for_broadcast_idle(&self) -> Result<(), libafl::Error> {
+        self.run_command(&format!(
+            "timeout {} am wait-for-broadcast-idle",
+            self.idle_detection.timeout.as_secs().max(1)
+        ))
+        .map(|_| ())
+    }
+
     /// Stops the app with the given name.
     pub fn stop_app(&self, app_name: &str) -> Result<(), libafl::Error> {
         println!("Stopping app: {}", app_name);
@@ -338,17 +1110,24 @@ impl AdbDevice {
 
     /// Restarts the app with the given name.
     pub fn restart_app(&self, app_name: &str) {
+        self.restart_app_after(app_name, 1);
+    }
+
+    /// Restarts the app with the given name after the given number of
+    /// consecutive failures to reach it, escalating the recovery once per
+    /// attempt.
+    fn restart_app_after(&self, app_name: &str, failures: u32) {
         println!("Restarting app: {}", app_name);
+        results_db::record_device_event("restart_app", app_name);
+        campaign_stats::count(&campaign_stats::APP_RESTARTS);
 
         for i in 0..5 {
-            if i > 1 {
-                self.restart_device();
-            }
+            self.escalate_device_recovery(failures.max(1) + i);
 
             self.stop_app(app_name).expect("Failed to stop app");
 
             // Some apps need to be started immediately, others need some time
-            std::thread::sleep(std::time::Duration::from_secs(i % 2));
+            std::thread::sleep(std::time::Duration::from_secs(u64::from(i % 2)));
 
             match self.start_app(app_name) {
                 Ok(_) => return,
@@ -362,6 +1141,100 @@ impl AdbDevice {
         panic!("Failed to re-start app");
     }
 
+    /// Recovers from the given number of consecutive failures to reach the
+    /// app by restarting it, escalating to restarting the framework or
+    /// rebooting the device according to the escalation policy.
+    pub fn recover(&self, app_name: &str, failures: u32) {
+        self.restart_app_after(app_name, failures);
+    }
+
+    /// Restarts the framework or reboots the device if the given number of
+    /// consecutive failures calls for it.
+    fn escalate_device_recovery(&self, failures: u32) {
+        let policy = &self.escalation_policy;
+        if policy.reboot_after.map_or(false, |after| failures > after) {
+            self.reboot_device();
+        } else if failures > policy.framework_restart_after {
+            self.restart_device();
+        }
+    }
+
+    /// Reboots the device, waits until it finished booting and restores what
+    /// the campaign set up on it.
+    pub fn reboot_device(&self) {
+        println!("Rebooting device");
+        results_db::record_device_event("reboot_device", "");
+        notifier::notify_device_failure("rebooting the device", "");
+        campaign_stats::count(&campaign_stats::DEVICE_REBOOTS);
+
+        // The forwards are gone with the connection to the device.
+        let forwards = self.forwards().unwrap_or_else(|err| {
+            println!("Failed to list the adb forwards: {}", err);
+            Vec::new()
+        });
+        let status = self
+            .adb()
+            .arg("reboot")
+            .status()
+            .and_then(|_| self.adb().arg("wait-for-device").status());
+        if let Err(err) = status {
+            println!("Failed to reboot device: {}", err);
+            return;
+        }
+
+        // The shell of the previous boot is gone.
+        *self.shell.lock().unwrap() = None;
+
+        if self.wait_for_boot() {
+            println!("Device booted");
+        } else {
+            println!("Device did not finish booting in time");
+        }
+        self.restore_after_reboot(&forwards);
+    }
+
+    /// Waits up to two minutes until the device finished booting. Returns
+    /// whether it did.
+    fn wait_for_boot(&self) -> bool {
+        for _ in 0..120 {
+            if self
+                .run_command("getprop sys.boot_completed")
+                .map_or(false, |output| output.trim() == "1")
+            {
+                return true;
+            }
+            std::thread::sleep(Duration::from_secs(1));
+        }
+        false
+    }
+
+    /// Restores adbd running as root (in root mode without su), the given adb
+    /// forwards and the app setup after a reboot.
+    fn restore_after_reboot(&self, forwards: &[(String, String)]) {
+        if self.sandbox_uid.is_some() && !self.use_su {
+            println!("Restarting adbd as root");
+            let status = self
+                .adb()
+                .arg("root")
+                .status()
+                .and_then(|_| self.adb().arg("wait-for-device").status());
+            if let Err(err) = status {
+                println!("Failed to restart adbd as root: {}", err);
+            }
+            // The persistent shell did not survive the restart of adbd.
+            *self.shell.lock().unwrap() = None;
+        }
+
+        for (local, remote) in forwards {
+            println!("Re-establishing adb forward {} -> {}", local, remote);
+            if let Err(err) = self.forward(local, remote) {
+                println!("{}", err);
+            }
+        }
+
+        self.set_up_app();
+    }
+
     /// Returns the pid of the app with the given name.
     pub fn pid_of(&self, app_name: &str) -> Result<String, libafl::Error> {
         let shell_command = format!("pidof -s {}", app_name,);
@@ -384,10 +1257,15 @@ impl AdbDevice {
     /// Restart the entire device via adb.
     pub fn restart_device(&self) {
         println!("Restarting device");
+        results_db::record_device_event("restart_device", "");
+        notifier::notify_device_failure("restarting the framework", "");
+        campaign_stats::count(&campaign_stats::DEVICE_RESTARTS);
         self.run_command("stop").expect("Failed to stop device");
         std::thread::sleep(std::time::Duration::from_secs(1));
         self.run_command("start").expect("Failed to start device");
         std::thread::sleep(std::time::Duration::from_secs(3));
+        // The URI grants are gone with the framework.
+        self.set_up_app();
     }
 
     /// Enables native hooking for an application and restarts it, if it was not already enabled.
@@ -399,7 +1277,7 @@ impl AdbDevice {
         let filename = format!("/data/user/0/{}/.hook_native", app_name);
 
         // Create the file
-        self.run_command(&format!("touch {}", filename))
+        self.run_app_command(app_name, &filename, &format!("touch {}", filename))
             .expect("Failed to touch file");
 
         if !was_enabled {
@@ -417,7 +1295,7 @@ impl AdbDevice {
         let filename = format!("/data/user/0/{}/.hook_native", app_name);
 
         // Delete the file
-        self.run_command(&format!("rm -f {}", filename))
+        self.run_app_command(app_name, &filename, &format!("rm -f {}", filename))
             .expect("Failed to delete file");
 
         if was_enabled {
@@ -432,7 +1310,7 @@ impl AdbDevice {
         let filename = format!("/data/user/0/{}/.hook_native", app_name);
 
         // Check if the file exists
-        let output = self.run_command(&format!("ls {}", filename));
+        let output = self.run_command(&self.app_command(app_name, &format!("ls {}", filename)));
 
         return match output {
             Ok(output) => output.trim() == filename,
@@ -451,79 +1329,124 @@ impl AdbDevice {
         // The trace files are located in the app's data directory
         let trace_dir = format!("/data/user/0/{}/native_traces", app_name);
 
-        // Pull the files to a temporary directory
-        let temp_dir = tempdir()?;
-        let temp_dir_path = temp_dir.path().to_owned();
-
-        // Pull the files
-        let output = Command::new(&self.adb_command)
-            .arg("pull")
-            .arg(&trace_dir)
-            .arg(&temp_dir_path)
-            .output()?;
-
-        // Print the output
-        println!("Output: {}", String::from_utf8_lossy(&output.stdout));
+        if self.run_as.as_deref() == Some(app_name) {
+            // adb cannot pull the private files of the app, read them as the
+            // app instead
+            self.read_app_files(app_name, &trace_dir, trace_dir_host)?;
+        } else {
+            // Pull the files to a temporary directory
+            let temp_dir = tempdir()?;
+            let temp_dir_path = temp_dir.path().to_owned();
+
+            // Pull the files
+            let output = self
+                .adb()
+                .arg("pull")
+                .arg(&trace_dir)
+                .arg(&temp_dir_path)
+                .output()?;
+
+            // Print the output
+            println!("Output: {}", String::from_utf8_lossy(&output.stdout));
+            if let Some(problem) = access_problem(&String::from_utf8_lossy(&output.stderr)) {
+                println!(
+                    "Cannot pull {}: {} (make the app debuggable to use run-as, or use --root)",
+                    trace_dir, problem
+                );
+            }
 
-        // Move the files to the destination directory
-        let native_traces_dir = temp_dir_path.join("native_traces");
+            // Move the files to the destination directory
+            let native_traces_dir = temp_dir_path.join("native_traces");
 
-        if !native_traces_dir.exists() {
-            println!("No native traces found");
-            return Ok(());
-        }
+            if !native_traces_dir.exists() {
+                println!("No native traces found");
+                return Ok(());
+            }
 
-        fs::create_dir_all(trace_dir_host)?;
-        for entry in fs::read_dir(native_traces_dir)? {
-            let entry = entry?;
-            let dest_path = trace_dir_host.join(entry.file_name());
-            fs::copy(entry.path(), dest_path)?;
+            fs::create_dir_all(trace_dir_host)?;
+            for entry in fs::read_dir(native_traces_dir)? {
+                let entry = entry?;
+                let dest_path = trace_dir_host.join(entry.file_name());
+                fs::copy(entry.path(), dest_path)?;
+            }
         }
 
         // Delete the files on the device
-        self.run_command(&format!("rm -rf {}", trace_dir))
+        self.run_app_command(app_name, &trace_dir, &format!("rm -rf {}", trace_dir))
             .expect("Failed to delete files on the device");
 
         Ok(())
     }
 
-    // Creates a file on the device with the given bytes
-    pub fn create_file(&self, filename: &str, content: Vec<u8>) {
-        //println!("Creating file: {} (length: {})", filename, content.len());
-
-        // Create the file
-        self.run_command(&format!("touch {}", filename))
-            .expect("Failed to touch file");
+    /// Copies the files in the private directory of the app to the host
+    /// directory, reading them with `run-as`.
+    fn read_app_files(&self, app_name: &str, dir: &str, dir_host: &Path) -> Result<(), io::Error> {
+        let Ok(listing) = self.run_command(&self.app_command(app_name, &format!("ls {}", dir)))
+        else {
+            println!("No files found in {}", dir);
+            return Ok(());
+        };
 
-        // Write the byte content to the file
-        self.run_command(&format!(
-            "echo -n -e \"{}\" > {}",
-            encode_hex(&content),
-            filename
-        ))
-        .expect("Failed to write to file");
+        fs::create_dir_all(dir_host)?;
+        for name in listing
+            .lines()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let output = self
+                .adb()
+                .arg("exec-out")
+                .arg(format!("run-as {} cat {}/{}", app_name, dir, name))
+                .output()?;
+            if !output.status.success() {
+                println!(
+                    "Cannot read {}/{}: {}",
+                    dir,
+                    name,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+                continue;
+            }
+            fs::write(dir_host.join(name), output.stdout)?;
+        }
+        Ok(())
     }
 
-    // Register a content on the device with the given bytes
-    pub fn register_content(&self, uri: &str, content: Vec<u8>) {
-        //println!("Registering content: {} (length: {})", uri, content.len());
-
-        let mut child = self
-            .run_command_io(&format!("content write --uri {}", uri))
-            .expect("Failed to register content");
-
-        // Write the byte content to the file
-        let mut stdin = child.stdin.take().unwrap();
-
-        stdin.write_all(&content).expect("Failed to write to file");
-
-        // Close stdin
-        drop(stdin);
+    /// Stages the given files and content provider URIs with their content on
+    /// the device in a single shell invocation, instead of one (or two) per
+    /// URI.
+    pub fn stage_uri_contents(
+        &self,
+        files: &[(String, Vec<u8>)],
+        contents: &[(String, Vec<u8>)],
+    ) -> Result<(), libafl::Error> {
+        // The app stages the files in its sandbox itself.
+        if let (Some(package), false) = (&self.run_as, files.is_empty()) {
+            if let Some(command) = staging_command(files, &[], None) {
+                let path = sandbox_staging_dir(package);
+                self.run_app_command(package, &path, &command)?;
+            }
+            if let Some(command) = staging_command(&[], contents, self.user) {
+                self.run_command(&command)?;
+            }
+            return Ok(());
+        }
 
-        // Wait for the command to finish
-        child
-            .wait()
-            .expect("Failed to wait for content command to finish");
+        if let Some(mut command) = staging_command(files, contents, self.user) {
+            // Hand the files staged in the sandbox over to the app.
+            if let (Some(uid), false) = (self.sandbox_uid, files.is_empty()) {
+                let paths = files
+                    .iter()
+                    .map(|(filename, _)| filename.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                command.push_str(&format!(
+                    " && chown {uid}:{uid} {paths} && restorecon {paths}"
+                ));
+            }
+            self.run_command(&self.as_root(&command))?;
+        }
+        Ok(())
     }
 
     // Grant content provider uri permissions to the given package
@@ -540,49 +1463,700 @@ impl AdbDevice {
         )).expect("Failed to grant uri permissions");
     }
 
+    /// Revokes the URI permissions granted by earlier campaigns, so only the
+    /// grants sent with every intent apply.
+    pub fn revoke_uri_permissions(&self, package: &str) {
+        self.run_command(&format!(
+            "am broadcast -n 'org.gts3.jnifuzz.contentprovider/org.gts3.jnifuzz.contentprovider.UriPermissionManager' \
+            -a org.gts3.jnifuzz.sampleintent.REVOKE_PERMISSION \
+            --es android.intent.extra.PACKAGE_NAME '{}'",
+            package,
+        ))
+        .expect("Failed to revoke uri permissions");
+    }
+
+    /// Prepares the app for the campaign according to the [AppSetup], if any:
+    /// grants it the URI permissions of the staged files (or revokes them)
+    /// and sets it as debug app.
+    pub fn set_up_app(&self) {
+        let Some(setup) = &self.app_setup else {
+            return;
+        };
+        // Standing grants would make the mutated grants of the inputs moot.
+        if setup.revoke_uri_grants {
+            self.revoke_uri_permissions(&setup.package);
+        } else {
+            self.grant_uri_permissions(&setup.package);
+        }
+        self.set_debug_app(&setup.package);
+    }
+
     // Set the given app as debug app
     pub fn set_debug_app(&self, package: &str) {
-        self.run_command(&format!(
-            "am set-debug-app --persistent {}",
+        self.run_command(&format!("am set-debug-app --persistent {}", package,))
+            .expect("Failed to set debug app");
+    }
+
+    /// Copies the coverage agent from /data/local/tmp into the startup agents
+    /// directory of the given app.
+    pub fn install_coverage_agent(&self, package: &str) -> Result<(), libafl::Error> {
+        let agents_dir = format!("/data/data/{}/code_cache/startup_agents/", package);
+        self.run_app_command(package, &agents_dir, &format!("mkdir -p {}", agents_dir))?;
+        self.run_app_command(
             package,
-        )).expect("Failed to set debug app");
+            &agents_dir,
+            &format!(
+                "cp /data/local/tmp/libcoverage_instrumenting_agent.so {}",
+                agents_dir
+            ),
+        )?;
+        Ok(())
     }
 
-    // Reports if a native crash happened in the app, and whether it's caused by
-    // the coverage agent (i.e., libcoverage_agent found in the stack trace)
-    pub fn report_native_crash(&self, app_name: &str) {
-        // Check the logcat 'crash' buffer of the past 3 seconds for native crashes
-        let start_time = (SystemTime::now() - Duration::from_secs(3))
-            .duration_since(UNIX_EPOCH)
-            .unwrap();
+    /// Returns the build fingerprint (`ro.build.fingerprint`) of the device.
+    pub fn build_fingerprint(&self) -> Result<String, libafl::Error> {
+        Ok(self
+            .run_command("getprop ro.build.fingerprint")?
+            .trim()
+            .to_owned())
+    }
+
+    /// Returns the version name and code of the given package.
+    pub fn package_version(&self, package: &str) -> Result<PackageVersion, libafl::Error> {
+        let output = self.run_command(&format!("dumpsys package {}", package))?;
+
+        let mut version = PackageVersion::default();
+        for token in output.split_whitespace() {
+            // Only take the first occurrence, which belongs to the installed package.
+            if let Some(name) = token.strip_prefix("versionName=") {
+                if version.version_name.is_empty() {
+                    version.version_name = name.to_owned();
+                }
+            } else if let Some(code) = token.strip_prefix("versionCode=") {
+                if version.version_code.is_empty() {
+                    version.version_code = code.to_owned();
+                }
+            }
+        }
+
+        if version.version_code.is_empty() {
+            return Err(libafl::Error::unknown(format!(
+                "Failed to get version of package {}",
+                package
+            )));
+        }
+
+        Ok(version)
+    }
+
+    /// Returns the content of the logcat 'crash' buffer of the past `duration`.
+    pub fn recent_crash_log(&self, duration: Duration) -> Result<String, libafl::Error> {
+        self.recent_log("crash", duration)
+    }
+
+    /// Returns the content of the crash log since the given time.
+    pub fn crash_log_since(&self, since: SystemTime) -> Result<String, libafl::Error> {
+        self.log_since("crash", since)
+    }
+
+    /// Checks whether the given app crashed (uncaught Java exception or fatal
+    /// signal) since the given time.
+    pub fn has_crashed_since(&self, app_name: &str, since: SystemTime) -> bool {
+        let Ok(crash_log) = self.log_since("crash", since) else {
+            return false;
+        };
+
+        crash_log.contains(&format!("Process: {},", app_name))
+            || NativeCrash::parse(&crash_log, app_name).is_some()
+    }
+
+    /// Returns the content of the given logcat buffer of the past `duration`.
+    fn recent_log(&self, buffer: &str, duration: Duration) -> Result<String, libafl::Error> {
+        self.log_since(buffer, SystemTime::now() - duration)
+    }
+
+    /// Returns the content of the given logcat buffer since the given time.
+    fn log_since(&self, buffer: &str, since: SystemTime) -> Result<String, libafl::Error> {
+        let start_time = since.duration_since(UNIX_EPOCH).unwrap();
 
-        let shell_command = &format!(
-            "logcat -b crash -t {}.{:03}",
+        self.run_command(&format!(
+            "logcat -b {} -t {}.{:03}",
+            buffer,
             start_time.as_secs(),
             start_time.subsec_millis()
-        );
+        ))
+    }
 
-        let output = self
-            .run_command(&shell_command)
-            .expect("Failed to start logcat command");
+    /// Returns the main and crash logcat lines the process with the given pid
+    /// logged since the given time.
+    pub fn process_log_since(&self, pid: &str, since: SystemTime) -> Result<String, libafl::Error> {
+        let start_time = since.duration_since(UNIX_EPOCH).unwrap();
+
+        self.run_command(&format!(
+            "logcat -b main,crash -t {}.{:03} --pid={}",
+            start_time.as_secs(),
+            start_time.subsec_millis(),
+            pid
+        ))
+    }
+
+    /// Returns the components (`package/class`) of the given package the
+    /// system created since the given time, according to the events log.
+    pub fn started_components(
+        &self,
+        package: &str,
+        since: SystemTime,
+    ) -> Result<Vec<String>, libafl::Error> {
+        let log = self.log_since("events", since)?;
+        let prefix = format!("{}/", package);
+
+        let mut components = Vec::new();
+        for line in log.lines().filter(|line| {
+            line.contains("am_create_activity")
+                || line.contains("am_restart_activity")
+                || line.contains("am_create_service")
+        }) {
+            // e.g. "am_create_activity: [0,123,45,com.example/.MainActivity,...]"
+            let Some(component) = line
+                .split(|c: char| c == ',' || c == '[' || c == ']' || c.is_whitespace())
+                .find(|field| field.starts_with(&prefix))
+            else {
+                continue;
+            };
+
+            // Expand the short form "com.example/.Foo" to "com.example/com.example.Foo".
+            let class = &component[prefix.len()..];
+            let component = match class.strip_prefix('.') {
+                Some(_) => format!("{}{}{}", prefix, package, class),
+                None => component.to_owned(),
+            };
+            if !components.contains(&component) {
+                components.push(component);
+            }
+        }
+
+        Ok(components)
+    }
+
+    /// Returns the Linux user id the given package runs as.
+    pub fn app_uid(&self, package: &str) -> Result<u32, libafl::Error> {
+        // e.g. "package:com.example uid:10123"
+        let output = self.run_command(&format!("pm list packages -U {}", package))?;
+        output
+            .lines()
+            .filter(|line| line.starts_with(&format!("package:{} ", package)))
+            .find_map(|line| line.split("uid:").nth(1))
+            .and_then(|uid| uid.trim().split(',').next()?.parse().ok())
+            .ok_or_else(|| libafl::Error::unknown(format!("Failed to get uid of app {}", package)))
+    }
+
+    /// Returns the names and uids of all installed packages.
+    pub fn installed_packages(&self) -> Result<Vec<(String, u32)>, libafl::Error> {
+        // e.g. "package:com.example uid:10123"
+        let output = self.run_command("pm list packages -U")?;
+        Ok(output
+            .lines()
+            .filter_map(|line| {
+                let (package, uid) = line.strip_prefix("package:")?.split_once(" uid:")?;
+                Some((
+                    package.to_owned(),
+                    uid.trim().split(',').next()?.parse().ok()?,
+                ))
+            })
+            .collect())
+    }
+
+    /// Returns the intents of the activity starts the app with the given uid
+    /// requested since the given time, as logged by the activity manager.
+    pub fn activity_starts_since(
+        &self,
+        uid: u32,
+        since: SystemTime,
+    ) -> Result<Vec<String>, libafl::Error> {
+        let log = self.log_since("system", since)?;
+        let from_uid = |line: &str| {
+            let rest = line.split("from uid ").nth(1)?;
+            let digits = rest.split(|c: char| !c.is_ascii_digit()).next()?;
+            digits.parse::<u32>().ok()
+        };
 
-        let mut found_crash = false;
-        let mut caused_by_coverage = false;
+        // e.g. "ActivityTaskManager: START u0 {act=... dat=... cmp=...} from uid 10123"
+        Ok(log
+            .lines()
+            .filter(|line| line.contains("START u") && from_uid(line) == Some(uid))
+            .filter_map(|line| {
+                let start = line.find('{')?;
+                let end = line.rfind('}')?;
+                (start < end).then(|| line[start + 1..end].to_owned())
+            })
+            .collect())
+    }
+
+    /// Returns the intents the given package started the [REDIRECTION_PROBE]
+    /// with since the given time, as `intent:` URIs including the extras.
+    pub fn redirection_probe_intents(
+        &self,
+        package: &str,
+        since: SystemTime,
+    ) -> Result<Vec<String>, libafl::Error> {
+        let log = self.log_since("main", since)?;
+        let prefix = format!("referrer=android-app://{} intent=", package);
+
+        // e.g. "IntentFuzzerProbe: referrer=android-app://com.example intent=intent:#Intent;...;end"
+        Ok(log
+            .lines()
+            .filter_map(|line| line.split_once("IntentFuzzerProbe: ").map(|(_, msg)| msg))
+            .filter_map(|msg| msg.strip_prefix(&prefix))
+            .map(str::to_owned)
+            .collect())
+    }
 
-        for line in output.lines() {
-            // Check if the line contains "pid: <pid>"
-            if line.contains("Fatal signal") {
-                found_crash = line.contains(&format!("({})", app_name));
+    /// Returns the App Links verification state of the given package. Only
+    /// supported since Android 12.
+    pub fn app_links(&self, package: &str) -> Result<AppLinks, libafl::Error> {
+        let output = self.run_command(&format!("pm get-app-links {}", package))?;
+        Ok(AppLinks::parse(&output))
+    }
+
+    /// Returns the static, dynamic, pinned and cached shortcuts of the given
+    /// package, one `ShortcutInfo {...}` per line.
+    pub fn shortcuts(&self, package: &str) -> Result<String, libafl::Error> {
+        // The flags match manifest, dynamic, pinned and cached shortcuts.
+        self.run_command(&format!(
+            "cmd shortcut get-shortcuts --flags 15 {}",
+            package
+        ))
+    }
+
+    /// Returns the PendingIntents of the notifications the given package
+    /// posted, as listed by the notification listener of the content provider
+    /// app: `kind,action,key` entries separated by `;`.
+    pub fn notification_pending_intents(&self, package: &str) -> Result<String, libafl::Error> {
+        self.run_command(&format!(
+            "cmd notification allow_listener {}",
+            PENDING_INTENT_LISTENER
+        ))?;
+        // The listener only registers its receiver once it is connected.
+        thread::sleep(Duration::from_secs(1));
+
+        let output = self.run_command(&format!(
+            "am broadcast -p {cp} -a {cp}.LIST_NOTIFICATIONS --es {cp}.PACKAGE '{package}'",
+            cp = CONTENT_PROVIDER_PACKAGE,
+            package = package
+        ))?;
+        match IntentResult::from_broadcast_output(&output) {
+            Some(IntentResult {
+                code: RESULT_OK,
+                data: Some(data),
+                ..
+            }) => Ok(data),
+            _ => Err(libafl::Error::unknown(format!(
+                "Failed to list the notifications of {}: {}",
+                package,
+                output.trim()
+            ))),
+        }
+    }
+
+    /// Returns the result code and data the given activity returned to the
+    /// result forwarder of the content provider app since the given time, if
+    /// it returned one. The result arrives after `am start` returned, so the
+    /// log of the forwarder is polled until the activity returned a result,
+    /// the forwarder failed to start it or [ACTIVITY_RESULT_TIMEOUT] passed.
+    pub fn activity_result(
+        &self,
+        component: &str,
+        since: SystemTime,
+    ) -> Result<Option<(i32, Option<String>)>, libafl::Error> {
+        let start = Instant::now();
+        loop {
+            let log = self.log_since("main", since)?;
+
+            // e.g. "IntentFuzzerResult: component=com.example/.Foo resultCode=-1 data=null"
+            let result = log
+                .lines()
+                .filter_map(|line| line.split_once("IntentFuzzerResult: ").map(|(_, msg)| msg))
+                .filter_map(|msg| {
+                    let rest = msg.strip_prefix(&format!("component={} resultCode=", component))?;
+                    let (code, data) = rest.split_once(" data=")?;
+                    let data = (data != "null").then(|| data.to_owned());
+                    Some((code.parse().ok()?, data))
+                })
+                .last();
+            let failed = log.contains(&format!(
+                "IntentFuzzerResult: Failed to start {}:",
+                component
+            ));
+
+            if result.is_some() || failed || start.elapsed() >= ACTIVITY_RESULT_TIMEOUT {
+                return Ok(result);
             }
+            std::thread::sleep(ACTIVITY_RESULT_POLL_INTERVAL);
+        }
+    }
 
-            if found_crash && line.contains("libcoverage_instrumenting_agent.so") {
-                caused_by_coverage = true;
-                break;
+    /// Checks whether the system reported an ANR for the app in the past `duration`.
+    pub fn has_recent_anr(&self, app_name: &str, duration: Duration) -> bool {
+        match self.recent_log("system", duration) {
+            Ok(output) => output
+                .lines()
+                .any(|line| line.contains(&format!("ANR in {}", app_name))),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns the DropBoxManager crash and ANR entries of the given app,
+    /// oldest first. Dropbox keeps the full stack traces even when the logcat
+    /// crash buffer has already rotated. Entries without a readable time are
+    /// left out.
+    pub fn dropbox_crash_entries(
+        &self,
+        app_name: &str,
+    ) -> Result<Vec<DropBoxEntry>, libafl::Error> {
+        // The entries are timestamped in the local time of the device.
+        let utc_offset = parse_utc_offset(&self.run_command("date +%z")?)
+            .ok_or_else(|| libafl::Error::unknown("Failed to read the time zone of the device"))?;
+        let mut entries = Vec::new();
+
+        for tag in ["data_app_crash", "data_app_anr"] {
+            let output = self.run_command(&format!("dumpsys dropbox --print {}", tag))?;
+
+            // Entries are separated by a line of '=' characters.
+            let mut entry = String::new();
+            for line in output.lines().chain(std::iter::once("=")) {
+                if line.starts_with('=') && line.chars().all(|c| c == '=') {
+                    if entry.contains(&format!("Process: {}", app_name)) {
+                        // e.g. "2024-01-31 12:00:00 data_app_crash (text, 1234 bytes)"
+                        if let Some(time) = entry
+                            .lines()
+                            .next()
+                            .and_then(|header| parse_local_time(header, utc_offset))
+                        {
+                            let text = entry.trim().to_owned();
+                            entries.push(DropBoxEntry { time, text });
+                        }
+                    }
+                    entry.clear();
+                } else {
+                    entry.push_str(line);
+                    entry.push('\n');
+                }
             }
         }
 
-        if found_crash {
-            println!("Found native crash (caused by coverage: {})", caused_by_coverage);
+        entries.sort_by_key(|entry| entry.time);
+        Ok(entries)
+    }
+
+    /// Checks whether the app logged a StrictMode policy violation in the past `duration`.
+    pub fn has_recent_strict_mode_violation(&self, app_name: &str, duration: Duration) -> bool {
+        let Ok(pid) = self.pid_of(app_name) else {
+            return false;
+        };
+
+        match self.recent_log("main", duration) {
+            Ok(output) => output.lines().any(|line| {
+                line.contains("StrictMode policy violation")
+                    && line.split_whitespace().nth(2) == Some(pid.as_str())
+            }),
+            Err(_) => false,
+        }
+    }
+
+    /// Captures a bugreport of the device and stores it at the given host path.
+    pub fn capture_bugreport(&self, dest: &Path) -> Result<(), io::Error> {
+        println!("Capturing bugreport to {:?}", dest);
+
+        // On recent devices this creates a zipped bugreport (bugreportz) and
+        // pulls it to the host.
+        let output = self.adb().arg("bugreport").arg(dest).output()?;
+
+        if !output.status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "Failed to capture bugreport: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether the given app threw an OutOfMemoryError since the given
+    /// time, either caught (in the log of the running process) or fatal (in
+    /// the crash log, also if the app was restarted since).
+    pub fn out_of_memory_error(
+        &self,
+        app_name: &str,
+        since: SystemTime,
+    ) -> Option<OutOfMemoryError> {
+        const ERROR: &str = "java.lang.OutOfMemoryError";
+        if let Ok(pid) = self.pid_of(app_name) {
+            if self
+                .process_log_since(&pid, since)
+                .map_or(false, |log| log.contains(ERROR))
+            {
+                return Some(OutOfMemoryError::Caught(pid));
+            }
         }
+        self.log_since("crash", since)
+            .map_or(false, |log| {
+                log.contains(&format!("Process: {},", app_name)) && log.contains(ERROR)
+            })
+            .then_some(OutOfMemoryError::Fatal)
     }
+
+    /// Dumps the Java heap of the app process with the given pid and pulls it
+    /// to the given host file. The heap of a process that already died can't
+    /// be dumped.
+    pub fn dump_heap(&self, app_name: &str, pid: &str, dest: &Path) -> Result<(), io::Error> {
+        let device_file = format!("/data/local/tmp/{}.hprof", app_name);
+
+        println!("Dumping heap of {} (pid {}) to {:?}", app_name, pid, dest);
+
+        // Recent Android versions wait until the dump is written.
+        self.run_command(&format!("am dumpheap {} {}", pid, device_file))
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+
+        let output = self
+            .adb()
+            .arg("pull")
+            .arg(&device_file)
+            .arg(dest)
+            .output()?;
+        let _ = self.run_command(&format!("rm -f {}", device_file));
+
+        if !output.status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "Failed to pull heap dump: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Free space of the data partition in MiB.
+    pub fn free_storage_mb(&self) -> Result<u64, libafl::Error> {
+        // e.g. "/dev/block/dm-5  6082144 3150424 2882216  53% /data"
+        let output = self.run_command("df -k /data")?;
+        output
+            .lines()
+            .nth(1)
+            .and_then(|line| line.split_whitespace().nth(3))
+            .and_then(|available| available.parse::<u64>().ok())
+            .map(|available| available / 1024)
+            .ok_or_else(|| libafl::Error::unknown(format!("Invalid df output: {}", output)))
+    }
+
+    /// Memory available for starting new apps in MiB.
+    pub fn available_memory_mb(&self) -> Result<u64, libafl::Error> {
+        // e.g. "MemAvailable:    1234567 kB"
+        let output = self.run_command("cat /proc/meminfo")?;
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix("MemAvailable:"))
+            .and_then(|line| line.split_whitespace().next())
+            .and_then(|available| available.parse::<u64>().ok())
+            .map(|available| available / 1024)
+            .ok_or_else(|| libafl::Error::unknown("No MemAvailable in /proc/meminfo"))
+    }
+
+    /// Thermal status of the device, from 0 (none) to 6 (shutdown).
+    pub fn thermal_status(&self) -> Result<u32, libafl::Error> {
+        // e.g. "Thermal Status: 0"
+        let output = self.run_command("dumpsys thermalservice")?;
+        output
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("Thermal Status:"))
+            .and_then(|status| status.trim().parse().ok())
+            .ok_or_else(|| libafl::Error::unknown("No thermal status in dumpsys output"))
+    }
+
+    /// Whether logd answers queries about its buffers.
+    pub fn logcat_healthy(&self) -> bool {
+        self.run_command("logcat -g").is_ok()
+    }
+
+    /// Frees storage by trimming the caches of all apps and removing the
+    /// files staged for earlier intents.
+    pub fn free_storage(&self) -> Result<(), libafl::Error> {
+        self.run_command("pm trim-caches 1000G")?;
+        self.run_command("rm -f /data/local/tmp/extra_input_* /data/local/tmp/*.hprof")?;
+        Ok(())
+    }
+
+    /// Frees memory by killing all background processes.
+    pub fn kill_background_processes(&self) -> Result<(), libafl::Error> {
+        self.run_command("am kill-all").map(|_| ())
+    }
+
+    /// Clears all logcat buffers.
+    pub fn clear_logcat(&self) -> Result<(), libafl::Error> {
+        self.run_command("logcat -c").map(|_| ())
+    }
+
+    /// Re-establishes the adb forward of the given local TCP port, if there is
+    /// one. Returns whether a forward was re-established.
+    pub fn refresh_forward(&self, local_port: u16) -> Result<bool, io::Error> {
+        let local = format!("tcp:{}", local_port);
+        let Some((_, remote)) = self.forwards()?.into_iter().find(|(l, _)| *l == local) else {
+            return Ok(false);
+        };
+
+        println!("Re-establishing adb forward {} -> {}", local, remote);
+        self.forward(&local, &remote)?;
+        Ok(true)
+    }
+
+    /// The adb forwards as pairs of the local and the remote socket, e.g.
+    /// `("tcp:6249", "tcp:6249")`.
+    fn forwards(&self) -> Result<Vec<(String, String)>, io::Error> {
+        let output = self.adb().arg("forward").arg("--list").output()?;
+
+        // Each line looks like "<serial> tcp:<local> tcp:<remote>".
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                (fields.len() == 3).then(|| (fields[1].to_owned(), fields[2].to_owned()))
+            })
+            .collect())
+    }
+
+    /// Forwards the local socket to the remote socket on the device.
+    fn forward(&self, local: &str, remote: &str) -> Result<(), io::Error> {
+        let status = self.adb().arg("forward").arg(local).arg(remote).status()?;
+        if !status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Failed to forward {} to {}", local, remote),
+            ));
+        }
+        Ok(())
+    }
+
+    // Reports if a native crash happened in the app, and whether it's caused by
+    // the coverage agent (i.e., libcoverage_agent found in the stack trace).
+    // Returns the parsed crash so it can be bucketed by its signature.
+    pub fn report_native_crash(&self, app_name: &str) -> Option<NativeCrash> {
+        // Check the logcat 'crash' buffer of the past 3 seconds for native crashes
+        let output = self
+            .recent_crash_log(Duration::from_secs(3))
+            .expect("Failed to start logcat command");
+
+        let crash = NativeCrash::parse(&output, app_name)?;
+
+        println!(
+            "Found native crash {} (caused by coverage: {})",
+            crash.signature(),
+            crash.caused_by_coverage
+        );
+
+        Some(crash)
+    }
+}
+
+/// Why a command could not access a file, if it failed for lack of access.
+fn access_problem(output: &str) -> Option<&'static str> {
+    if output.contains("not debuggable") {
+        Some("the app is not debuggable")
+    } else if output.contains("avc: denied") {
+        Some("SELinux denies the access")
+    } else if output.contains("Permission denied") || output.contains("Operation not permitted") {
+        Some("the file is owned by another user or SELinux denies the access")
+    } else if output.contains("Unknown package") || output.contains("unknown package") {
+        Some("the app is not installed")
+    } else {
+        None
+    }
+}
+
+/// The command running the given command as root with `su`. Quoted for
+/// `sh -c`, as some builds of `su` only run a single program otherwise.
+fn su_command(command: &str) -> String {
+    match shlex::try_quote(command) {
+        Ok(quoted) => format!("su 0 sh -c {}", quoted),
+        Err(_) => format!("su 0 {}", command),
+    }
+}
+
+/// The shell command staging the given files and content provider URIs with
+/// their content on the device, None if there is nothing to stage. The
+/// content is written for the given Android user, the current one if None.
+pub fn staging_command(
+    files: &[(String, Vec<u8>)],
+    contents: &[(String, Vec<u8>)],
+    user: Option<u32>,
+) -> Option<String> {
+    let user = user.map_or(String::new(), |user| format!("--user {} ", user));
+    let commands: Vec<String> = files
+        .iter()
+        .map(|(filename, content)| format!("echo -n -e \"{}\" > {}", encode_hex(content), filename))
+        .chain(contents.iter().map(|(uri, content)| {
+            format!(
+                "echo -n -e \"{}\" | content write {}--uri {}",
+                encode_hex(content),
+                user,
+                uri
+            )
+        }))
+        .collect();
+
+    (!commands.is_empty()).then(|| commands.join(" && "))
+}
+
+/// The offset of a time zone in seconds, as printed by `date +%z`, e.g.
+/// `+0130`.
+fn parse_utc_offset(offset: &str) -> Option<i64> {
+    let offset = offset.trim();
+    let (sign, digits) = match offset.split_at(offset.len().min(1)) {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i64 = digits[..2].parse().ok()?;
+    let minutes: i64 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// The time a line starting with a local `YYYY-MM-DD HH:MM:SS` timestamp
+/// refers to, given the offset of the time zone.
+fn parse_local_time(line: &str, utc_offset: i64) -> Option<SystemTime> {
+    let mut fields = line.split_whitespace();
+    let date: Vec<i64> = fields
+        .next()?
+        .split('-')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    let time: Vec<i64> = fields
+        .next()?
+        .split(':')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    let (&[year, month, day], &[hour, minute, second]) = (date.as_slice(), time.as_slice()) else {
+        return None;
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days since the epoch of the proleptic Gregorian date.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second - utc_offset;
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(seconds).ok()?))
 }
@@ -4,17 +4,35 @@
 //! [Executor] trait. This struct contains the logic to actually invoke and
 //! monitor the execution of the intent on the device.
 
+use std::collections::HashSet;
 use std::fmt::Debug;
-use std::time::Duration;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant, SystemTime};
 use std::{fmt::Formatter, marker::PhantomData};
 
-use libafl::prelude::{
-    ExitKind, HasBytesVec, HasObservers, MatchName, ObserversTuple, UsesObservers,
-};
+use libafl::prelude::{ExitKind, HasObservers, MatchName, ObserversTuple, UsesObservers};
 use libafl::{executors::Executor, prelude::UsesInput, state::UsesState};
 
-use crate::adb_device::AdbDevice;
-use crate::intent_input::{ExtraType, IntentInput, ReceiverType, URIScheme};
+use crate::adb_device::{AdbDevice, AmStartError};
+use crate::campaign_stats;
+use crate::delivery::DeliveryObserver;
+use crate::intent_input::{IntentInput, ReceiverType};
+use crate::intent_result::{IntentResult, IntentResultObserver};
+use crate::pending_intents;
+use crate::pipelined_stage::PreparesNextInput;
+use crate::socket_coverage_observer::SocketCoverageObserver;
+
+/// How broadcasts to receivers requiring a permission are sent.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PermissionDelivery {
+    /// As the shell user, which only holds a few permissions
+    Shell,
+    /// As root, which passes all permission checks (needs a rooted device)
+    Root,
+    /// Through the broadcast forwarder of the content provider app, built to
+    /// hold the permission
+    Companion,
+}
 
 // Lots of single letter generic types get confusing. A best-effort explanation
 // from my understanding:
@@ -25,6 +43,32 @@ use crate::intent_input::{ExtraType, IntentInput, ReceiverType, URIScheme};
 // S: State, the input state for the program?
 pub struct AdbExecutor<EM, OT, Z, S> {
     adb_device: AdbDevice,
+    /// Whether to deliver activity intents through the result forwarder and
+    /// capture the results the activities return.
+    capture_activity_results: bool,
+    /// Whether to capture the results receivers set on broadcasts.
+    capture_broadcast_results: bool,
+    /// Whether to deliver the intents through the delivery instrumentation
+    /// running in the app.
+    deliver_via_instrumentation: bool,
+    /// Whether to re-execute inputs covering new edges and keep only the new
+    /// edges covered again.
+    confirm_new_coverage: bool,
+    /// How broadcasts to receivers requiring a permission are sent.
+    permission_delivery: PermissionDelivery,
+    /// The permissions already warned about as the shell may lack them.
+    warned_permissions: HashSet<String>,
+    /// The keys of the posted notifications, listed after the given number of
+    /// app restarts.
+    posted_notifications: Option<(u64, HashSet<String>)>,
+    /// The devices delivering to the app instances of further Android users,
+    /// by user, see [AdbExecutor::with_instances].
+    instance_devices: Vec<(u32, AdbDevice)>,
+    /// The input executed after the current one, see [PreparesNextInput].
+    next_input: Option<IntentInput>,
+    /// The user and hash of the input whose content URIs were staged ahead
+    /// of its execution.
+    prestaged: Option<(Option<u32>, String)>,
 
     observers: OT,
     phantom: PhantomData<(EM, S, Z)>,
@@ -34,10 +78,151 @@ impl<EM, OT, Z, S> AdbExecutor<EM, OT, Z, S> {
     pub fn new(adb_device: AdbDevice, observers: OT) -> Self {
         Self {
             adb_device,
+            capture_activity_results: false,
+            capture_broadcast_results: false,
+            deliver_via_instrumentation: false,
+            confirm_new_coverage: false,
+            permission_delivery: PermissionDelivery::Shell,
+            warned_permissions: HashSet::new(),
+            posted_notifications: None,
+            instance_devices: Vec::new(),
+            next_input: None,
+            prestaged: None,
             observers,
             phantom: PhantomData,
         }
     }
+
+    /// Captures the results activities return into the
+    /// [IntentResultObserver], if enabled.
+    pub fn capture_activity_results(mut self, enabled: bool) -> Self {
+        self.capture_activity_results = enabled;
+        self
+    }
+
+    /// Captures the results receivers set on (ordered) broadcasts into the
+    /// [IntentResultObserver], if enabled.
+    pub fn capture_broadcast_results(mut self, enabled: bool) -> Self {
+        self.capture_broadcast_results = enabled;
+        self
+    }
+
+    /// Delivers the intents through the delivery instrumentation the app was
+    /// started with, if enabled. The outcome of every delivery is captured
+    /// into the [IntentResultObserver].
+    pub fn deliver_via_instrumentation(mut self, enabled: bool) -> Self {
+        self.deliver_via_instrumentation = enabled;
+        self
+    }
+
+    /// Re-executes inputs that cover new edges and drops the new edges not
+    /// covered again, if enabled. This keeps the nondeterminism of the app's
+    /// background threads from inflating the corpus.
+    pub fn confirm_new_coverage(mut self, enabled: bool) -> Self {
+        self.confirm_new_coverage = enabled;
+        self
+    }
+
+    /// Sends the broadcasts to receivers requiring a permission this way.
+    pub fn permission_delivery(mut self, permission_delivery: PermissionDelivery) -> Self {
+        self.permission_delivery = permission_delivery;
+        self
+    }
+
+    /// Delivers the intents to the app instances of the given Android users
+    /// as well, in the order the [SocketCoverageObserver] spreads the
+    /// executions over them. While the intent of one instance is delivered,
+    /// the content URIs of the next input are staged for the next instance.
+    pub fn with_instances(mut self, users: &[u32]) -> Self {
+        self.instance_devices = users
+            .iter()
+            .map(|&user| (user, self.adb_device.for_user(user)))
+            .collect();
+        self
+    }
+
+    /// The device delivering to the app instance of the user, the current
+    /// user if None.
+    fn instance_device(&self, user: Option<u32>) -> &AdbDevice {
+        self.instance_devices
+            .iter()
+            .find(|(instance_user, _)| Some(*instance_user) == user)
+            .map_or(&self.adb_device, |(_, device)| device)
+    }
+
+    /// The command sending a broadcast to a receiver requiring the
+    /// permission.
+    fn permission_shell_command(
+        &mut self,
+        device: &AdbDevice,
+        input: &IntentInput,
+        permission: &str,
+    ) -> String {
+        match self.permission_delivery {
+            PermissionDelivery::Shell => {
+                if self.warned_permissions.insert(permission.to_owned()) {
+                    println!(
+                        "Warning: {} requires {}, which the shell may not hold \
+                         (see --permission-delivery)",
+                        input.component(),
+                        permission
+                    );
+                }
+                input.shell_command()
+            }
+            // Root passes the permission checks of the receiver.
+            PermissionDelivery::Root => device.root_delivery_command(&input.shell_command()),
+            PermissionDelivery::Companion => input.forwarded_shell_command(),
+        }
+    }
+
+    /// Whether the notification with the key is still posted. The
+    /// notifications are listed again after every restart of the app, which
+    /// cancels them.
+    fn notification_posted(&mut self, package: &str, key: &str) -> bool {
+        let restarts = campaign_stats::APP_RESTARTS.load(Ordering::Relaxed);
+        if self
+            .posted_notifications
+            .as_ref()
+            .map_or(true, |(listed_at, _)| *listed_at != restarts)
+        {
+            let keys = pending_intents::posted_notification_keys(&self.adb_device, package)
+                .unwrap_or_else(|err| {
+                    println!("{}", err);
+                    HashSet::new()
+                });
+            self.posted_notifications = Some((restarts, keys));
+        }
+        self.posted_notifications
+            .as_ref()
+            .map_or(false, |(_, keys)| keys.contains(key))
+    }
+
+    /// Marks the intent of the execution as not delivered, so the feedbacks
+    /// skip it.
+    fn set_delivered(&mut self, delivered: bool)
+    where
+        OT: MatchName,
+    {
+        if let Some(observer) = self
+            .observers
+            .match_name_mut::<DeliveryObserver>("DeliveryObserver")
+        {
+            observer.set_delivered(delivered);
+        }
+    }
+
+    fn set_result(&mut self, result: Option<IntentResult>)
+    where
+        OT: MatchName,
+    {
+        if let Some(observer) = self
+            .observers
+            .match_name_mut::<IntentResultObserver>("IntentResultObserver")
+        {
+            observer.set_result(result);
+        }
+    }
 }
 
 impl<EM, OT, Z, S> Executor<EM, Z> for AdbExecutor<EM, OT, Z, S>
@@ -62,46 +247,222 @@ where
             _ => Duration::from_secs(20),
         };
 
+        // PendingIntents are always sent by the notification listener of the
+        // content provider app, as long as their notification is posted.
+        let fires_pending_intent = input.pending_intent.is_some();
+        if let Some(target) = &input.pending_intent {
+            if !self.notification_posted(&input.component_package, &target.notification_key) {
+                println!(
+                    "Notification {} is no longer posted, skipping its PendingIntent",
+                    target.notification_key
+                );
+                campaign_stats::count(&campaign_stats::SETUP_ERRORS);
+                self.set_delivered(false);
+                return Ok(ExitKind::Ok);
+            }
+        }
+        // The app instance of this execution and of the next one, if the
+        // executions are spread over several.
+        let (user, next_user) = self
+            .observers
+            .match_name::<SocketCoverageObserver>("SocketCoverageObserver")
+            .map_or((None, None), |observer| {
+                (observer.active_user(), observer.next_user())
+            });
+        let device = self.instance_device(user).clone();
+
+        let instrumented = self.deliver_via_instrumentation && !fires_pending_intent;
+        let capture_result = self.capture_activity_results
+            && !instrumented
+            && !fires_pending_intent
+            && input.receiver_type == ReceiverType::Activity;
+
         // Get the command to run on the device
-        let shell_command = input.shell_command();
+        let shell_command = if instrumented {
+            input.instrumented_shell_command()
+        } else if capture_result {
+            input.result_forwarding_shell_command()
+        } else if let (Some(permission), ReceiverType::BroadcastReceiver, false) =
+            (&input.permission, input.receiver_type, fires_pending_intent)
+        {
+            self.permission_shell_command(&device, input, permission)
+        } else {
+            input.shell_command()
+        };
+
+        campaign_stats::count(&campaign_stats::EXECUTIONS);
 
         // Create required files and content on the device for all URI extras
-        input
-            .extras
-            .iter()
-            .enumerate()
-            .filter_map(|(index, extra)| match &extra.value {
-                ExtraType::URI(uri) => Some((index + 1, uri)),
-                _ => None,
-            })
-            .chain(input.data.iter().map(|uri| (0, uri)))
-            .for_each(|(id, uri)| {
-                let identifier = uri.identifier(id);
-                let content_bytes = uri.content.bytes().to_vec();
-
-                // Depending on the scheme, create the file or register the content on the adb device
-                // Note that we need to skip the "file://" prefix for the identifier if it is a file
-                match uri.scheme {
-                    URIScheme::Content => {
-                        self.adb_device.register_content(&identifier, content_bytes)
-                    }
-                    URIScheme::File => self.adb_device.create_file(&identifier[7..], content_bytes),
-                    URIScheme::Other => {}
-                }
-            });
+        let staging_start = Instant::now();
+        let (files, contents) = input.uri_payloads();
+        // The content URIs may have been staged during the previous execution.
+        let prestaged = self.prestaged.take() == Some((user, input.hash()));
+        let staged = device.stage_uri_contents(&files, if prestaged { &[] } else { &contents });
+        campaign_stats::add_time(&campaign_stats::STAGING_TIME, staging_start.elapsed());
+        // Without its URI contents, the intent would not carry the input.
+        if let Err(err) = staged {
+            println!("Failed to stage URI contents: {:?}", err);
+            campaign_stats::count(&campaign_stats::DELIVERY_FAILURES);
+            self.set_delivered(false);
+            return Ok(ExitKind::Ok);
+        }
 
         // Run the command
         println!("Running command: {:?}", shell_command);
-        let result = self
+        let exec_start = SystemTime::now();
+        let delivery_start = Instant::now();
+        // Stage the content URIs of the next input for the next app instance
+        // while waiting on this one. Its files are staged right before its
+        // execution, as all instances share them.
+        let next = self
+            .next_input
+            .take()
+            .zip(next_user.filter(|next_user| *next_user != user))
+            .map(|(next_input, next_user)| {
+                let (_, contents) = next_input.uri_payloads();
+                (next_input.hash(), next_user, contents)
+            })
+            .filter(|(_, _, contents)| !contents.is_empty());
+        let next_device = next
+            .as_ref()
+            .map(|(_, next_user, _)| self.instance_device(*next_user).clone());
+        let (result, next_staged) = std::thread::scope(|scope| {
+            let prestaging =
+                next.as_ref()
+                    .zip(next_device.as_ref())
+                    .map(|((_, _, contents), next_device)| {
+                        scope.spawn(move || next_device.stage_uri_contents(&[], contents))
+                    });
+            let result = device.run_am_start(&shell_command, &input.component_package, timeout);
+            (result, prestaging.map(|prestaging| prestaging.join()))
+        });
+        campaign_stats::add_time(&campaign_stats::DELIVERY_TIME, delivery_start.elapsed());
+        match (next, next_staged) {
+            (Some((hash, next_user, _)), Some(Ok(Ok(())))) => {
+                self.prestaged = Some((next_user, hash));
+            }
+            (_, Some(Ok(Err(err)))) => {
+                println!("Failed to stage URI contents of the next input: {:?}", err)
+            }
+            _ => {}
+        }
+
+        // Let the coverage map arrive while doing the remaining device work
+        // of this execution.
+        if let Some(observer) = self
+            .observers
+            .match_name_mut::<SocketCoverageObserver>("SocketCoverageObserver")
+        {
+            observer.request_coverage();
+        }
+
+        if capture_result {
+            match self
+                .adb_device
+                .activity_result(&input.component(), exec_start)
+            {
+                Ok(result) => self.set_result(result.map(|(code, data)| IntentResult {
+                    code,
+                    data,
+                    extras: None,
+                })),
+                Err(err) => println!("Failed to read activity result: {:?}", err),
+            }
+        }
+
+        // `am broadcast` sends the intent as an ordered broadcast and prints
+        // the final result code, data and extras once it completed.
+        if self.capture_broadcast_results
+            && !fires_pending_intent
+            && input.receiver_type == ReceiverType::BroadcastReceiver
+        {
+            if let Ok(output) = &result {
+                self.set_result(IntentResult::from_broadcast_output(output));
+            }
+        }
+
+        // The instrumentation and the notification listener return the
+        // outcome of the delivery as the result of their broadcast. An
+        // activity (or PendingIntent) they failed to start carries the
+        // exception as data.
+        let mut delivery_failed = false;
+        if instrumented || fires_pending_intent {
+            if let Ok(output) = &result {
+                let delivery = IntentResult::from_broadcast_output(output);
+                delivery_failed = (input.receiver_type == ReceiverType::Activity
+                    || fires_pending_intent)
+                    && delivery
+                        .as_ref()
+                        .map_or(false, |d| d.code == 0 && d.data.is_some());
+                self.set_result(delivery);
+            }
+        }
+
+        // A crash of the app takes precedence over how the command ended, as
+        // a crashing app often also makes the command time out.
+        if self
             .adb_device
-            .run_am_start(&shell_command, &input.component_package, timeout);
+            .has_crashed_since(&input.component_package, exec_start)
+        {
+            println!("App crashed");
+            return Ok(ExitKind::Crash);
+        }
+
+        if self.confirm_new_coverage && result.is_ok() && !delivery_failed {
+            if let Some(observer) = self
+                .observers
+                .match_name_mut::<SocketCoverageObserver>("SocketCoverageObserver")
+            {
+                observer.confirm_new_coverage(input, || {
+                    println!("Re-running command: {:?}", shell_command);
+                    let delivery_start = Instant::now();
+                    let rerun =
+                        device.run_am_start(&shell_command, &input.component_package, timeout);
+                    campaign_stats::add_time(
+                        &campaign_stats::DELIVERY_TIME,
+                        delivery_start.elapsed(),
+                    );
+                    if let Err(err) = rerun {
+                        println!("Failed to re-run command: {:?}", err);
+                    }
+                });
+            }
+        }
 
-        // The command failed when there is either a non-zero exit code or
-        // output on stderr.
-        // Thus, we return Ok only if the command succeeded.
         match result {
+            Ok(_) if delivery_failed => {
+                println!("Failed to deliver intent in-process");
+                campaign_stats::count(&campaign_stats::SETUP_ERRORS);
+                self.set_delivered(false);
+                Ok(ExitKind::Ok)
+            }
             Ok(_) => Ok(ExitKind::Ok),
-            Err(_) => Ok(ExitKind::Timeout)
+            Err(AmStartError::Timeout) => Ok(ExitKind::Timeout),
+            // The intent never reached the app, so there is nothing to observe
+            // and the feedbacks skip the execution. This is counted separately
+            // instead of being reported as a timeout.
+            Err(AmStartError::SetupError(reason)) => {
+                println!("Failed to deliver intent: {}", reason.trim());
+                campaign_stats::count(&campaign_stats::SETUP_ERRORS);
+                self.set_delivered(false);
+                Ok(ExitKind::Ok)
+            }
+            Err(AmStartError::Failed(reason)) => {
+                println!("Failed to deliver intent: {}", reason.trim());
+                campaign_stats::count(&campaign_stats::DELIVERY_FAILURES);
+                self.set_delivered(false);
+                Ok(ExitKind::Ok)
+            }
+        }
+    }
+}
+
+impl<EM, OT, Z, S> PreparesNextInput for AdbExecutor<EM, OT, Z, S> {
+    fn prepare_next(&mut self, input: Option<&IntentInput>) {
+        // Only the instance of the next execution can be prepared while the
+        // current one runs.
+        if !self.instance_devices.is_empty() {
+            self.next_input = input.cloned();
         }
     }
 }
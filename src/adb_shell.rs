@@ -0,0 +1,153 @@
+//! Long-lived `adb shell` to run device commands in.
+//!
+//! Spawning a new `adb shell` process for every device command dominates the
+//! latency of an execution and the CPU use of the host. An [AdbShell] keeps a
+//! single interactive shell open and runs the commands in it one after the
+//! other, separating their outputs with a marker line that also carries the
+//! exit code of the command.
+//!
+//! The shell needs the `shell_v2` feature of adbd, which keeps stdout and
+//! stderr apart and does not allocate a terminal for the shell.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+/// Marker printed after the output of every command.
+const END_MARKER: &str = "__MALINTENT_END__";
+
+/// Output of a command run in the shell.
+#[derive(Debug)]
+pub struct ShellOutput {
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+#[derive(Debug)]
+pub struct AdbShell {
+    child: Child,
+    stdin: ChildStdin,
+    /// The lines of stdout and stderr, read on background threads so neither
+    /// pipe fills up while the other one is read.
+    stdout: Receiver<Vec<u8>>,
+    stderr: Receiver<Vec<u8>>,
+}
+
+impl AdbShell {
+    /// Opens an interactive shell on the device of the adb command.
+    pub fn spawn(mut adb_command: Command) -> io::Result<Self> {
+        let mut child = adb_command
+            .arg("shell")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        Ok(Self {
+            stdin: child.stdin.take().unwrap(),
+            stdout: read_lines(child.stdout.take().unwrap()),
+            stderr: read_lines(child.stderr.take().unwrap()),
+            child,
+        })
+    }
+
+    /// Runs the command in the shell and waits for it to finish, for at most
+    /// the timeout. An error means the shell is gone or out of sync (an error
+    /// of kind [io::ErrorKind::TimedOut] if the command did not finish in
+    /// time) and has to be re-established. Only an error of kind
+    /// [io::ErrorKind::BrokenPipe] means the command was certainly not run.
+    pub fn run(&mut self, command: &str, timeout: Duration) -> io::Result<ShellOutput> {
+        let deadline = Instant::now() + timeout;
+
+        // The command must not read from the stdin of the shell, which is the
+        // channel of the following commands. The newline before the marker
+        // makes sure it starts a line of its own, and is removed again below.
+        write!(
+            self.stdin,
+            "{{ {}\n}} </dev/null\nprintf '\\n{} %d\\n' $?\nprintf '\\n{}\\n' >&2\n",
+            command, END_MARKER, END_MARKER
+        )?;
+        self.stdin.flush()?;
+
+        let (stdout, marker) = read_until_marker(&self.stdout, deadline)?;
+        let (stderr, _) = read_until_marker(&self.stderr, deadline)?;
+
+        let exit_code = marker
+            .trim()
+            .strip_prefix(END_MARKER)
+            .and_then(|code| code.trim().parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid end marker"))?;
+
+        Ok(ShellOutput {
+            exit_code,
+            stdout,
+            stderr,
+        })
+    }
+}
+
+impl Drop for AdbShell {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Reads the lines of the reader on a background thread until it is closed.
+fn read_lines<R: Read + Send + 'static>(reader: R) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        loop {
+            let mut line = Vec::new();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    receiver
+}
+
+/// Reads the output up to the marker line, returning the output (without the
+/// newline added before the marker) and the marker line.
+fn read_until_marker(lines: &Receiver<Vec<u8>>, deadline: Instant) -> io::Result<(String, String)> {
+    let mut output = Vec::new();
+    loop {
+        let line = match lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(line) => line,
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "adb shell command timed out",
+                ))
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "adb shell exited",
+                ))
+            }
+        };
+
+        if line.starts_with(END_MARKER.as_bytes()) && output.last() == Some(&b'\n') {
+            output.pop();
+            if output.last() == Some(&b'\r') {
+                output.pop();
+            }
+            return Ok((
+                String::from_utf8_lossy(&output).into_owned(),
+                String::from_utf8_lossy(&line).into_owned(),
+            ));
+        }
+        output.extend_from_slice(&line);
+    }
+}
@@ -0,0 +1,92 @@
+//! App Links verification state of the target.
+//!
+//! Since Android 12, web URIs only open an app directly if the app verified
+//! the host (or the user approved it), otherwise they open in the browser.
+//! The [AppLinks] read from `pm get-app-links` tell which boundary URIs an
+//! attacker's web link would actually deliver to the target, so the
+//! [IntentDataBoundaryMutator](crate::intent_mutator::IntentDataBoundaryMutator)
+//! focuses on those.
+
+use libafl::impl_serdeany;
+use serde::{Deserialize, Serialize};
+
+/// Verification states in which web URIs of the host resolve to the app.
+const RESOLVING_STATES: [&str; 5] = [
+    "verified",
+    "approved",
+    "migrated",
+    "restored",
+    "system_configured",
+];
+
+/// The hosts the app declared App Links for, by verification state.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AppLinks {
+    pub verified_hosts: Vec<String>,
+    pub unverified_hosts: Vec<String>,
+}
+
+impl_serdeany!(AppLinks);
+
+impl AppLinks {
+    /// Parses the output of `pm get-app-links <package>`, e.g.
+    ///
+    /// ```text
+    ///   com.example:
+    ///     ID: 01234567-89ab-cdef-0123-456789abcdef
+    ///     Signatures: [...]
+    ///     Domain verification state:
+    ///       example.com: verified
+    ///       www.example.com: 1024
+    /// ```
+    pub fn parse(output: &str) -> Self {
+        let mut app_links = Self::default();
+        let mut in_states = false;
+        for line in output.lines() {
+            let line = line.trim();
+            if line == "Domain verification state:" {
+                in_states = true;
+                continue;
+            }
+            let Some((host, state)) = line.split_once(": ").filter(|_| in_states) else {
+                in_states = false;
+                continue;
+            };
+
+            if RESOLVING_STATES.contains(&state.trim()) {
+                app_links.verified_hosts.push(host.to_owned());
+            } else {
+                app_links.unverified_hosts.push(host.to_owned());
+            }
+        }
+        app_links
+    }
+
+    /// Whether a web link with the URI opens the app directly. URIs with
+    /// custom schemes are not subject to verification.
+    pub fn resolves(&self, uri: &str) -> bool {
+        let Some((scheme, rest)) = uri.split_once("://") else {
+            return true;
+        };
+        if scheme != "http" && scheme != "https" {
+            return true;
+        }
+
+        let host = rest
+            .split(['/', '?', '#'])
+            .next()
+            .unwrap_or_default()
+            .rsplit('@')
+            .next()
+            .unwrap_or_default()
+            .split(':')
+            .next()
+            .unwrap_or_default();
+        self.verified_hosts
+            .iter()
+            .any(|verified| match verified.strip_prefix("*.") {
+                Some(domain) => host.ends_with(&format!(".{}", domain)),
+                None => verified == host,
+            })
+    }
+}
@@ -0,0 +1,165 @@
+//! Campaign statistics reported to the monitor as user stats.
+//!
+//! These are the numbers that tell whether a campaign is still productive:
+//! the time since the overall coverage last grew, the number of distinct crash
+//! buckets, how often the device, the app or the coverage connection had to be
+//! restarted, and how many intents could not be delivered at all. The average
+//! time an execution spends in each of its phases tells where the time goes.
+
+use std::{
+    marker::PhantomData,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use libafl::{
+    bolts::current_time,
+    prelude::{Corpus, Event, EventFirer, UserStats},
+    state::HasSolutions,
+};
+
+use crate::{ci_summary, intent_input::IntentInput};
+
+/// Number of times the Android framework of the device was restarted.
+pub static DEVICE_RESTARTS: AtomicU64 = AtomicU64::new(0);
+/// Number of times the device was rebooted.
+pub static DEVICE_REBOOTS: AtomicU64 = AtomicU64::new(0);
+/// Number of times the app was restarted.
+pub static APP_RESTARTS: AtomicU64 = AtomicU64::new(0);
+/// Number of times the connection to the coverage agent was re-established.
+pub static SOCKET_RECONNECTS: AtomicU64 = AtomicU64::new(0);
+/// Number of times the campaign paused to remediate an unhealthy device.
+pub static HEALTH_REMEDIATIONS: AtomicU64 = AtomicU64::new(0);
+/// Number of executions whose intent can never be delivered to the app, e.g.
+/// as the component does not exist.
+pub static SETUP_ERRORS: AtomicU64 = AtomicU64::new(0);
+/// Number of executions whose intent could not be delivered as the delivery
+/// kept failing for other, possibly transient reasons.
+pub static DELIVERY_FAILURES: AtomicU64 = AtomicU64::new(0);
+/// Number of executions whose new coverage was not covered again when
+/// re-executing the input.
+pub static FLAKY_COVERAGE: AtomicU64 = AtomicU64::new(0);
+
+/// Number of executions of the executor, over which the phase times are
+/// averaged.
+pub static EXECUTIONS: AtomicU64 = AtomicU64::new(0);
+/// Microseconds spent staging the files and contents of the URIs.
+pub static STAGING_TIME: AtomicU64 = AtomicU64::new(0);
+/// Microseconds spent running the `am` commands delivering the intents.
+pub static DELIVERY_TIME: AtomicU64 = AtomicU64::new(0);
+/// Microseconds spent waiting for the (re)started app to become idle.
+pub static IDLE_WAIT_TIME: AtomicU64 = AtomicU64::new(0);
+/// Microseconds spent waiting for the coverage maps of the agent.
+pub static COVERAGE_RETRIEVAL_TIME: AtomicU64 = AtomicU64::new(0);
+
+/// Increments the given event counter.
+pub fn count(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Adds the time spent in a phase to the given timer.
+pub fn add_time(timer: &AtomicU64, elapsed: Duration) {
+    timer.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+}
+
+/// Periodically reports the campaign statistics to the monitor.
+#[derive(Debug)]
+pub struct CampaignStats {
+    interval: Duration,
+    last_report: Duration,
+    // Number of solutions and crash buckets at the last count, as counting
+    // the buckets needs to load all solutions.
+    solutions: usize,
+    crash_buckets: usize,
+}
+
+impl CampaignStats {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_report: current_time(),
+            solutions: 0,
+            crash_buckets: 0,
+        }
+    }
+
+    /// Reports the statistics if the report interval passed since the last
+    /// report.
+    pub fn maybe_report<EM, S>(
+        &mut self,
+        manager: &mut EM,
+        state: &mut S,
+        secs_since_new_coverage: u64,
+    ) -> Result<(), libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        S: HasSolutions<Input = IntentInput>,
+    {
+        let now = current_time();
+        if now - self.last_report < self.interval {
+            return Ok(());
+        }
+        self.last_report = now;
+
+        if state.solutions().count() != self.solutions {
+            self.solutions = state.solutions().count();
+            self.crash_buckets = ci_summary::count_crash_buckets(state.solutions());
+        }
+
+        let stats = [
+            ("secs_since_new_coverage", secs_since_new_coverage),
+            ("crash_buckets", self.crash_buckets as u64),
+            ("device_restarts", DEVICE_RESTARTS.load(Ordering::Relaxed)),
+            ("device_reboots", DEVICE_REBOOTS.load(Ordering::Relaxed)),
+            ("app_restarts", APP_RESTARTS.load(Ordering::Relaxed)),
+            (
+                "socket_reconnects",
+                SOCKET_RECONNECTS.load(Ordering::Relaxed),
+            ),
+            ("setup_errors", SETUP_ERRORS.load(Ordering::Relaxed)),
+            (
+                "delivery_failures",
+                DELIVERY_FAILURES.load(Ordering::Relaxed),
+            ),
+            ("flaky_coverage", FLAKY_COVERAGE.load(Ordering::Relaxed)),
+            (
+                "health_remediations",
+                HEALTH_REMEDIATIONS.load(Ordering::Relaxed),
+            ),
+        ];
+        for (name, value) in stats {
+            manager.fire(
+                state,
+                Event::UpdateUserStats {
+                    name: name.to_owned(),
+                    value: UserStats::Number(value),
+                    phantom: PhantomData,
+                },
+            )?;
+        }
+
+        let executions = EXECUTIONS.load(Ordering::Relaxed);
+        if executions == 0 {
+            return Ok(());
+        }
+        let timers = [
+            ("avg_staging_ms", &STAGING_TIME),
+            ("avg_delivery_ms", &DELIVERY_TIME),
+            ("avg_idle_wait_ms", &IDLE_WAIT_TIME),
+            ("avg_coverage_retrieval_ms", &COVERAGE_RETRIEVAL_TIME),
+        ];
+        for (name, timer) in timers {
+            let micros = timer.load(Ordering::Relaxed);
+            manager.fire(
+                state,
+                Event::UpdateUserStats {
+                    name: name.to_owned(),
+                    value: UserStats::Float(micros as f64 / executions as f64 / 1000.0),
+                    phantom: PhantomData,
+                },
+            )?;
+        }
+
+        Ok(())
+    }
+}
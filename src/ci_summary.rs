@@ -0,0 +1,102 @@
+//! Machine-readable summary of a fuzzing campaign for CI pipelines.
+//!
+//! In CI mode the fuzzer runs for a fixed time budget, writes a [CiSummary]
+//! as JSON and exits with a non-zero code if crashes were found, so that it
+//! can be used to gate app releases.
+
+use std::{collections::HashSet, path::Path};
+
+use libafl::prelude::{Corpus, HasMetadata};
+use serde::Serialize;
+
+use crate::{
+    crash_layout::SolutionLocation, intent_input::IntentInput, native_crash::CrashSignature,
+};
+
+/// Exit code used when the campaign found at least one crash.
+pub const EXIT_CODE_CRASHES_FOUND: i32 = 1;
+
+/// The summary written at the end of a CI run.
+#[derive(Serialize, Debug)]
+pub struct CiSummary {
+    /// Number of edges covered over the whole campaign.
+    pub edges: usize,
+    /// Total number of executions.
+    pub execs: usize,
+    /// Number of distinct crash buckets in the solutions corpus.
+    pub crash_buckets: usize,
+    /// Wall time spent fuzzing, in seconds.
+    pub elapsed_secs: u64,
+}
+
+impl CiSummary {
+    /// Writes the summary as pretty-printed JSON to the given file.
+    pub fn write_to_file(&self, path: &Path) -> Result<(), std::io::Error> {
+        let json = serde_json::to_string_pretty(self).expect("Failed to serialize CI summary");
+        std::fs::write(path, json)
+    }
+
+    /// The process exit code for this summary.
+    pub fn exit_code(&self) -> i32 {
+        if self.crash_buckets > 0 {
+            EXIT_CODE_CRASHES_FOUND
+        } else {
+            0
+        }
+    }
+}
+
+/// Counts the distinct crash buckets in the solutions corpus. Solutions with a
+/// crash signature are bucketed by it, all others by the bucket directory
+/// their input was stored in.
+pub fn count_crash_buckets<C>(solutions: &C) -> usize
+where
+    C: Corpus<Input = IntentInput>,
+{
+    let mut buckets = HashSet::new();
+
+    for id in solutions.ids() {
+        let testcase = solutions.get(id).unwrap().borrow();
+        if let Ok(signature) = testcase.metadata::<CrashSignature>() {
+            buckets.insert(signature.0.clone());
+        } else if let Ok(location) = testcase.metadata::<SolutionLocation>() {
+            let bucket = location.dir.parent().unwrap_or(&location.dir);
+            buckets.insert(bucket.to_string_lossy().into_owned());
+        }
+    }
+
+    buckets.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::prelude::{ExitKind, InMemoryCorpus, Testcase};
+
+    use super::*;
+    use crate::input_schema;
+
+    fn solution(dir: &str) -> Testcase<IntentInput> {
+        let (input, _) =
+            input_schema::from_bytes(include_bytes!("../tests/fixtures/input_v0.json")).unwrap();
+        let mut testcase = Testcase::new(input);
+        testcase.add_metadata(SolutionLocation {
+            dir: dir.into(),
+            exit_kind: ExitKind::Crash,
+        });
+        testcase
+    }
+
+    #[test]
+    fn counts_inputs_of_a_bucket_once() {
+        let mut solutions = InMemoryCorpus::new();
+        solutions
+            .add(solution("crashes/NullPointerException_1a2b/0123"))
+            .unwrap();
+        solutions
+            .add(solution("crashes/NullPointerException_1a2b/4567"))
+            .unwrap();
+        solutions.add(solution("crashes/timeout/89ab")).unwrap();
+
+        assert_eq!(count_crash_buckets(&solutions), 2);
+    }
+}
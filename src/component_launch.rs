@@ -0,0 +1,147 @@
+//! Feedback on the components of the app reached by an intent.
+//!
+//! Intents often start further activities or services of the app as a side
+//! effect. The [ComponentLaunchObserver] collects the components the system
+//! created during an execution from the events log, and the
+//! [ComponentLaunchFeedback] treats inputs reaching a component for the first
+//! time as interesting, even if the edge map didn't change.
+
+use std::{collections::HashSet, time::SystemTime};
+
+use libafl::{
+    impl_serdeany,
+    prelude::{EventFirer, ExitKind, Feedback, Named, Observer, ObserversTuple, UsesInput},
+    state::{HasClientPerfMonitor, HasNamedMetadata},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{adb_device::AdbDevice, intent_input::IntentInput};
+
+/// Observer of the components of the app created during an execution.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ComponentLaunchObserver {
+    adb_device: AdbDevice,
+    app_name: String,
+    enabled: bool,
+    exec_start: SystemTime,
+    launched: Vec<String>,
+}
+
+impl ComponentLaunchObserver {
+    /// Creates the observer. A disabled observer never reads the log and
+    /// never reports any components.
+    pub fn new(adb_device: AdbDevice, app_name: String, enabled: bool) -> Self {
+        Self {
+            adb_device,
+            app_name,
+            enabled,
+            exec_start: SystemTime::now(),
+            launched: Vec::new(),
+        }
+    }
+
+    /// The components created during the last execution.
+    pub fn launched(&self) -> &[String] {
+        &self.launched
+    }
+}
+
+impl Named for ComponentLaunchObserver {
+    fn name(&self) -> &str {
+        "ComponentLaunchObserver"
+    }
+}
+
+impl<S> Observer<S> for ComponentLaunchObserver
+where
+    S: UsesInput<Input = IntentInput>,
+{
+    fn pre_exec(&mut self, _state: &mut S, _input: &IntentInput) -> Result<(), libafl::Error> {
+        self.exec_start = SystemTime::now();
+        self.launched.clear();
+        Ok(())
+    }
+
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _input: &IntentInput,
+        _exit_kind: &ExitKind,
+    ) -> Result<(), libafl::Error> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        match self
+            .adb_device
+            .started_components(&self.app_name, self.exec_start)
+        {
+            Ok(launched) => self.launched = launched,
+            Err(err) => println!("Failed to read started components: {:?}", err),
+        }
+        Ok(())
+    }
+}
+
+/// All components reached so far, stored in the state.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ReachedComponents {
+    pub components: HashSet<String>,
+}
+
+impl_serdeany!(ReachedComponents);
+
+/// Feedback that marks inputs reaching a new component as interesting.
+#[derive(Debug, Default)]
+pub struct ComponentLaunchFeedback;
+
+impl ComponentLaunchFeedback {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Named for ComponentLaunchFeedback {
+    fn name(&self) -> &str {
+        "ComponentLaunchFeedback"
+    }
+}
+
+impl<S> Feedback<S> for ComponentLaunchFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor + HasNamedMetadata,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let Some(observer) =
+            observers.match_name::<ComponentLaunchObserver>("ComponentLaunchObserver")
+        else {
+            return Ok(false);
+        };
+
+        if !state.has_named_metadata::<ReachedComponents>("reached_components") {
+            state.add_named_metadata(ReachedComponents::default(), "reached_components");
+        }
+        let reached = state.named_metadata_mut::<ReachedComponents>("reached_components")?;
+
+        let mut interesting = false;
+        for component in observer.launched() {
+            if reached.components.insert(component.clone()) {
+                println!("Reached new component {}", component);
+                interesting = true;
+            }
+        }
+
+        Ok(interesting)
+    }
+}
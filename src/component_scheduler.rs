@@ -0,0 +1,378 @@
+//! Round-robin scheduling of the components of a multi-template campaign.
+//!
+//! Without a slice configured, the [ComponentScheduler] walks the whole corpus
+//! like a [libafl::schedulers::QueueScheduler]. With a slice, only the corpus
+//! entries of the active component are scheduled, and the active component
+//! rotates once its slice is used up. A component that did not add a new
+//! corpus entry for the stale timeout (counted in its own fuzzing time) is
+//! finished early and leaves the rotation, as does a component that used up
+//! its total budget.
+//!
+//! Corpus entries retired for timing out too often (see [crate::timeout_culling])
+//! are skipped, unless all candidates are retired.
+//!
+//! The progress of all components is persisted to a file, so resuming the
+//! fuzzer (e.g. after a host crash) continues with the remaining components
+//! and budgets instead of starting over. The state of the queue can be
+//! exported as well (see [ComponentScheduler::export_queue]), to debug which
+//! entries get scheduled.
+
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use libafl::{
+    prelude::{Corpus, CorpusId, Scheduler, UsesInput},
+    state::{HasCorpus, UsesState},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{intent_input::IntentInput, timeout_culling};
+
+/// How often the component progress is written to the progress file.
+const PROGRESS_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How much fuzzing time a component got so far.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ComponentProgress {
+    pub component: String,
+    /// Time the component was the active one.
+    pub fuzzed: Duration,
+    /// Fuzzing time of the component when it last added a corpus entry.
+    pub fuzzed_at_last_entry: Duration,
+    /// Whether the component stopped finding new coverage or used up its
+    /// budget.
+    pub finished: bool,
+}
+
+impl ComponentProgress {
+    fn new(component: String) -> Self {
+        Self {
+            component,
+            fuzzed: Duration::ZERO,
+            fuzzed_at_last_entry: Duration::ZERO,
+            finished: false,
+        }
+    }
+}
+
+/// The scheduling state of a corpus entry, as exported by
+/// [ComponentScheduler::export_queue].
+#[derive(Serialize, Debug)]
+struct QueueEntry {
+    id: usize,
+    component: Option<String>,
+    /// How often the entry was scheduled for fuzzing.
+    scheduled_count: usize,
+    /// The execution time of the entry, in milliseconds.
+    exec_time_ms: Option<u128>,
+    /// The number of executions of the campaign when it was found.
+    executions_at_discovery: usize,
+    /// Whether the entry is currently eligible, i.e. belongs to the active
+    /// component and was not retired.
+    eligible: bool,
+    retired: bool,
+}
+
+/// The exported state of the queue.
+#[derive(Serialize, Debug)]
+struct QueueState {
+    current: Option<usize>,
+    active_component: Option<String>,
+    entries: Vec<QueueEntry>,
+}
+
+/// Scheduler rotating through the components of the campaign.
+#[derive(Debug)]
+pub struct ComponentScheduler<S> {
+    components: Vec<ComponentProgress>,
+    slice: Option<Duration>,
+    stale_timeout: Option<Duration>,
+    budget: Option<Duration>,
+    active: usize,
+    /// Whether an entry was scheduled yet. The clocks of the components only
+    /// start then, so the seed generation and calibration are not charged to
+    /// the first component.
+    started: bool,
+    slice_start: Instant,
+    last_tick: Instant,
+    component_of: HashMap<CorpusId, String>,
+    progress_file: PathBuf,
+    last_save: Instant,
+    phantom: PhantomData<S>,
+}
+
+impl<S> ComponentScheduler<S> {
+    /// Creates a scheduler for the given components, in rotation order. When
+    /// resuming, the progress of a previous run is restored from the progress
+    /// file, if it exists.
+    pub fn new(
+        components: Vec<String>,
+        slice: Option<Duration>,
+        stale_timeout: Option<Duration>,
+        budget: Option<Duration>,
+        progress_file: &Path,
+        resume: bool,
+    ) -> Self {
+        let mut scheduler = Self {
+            components: components.into_iter().map(ComponentProgress::new).collect(),
+            slice,
+            stale_timeout,
+            budget,
+            active: 0,
+            started: false,
+            slice_start: Instant::now(),
+            last_tick: Instant::now(),
+            component_of: HashMap::new(),
+            progress_file: progress_file.to_owned(),
+            last_save: Instant::now(),
+            phantom: PhantomData,
+        };
+
+        if slice.is_some() && resume {
+            scheduler.restore_progress();
+        }
+
+        scheduler
+    }
+
+    /// Restores the progress of the components from the progress file, which
+    /// has to be of the same components.
+    fn restore_progress(&mut self) {
+        let Ok(json) = std::fs::read_to_string(&self.progress_file) else {
+            return;
+        };
+        let mut saved: Vec<ComponentProgress> =
+            serde_json::from_str(&json).expect("Failed to parse component progress file");
+
+        let mut saved_components: Vec<&str> = saved.iter().map(|p| p.component.as_str()).collect();
+        let mut components: Vec<&str> = self
+            .components
+            .iter()
+            .map(|p| p.component.as_str())
+            .collect();
+        saved_components.sort_unstable();
+        components.sort_unstable();
+        if saved_components != components {
+            panic!(
+                "The component progress file {:?} is of other components than the campaign",
+                self.progress_file
+            );
+        }
+
+        // Keep the rotation order of the campaign.
+        saved.sort_by_key(|progress| {
+            self.components
+                .iter()
+                .position(|c| c.component == progress.component)
+        });
+        for progress in &saved {
+            println!(
+                "Resuming component {} after {}s{}",
+                progress.component,
+                progress.fuzzed.as_secs(),
+                if progress.finished { " (finished)" } else { "" }
+            );
+        }
+        self.components = saved;
+    }
+
+    /// Writes the progress of the components to the progress file.
+    fn save_progress(&mut self) {
+        let json = serde_json::to_string_pretty(&self.components)
+            .expect("Failed to serialize component progress");
+        if let Err(err) = std::fs::write(&self.progress_file, json) {
+            println!("Failed to write component progress: {}", err);
+        }
+        self.last_save = Instant::now();
+    }
+
+    /// Writes the state of the queue of the corpus as JSON to the file.
+    pub fn export_queue<C>(&self, corpus: &C, file: &Path) -> Result<(), libafl::Error>
+    where
+        C: Corpus,
+    {
+        let active_component = self
+            .slice
+            .and_then(|_| self.components.get(self.active))
+            .map(|progress| progress.component.clone());
+        let mut entries = Vec::with_capacity(corpus.count());
+        for id in corpus.ids() {
+            let testcase = corpus.get(id)?.borrow();
+            let component = self.component_of.get(&id).cloned();
+            let retired = timeout_culling::is_retired(corpus, id);
+            let eligible = !retired
+                && active_component
+                    .as_ref()
+                    .map_or(true, |active| component.as_ref() == Some(active));
+            entries.push(QueueEntry {
+                id: id.into(),
+                component,
+                scheduled_count: testcase.scheduled_count(),
+                exec_time_ms: testcase.exec_time().map(|time| time.as_millis()),
+                executions_at_discovery: *testcase.executions(),
+                eligible,
+                retired,
+            });
+        }
+
+        let queue = QueueState {
+            current: corpus.current().map(usize::from),
+            active_component,
+            entries,
+        };
+        let json = serde_json::to_string_pretty(&queue)
+            .map_err(|err| libafl::Error::serialize(err.to_string()))?;
+        std::fs::write(file, json)?;
+        Ok(())
+    }
+
+    /// Whether all components finished early, so fuzzing can stop.
+    pub fn all_finished(&self) -> bool {
+        !self.components.is_empty() && self.components.iter().all(|c| c.finished)
+    }
+
+    fn index_of(&mut self, component: &str) -> usize {
+        match self
+            .components
+            .iter()
+            .position(|c| c.component == component)
+        {
+            Some(index) => index,
+            None => {
+                self.components
+                    .push(ComponentProgress::new(component.to_owned()));
+                self.components.len() - 1
+            }
+        }
+    }
+
+    /// Accounts the time since the last call to the active component and
+    /// finishes it if it went stale or used up its budget.
+    fn tick(&mut self) {
+        let now = Instant::now();
+        let Some(active) = self.components.get_mut(self.active) else {
+            return;
+        };
+        active.fuzzed += now - self.last_tick;
+        self.last_tick = now;
+
+        let mut finished = false;
+        if let Some(stale_timeout) = self.stale_timeout {
+            if !active.finished && active.fuzzed - active.fuzzed_at_last_entry >= stale_timeout {
+                println!(
+                    "No new coverage for {} in {}s, finishing it",
+                    active.component,
+                    stale_timeout.as_secs()
+                );
+                active.finished = true;
+                finished = true;
+            }
+        }
+        if let Some(budget) = self.budget {
+            if !active.finished && active.fuzzed >= budget {
+                println!("Budget of {} used up, finishing it", active.component);
+                active.finished = true;
+                finished = true;
+            }
+        }
+
+        if finished || self.last_save.elapsed() >= PROGRESS_SAVE_INTERVAL {
+            self.save_progress();
+        }
+    }
+
+    /// Makes the next unfinished component with corpus entries the active one.
+    fn rotate(&mut self) {
+        let count = self.components.len();
+        for offset in 1..=count {
+            let index = (self.active + offset) % count;
+            let progress = &self.components[index];
+            if !progress.finished && self.component_of.values().any(|c| *c == progress.component) {
+                if index != self.active {
+                    println!("Switching to component {}", progress.component);
+                }
+                self.active = index;
+                break;
+            }
+        }
+        self.slice_start = Instant::now();
+    }
+}
+
+impl<S> UsesState for ComponentScheduler<S>
+where
+    S: UsesInput,
+{
+    type State = S;
+}
+
+impl<S> Scheduler for ComponentScheduler<S>
+where
+    S: HasCorpus + UsesInput<Input = IntentInput>,
+{
+    fn on_add(&mut self, state: &mut Self::State, idx: CorpusId) -> Result<(), libafl::Error> {
+        // Set parent id
+        let current_idx = *state.corpus().current();
+        let mut testcase = state.corpus().get(idx)?.borrow_mut();
+        testcase.set_parent_id_optional(current_idx);
+
+        let component = testcase.load_input(state.corpus())?.component();
+        drop(testcase);
+
+        let index = self.index_of(&component);
+        let progress = &mut self.components[index];
+        progress.fuzzed_at_last_entry = progress.fuzzed;
+        self.component_of.insert(idx, component);
+
+        Ok(())
+    }
+
+    /// Gets the next entry of the active component in the queue
+    fn next(&mut self, state: &mut Self::State) -> Result<CorpusId, libafl::Error> {
+        let corpus = state.corpus();
+        if corpus.count() == 0 {
+            return Err(libafl::Error::empty("No entries in corpus"));
+        }
+
+        let mut id = corpus
+            .current()
+            .and_then(|id| corpus.next(id))
+            .unwrap_or_else(|| corpus.first().unwrap());
+
+        let mut active = None;
+        if let Some(slice) = self.slice {
+            if !self.started {
+                self.started = true;
+                self.slice_start = Instant::now();
+                self.last_tick = self.slice_start;
+            }
+            self.tick();
+            let active_finished = self
+                .components
+                .get(self.active)
+                .map_or(true, |c| c.finished);
+            if active_finished || self.slice_start.elapsed() >= slice {
+                self.rotate();
+            }
+            active = Some(&self.components[self.active].component);
+        }
+
+        // Walk the queue until the next entry of the active component that
+        // is not retired.
+        for _ in 0..corpus.count() {
+            if active.map_or(true, |active| self.component_of.get(&id) == Some(active))
+                && !timeout_culling::is_retired(corpus, id)
+            {
+                break;
+            }
+            id = corpus.next(id).unwrap_or_else(|| corpus.first().unwrap());
+        }
+
+        self.set_current_scheduled(state, Some(id))?;
+        Ok(id)
+    }
+}
@@ -0,0 +1,178 @@
+//! Small HTTP API to control a running fuzzer.
+//!
+//! The API is meant for orchestration systems managing many fuzzer instances
+//! across an emulator farm. It is served from a background thread and
+//! exchanges data with the fuzzing loop through a [ControlApi] handle:
+//!
+//! - `GET /status`: the [Status] of the campaign as JSON
+//! - `POST /pause` and `POST /resume`: pause or resume fuzzing
+//! - `POST /seeds`: queue an [IntentInput] (as JSON) to be added to the corpus
+//! - `GET /crashes`: the names of the solutions found so far as JSON
+//! - `GET /coverage`: the overall coverage file
+//! - `POST /coverage/dump`: dump the overall coverage map and the list of
+//!   covered map entries next to the overall coverage file, while fuzzing
+//!   continues
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{crash_layout, input_schema, intent_input::IntentInput};
+
+/// How long the fuzzing loop sleeps between checks while paused.
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Status of the campaign as reported by `GET /status`.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Status {
+    pub paused: bool,
+    pub execs: usize,
+    pub corpus_size: usize,
+    pub crashes: usize,
+    pub edges: usize,
+    pub elapsed_secs: u64,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    status: Status,
+    seeds: Vec<IntentInput>,
+    dump_requested: bool,
+}
+
+/// Handle of the fuzzing loop to the control API.
+#[derive(Debug, Clone)]
+pub struct ControlApi {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl ControlApi {
+    /// Starts serving the API on the given address in a background thread.
+    pub fn start(
+        address: &str,
+        crashes_dir: PathBuf,
+        overall_coverage_file: PathBuf,
+    ) -> Result<Self, libafl::Error> {
+        let server = Server::http(address).map_err(|err| {
+            libafl::Error::unknown(format!(
+                "Failed to start control API on {}: {}",
+                address, err
+            ))
+        })?;
+        println!("Control API listening on {}", address);
+
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let handler_shared = shared.clone();
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                handle_request(
+                    request,
+                    &handler_shared,
+                    &crashes_dir,
+                    &overall_coverage_file,
+                );
+            }
+        });
+
+        Ok(Self { shared })
+    }
+
+    /// Publishes the current status of the campaign, keeping the paused flag.
+    pub fn update_status(&self, status: Status) {
+        let mut shared = self.shared.lock().unwrap();
+        let paused = shared.status.paused;
+        shared.status = Status { paused, ..status };
+    }
+
+    /// Takes all seeds injected since the last call.
+    pub fn take_seeds(&self) -> Vec<IntentInput> {
+        std::mem::take(&mut self.shared.lock().unwrap().seeds)
+    }
+
+    /// Whether a coverage dump was requested since the last call.
+    pub fn take_dump_request(&self) -> bool {
+        std::mem::take(&mut self.shared.lock().unwrap().dump_requested)
+    }
+
+    /// Blocks as long as the campaign is paused.
+    pub fn wait_while_paused(&self) {
+        while self.shared.lock().unwrap().status.paused {
+            std::thread::sleep(PAUSE_POLL_INTERVAL);
+        }
+    }
+}
+
+fn handle_request(
+    mut request: Request,
+    shared: &Mutex<Shared>,
+    crashes_dir: &Path,
+    overall_coverage_file: &Path,
+) {
+    let response = match (request.method(), request.url()) {
+        (Method::Get, "/status") => json_response(&shared.lock().unwrap().status),
+        (Method::Post, "/pause") => {
+            shared.lock().unwrap().status.paused = true;
+            Response::from_string("paused")
+        }
+        (Method::Post, "/resume") => {
+            shared.lock().unwrap().status.paused = false;
+            Response::from_string("resumed")
+        }
+        (Method::Post, "/seeds") => {
+            let mut body = String::new();
+            let seed = request
+                .as_reader()
+                .read_to_string(&mut body)
+                .map_err(|err| err.to_string())
+                .and_then(|_| {
+                    input_schema::from_bytes(body.as_bytes())
+                        .map(|(seed, _)| seed)
+                        .map_err(|err| err.to_string())
+                });
+
+            match seed {
+                Ok(seed) => {
+                    shared.lock().unwrap().seeds.push(seed);
+                    Response::from_string("queued")
+                }
+                Err(err) => {
+                    Response::from_string(format!("Invalid seed: {}", err)).with_status_code(400)
+                }
+            }
+        }
+        (Method::Get, "/crashes") => json_response(&list_crashes(crashes_dir)),
+        (Method::Get, "/coverage") => match std::fs::read_to_string(overall_coverage_file) {
+            Ok(coverage) => Response::from_string(coverage),
+            Err(err) => Response::from_string(format!("Failed to read coverage: {}", err))
+                .with_status_code(500),
+        },
+        (Method::Post, "/coverage/dump") => {
+            shared.lock().unwrap().dump_requested = true;
+            Response::from_string("queued")
+        }
+        _ => Response::from_string("Not found").with_status_code(404),
+    };
+
+    if let Err(err) = request.respond(response) {
+        println!("Failed to respond to control API request: {}", err);
+    }
+}
+
+fn json_response<T: Serialize>(value: &T) -> Response<std::io::Cursor<Vec<u8>>> {
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+    Response::from_string(serde_json::to_string(value).unwrap()).with_header(header)
+}
+
+/// The solutions in the crashes directory, as `<bucket>/<hash>`.
+fn list_crashes(crashes_dir: &Path) -> Vec<String> {
+    crash_layout::solution_inputs(crashes_dir)
+        .iter()
+        .filter_map(|input| input.parent()?.strip_prefix(crashes_dir).ok())
+        .map(|solution| solution.to_string_lossy().into_owned())
+        .collect()
+}
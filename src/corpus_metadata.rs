@@ -0,0 +1,116 @@
+//! On-disk persistence of the corpus entry metadata.
+//!
+//! libAFL stores the metadata, the execution time and the executions at
+//! discovery of every corpus entry next to it as `.<entry>.metadata`. The
+//! [CorpusMetadataFormat] selects the format of these files (or disables
+//! them), and [persist_scheduling_stats] adds how often each entry was
+//! scheduled, so the scheduling of a campaign can be analyzed from the corpus
+//! directory alone. [SchedulingStatsPersistence] does so periodically, so the
+//! stats survive a campaign that is killed rather than stopping on its own.
+
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use libafl::{
+    corpus::ondisk::OnDiskMetadataFormat,
+    impl_serdeany,
+    prelude::{CachedOnDiskCorpus, Corpus, HasMetadata},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::intent_input::IntentInput;
+
+/// The format of the metadata files of the corpus entries.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CorpusMetadataFormat {
+    /// Do not store any metadata
+    None,
+    Postcard,
+    Json,
+    JsonPretty,
+    JsonGzip,
+}
+
+impl CorpusMetadataFormat {
+    /// Creates the corpus in the directory, storing the metadata in this
+    /// format.
+    pub fn corpus(
+        self,
+        dir: PathBuf,
+        cache_max_len: usize,
+    ) -> Result<CachedOnDiskCorpus<IntentInput>, libafl::Error> {
+        let format = match self {
+            CorpusMetadataFormat::None => return CachedOnDiskCorpus::no_meta(dir, cache_max_len),
+            CorpusMetadataFormat::Postcard => OnDiskMetadataFormat::Postcard,
+            CorpusMetadataFormat::Json => OnDiskMetadataFormat::Json,
+            CorpusMetadataFormat::JsonPretty => OnDiskMetadataFormat::JsonPretty,
+            CorpusMetadataFormat::JsonGzip => OnDiskMetadataFormat::JsonGzip,
+        };
+        CachedOnDiskCorpus::with_meta_format(dir, cache_max_len, format)
+    }
+}
+
+/// How often a corpus entry was scheduled for fuzzing.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SchedulingStats {
+    pub scheduled_count: usize,
+}
+
+impl_serdeany!(SchedulingStats);
+
+/// Rewrites the metadata files of the corpus entries whose [SchedulingStats]
+/// changed. libAFL only writes the metadata when an entry is added.
+pub fn persist_scheduling_stats<C>(corpus: &mut C) -> Result<(), libafl::Error>
+where
+    C: Corpus<Input = IntentInput>,
+{
+    let ids: Vec<_> = corpus.ids().collect();
+    for id in ids {
+        // Getting the entry loads its input, which is written back as well.
+        let mut testcase = corpus.get(id)?.borrow().clone();
+        let scheduled_count = testcase.scheduled_count();
+        if testcase
+            .metadata::<SchedulingStats>()
+            .map_or(false, |stats| stats.scheduled_count == scheduled_count)
+        {
+            continue;
+        }
+        testcase.add_metadata(SchedulingStats { scheduled_count });
+        corpus.replace(id, testcase)?;
+    }
+    Ok(())
+}
+
+/// Periodically persists the [SchedulingStats] of the corpus entries.
+#[derive(Debug)]
+pub struct SchedulingStatsPersistence {
+    interval: Duration,
+    last_persist: Instant,
+}
+
+impl SchedulingStatsPersistence {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_persist: Instant::now(),
+        }
+    }
+
+    /// Persists the stats if the interval passed since they were persisted
+    /// last.
+    pub fn maybe_persist<C>(&mut self, corpus: &mut C)
+    where
+        C: Corpus<Input = IntentInput>,
+    {
+        if self.last_persist.elapsed() < self.interval {
+            return;
+        }
+        self.last_persist = Instant::now();
+
+        if let Err(err) = persist_scheduling_stats(corpus) {
+            println!("Failed to persist the scheduling stats: {}", err);
+        }
+    }
+}
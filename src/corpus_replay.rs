@@ -0,0 +1,159 @@
+//! Replay of the corpus sharded across several devices.
+//!
+//! Replaying a full corpus on a single emulator takes hours. With
+//! `--replay-device`, the corpus entries are split round-robin across the
+//! given devices, which replay their shard in parallel, each through its own
+//! coverage agent connection. Afterwards, [merge_overall_coverage] merges the
+//! overall coverage of the devices and [merge_traces] moves their native
+//! traces into the traces directory, so the results look like those of a
+//! replay on a single device.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    coverage_report::overall_map_file,
+    socket_coverage_observer::{unix_time, OVERALL_COVERAGE_HEADER},
+};
+
+/// A device replaying a shard of the corpus.
+#[derive(Clone, Debug)]
+pub struct ReplayDevice {
+    /// The serial of the device, as in `ANDROID_SERIAL`.
+    pub serial: String,
+    /// The address of the coverage agent socket of the device.
+    pub coverage_socket_address: String,
+}
+
+impl ReplayDevice {
+    /// Parses `<serial>=<coverage socket address>`.
+    pub fn parse(spec: &str) -> Result<Self, libafl::Error> {
+        let (serial, address) = spec.split_once('=').ok_or_else(|| {
+            libafl::Error::illegal_argument(format!(
+                "Invalid replay device {:?}, expected <serial>=<coverage socket address>",
+                spec
+            ))
+        })?;
+        Ok(Self {
+            serial: serial.to_owned(),
+            coverage_socket_address: address.to_owned(),
+        })
+    }
+}
+
+/// The files of the corpus entries in the directory, like libAFL loads them:
+/// recursively, skipping hidden (metadata) and empty files.
+pub fn corpus_files(corpus_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(corpus_dir)? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .map_or(true, |name| name.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+        let metadata = fs::metadata(&path)?;
+        if metadata.is_file() && metadata.len() > 0 {
+            files.push(path);
+        } else if metadata.is_dir() {
+            files.extend(corpus_files(&path)?);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Splits the files round-robin into the given number of shards.
+pub fn shard(files: Vec<PathBuf>, shards: usize) -> Vec<Vec<PathBuf>> {
+    let mut sharded = vec![Vec::new(); shards];
+    for (index, file) in files.into_iter().enumerate() {
+        sharded[index % shards].push(file);
+    }
+    sharded
+}
+
+/// The file of the device with the given index next to the file, e.g.
+/// `edgecount.device1.csv` for `edgecount.csv`.
+pub fn device_file(file: &Path, index: usize) -> PathBuf {
+    let mut name = file.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!(".device{}", index));
+    if let Some(extension) = file.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    file.with_file_name(name)
+}
+
+/// Merges the overall coverage files (and maps) of the devices into the
+/// overall coverage file: the map is the union of their maps, and the record
+/// sums up their executions and corpus sizes. Returns the number of covered
+/// map entries.
+pub fn merge_overall_coverage(device_files: &[PathBuf], merged: &Path) -> io::Result<usize> {
+    let mut map: Vec<u8> = Vec::new();
+    let (mut elapsed, mut execs, mut corpus_size) = (0, 0, 0);
+    for file in device_files {
+        if let Ok(device_map) = fs::read(overall_map_file(file)) {
+            if map.len() < device_map.len() {
+                map.resize(device_map.len(), 0);
+            }
+            for (merged, entry) in map.iter_mut().zip(device_map) {
+                *merged = (*merged).max(entry);
+            }
+        }
+
+        // e.g. "wall_time,elapsed_secs,execs,corpus_size,edges"
+        let csv = fs::read_to_string(file)?;
+        let last: Vec<u64> = csv
+            .lines()
+            .last()
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|field| field.parse().ok())
+            .collect();
+        if let [_, device_elapsed, device_execs, device_corpus_size, _] = last[..] {
+            elapsed = elapsed.max(device_elapsed);
+            execs += device_execs;
+            corpus_size += device_corpus_size;
+        }
+    }
+
+    let edges = map.iter().filter(|entry| **entry != 0).count();
+    let mut file = fs::File::create(merged)?;
+    file.write_all(OVERALL_COVERAGE_HEADER.as_bytes())?;
+    writeln!(
+        file,
+        "{},{},{},{},{}",
+        unix_time(),
+        elapsed,
+        execs,
+        corpus_size,
+        edges
+    )?;
+    fs::write(overall_map_file(merged), &map)?;
+    Ok(edges)
+}
+
+/// Moves the traces in the directories of the devices into the traces
+/// directory, prefixing the names already taken with the device directory.
+pub fn merge_traces(device_dirs: &[PathBuf], traces_dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(traces_dir)?;
+    for dir in device_dirs.iter().filter(|dir| dir.is_dir()) {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let mut dest = traces_dir.join(entry.file_name());
+            if dest.exists() {
+                let mut name = dir.file_name().unwrap_or_default().to_os_string();
+                name.push("-");
+                name.push(entry.file_name());
+                dest = traces_dir.join(name);
+            }
+            fs::rename(entry.path(), dest)?;
+        }
+        fs::remove_dir(dir)?;
+    }
+    Ok(())
+}
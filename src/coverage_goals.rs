@@ -0,0 +1,192 @@
+//! Coverage goals: classes or methods a campaign is meant to reach.
+//!
+//! When a campaign is about reaching one suspicious intent handler, the
+//! overall coverage says little about the progress. The
+//! [CoverageGoalFeedback] keeps every input reaching a goal for the first time
+//! in the corpus, tags it with the [ReachedGoals], and reports the reached
+//! goals as the `coverage_goals` user stat. The map entries of the goals are
+//! found through the coverage mapping, so goals need one.
+
+use std::marker::PhantomData;
+
+use libafl::{
+    impl_serdeany,
+    prelude::{
+        Event, EventFirer, ExitKind, Feedback, HasMetadata, MapObserver, Named, ObserversTuple,
+        Testcase, UserStats, UsesInput,
+    },
+    state::{HasClientPerfMonitor, HasNamedMetadata},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    coverage_mapping::SourceLocation, intent_input::IntentInput,
+    socket_coverage_observer::SocketCoverageObserver,
+};
+
+/// The goals reached over the campaign, stored in the state.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CoverageGoalProgress {
+    pub reached: Vec<String>,
+    pub total: usize,
+}
+
+impl_serdeany!(CoverageGoalProgress);
+
+impl CoverageGoalProgress {
+    pub fn all_reached(&self) -> bool {
+        self.total > 0 && self.reached.len() == self.total
+    }
+}
+
+/// The goals a corpus entry reached first.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReachedGoals(pub Vec<String>);
+
+impl_serdeany!(ReachedGoals);
+
+/// A class (`com.example.Foo`) or method (`com.example.Foo#bar`) to reach.
+#[derive(Debug)]
+struct Goal {
+    target: String,
+    /// The map entries of the goal, found on the first execution.
+    entries: Option<Vec<usize>>,
+    reached: bool,
+}
+
+impl Goal {
+    /// Whether the location is in the class or method of the goal. Methods
+    /// match with or without their descriptor.
+    fn matches(&self, location: &SourceLocation) -> bool {
+        match self.target.split_once('#') {
+            Some((class, method)) => {
+                location.class == class
+                    && location.method.as_deref().is_some_and(|name| {
+                        name.strip_prefix(method)
+                            .is_some_and(|rest| rest.is_empty() || rest.starts_with('('))
+                    })
+            }
+            None => location.class == self.target,
+        }
+    }
+}
+
+/// Feedback marking inputs that reach a coverage goal for the first time as
+/// interesting.
+#[derive(Debug)]
+pub struct CoverageGoalFeedback {
+    goals: Vec<Goal>,
+    last_reached: Vec<String>,
+}
+
+impl CoverageGoalFeedback {
+    pub fn new(targets: Vec<String>) -> Self {
+        Self {
+            goals: targets
+                .into_iter()
+                .map(|target| Goal {
+                    target,
+                    entries: None,
+                    reached: false,
+                })
+                .collect(),
+            last_reached: Vec::new(),
+        }
+    }
+}
+
+impl Named for CoverageGoalFeedback {
+    fn name(&self) -> &str {
+        "CoverageGoalFeedback"
+    }
+}
+
+impl<S> Feedback<S> for CoverageGoalFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor + HasNamedMetadata,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        _input: &IntentInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        if self.goals.iter().all(|goal| goal.reached) {
+            return Ok(false);
+        }
+        let Some(observer) =
+            observers.match_name::<SocketCoverageObserver>("SocketCoverageObserver")
+        else {
+            return Ok(false);
+        };
+
+        for goal in self.goals.iter_mut().filter(|goal| !goal.reached) {
+            if goal.entries.is_none() {
+                let entries: Vec<usize> = (0..observer.usable_count())
+                    .filter(|&index| observer.location(index).is_some_and(|l| goal.matches(l)))
+                    .collect();
+                if entries.is_empty() {
+                    println!("Coverage goal {} matches no map entries", goal.target);
+                }
+                goal.entries = Some(entries);
+            }
+            let entries = goal.entries.as_deref().unwrap_or_default();
+            if entries.iter().any(|&index| *observer.get(index) != 0) {
+                println!("Reached coverage goal {}", goal.target);
+                goal.reached = true;
+                self.last_reached.push(goal.target.clone());
+            }
+        }
+        if self.last_reached.is_empty() {
+            return Ok(false);
+        }
+
+        if !state.has_named_metadata::<CoverageGoalProgress>("coverage_goal_progress") {
+            state.add_named_metadata(CoverageGoalProgress::default(), "coverage_goal_progress");
+        }
+        let progress =
+            state.named_metadata_mut::<CoverageGoalProgress>("coverage_goal_progress")?;
+        progress.reached.extend(self.last_reached.iter().cloned());
+        progress.total = self.goals.len();
+        let (reached, total) = (progress.reached.len() as u64, progress.total as u64);
+        manager.fire(
+            state,
+            Event::UpdateUserStats {
+                name: "coverage_goals".to_owned(),
+                value: UserStats::Ratio(reached, total),
+                phantom: PhantomData,
+            },
+        )?;
+        Ok(true)
+    }
+
+    fn append_metadata<OT>(
+        &mut self,
+        _state: &mut S,
+        _observers: &OT,
+        testcase: &mut Testcase<IntentInput>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        if !self.last_reached.is_empty() {
+            testcase.add_metadata(ReachedGoals(std::mem::take(&mut self.last_reached)));
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(
+        &mut self,
+        _state: &mut S,
+        _input: &IntentInput,
+    ) -> Result<(), libafl::Error> {
+        self.last_reached.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+//! Mapping of the coverage map entries to source locations.
+//!
+//! The instrumentation of the agent assigns every edge a map entry. Its
+//! sidecar mapping file names the location of every entry, so coverage can be
+//! reported as methods and lines instead of opaque indices. Every line of the
+//! file maps one entry, either as `<index> <class>#<method>:<line>` (the
+//! format exported by the agent, method and line are optional) or as
+//! `<index> <class> [<method>]`.
+
+use std::{collections::BTreeMap, fmt, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+/// Where a map entry was instrumented.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SourceLocation {
+    /// The class name, e.g. `com.example.Foo`.
+    pub class: String,
+    /// The method name, possibly with its descriptor.
+    pub method: Option<String>,
+    pub line: Option<u32>,
+}
+
+impl SourceLocation {
+    /// The location of the method, without the line.
+    pub fn method_location(&self) -> SourceLocation {
+        SourceLocation {
+            line: None,
+            ..self.clone()
+        }
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.class)?;
+        if let Some(method) = &self.method {
+            write!(f, "#{}", method)?;
+        }
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// The locations of the mapped map entries, ordered by index so that
+/// everything derived from them (e.g. the method ids) is the same on every
+/// run.
+#[derive(Clone, Debug, Default)]
+pub struct CoverageMapping {
+    locations: BTreeMap<usize, SourceLocation>,
+}
+
+impl CoverageMapping {
+    /// Reads a mapping file, ignoring the lines it can't parse.
+    pub fn load(path: &Path) -> Result<Self, libafl::Error> {
+        let locations: BTreeMap<usize, SourceLocation> = std::fs::read_to_string(path)?
+            .lines()
+            .filter_map(parse_line)
+            .collect();
+        println!(
+            "Loaded the locations of {} map entries from {:?}",
+            locations.len(),
+            path
+        );
+        Ok(Self { locations })
+    }
+
+    /// The location of the map entry, if mapped.
+    pub fn location(&self, index: usize) -> Option<&SourceLocation> {
+        self.locations.get(&index)
+    }
+
+    /// All mapped map entries with their locations, by index.
+    pub fn locations(&self) -> impl Iterator<Item = (usize, &SourceLocation)> {
+        self.locations
+            .iter()
+            .map(|(index, location)| (*index, location))
+    }
+}
+
+fn parse_line(line: &str) -> Option<(usize, SourceLocation)> {
+    let mut fields = line.split_whitespace();
+    let index = fields.next()?.parse().ok()?;
+    let location = fields.next()?;
+    let location = match location.split_once('#') {
+        Some((class, method)) => {
+            let (method, line) = match method.rsplit_once(':') {
+                Some((method, line)) if line.parse::<u32>().is_ok() => (method, line.parse().ok()),
+                _ => (method, None),
+            };
+            SourceLocation {
+                class: class.to_owned(),
+                method: Some(method.to_owned()),
+                line,
+            }
+        }
+        None => SourceLocation {
+            class: location.to_owned(),
+            method: fields.next().map(str::to_owned),
+            line: None,
+        },
+    };
+    Some((index, location))
+}
@@ -0,0 +1,173 @@
+//! HTML report of the coverage of a campaign.
+//!
+//! The observer writes the overall coverage map next to the overall coverage
+//! CSV file. The `report` subcommand maps its entries to source locations
+//! with the mapping file of the agent and writes an `index.html` listing the
+//! covered methods of every class, and a page per class listing its covered
+//! and uncovered methods. With the decompiled sources of the app (e.g. the
+//! output directory of jadx), the page of a class also shows its source with
+//! the covered lines highlighted.
+//!
+//! The map of a campaign in the methods coverage mode folded on the host
+//! holds methods instead of map entries of the agent and can't be reported.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::coverage_mapping::CoverageMapping;
+
+/// The coverage of the methods of a class.
+#[derive(Default)]
+struct ClassCoverage {
+    /// Whether any map entry of the method was covered, by method.
+    methods: BTreeMap<String, bool>,
+    covered_lines: BTreeSet<u32>,
+    uncovered_lines: BTreeSet<u32>,
+}
+
+impl ClassCoverage {
+    fn covered_methods(&self) -> usize {
+        self.methods.values().filter(|&&covered| covered).count()
+    }
+}
+
+/// The file the overall coverage map is written to, next to the overall
+/// coverage CSV file.
+pub fn overall_map_file(overall_coverage_file: &Path) -> PathBuf {
+    overall_coverage_file.with_extension("map")
+}
+
+/// Writes the report of the coverage map to the output directory. Returns
+/// the number of covered and mapped methods.
+pub fn write_report(
+    coverage_map: &Path,
+    mapping: &CoverageMapping,
+    sources_dir: Option<&Path>,
+    output_dir: &Path,
+) -> Result<(usize, usize), libafl::Error> {
+    let map = std::fs::read(coverage_map)?;
+    let mut classes: BTreeMap<String, ClassCoverage> = BTreeMap::new();
+    for (index, location) in mapping.locations() {
+        let covered = map.get(index).is_some_and(|&b| b != 0);
+        let class = classes.entry(location.class.clone()).or_default();
+        if let Some(method) = &location.method {
+            *class.methods.entry(method.clone()).or_default() |= covered;
+        }
+        if let Some(line) = location.line {
+            if covered {
+                class.covered_lines.insert(line);
+            } else {
+                class.uncovered_lines.insert(line);
+            }
+        }
+    }
+
+    std::fs::create_dir_all(output_dir)?;
+    let mut index = page_header("Coverage report");
+    index.push_str(
+        "<table>\n<tr><th>Class</th><th>Covered methods</th><th>Methods</th><th></th></tr>\n",
+    );
+    let (mut covered_total, mut methods_total) = (0, 0);
+    for (name, class) in &classes {
+        let covered = class.covered_methods();
+        covered_total += covered;
+        methods_total += class.methods.len();
+        let page = format!("{}.html", name.replace(['/', '$'], "_"));
+        let _ = writeln!(
+            index,
+            "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&page),
+            escape(name),
+            covered,
+            class.methods.len(),
+            percentage(covered, class.methods.len())
+        );
+
+        let source = sources_dir.and_then(|dir| read_source(dir, name));
+        std::fs::write(
+            output_dir.join(&page),
+            class_page(name, class, source.as_deref()),
+        )?;
+    }
+    index.push_str("</table>\n");
+    let _ = writeln!(
+        index,
+        "<p>{} of {} methods covered ({})</p>",
+        covered_total,
+        methods_total,
+        percentage(covered_total, methods_total)
+    );
+    index.push_str("</body></html>\n");
+    std::fs::write(output_dir.join("index.html"), index)?;
+
+    Ok((covered_total, methods_total))
+}
+
+/// The page of a class, listing its methods and its source.
+fn class_page(name: &str, class: &ClassCoverage, source: Option<&str>) -> String {
+    let mut page = page_header(name);
+    page.push_str("<p><a href=\"index.html\">All classes</a></p>\n<ul>\n");
+    for (method, &covered) in &class.methods {
+        let _ = writeln!(
+            page,
+            "<li class=\"{}\">{}</li>",
+            if covered { "covered" } else { "uncovered" },
+            escape(method)
+        );
+    }
+    page.push_str("</ul>\n");
+
+    if let Some(source) = source {
+        page.push_str("<pre>\n");
+        for (i, line) in source.lines().enumerate() {
+            let number = i as u32 + 1;
+            let class = if class.covered_lines.contains(&number) {
+                " class=\"covered\""
+            } else if class.uncovered_lines.contains(&number) {
+                " class=\"uncovered\""
+            } else {
+                ""
+            };
+            let _ = writeln!(page, "<span{}>{:5} {}</span>", class, number, escape(line));
+        }
+        page.push_str("</pre>\n");
+    }
+    page.push_str("</body></html>\n");
+    page
+}
+
+fn page_header(title: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title>\n\
+        <style>.covered {{ background: #c8f0c8; }} .uncovered {{ background: #f0c8c8; }}</style>\n\
+        </head><body>\n<h1>{0}</h1>\n",
+        escape(title)
+    )
+}
+
+/// The decompiled source of the class, or of its outer class for nested
+/// classes.
+fn read_source(sources_dir: &Path, class: &str) -> Option<String> {
+    let outer = class.split('$').next().unwrap_or(class);
+    let path = sources_dir.join(outer.replace('.', "/"));
+    ["java", "kt"]
+        .iter()
+        .find_map(|extension| std::fs::read_to_string(path.with_extension(extension)).ok())
+}
+
+fn percentage(covered: usize, total: usize) -> String {
+    if total == 0 {
+        return "-".to_owned();
+    }
+    format!("{:.1}%", covered as f64 * 100.0 / total as f64)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
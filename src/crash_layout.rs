@@ -0,0 +1,274 @@
+//! Layout of the solutions on disk.
+//!
+//! Every solution is stored as `<bucket>/<hash>/input.json` in the crashes
+//! directory, next to a `metadata.json` describing the finding, so triage
+//! tooling does not depend on the internals of a libAFL corpus. Solutions are
+//! bucketed by their crash signature: the signature of a native crash, the
+//! root cause exception and top frame of a Java crash (as reported by the
+//! coverage agent, or else found in the crash log), or the severity if
+//! neither is known.
+
+use std::{
+    hash::Hasher,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use fasthash::{farm::Hasher64, FastHasher};
+use libafl::{
+    impl_serdeany,
+    prelude::{
+        EventFirer, ExitKind, Feedback, HasMetadata, Named, ObserversTuple, Testcase, UsesInput,
+    },
+    state::HasClientPerfMonitor,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    adb_device::AdbDevice,
+    exception_report::ExceptionReport,
+    input_schema,
+    intent_input::IntentInput,
+    logcat_capture::LogcatObserver,
+    native_crash::CrashSignature,
+    notifier,
+    run_config::{self, CampaignInfo},
+    triage::Severity,
+};
+
+/// How far back the crash log is searched for the exception of a finding
+/// if the start of its execution is unknown.
+const CRASH_LOG_DURATION: Duration = Duration::from_secs(10);
+
+/// The file of a solution holding the input.
+pub const INPUT_FILE: &str = "input.json";
+
+/// The file of a solution describing the finding.
+pub const METADATA_FILE: &str = "metadata.json";
+
+/// Where a solution was stored and how its execution ended.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SolutionLocation {
+    pub dir: PathBuf,
+    pub exit_kind: ExitKind,
+}
+
+impl_serdeany!(SolutionLocation);
+
+/// The sidecar of a solution describing the finding.
+#[derive(Serialize, Debug)]
+struct SolutionMetadata {
+    hash: String,
+    bucket: String,
+    component: String,
+    exit_kind: String,
+    /// Seconds since the UNIX epoch.
+    found_at: u64,
+    severity: Option<String>,
+    exception_signature: Option<String>,
+    adb_command: String,
+    campaign: Option<CampaignInfo>,
+}
+
+/// Objective feedback writing every solution in the layout described above.
+/// It never reports any inputs itself, so it belongs at the end of the
+/// objective, after the feedbacks adding the metadata it reads.
+#[derive(Debug)]
+pub struct CrashLayoutFeedback {
+    adb_device: AdbDevice,
+    app_name: String,
+    crashes_dir: PathBuf,
+    last_exit_kind: Option<ExitKind>,
+}
+
+impl CrashLayoutFeedback {
+    pub fn new(adb_device: AdbDevice, app_name: String, crashes_dir: PathBuf) -> Self {
+        Self {
+            adb_device,
+            app_name,
+            crashes_dir,
+            last_exit_kind: None,
+        }
+    }
+}
+
+impl Named for CrashLayoutFeedback {
+    fn name(&self) -> &str {
+        "CrashLayoutFeedback"
+    }
+}
+
+impl<S> Feedback<S> for CrashLayoutFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        _observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.last_exit_kind = Some(*exit_kind);
+        Ok(false)
+    }
+
+    fn append_metadata<OT>(
+        &mut self,
+        _state: &mut S,
+        observers: &OT,
+        testcase: &mut Testcase<IntentInput>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        let Some(input) = testcase.input() else {
+            return Ok(());
+        };
+
+        let severity = testcase.metadata::<Severity>().ok().copied();
+        let (bucket, exception_signature) =
+            if let Ok(signature) = testcase.metadata::<CrashSignature>() {
+                (format!("native-{:.16}", signature.0), None)
+            } else if let Some(signature) = testcase
+                .metadata::<ExceptionReport>()
+                .ok()
+                .filter(|report| report.uncaught)
+                .map(|report| report.signature())
+                .or_else(|| {
+                    execution_crash_log(&self.adb_device, observers)
+                        .and_then(|log| java_exception_signature(&log, &self.app_name))
+                })
+            {
+                let mut hasher = Hasher64::new();
+                hasher.write(signature.as_bytes());
+                (format!("java-{:016x}", hasher.finish()), Some(signature))
+            } else {
+                let bucket = severity.map_or("unknown".to_owned(), |severity| {
+                    format!("{:?}", severity).to_lowercase()
+                });
+                (bucket, None)
+            };
+
+        let hash = input.hash();
+        let new_bucket = !self.crashes_dir.join(&bucket).exists();
+        let dir = self.crashes_dir.join(&bucket).join(&hash);
+        std::fs::create_dir_all(&dir)?;
+        notifier::notify_crash(
+            &bucket,
+            new_bucket,
+            &input.component(),
+            exception_signature.as_deref(),
+        );
+
+        let exit_kind = self.last_exit_kind.unwrap_or(ExitKind::Crash);
+        let metadata = SolutionMetadata {
+            hash,
+            bucket,
+            component: input.component(),
+            exit_kind: format!("{:?}", exit_kind),
+            found_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            severity: severity.map(|severity| severity.to_string()),
+            exception_signature,
+            adb_command: input.shell_command(),
+            campaign: run_config::campaign().cloned(),
+        };
+
+        std::fs::write(dir.join(INPUT_FILE), input_schema::to_json(input)?)?;
+        std::fs::write(dir.join(METADATA_FILE), to_pretty_json(&metadata)?)?;
+        println!("Saved solution to {:?}", dir);
+        testcase.add_metadata(SolutionLocation { dir, exit_kind });
+        // The input is on disk now, so the solutions corpus does not keep
+        // every solution of the campaign in memory.
+        *testcase.input_mut() = None;
+
+        Ok(())
+    }
+}
+
+/// The crash log since the start of the last execution, as recorded by the
+/// [LogcatObserver], so an earlier crash is not attributed to it.
+pub fn execution_crash_log<S, OT>(adb_device: &AdbDevice, observers: &OT) -> Option<String>
+where
+    S: UsesInput,
+    OT: ObserversTuple<S>,
+{
+    let since = observers
+        .match_name::<LogcatObserver>("LogcatObserver")
+        .map_or_else(
+            || SystemTime::now() - CRASH_LOG_DURATION,
+            LogcatObserver::exec_start,
+        );
+    adb_device.crash_log_since(since).ok()
+}
+
+pub fn to_pretty_json<T: Serialize>(value: &T) -> Result<String, libafl::Error> {
+    serde_json::to_string_pretty(value).map_err(|err| libafl::Error::serialize(err.to_string()))
+}
+
+/// The exception and top frame of the root cause of the last Java crash of
+/// the app in the crash log, e.g.
+/// `java.lang.NullPointerException at com.example.Foo.bar(Foo.java:12)`.
+/// The exception messages are left out, as they often contain input data.
+pub fn java_exception_signature(log: &str, app_name: &str) -> Option<String> {
+    let process = format!("Process: {},", app_name);
+    let crash = log.rsplit("FATAL EXCEPTION").next()?;
+    if !crash.contains(&process) {
+        return None;
+    }
+
+    let mut exception = None;
+    let mut frame = None;
+    for line in crash
+        .lines()
+        .skip_while(|line| !line.contains(&process))
+        .skip(1)
+    {
+        // e.g. "E AndroidRuntime: Caused by: java.lang.Foo: message"
+        let message = line
+            .split_once(": ")
+            .map_or(line, |(_, message)| message)
+            .trim();
+        if let Some(at) = message.strip_prefix("at ") {
+            if frame.is_none() {
+                frame = Some(at.to_owned());
+            }
+        } else if !message.starts_with("...") {
+            let cause = message.strip_prefix("Caused by: ").unwrap_or(message);
+            let name = cause.split(':').next().unwrap_or(cause).trim();
+            if exception.is_none() || message.starts_with("Caused by: ") {
+                exception = Some(name.to_owned());
+                frame = None;
+            }
+        }
+    }
+
+    Some(match frame {
+        Some(frame) => format!("{} at {}", exception?, frame),
+        None => exception?,
+    })
+}
+
+/// The input files of all solutions in the crashes directory, sorted.
+pub fn solution_inputs(crashes_dir: &Path) -> Vec<PathBuf> {
+    let mut inputs: Vec<PathBuf> = std::fs::read_dir(crashes_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|bucket| bucket.ok())
+        .filter_map(|bucket| std::fs::read_dir(bucket.path()).ok())
+        .flatten()
+        .filter_map(|solution| solution.ok())
+        .map(|solution| solution.path().join(INPUT_FILE))
+        .filter(|input| input.is_file())
+        .collect();
+    inputs.sort();
+    inputs
+}
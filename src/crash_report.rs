@@ -0,0 +1,285 @@
+//! Markdown reports for crashes found during fuzzing.
+//!
+//! The [CrashReportFeedback] is combined with the objective feedback and
+//! writes one report per distinct crashing input when it is added to the
+//! solutions corpus. The report is meant to be pasted into a bug tracker.
+//! Every finding is classified by its [Severity] and listed in an index sorted
+//! by severity. Optionally, a device bugreport is captured for native crashes
+//! and ANRs.
+
+use std::{
+    fmt::Write,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use libafl::{
+    prelude::{
+        EventFirer, ExitKind, Feedback, HasMetadata, Named, ObserversTuple, Testcase, UsesInput,
+    },
+    state::HasClientPerfMonitor,
+};
+
+use crate::{
+    adb_device::AdbDevice,
+    intent_input::IntentInput,
+    logcat_capture::LogcatObserver,
+    run_config,
+    triage::{self, Severity},
+};
+
+/// Maximum number of crash log lines included in a report.
+const MAX_CRASH_LOG_LINES: usize = 40;
+
+/// Feedback that never marks an input as interesting, but writes a Markdown
+/// report for every new solution.
+#[derive(Debug)]
+pub struct CrashReportFeedback {
+    adb_device: AdbDevice,
+    app_name: String,
+    reports_dir: PathBuf,
+    capture_bugreport: bool,
+    /// Severity and input hash of all reported findings.
+    findings: Vec<(Severity, String)>,
+}
+
+impl CrashReportFeedback {
+    pub fn new(
+        adb_device: AdbDevice,
+        app_name: String,
+        reports_dir: PathBuf,
+        capture_bugreport: bool,
+    ) -> Self {
+        Self {
+            adb_device,
+            app_name,
+            reports_dir,
+            capture_bugreport,
+            findings: Vec::new(),
+        }
+    }
+
+    /// Writes an index of all findings, sorted by severity.
+    fn write_index(&self) -> Result<(), std::io::Error> {
+        let mut findings = self.findings.clone();
+        findings.sort_by(|a, b| b.0.cmp(&a.0));
+
+        let mut index = String::new();
+        writeln!(index, "# Findings\n").unwrap();
+        writeln!(index, "| Severity | Report |\n|---|---|").unwrap();
+        for (severity, hash) in findings {
+            writeln!(index, "| {} | [{}]({}.md) |", severity, hash, hash).unwrap();
+        }
+
+        std::fs::write(self.reports_dir.join("index.md"), index)
+    }
+
+    /// Renders the Markdown report for the given crashing input, executed at
+    /// `exec_start`.
+    fn render_report(
+        &self,
+        input: &IntentInput,
+        severity: Severity,
+        exec_start: SystemTime,
+    ) -> String {
+        let mut report = String::new();
+
+        writeln!(report, "# Crash in `{}`\n", input.component()).unwrap();
+        writeln!(report, "Severity: **{}**\n", severity).unwrap();
+
+        // Reproduction
+        writeln!(report, "## Reproduction\n").unwrap();
+        // The quoted heredoc passes the command to the device as it is, like
+        // the reproduction scripts do.
+        writeln!(
+            report,
+            "```sh\nadb shell \"$(cat <<'EOF'\n{}\nEOF\n)\"\n```\n",
+            input.shell_command()
+        )
+        .unwrap();
+        if input.data.is_some() || input.extras.iter().any(|e| e.value.is_uri()) {
+            writeln!(
+                report,
+                "The intent references URI payloads, they need to be staged on the device first \
+                 (see the input JSON with hash `{}`).\n",
+                input.hash()
+            )
+            .unwrap();
+        }
+
+        // Intent fields
+        writeln!(report, "## Intent\n").unwrap();
+        writeln!(report, "| Field | Value |\n|---|---|").unwrap();
+        writeln!(report, "| Receiver type | {:?} |", input.receiver_type).unwrap();
+        writeln!(report, "| Component | `{}` |", input.component()).unwrap();
+        writeln!(report, "| Action | `{}` |", input.action).unwrap();
+        writeln!(report, "| Category | `{}` |", input.category).unwrap();
+        if let Some(data) = &input.data {
+            writeln!(report, "| Data | `{}` |", data.identifier(0)).unwrap();
+        }
+        writeln!(report, "| Mime type | `{}` |", input.mime_type).unwrap();
+        writeln!(report, "| Flags | `{:#010x}` |\n", input.flags).unwrap();
+
+        if !input.extras.is_empty() {
+            writeln!(report, "### Extras\n").unwrap();
+            writeln!(report, "| Key | Type | Value |\n|---|---|---|").unwrap();
+            for (index, extra) in input.extras.iter().enumerate() {
+                writeln!(
+                    report,
+                    "| `{}` | {} | `{}` |",
+                    extra.key,
+                    extra.value,
+                    extra.value_arg(index + 1).unwrap_or_default()
+                )
+                .unwrap();
+            }
+            writeln!(report).unwrap();
+        }
+
+        // Exception / stack excerpt
+        writeln!(report, "## Crash log\n").unwrap();
+        match self.adb_device.recent_crash_log(Duration::from_secs(10)) {
+            Ok(log) => {
+                let lines: Vec<&str> = log.lines().collect();
+                let start = lines
+                    .iter()
+                    .position(|l| l.contains("FATAL EXCEPTION") || l.contains("Fatal signal"))
+                    .unwrap_or(lines.len().saturating_sub(MAX_CRASH_LOG_LINES));
+                let excerpt = lines
+                    .iter()
+                    .skip(start)
+                    .take(MAX_CRASH_LOG_LINES)
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                writeln!(report, "```\n{}\n```\n", excerpt).unwrap();
+            }
+            Err(err) => writeln!(report, "Failed to read crash log: {}\n", err).unwrap(),
+        }
+
+        // The dropbox keeps the full stack trace even if the crash buffer
+        // rotated. Its times are truncated to the second.
+        if let Some(entry) = self
+            .adb_device
+            .dropbox_crash_entries(&self.app_name)
+            .ok()
+            .and_then(|entries| {
+                entries
+                    .into_iter()
+                    .filter(|entry| entry.time + Duration::from_secs(1) > exec_start)
+                    .last()
+            })
+        {
+            writeln!(report, "## Latest DropBox entry\n").unwrap();
+            writeln!(report, "```\n{}\n```\n", entry.text).unwrap();
+        }
+
+        // Environment
+        writeln!(report, "## Environment\n").unwrap();
+        let fingerprint = self
+            .adb_device
+            .build_fingerprint()
+            .unwrap_or_else(|_| "unknown".to_owned());
+        writeln!(report, "- Device: `{}`", fingerprint).unwrap();
+        match self.adb_device.package_version(&self.app_name) {
+            Ok(version) => writeln!(
+                report,
+                "- App: `{}` {} (versionCode {})",
+                self.app_name, version.version_name, version.version_code
+            )
+            .unwrap(),
+            Err(_) => writeln!(report, "- App: `{}` (unknown version)", self.app_name).unwrap(),
+        }
+        if let Some(campaign) = run_config::campaign() {
+            writeln!(
+                report,
+                "- Campaign: `{}` (configuration `{}`)",
+                campaign.id, campaign.config_hash
+            )
+            .unwrap();
+        }
+
+        report
+    }
+}
+
+impl Named for CrashReportFeedback {
+    fn name(&self) -> &str {
+        "CrashReportFeedback"
+    }
+}
+
+impl<S> Feedback<S> for CrashReportFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        Ok(false)
+    }
+
+    fn append_metadata<OT>(
+        &mut self,
+        _state: &mut S,
+        observers: &OT,
+        testcase: &mut Testcase<IntentInput>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        let Some(input) = testcase.input() else {
+            return Ok(());
+        };
+
+        // Keep the severity of escalated exceptions.
+        let severity = match testcase.metadata::<Severity>() {
+            Ok(Severity::Escalated) => Severity::Escalated,
+            _ => triage::classify(&self.adb_device, &self.app_name),
+        };
+        let hash = input.hash();
+
+        // Only write one report per distinct crashing input.
+        let report_file = self.reports_dir.join(format!("{}.md", hash));
+        if report_file.exists() {
+            testcase.add_metadata(severity);
+            return Ok(());
+        }
+
+        std::fs::create_dir_all(&self.reports_dir)?;
+        // Without the logcat observer, the latest entry is the best guess.
+        let exec_start = observers
+            .match_name::<LogcatObserver>("LogcatObserver")
+            .map_or(UNIX_EPOCH, LogcatObserver::exec_start);
+        std::fs::write(
+            &report_file,
+            self.render_report(input, severity, exec_start),
+        )?;
+        println!("Wrote crash report ({}): {:?}", severity, report_file);
+
+        self.findings.push((severity, hash));
+        self.write_index()?;
+
+        if self.capture_bugreport && severity.is_high() {
+            let bugreport_file = self
+                .reports_dir
+                .join(format!("{}_bugreport.zip", input.hash()));
+            if let Err(err) = self.adb_device.capture_bugreport(&bugreport_file) {
+                println!("{}", err);
+            }
+        }
+
+        testcase.add_metadata(severity);
+
+        Ok(())
+    }
+}
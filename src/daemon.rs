@@ -0,0 +1,215 @@
+//! Sequential multi-campaign daemon mode.
+//!
+//! The daemon consumes a queue directory of [CampaignDefinition] JSON files
+//! and runs them back-to-back on the device, each in its own fuzzer process
+//! with a time budget. All outputs of a campaign (corpus, crashes, reports,
+//! stats, coverage and log) are archived in a directory of their own, so an
+//! emulator can grind through a list of apps unattended.
+
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::adb_device::{AdbDevice, AdbServer};
+
+/// The environment variables of the options which make the fuzzer process of
+/// a campaign do something else than fuzzing, e.g. consume the queue as
+/// another daemon. The campaigns must not inherit them from the daemon.
+const DAEMON_ONLY_VARIABLES: &[&str] = &[
+    "MALINTENT_CAMPAIGN_QUEUE",
+    "MALINTENT_ARCHIVE_DIR",
+    "MALINTENT_EXPORT_SCRIPTS",
+    "MALINTENT_RUN_CORPUS",
+];
+
+/// A campaign in the queue.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CampaignDefinition {
+    /// The package of the app to fuzz.
+    pub package: String,
+    /// The intent template file or directory of the app.
+    pub intent_config: PathBuf,
+    /// How long to fuzz the app, in seconds.
+    pub time_budget: u64,
+}
+
+/// Options passed on to the fuzzer process of every campaign.
+#[derive(Debug, Clone)]
+pub struct DaemonOptions {
+    pub adb_command: String,
+    pub adb_server: Option<AdbServer>,
+    /// Further options of every adb command, as given on the command line.
+    pub adb_args: Option<String>,
+    pub coverage_socket_address: String,
+    pub no_coverage: bool,
+    pub capture_bugreport: bool,
+    pub results_db: Option<PathBuf>,
+}
+
+/// Runs all campaigns in the queue directory (in file name order) until the
+/// queue is empty, archiving their outputs in the archive directory.
+pub fn run(queue_dir: &Path, archive_dir: &Path, options: &DaemonOptions) {
+    let adb_device = AdbDevice::new(&options.adb_command)
+        .with_server(options.adb_server.clone())
+        .with_extra_args(
+            options
+                .adb_args
+                .as_deref()
+                .and_then(shlex::split)
+                .unwrap_or_default(),
+        );
+
+    while let Some(definition_file) = next_campaign(queue_dir) {
+        let definition: CampaignDefinition = match File::open(&definition_file)
+            .map_err(|err| err.to_string())
+            .and_then(|file| serde_json::from_reader(file).map_err(|err| err.to_string()))
+        {
+            Ok(definition) => definition,
+            Err(err) => {
+                println!(
+                    "Skipping invalid campaign {}: {}",
+                    definition_file.display(),
+                    err
+                );
+                if let Err(err) = archive_definition(&definition_file, &archive_dir.join("invalid"))
+                {
+                    println!(
+                        "Stopping, failed to move the invalid campaign out of the queue: {}",
+                        err
+                    );
+                    return;
+                }
+                continue;
+            }
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let campaign_dir = archive_dir.join(format!("{}-{}", timestamp, definition.package));
+        // A campaign left in the queue would be picked up again and again.
+        if let Err(err) = archive_definition(&definition_file, &campaign_dir) {
+            println!(
+                "Stopping, failed to move the campaign out of the queue: {}",
+                err
+            );
+            return;
+        }
+
+        println!(
+            "Starting campaign for {} ({}s) in {}",
+            definition.package,
+            definition.time_budget,
+            campaign_dir.display()
+        );
+        run_campaign(&adb_device, &definition, &campaign_dir, options);
+    }
+
+    println!("Campaign queue is empty");
+}
+
+/// The next campaign definition in the queue, if any.
+fn next_campaign(queue_dir: &Path) -> Option<PathBuf> {
+    let mut definitions: Vec<PathBuf> = std::fs::read_dir(queue_dir)
+        .expect("Failed to read campaign queue")
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+        .collect();
+    definitions.sort();
+    definitions.into_iter().next()
+}
+
+/// Moves the definition out of the queue into the given campaign directory.
+/// Falls back to copying and removing it if it can't be renamed, e.g. if the
+/// queue and the archive are on different filesystems.
+fn archive_definition(definition_file: &Path, campaign_dir: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(campaign_dir)?;
+    let archived = campaign_dir.join(definition_file.file_name().unwrap());
+    if let Err(err) = std::fs::rename(definition_file, &archived) {
+        println!(
+            "Failed to move {} to {} ({}), copying it instead",
+            definition_file.display(),
+            archived.display(),
+            err
+        );
+        std::fs::copy(definition_file, &archived)?;
+        std::fs::remove_file(definition_file)?;
+    }
+    Ok(())
+}
+
+/// Runs a single campaign in a fuzzer process and waits for it to finish.
+fn run_campaign(
+    adb_device: &AdbDevice,
+    definition: &CampaignDefinition,
+    campaign_dir: &Path,
+    options: &DaemonOptions,
+) {
+    if let Err(err) = adb_device.install_coverage_agent(&definition.package) {
+        println!("Failed to install coverage agent: {:?}", err);
+        return;
+    }
+
+    let log = File::create(campaign_dir.join("log.txt")).expect("Failed to create campaign log");
+    let mut command = Command::new(std::env::current_exe().expect("Failed to get fuzzer binary"));
+    command
+        .arg("--adb-command")
+        .arg(&options.adb_command)
+        .arg("--coverage-socket-address")
+        .arg(&options.coverage_socket_address)
+        .arg("--intent-config")
+        .arg(&definition.intent_config)
+        .arg("--time-budget")
+        .arg(definition.time_budget.to_string())
+        .arg("--corpus-dir")
+        .arg(campaign_dir.join("corpus"))
+        .arg("--crashes-dir")
+        .arg(campaign_dir.join("crashes"))
+        .arg("--crash-reports-dir")
+        .arg(campaign_dir.join("crash_reports"))
+        .arg("--stats-file")
+        .arg(campaign_dir.join("fuzzer_stats.toml"))
+        .arg("--overall-coverage-file")
+        .arg(campaign_dir.join("edgecount.csv"))
+        .stdout(log.try_clone().expect("Failed to clone campaign log"))
+        .stderr(Stdio::from(log));
+    for variable in DAEMON_ONLY_VARIABLES {
+        command.env_remove(variable);
+    }
+
+    if options.no_coverage {
+        command.arg("--no-coverage");
+    }
+    if options.capture_bugreport {
+        command.arg("--capture-bugreport");
+    }
+    if let Some(server) = &options.adb_server {
+        command
+            .arg("--adb-server")
+            .arg(format!("{}:{}", server.host, server.port));
+    }
+    if let Some(adb_args) = &options.adb_args {
+        command.arg("--adb-args").arg(adb_args);
+    }
+    if let Some(results_db) = &options.results_db {
+        command.arg("--results-db").arg(results_db);
+    }
+
+    match command.status() {
+        Ok(status) => println!("Campaign for {} finished: {}", definition.package, status),
+        Err(err) => println!("Failed to run campaign for {}: {}", definition.package, err),
+    }
+
+    // Leave the device clean for the next campaign.
+    if let Err(err) = adb_device.stop_app(&definition.package) {
+        println!("Failed to stop {}: {:?}", definition.package, err);
+    }
+}
@@ -0,0 +1,259 @@
+//! Data constraints of intent filters and URIs at their match boundary.
+//!
+//! Components declare the URIs they handle with `<data>` elements in their
+//! intent filters. Bugs hide where the filter matching of the framework and
+//! the app's own URI parsing disagree, so for every [DataFilter] of a template
+//! we generate URIs that just match it, that just miss it, and that smuggle
+//! pattern metacharacters into the parts the filter checks. The initial
+//! corpus also gets an intent with data and type matching every filter, so
+//! the paths guarded by the filter are reached from the start.
+//!
+//! The framework matches the schemes, hosts and types of all `<data>`
+//! elements of an intent filter independently, so any scheme combines with
+//! any host and type of the same filter (see [combined_filters]).
+
+use std::collections::HashMap;
+
+use libafl::impl_serdeany;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+use crate::intent_input::{MimeType, URIInput, URIScheme, URISuffix};
+
+/// The constraints of a single `<data>` element of an intent filter.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct DataFilter {
+    #[serde(default)]
+    pub scheme: Option<String>,
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default)]
+    pub port: Option<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub path_prefix: Option<String>,
+    #[serde(default)]
+    pub path_pattern: Option<String>,
+    /// The declared mime type, e.g. `image/*`
+    #[serde(default)]
+    pub mime_type: Option<String>,
+    /// The index of the intent filter of the component declaring the
+    /// element. Elements without one form an intent filter of their own.
+    #[serde(default)]
+    pub intent_filter: Option<usize>,
+}
+
+impl DataFilter {
+    /// The URI matching this filter, if it declares a scheme.
+    pub fn matching_uri(&self) -> Option<String> {
+        let scheme = self.scheme.as_ref()?;
+        let host = self.host.as_deref().unwrap_or("");
+        let port = self
+            .port
+            .as_ref()
+            .map(|p| format!(":{}", p))
+            .unwrap_or_default();
+        let path = self.matching_path();
+        Some(format!("{}://{}{}{}", scheme, host, port, path))
+    }
+
+    /// The data and type of an intent matching this filter, if it declares
+    /// either. A filter declaring only a mime type matches content URIs, so
+    /// these carry staged content of that type.
+    pub fn matching_data(&self) -> Option<(URIInput, MimeType)> {
+        let mime_type = self.mime_type.as_deref().map(matching_mime_type);
+        match self.matching_uri() {
+            Some(uri) => Some((URIInput::raw(&uri), mime_type.unwrap_or(MimeType::Unset))),
+            None => {
+                let mime_type = mime_type?;
+                let suffix = mime_type.suffix().unwrap_or(URISuffix::TXT);
+                Some((URIInput::staged(URIScheme::Content, suffix), mime_type))
+            }
+        }
+    }
+
+    /// Whether URIs with the scheme and host pass this filter, leaving the
+    /// path aside. A host may start with the `*` wildcard of the framework.
+    pub fn matches_authority(&self, scheme: &str, host: &str) -> bool {
+        let Some(filter_scheme) = &self.scheme else {
+            return false;
+        };
+        filter_scheme.eq_ignore_ascii_case(scheme)
+            && match self.host.as_deref() {
+                None | Some("*") => true,
+                Some(filter_host) => match filter_host.strip_prefix('*') {
+                    Some(suffix) => host.ends_with(suffix),
+                    None => filter_host.eq_ignore_ascii_case(host),
+                },
+            }
+    }
+
+    /// URIs sitting at the match boundary of this filter.
+    pub fn boundary_uris(&self) -> Vec<String> {
+        let Some(scheme) = &self.scheme else {
+            // Without a scheme, the filter only matches by mime type.
+            return Vec::new();
+        };
+        let host = self.host.as_deref().unwrap_or("");
+        let port = self
+            .port
+            .as_ref()
+            .map(|p| format!(":{}", p))
+            .unwrap_or_default();
+        let path = self.matching_path();
+
+        let mut uris = vec![
+            // Matching
+            self.matching_uri().unwrap_or_default(),
+            // Scheme and host matching is case sensitive in the framework,
+            // but often not in the app.
+            format!("{}://{}{}{}", scheme.to_uppercase(), host, port, path),
+            format!("{}://{}{}{}", scheme, host.to_uppercase(), port, path),
+            // Host confusion
+            format!("{}://{}.{}{}", scheme, host, port, path),
+            format!("{}://attacker@{}{}{}", scheme, host, port, path),
+            format!("{}://{}.attacker.com{}{}", scheme, host, port, path),
+            format!("{}://attacker.com\\@{}{}{}", scheme, host, port, path),
+            format!("{}://attacker.com#{}{}{}", scheme, host, port, path),
+            // Path traversal and encoding
+            format!("{}://{}{}{}/../../", scheme, host, port, path),
+            format!("{}://{}{}{}%2F..%2F", scheme, host, port, path),
+            format!("{}://{}{}{}?{}", scheme, host, port, path, path),
+        ];
+
+        if let Some(port) = &self.port {
+            if let Ok(number) = port.parse::<u32>() {
+                uris.push(format!("{}://{}:{}{}", scheme, host, number + 1, path));
+            }
+            uris.push(format!("{}://{}{}", scheme, host, path));
+        }
+
+        if let Some(prefix) = &self.path_prefix {
+            // Just short of the prefix
+            let mut short = prefix.clone();
+            short.pop();
+            uris.push(format!("{}://{}{}{}", scheme, host, port, short));
+            uris.push(format!("{}://{}{}{}", scheme, host, port, prefix));
+        }
+
+        if let Some(pattern) = &self.path_pattern {
+            // The pattern itself, with its metacharacters taken literally.
+            uris.push(format!("{}://{}{}{}", scheme, host, port, pattern));
+            uris.push(format!("{}://{}{}{}.*", scheme, host, port, path));
+            uris.push(format!("{}://{}{}{}\\", scheme, host, port, path));
+        }
+
+        uris
+    }
+
+    /// A path matching the path constraints, empty if there are none.
+    fn matching_path(&self) -> String {
+        if let Some(path) = &self.path {
+            path.clone()
+        } else if let Some(prefix) = &self.path_prefix {
+            format!("{}fuzz", prefix)
+        } else if let Some(pattern) = &self.path_pattern {
+            // Fill the wildcards of the simple glob the framework supports.
+            pattern
+                .replace(".*", "fuzz")
+                .replace('.', "a")
+                .replace(['*', '\\'], "")
+        } else {
+            String::new()
+        }
+    }
+}
+
+/// Every combination of the schemes, hosts and types of the `<data>`
+/// elements of each intent filter, as a single element. The path is taken
+/// from the first element of the intent filter constraining it.
+pub fn combined_filters(filters: &[DataFilter]) -> Vec<DataFilter> {
+    let mut intent_filters: Vec<Vec<&DataFilter>> = Vec::new();
+    for filter in filters {
+        let group = filter.intent_filter.and_then(|index| {
+            intent_filters
+                .iter_mut()
+                .find(|group| group[0].intent_filter == Some(index))
+        });
+        match group {
+            Some(group) => group.push(filter),
+            None => intent_filters.push(vec![filter]),
+        }
+    }
+
+    let mut combined = Vec::new();
+    for elements in intent_filters {
+        let schemes = distinct_or_none(elements.iter().map(|e| e.scheme.clone()));
+        // The port of an element only counts with its host.
+        let authorities = distinct_or_none(
+            elements
+                .iter()
+                .filter(|e| e.host.is_some())
+                .map(|e| Some((e.host.clone(), e.port.clone()))),
+        );
+        let mime_types = distinct_or_none(elements.iter().map(|e| e.mime_type.clone()));
+        let path = elements
+            .iter()
+            .find(|e| e.path.is_some() || e.path_prefix.is_some() || e.path_pattern.is_some());
+
+        for scheme in &schemes {
+            for authority in &authorities {
+                let (host, port) = authority.clone().unwrap_or_default();
+                for mime_type in &mime_types {
+                    combined.push(DataFilter {
+                        scheme: scheme.clone(),
+                        host: host.clone(),
+                        port: port.clone(),
+                        path: path.and_then(|e| e.path.clone()),
+                        path_prefix: path.and_then(|e| e.path_prefix.clone()),
+                        path_pattern: path.and_then(|e| e.path_pattern.clone()),
+                        mime_type: mime_type.clone(),
+                        intent_filter: None,
+                    });
+                }
+            }
+        }
+    }
+    combined
+}
+
+/// The distinct declared values, or just `None` if none is declared.
+fn distinct_or_none<T: PartialEq>(values: impl Iterator<Item = Option<T>>) -> Vec<Option<T>> {
+    let mut distinct = Vec::new();
+    for value in values.filter(Option::is_some) {
+        if !distinct.contains(&value) {
+            distinct.push(value);
+        }
+    }
+    if distinct.is_empty() {
+        distinct.push(None);
+    }
+    distinct
+}
+
+/// The mime type of the fuzzer matching a declared type, which may contain
+/// wildcards like `image/*`. Types the fuzzer cannot send are left unset.
+fn matching_mime_type(declared: &str) -> MimeType {
+    let declared = declared.to_lowercase();
+    let (top_level, subtype) = declared.split_once('/').unwrap_or((&declared, "*"));
+    MimeType::iter()
+        .find(|mime_type| {
+            let name = mime_type.to_string();
+            let Some((top, sub)) = name.split_once('/') else {
+                return false;
+            };
+            (top_level == "*" || top_level == top) && (subtype == "*" || subtype == sub)
+        })
+        .unwrap_or(MimeType::Unset)
+}
+
+/// The boundary URIs of the data filters of every component.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct DataBoundaryURIs {
+    pub uris: HashMap<String, Vec<String>>,
+}
+
+impl_serdeany!(DataBoundaryURIs);
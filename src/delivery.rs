@@ -0,0 +1,94 @@
+//! Whether the intent of an execution reached the app at all.
+//!
+//! An intent the executor could not deliver, e.g. to a component that does
+//! not exist, for a PendingIntent whose notification is gone or while adb
+//! keeps failing, has no observations of its own: the coverage, log lines and
+//! results observed for it may be left over from earlier executions. The
+//! executor records the outcome in the [DeliveryObserver], and the
+//! [DeliveredFeedback] in front of the feedbacks and objectives (with
+//! `feedback_and_fast!`) keeps them from evaluating such inputs at all.
+
+use libafl::{
+    prelude::{EventFirer, ExitKind, Feedback, Named, Observer, ObserversTuple, UsesInput},
+    state::HasClientPerfMonitor,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::intent_input::IntentInput;
+
+/// Observer of whether the intent of the last execution was delivered, set
+/// by the executor.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeliveryObserver {
+    delivered: bool,
+}
+
+impl DeliveryObserver {
+    pub fn new() -> Self {
+        Self { delivered: true }
+    }
+
+    /// Whether the intent of the last execution was delivered.
+    pub fn delivered(&self) -> bool {
+        self.delivered
+    }
+
+    pub fn set_delivered(&mut self, delivered: bool) {
+        self.delivered = delivered;
+    }
+}
+
+impl Named for DeliveryObserver {
+    fn name(&self) -> &str {
+        "DeliveryObserver"
+    }
+}
+
+impl<S> Observer<S> for DeliveryObserver
+where
+    S: UsesInput<Input = IntentInput>,
+{
+    fn pre_exec(&mut self, _state: &mut S, _input: &IntentInput) -> Result<(), libafl::Error> {
+        self.delivered = true;
+        Ok(())
+    }
+}
+
+/// Feedback that is only interesting if the intent was delivered, to be
+/// combined with the actual feedbacks.
+#[derive(Debug, Default)]
+pub struct DeliveredFeedback;
+
+impl DeliveredFeedback {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Named for DeliveredFeedback {
+    fn name(&self) -> &str {
+        "DeliveredFeedback"
+    }
+}
+
+impl<S> Feedback<S> for DeliveredFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        Ok(observers
+            .match_name::<DeliveryObserver>("DeliveryObserver")
+            .map_or(true, DeliveryObserver::delivered))
+    }
+}
@@ -0,0 +1,166 @@
+//! Watchdog for the health of the device.
+//!
+//! Long campaigns slowly wear down a device: the data partition fills up,
+//! memory gets tight, the device heats up or logd stops answering. Instead of
+//! letting the executions silently start failing, the [HealthWatchdog]
+//! samples the device in a background thread, and the fuzzing loop pauses to
+//! remediate (free storage or memory, clear the logs, cool down, and as a
+//! last resort reboot) whenever a sample crosses the [HealthThresholds].
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{adb_device::AdbDevice, campaign_stats, notifier};
+
+/// How long to wait between samples while the device cools down.
+const COOL_DOWN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Maximum number of cool down intervals before rebooting.
+const MAX_COOL_DOWN_INTERVALS: u32 = 20;
+
+/// Thresholds below (or above) which the device is unhealthy.
+#[derive(Debug, Clone)]
+pub struct HealthThresholds {
+    pub min_free_storage_mb: u64,
+    pub min_available_memory_mb: u64,
+    /// Highest acceptable thermal status (0 = none, 6 = shutdown).
+    pub max_thermal_status: u32,
+}
+
+/// A health problem of the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthProblem {
+    LowStorage,
+    LowMemory,
+    Overheating,
+    LogcatBroken,
+}
+
+impl fmt::Display for HealthProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HealthProblem::LowStorage => write!(f, "low storage"),
+            HealthProblem::LowMemory => write!(f, "low memory"),
+            HealthProblem::Overheating => write!(f, "overheating"),
+            HealthProblem::LogcatBroken => write!(f, "logcat not responding"),
+        }
+    }
+}
+
+/// Samples the device and returns its health problems. Values the device
+/// doesn't report are not considered a problem.
+fn check(adb_device: &AdbDevice, thresholds: &HealthThresholds) -> Vec<HealthProblem> {
+    let mut problems = Vec::new();
+
+    if let Ok(free) = adb_device.free_storage_mb() {
+        if free < thresholds.min_free_storage_mb {
+            problems.push(HealthProblem::LowStorage);
+        }
+    }
+    if let Ok(available) = adb_device.available_memory_mb() {
+        if available < thresholds.min_available_memory_mb {
+            problems.push(HealthProblem::LowMemory);
+        }
+    }
+    if let Ok(status) = adb_device.thermal_status() {
+        if status > thresholds.max_thermal_status {
+            problems.push(HealthProblem::Overheating);
+        }
+    }
+    if !adb_device.logcat_healthy() {
+        problems.push(HealthProblem::LogcatBroken);
+    }
+
+    problems
+}
+
+/// Background sampler of the device health.
+#[derive(Debug)]
+pub struct HealthWatchdog {
+    adb_device: AdbDevice,
+    app_name: String,
+    thresholds: HealthThresholds,
+    problems: Arc<Mutex<Vec<HealthProblem>>>,
+}
+
+impl HealthWatchdog {
+    /// Starts sampling the device at the given interval in a background
+    /// thread.
+    pub fn start(
+        adb_device: AdbDevice,
+        app_name: String,
+        interval: Duration,
+        thresholds: HealthThresholds,
+    ) -> Self {
+        let problems = Arc::new(Mutex::new(Vec::new()));
+
+        let sampler_device = adb_device.clone();
+        let sampler_problems = problems.clone();
+        let sampler_thresholds = thresholds.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            let found = check(&sampler_device, &sampler_thresholds);
+            *sampler_problems.lock().unwrap() = found;
+        });
+
+        Self {
+            adb_device,
+            app_name,
+            thresholds,
+            problems,
+        }
+    }
+
+    /// Remediates the problems found by the last sample, if any, and restarts
+    /// the app afterwards. Blocks the fuzzing loop while doing so.
+    pub fn remediate_if_needed(&self) {
+        let adb_device = &self.adb_device;
+        let problems = std::mem::take(&mut *self.problems.lock().unwrap());
+        if problems.is_empty() {
+            return;
+        }
+
+        println!("Device unhealthy ({:?}), pausing to remediate", problems);
+        campaign_stats::count(&campaign_stats::HEALTH_REMEDIATIONS);
+        notifier::notify_device_failure("remediating", &format!("{:?}", problems));
+
+        for problem in &problems {
+            let result = match problem {
+                HealthProblem::LowStorage => adb_device.free_storage(),
+                HealthProblem::LowMemory => adb_device.kill_background_processes(),
+                HealthProblem::LogcatBroken => adb_device.clear_logcat(),
+                HealthProblem::Overheating => {
+                    self.cool_down(adb_device);
+                    Ok(())
+                }
+            };
+            if let Err(err) = result {
+                println!("Failed to remediate {}: {:?}", problem, err);
+            }
+        }
+
+        let remaining = check(adb_device, &self.thresholds);
+        if !remaining.is_empty() {
+            println!("Device still unhealthy ({:?}), rebooting", remaining);
+            adb_device.reboot_device();
+        }
+
+        adb_device.restart_app(&self.app_name);
+    }
+
+    /// Waits until the device is no longer overheating.
+    fn cool_down(&self, adb_device: &AdbDevice) {
+        for _ in 0..MAX_COOL_DOWN_INTERVALS {
+            match adb_device.thermal_status() {
+                Ok(status) if status > self.thresholds.max_thermal_status => {
+                    println!("Thermal status {}, cooling down", status);
+                    std::thread::sleep(COOL_DOWN_INTERVAL);
+                }
+                _ => return,
+            }
+        }
+    }
+}
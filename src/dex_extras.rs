@@ -0,0 +1,368 @@
+//! Extras keys mined from the dex code of the APK.
+//!
+//! Listing the extras keys of the templates by hand is the bottleneck for
+//! reaching the code guarded by extras. [mine_extras_keys] scans the methods
+//! of every dex file of the APK for the string constants passed as key to the
+//! typed extra getters of Intent (e.g. `getStringExtra`), to `hasExtra`, and
+//! to the getters of the Bundle returned by `getExtras`, and types each key
+//! by its getter. The scan is linear and only follows the registers within a
+//! method, which is how d8 emits `intent.getStringExtra("key")`.
+
+use std::{collections::HashMap, fs::File, io::Read, path::Path};
+
+use dex::{Dex, DexReader};
+
+const INTENT_CLASS: &str = "Landroid/content/Intent;";
+const BUNDLE_CLASSES: [&str; 2] = ["Landroid/os/Bundle;", "Landroid/os/BaseBundle;"];
+
+/// The extra types of the getters, as in the templates, by the type in the
+/// getter name.
+const GETTER_TYPES: [(&str, &str); 15] = [
+    ("Boolean", "Boolean"),
+    ("Int", "Int"),
+    ("Long", "Long"),
+    ("Float", "Float"),
+    ("String", "String"),
+    ("CharSequence", "String"),
+    ("Parcelable", "URI"),
+    ("IntArray", "IntArray"),
+    ("IntegerArrayList", "IntArrayList"),
+    ("LongArray", "LongArray"),
+    ("FloatArray", "FloatArray"),
+    ("StringArray", "StringArray"),
+    ("StringArrayList", "StringArrayList"),
+    ("CharSequenceArray", "StringArray"),
+    ("CharSequenceArrayList", "StringArrayList"),
+];
+
+/// The type of the keys only checked for presence, which any value passes.
+const PRESENCE_TYPE: &str = "String";
+
+/// What a called method of Intent or Bundle does with its arguments.
+#[derive(Clone, Copy)]
+enum Call {
+    /// An Intent getter (with the extra type) or presence check (None)
+    /// taking the key.
+    IntentKey(Option<&'static str>),
+    /// A Bundle getter or presence check taking the key.
+    BundleKey(Option<&'static str>),
+    /// `Intent.getExtras`, whose result is the extras Bundle.
+    GetExtras,
+}
+
+/// What is known about the content of a register.
+#[derive(Clone, Copy, PartialEq)]
+enum Value {
+    String(u32),
+    ExtrasBundle,
+}
+
+/// Returns the extras keys the code of the APK reads, with their types.
+pub fn mine_extras_keys(apk: &Path) -> Result<HashMap<String, String>, libafl::Error> {
+    let mut archive = zip::ZipArchive::new(File::open(apk)?).map_err(|err| {
+        libafl::Error::illegal_argument(format!("Failed to open the APK: {}", err))
+    })?;
+
+    let mut keys = HashMap::new();
+    for index in 0..archive.len() {
+        let mut file = archive
+            .by_index(index)
+            .map_err(|err| libafl::Error::unknown(format!("Failed to read the APK: {}", err)))?;
+        if !(file.name().starts_with("classes") && file.name().ends_with(".dex")) {
+            continue;
+        }
+        let name = file.name().to_owned();
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+
+        match DexReader::from_vec(buffer) {
+            Ok(dex) => mine_dex(&dex, &mut keys),
+            Err(err) => println!("Skipping {} of the APK: {}", name, err),
+        }
+    }
+    Ok(keys)
+}
+
+/// Adds the extras keys the code of the dex file reads. The type of a getter
+/// wins over a presence check.
+fn mine_dex(dex: &Dex<Vec<u8>>, keys: &mut HashMap<String, String>) {
+    let calls = relevant_calls(dex);
+    if calls.is_empty() {
+        return;
+    }
+
+    for class in dex.classes().flatten() {
+        for method in class.methods() {
+            let Some(code) = method.code() else {
+                continue;
+            };
+            for (string_id, extra_type) in keys_of_method(code.insns(), &calls) {
+                let Ok(key) = dex.get_string(string_id) else {
+                    continue;
+                };
+                if key.is_empty() {
+                    continue;
+                }
+                match extra_type {
+                    Some(extra_type) => {
+                        keys.insert(key.to_string(), extra_type.to_owned());
+                    }
+                    None => {
+                        keys.entry(key.to_string())
+                            .or_insert_with(|| PRESENCE_TYPE.to_owned());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The methods of Intent and Bundle the dex file calls that take an extras
+/// key or return the extras, by method id.
+fn relevant_calls(dex: &Dex<Vec<u8>>) -> HashMap<u32, Call> {
+    let mut calls = HashMap::new();
+    for (method_id, item) in dex.method_ids().enumerate() {
+        let Ok(item) = item else {
+            continue;
+        };
+        let Ok(class) = dex.get_type(u32::from(item.class_idx())) else {
+            continue;
+        };
+        let class = class.type_descriptor().to_string();
+        let is_intent = class == INTENT_CLASS;
+        if !is_intent && !BUNDLE_CLASSES.contains(&class.as_str()) {
+            continue;
+        }
+        let Ok(name) = dex.get_string(item.name_idx()) else {
+            continue;
+        };
+
+        let call = if is_intent && name == "getExtras" {
+            Some(Call::GetExtras)
+        } else if is_intent {
+            getter_type(&name, "get", "Extra").map(Call::IntentKey)
+        } else {
+            getter_type(&name, "get", "").map(Call::BundleKey)
+        };
+        if let Some(call) = call {
+            calls.insert(method_id as u32, call);
+        }
+    }
+    calls
+}
+
+/// Whether the method of the given name is a presence check (None) or a
+/// getter of an extra type the mutators can create, e.g. `getStringExtra`
+/// with the prefix `get` and the suffix `Extra`.
+fn getter_type(name: &str, prefix: &str, suffix: &str) -> Option<Option<&'static str>> {
+    if name == "hasExtra" || name == "containsKey" {
+        return Some(None);
+    }
+    let getter = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    GETTER_TYPES
+        .iter()
+        .find(|(name, _)| *name == getter)
+        .map(|(_, extra_type)| Some(*extra_type))
+}
+
+/// The string ids of the keys the given method code passes to the relevant
+/// calls, with the extra types of the calls.
+fn keys_of_method(insns: &[u16], calls: &HashMap<u32, Call>) -> Vec<(u32, Option<&'static str>)> {
+    let mut keys = Vec::new();
+    let mut registers: HashMap<u16, Value> = HashMap::new();
+    let mut last_result = None;
+
+    let mut pc = 0;
+    while pc < insns.len() {
+        let unit = insns[pc];
+        let opcode = (unit & 0xff) as u8;
+        let length = instruction_length(insns, pc);
+        let operand = |offset: usize| insns.get(pc + offset).copied().unwrap_or_default();
+        let result = last_result.take();
+
+        match opcode {
+            // const-string, const-string/jumbo
+            0x1a => {
+                registers.insert(unit >> 8, Value::String(u32::from(operand(1))));
+            }
+            0x1b => {
+                let string_id = u32::from(operand(1)) | u32::from(operand(2)) << 16;
+                registers.insert(unit >> 8, Value::String(string_id));
+            }
+            // move-object, move-object/from16, move-object/16
+            0x07 | 0x08 | 0x09 => {
+                let (dest, src) = match opcode {
+                    0x07 => ((unit >> 8) & 0xf, unit >> 12),
+                    0x08 => (unit >> 8, operand(1)),
+                    _ => (operand(1), operand(2)),
+                };
+                match registers.get(&src).copied() {
+                    Some(value) => registers.insert(dest, value),
+                    None => registers.remove(&dest),
+                };
+            }
+            // move-result-object
+            0x0c => {
+                match result {
+                    Some(value) => registers.insert(unit >> 8, value),
+                    None => registers.remove(&(unit >> 8)),
+                };
+            }
+            // invoke-kind, invoke-kind/range
+            0x6e..=0x72 | 0x74..=0x78 => {
+                let args: Vec<u16> = if opcode <= 0x72 {
+                    let packed = operand(2);
+                    (0..(unit >> 12).min(5))
+                        .map(|i| match i {
+                            4 => (unit >> 8) & 0xf,
+                            _ => (packed >> (4 * i)) & 0xf,
+                        })
+                        .collect()
+                } else {
+                    (operand(2)..operand(2).saturating_add(unit >> 8)).collect()
+                };
+                let receiver = args.first().and_then(|r| registers.get(r));
+                let key = args.get(1).and_then(|r| registers.get(r));
+                match (calls.get(&u32::from(operand(1))), receiver, key) {
+                    (Some(Call::GetExtras), _, _) => last_result = Some(Value::ExtrasBundle),
+                    (Some(Call::IntentKey(extra_type)), _, Some(Value::String(string_id)))
+                    | (
+                        Some(Call::BundleKey(extra_type)),
+                        Some(Value::ExtrasBundle),
+                        Some(Value::String(string_id)),
+                    ) => keys.push((*string_id, *extra_type)),
+                    _ => {}
+                }
+            }
+            _ => {
+                if let Some(dest) = destination(opcode, unit, operand(1)) {
+                    registers.remove(&dest);
+                }
+            }
+        }
+
+        pc += length;
+    }
+    keys
+}
+
+/// The register the instruction writes, for the instructions that write one.
+fn destination(opcode: u8, unit: u16, operand: u16) -> Option<u16> {
+    let a4 = (unit >> 8) & 0xf;
+    let aa = unit >> 8;
+    match opcode {
+        // move, move-wide, unary operations, array-length, binary operations
+        // on two addresses
+        0x01 | 0x04 | 0x21 | 0x7b..=0x8f | 0xb0..=0xcf => Some(a4),
+        // const/4
+        0x12 => Some(a4),
+        // instance-of, new-array, iget, binary operations with a literal
+        0x20 | 0x23 | 0x52..=0x58 | 0xd0..=0xe2 => Some(a4),
+        // move/from16, move-wide/from16, move-result, move-exception, const,
+        // const-class, new-instance, cmp, aget, sget, binary operations,
+        // const-method-handle, const-method-type
+        0x02
+        | 0x05
+        | 0x0a..=0x0d
+        | 0x13..=0x19
+        | 0x1c
+        | 0x22
+        | 0x2d..=0x31
+        | 0x44..=0x4a
+        | 0x60..=0x66
+        | 0x90..=0xaf
+        | 0xfe
+        | 0xff => Some(aa),
+        // move/16, move-wide/16
+        0x03 | 0x06 => Some(operand),
+        _ => None,
+    }
+}
+
+/// The length of the instruction at `pc` in code units, including the
+/// payloads of the switch and array instructions.
+fn instruction_length(insns: &[u16], pc: usize) -> usize {
+    let unit = insns[pc];
+    let word = |offset: usize| insns.get(pc + offset).copied().unwrap_or_default() as usize;
+    match unit {
+        // packed-switch-payload
+        0x0100 => word(1) * 2 + 4,
+        // sparse-switch-payload
+        0x0200 => word(1) * 4 + 2,
+        // fill-array-data-payload
+        0x0300 => (word(1) * (word(2) | word(3) << 16) + 1) / 2 + 4,
+        _ => match (unit & 0xff) as u8 {
+            0x00..=0x01 | 0x04 | 0x07 | 0x0a..=0x12 | 0x1d | 0x1e | 0x21 | 0x27 | 0x28 => 1,
+            0x3e..=0x43 | 0x73 | 0x79 | 0x7a | 0x7b..=0x8f | 0xb0..=0xcf | 0xe3..=0xf9 => 1,
+            0x02 | 0x05 | 0x08 | 0x13 | 0x15 | 0x16 | 0x19 | 0x1a | 0x1c => 2,
+            0x1f | 0x20 | 0x22 | 0x23 | 0x29 | 0x2d..=0x3d | 0x44..=0x6d => 2,
+            0x90..=0xaf | 0xd0..=0xe2 | 0xfe | 0xff => 2,
+            0x03 | 0x06 | 0x09 | 0x14 | 0x17 | 0x1b | 0x24..=0x26 | 0x2a..=0x2c => 3,
+            0x6e..=0x72 | 0x74..=0x78 | 0xfc | 0xfd => 3,
+            0xfa | 0xfb => 4,
+            0x18 => 5,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Method ids of the calls in the hand-assembled code.
+    const GET_STRING_EXTRA: u32 = 5;
+    const GET_EXTRAS: u32 = 6;
+    const BUNDLE_GET_INT: u32 = 7;
+
+    fn calls() -> HashMap<u32, Call> {
+        HashMap::from([
+            (GET_STRING_EXTRA, Call::IntentKey(Some("String"))),
+            (GET_EXTRAS, Call::GetExtras),
+            (BUNDLE_GET_INT, Call::BundleKey(Some("Int"))),
+        ])
+    }
+
+    #[test]
+    fn const_string_passed_to_intent_getter() {
+        let insns = [
+            0x011a, 0x0003, // const-string v1, string@3
+            0x206e, 0x0005, 0x0010, // invoke-virtual {v0, v1}, getStringExtra
+        ];
+        assert_eq!(keys_of_method(&insns, &calls()), [(3, Some("String"))]);
+    }
+
+    #[test]
+    fn const_string_passed_to_getter_of_extras_bundle() {
+        let insns = [
+            0x106e, 0x0006, 0x0000, // invoke-virtual {v0}, getExtras
+            0x020c, // move-result-object v2
+            0x031a, 0x0004, // const-string v3, string@4
+            0x206e, 0x0007, 0x0032, // invoke-virtual {v2, v3}, Bundle.getInt
+            0x206e, 0x0007, 0x0035, // invoke-virtual {v5, v3}, Bundle.getInt
+        ];
+        // Only the Bundle returned by getExtras holds the extras.
+        assert_eq!(keys_of_method(&insns, &calls()), [(4, Some("Int"))]);
+    }
+
+    #[test]
+    fn const_string_passed_to_range_invoke() {
+        let insns = [
+            0x041a, 0x0008, // const-string v4, string@8
+            0x0274, 0x0005, 0x0003, // invoke-virtual/range {v3 .. v4}, getStringExtra
+        ];
+        assert_eq!(keys_of_method(&insns, &calls()), [(8, Some("String"))]);
+    }
+
+    #[test]
+    fn skips_switch_payload() {
+        let insns = [
+            0x011a, 0x0009, // const-string v1, string@9
+            0x022b, 0x0004, 0x0000, // packed-switch v2, +4
+            0x0928, // goto +9
+            0x0100, 0x0002, 0x0000, 0x0000, // packed-switch-payload of two targets,
+            0x0112, 0x0000, 0x0112, 0x0000, // which would overwrite v1 if read as code
+            0x206e, 0x0005, 0x0010, // invoke-virtual {v0, v1}, getStringExtra
+        ];
+        assert_eq!(keys_of_method(&insns, &calls()), [(9, Some("String"))]);
+    }
+}
@@ -0,0 +1,145 @@
+//! Retention policies for the campaign outputs on the host disk.
+//!
+//! Multi-week campaigns slowly fill the host disk with the spilled corpus, the
+//! native trace files and the crash bundles (bugreports and heap dumps). The
+//! [DiskRetention] periodically enforces a [RetentionPolicy]: it caps the
+//! number of kept crash bundles by deleting the oldest ones, and tells the
+//! fuzzing loop to stop once the corpus outgrew its budget, as corpus entries
+//! cannot be deleted from under libAFL.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Limits on the outputs kept on disk.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Size (in MiB) of the corpus directory at which the campaign stops.
+    pub max_corpus_size_mb: Option<u64>,
+    /// Number of crash bundles to keep per directory, the oldest ones are
+    /// deleted first.
+    pub max_crash_bundles: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct DiskRetention {
+    policy: RetentionPolicy,
+    corpus_dir: PathBuf,
+    /// Directories holding the crash bundles, with the file name suffix of
+    /// the bundles in them.
+    bundle_dirs: Vec<(PathBuf, &'static str)>,
+    interval: Duration,
+    last_check: Instant,
+}
+
+impl DiskRetention {
+    pub fn new(policy: RetentionPolicy, corpus_dir: PathBuf, interval: Duration) -> Self {
+        Self {
+            policy,
+            corpus_dir,
+            bundle_dirs: Vec::new(),
+            interval,
+            last_check: Instant::now(),
+        }
+    }
+
+    /// Also caps the files ending in the suffix in the given directory.
+    pub fn with_bundle_dir(mut self, dir: PathBuf, suffix: &'static str) -> Self {
+        self.bundle_dirs.push((dir, suffix));
+        self
+    }
+
+    /// Enforces the policy if the check interval passed since the last check.
+    /// Returns whether the corpus exceeds its budget and the campaign should
+    /// stop.
+    pub fn maybe_enforce(&mut self) -> bool {
+        if self.last_check.elapsed() < self.interval {
+            return false;
+        }
+        self.last_check = Instant::now();
+
+        if let Some(max_bundles) = self.policy.max_crash_bundles {
+            for (dir, suffix) in &self.bundle_dirs {
+                if let Err(err) = prune_oldest(dir, suffix, max_bundles) {
+                    println!("Failed to prune crash bundles in {:?}: {}", dir, err);
+                }
+            }
+        }
+
+        let Some(max_corpus_size_mb) = self.policy.max_corpus_size_mb else {
+            return false;
+        };
+        let corpus_size_mb = dir_size(&self.corpus_dir).unwrap_or(0) / (1024 * 1024);
+        if corpus_size_mb >= max_corpus_size_mb {
+            println!(
+                "Corpus uses {} MiB on disk (limit {} MiB), stopping the campaign",
+                corpus_size_mb, max_corpus_size_mb
+            );
+            return true;
+        }
+        false
+    }
+}
+
+/// Total size in bytes of the files in the directory.
+fn dir_size(dir: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let metadata = entry?.metadata()?;
+        if metadata.is_file() {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+/// Deletes the oldest files ending in the suffix until at most `keep` are
+/// left.
+fn prune_oldest(dir: &Path, suffix: &str, keep: usize) -> io::Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    let mut bundles = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().ends_with(suffix) {
+            let modified = entry
+                .metadata()?
+                .modified()
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            bundles.push((modified, entry.path()));
+        }
+    }
+    if bundles.len() <= keep {
+        return Ok(());
+    }
+
+    bundles.sort();
+    let excess = bundles.len() - keep;
+    for (_, path) in bundles.into_iter().take(excess) {
+        println!("Deleting old crash bundle {:?}", path);
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Compresses the (not yet compressed) trace files in the directory with the
+/// host's gzip.
+pub fn compress_traces(traces_dir: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(traces_dir)? {
+        let path = entry?.path();
+        if !path.is_file() || path.extension().map_or(false, |ext| ext == "gz") {
+            continue;
+        }
+
+        let status = Command::new("gzip").arg("-f").arg(&path).status()?;
+        if !status.success() {
+            println!("Failed to compress trace file {:?}", path);
+        }
+    }
+    Ok(())
+}
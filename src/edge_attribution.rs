@@ -0,0 +1,103 @@
+//! Attribution of newly covered edges to corpus entries.
+//!
+//! For every input added to the corpus, the indices of the edges it covered
+//! for the first time in the campaign are written to `<dir>/<hash>.json`, so
+//! the edges can later be mapped back to the input (and e.g. the extra key)
+//! that unlocked them. With a coverage mapping, their source locations are
+//! written as well.
+
+use std::path::PathBuf;
+
+use libafl::{
+    prelude::{EventFirer, ExitKind, Feedback, Named, ObserversTuple, Testcase, UsesInput},
+    state::HasClientPerfMonitor,
+};
+use serde::Serialize;
+
+use crate::{intent_input::IntentInput, socket_coverage_observer::SocketCoverageObserver};
+
+/// The record written for a corpus entry.
+#[derive(Serialize, Debug)]
+struct EdgeAttribution<'a> {
+    input: String,
+    component: String,
+    new_edges: &'a [usize],
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    new_locations: Vec<String>,
+}
+
+/// Feedback that never marks an input as interesting, but writes the edges
+/// first covered by every input added to the corpus. Does nothing without a
+/// directory.
+#[derive(Debug)]
+pub struct EdgeAttributionFeedback {
+    dir: Option<PathBuf>,
+}
+
+impl EdgeAttributionFeedback {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        if let Some(dir) = &dir {
+            std::fs::create_dir_all(dir).expect("Failed to create edge attribution directory");
+        }
+        Self { dir }
+    }
+}
+
+impl Named for EdgeAttributionFeedback {
+    fn name(&self) -> &str {
+        "EdgeAttributionFeedback"
+    }
+}
+
+impl<S> Feedback<S> for EdgeAttributionFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        Ok(false)
+    }
+
+    fn append_metadata<OT>(
+        &mut self,
+        _state: &mut S,
+        observers: &OT,
+        testcase: &mut Testcase<IntentInput>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        let (Some(dir), Some(input)) = (&self.dir, testcase.input()) else {
+            return Ok(());
+        };
+        let Some(observer) =
+            observers.match_name::<SocketCoverageObserver>("SocketCoverageObserver")
+        else {
+            return Ok(());
+        };
+        if observer.new_edges().is_empty() {
+            return Ok(());
+        }
+
+        let attribution = EdgeAttribution {
+            input: input.hash(),
+            component: input.component(),
+            new_edges: observer.new_edges(),
+            new_locations: observer.new_locations(),
+        };
+        let path = dir.join(format!("{}.json", attribution.input));
+        std::fs::write(path, serde_json::to_string(&attribution).unwrap())?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+//! Coverage and crash contribution of the entry points of the app.
+//!
+//! The [EntryPointFeedback] aggregates, per intent action and per category,
+//! how many corpus entries and solutions the inputs targeting them produced
+//! and how many edges these covered for the first time. The
+//! [EntryPointReport] periodically prints the breakdown and reports it as
+//! user stats, revealing which entry points are productive and which are
+//! dead weight.
+
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    time::{Duration, Instant},
+};
+
+use libafl::{
+    impl_serdeany,
+    prelude::{
+        Event, EventFirer, ExitKind, Feedback, Named, ObserversTuple, Testcase, UserStats,
+        UsesInput,
+    },
+    state::{HasClientPerfMonitor, HasMetadata},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{intent_input::IntentInput, socket_coverage_observer::SocketCoverageObserver};
+
+/// What the inputs targeting an entry point contributed.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Contribution {
+    pub corpus_entries: u64,
+    pub new_edges: u64,
+    pub crashes: u64,
+}
+
+/// The contribution of every action and category.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct EntryPointStats {
+    pub actions: HashMap<String, Contribution>,
+    pub categories: HashMap<String, Contribution>,
+}
+
+impl_serdeany!(EntryPointStats);
+
+impl EntryPointStats {
+    fn record(&mut self, input: &IntentInput, new_edges: usize, crash: bool) {
+        for contribution in [
+            self.actions.entry(input.action.clone()).or_default(),
+            self.categories.entry(input.category.clone()).or_default(),
+        ] {
+            if crash {
+                contribution.crashes += 1;
+            } else {
+                contribution.corpus_entries += 1;
+                contribution.new_edges += new_edges as u64;
+            }
+        }
+    }
+}
+
+/// Feedback that never marks an input as interesting, but accounts every
+/// input added to the corpus (or, as an objective, to the solutions) to its
+/// action and category.
+#[derive(Debug)]
+pub struct EntryPointFeedback {
+    objective: bool,
+}
+
+impl EntryPointFeedback {
+    pub fn new(objective: bool) -> Self {
+        Self { objective }
+    }
+}
+
+impl Named for EntryPointFeedback {
+    fn name(&self) -> &str {
+        "EntryPointFeedback"
+    }
+}
+
+impl<S> Feedback<S> for EntryPointFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor + HasMetadata,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        Ok(false)
+    }
+
+    fn append_metadata<OT>(
+        &mut self,
+        state: &mut S,
+        observers: &OT,
+        testcase: &mut Testcase<IntentInput>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        let Some(input) = testcase.input() else {
+            return Ok(());
+        };
+        let new_edges = observers
+            .match_name::<SocketCoverageObserver>("SocketCoverageObserver")
+            .map_or(0, |observer| observer.new_edges().len());
+
+        if !state.has_metadata::<EntryPointStats>() {
+            state.add_metadata(EntryPointStats::default());
+        }
+        state
+            .metadata_mut::<EntryPointStats>()?
+            .record(input, new_edges, self.objective);
+        Ok(())
+    }
+}
+
+/// Periodically prints the contribution of the entry points and reports it
+/// to the monitor as user stats.
+#[derive(Debug)]
+pub struct EntryPointReport {
+    interval: Duration,
+    last_report: Instant,
+}
+
+impl EntryPointReport {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_report: Instant::now(),
+        }
+    }
+
+    /// Prints and reports the breakdown if the report interval passed since
+    /// the last report.
+    pub fn maybe_report<EM, S>(
+        &mut self,
+        manager: &mut EM,
+        state: &mut S,
+    ) -> Result<(), libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        S: UsesInput + HasMetadata,
+    {
+        if self.interval.is_zero() || self.last_report.elapsed() < self.interval {
+            return Ok(());
+        }
+        self.last_report = Instant::now();
+
+        let Ok(stats) = state.metadata::<EntryPointStats>() else {
+            return Ok(());
+        };
+        let breakdowns = [
+            ("action", sorted(&stats.actions)),
+            ("category", sorted(&stats.categories)),
+        ];
+        for (kind, contributions) in breakdowns {
+            print_breakdown(kind, &contributions);
+            manager.fire(
+                state,
+                Event::UpdateUserStats {
+                    name: format!("entry_points_by_{}", kind),
+                    value: UserStats::String(summary(&contributions)),
+                    phantom: PhantomData,
+                },
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The contributions with their names, the most productive first.
+fn sorted(contributions: &HashMap<String, Contribution>) -> Vec<(String, Contribution)> {
+    let mut contributions: Vec<_> = contributions
+        .iter()
+        .map(|(name, contribution)| {
+            let name = if name.is_empty() { "(none)" } else { name };
+            (name.to_owned(), contribution.clone())
+        })
+        .collect();
+    contributions.sort_by(|(a_name, a), (b_name, b)| {
+        (b.new_edges, b.crashes, a_name).cmp(&(a.new_edges, a.crashes, b_name))
+    });
+    contributions
+}
+
+/// Prints the contributions.
+fn print_breakdown(kind: &str, contributions: &[(String, Contribution)]) {
+    println!(
+        "Contribution by {} (corpus entries, new edges, crashes):",
+        kind
+    );
+    for (name, contribution) in contributions {
+        println!(
+            "  {}: {}, {}, {}",
+            name, contribution.corpus_entries, contribution.new_edges, contribution.crashes
+        );
+    }
+}
+
+/// The contributions on one line for the monitor, e.g.
+/// `VIEW: 3, 120, 0; SEND: 1, 4, 1`.
+fn summary(contributions: &[(String, Contribution)]) -> String {
+    contributions
+        .iter()
+        .map(|(name, contribution)| {
+            format!(
+                "{}: {}, {}, {}",
+                name, contribution.corpus_entries, contribution.new_edges, contribution.crashes
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
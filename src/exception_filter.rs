@@ -0,0 +1,137 @@
+//! Allow and deny lists for the exceptions of solutions.
+//!
+//! A known crasher or a noisy third-party SDK can dominate the solutions of a
+//! campaign. The [ExceptionFilterFeedback] is combined with the objective and
+//! rejects crashes whose exception signature (as in the crash layout, e.g.
+//! `java.lang.NullPointerException at com.example.Foo.bar(Foo.java:12)`)
+//! contains one of the ignored patterns, and classifies the ones containing an
+//! escalated pattern as [Severity::Escalated].
+
+use libafl::{
+    prelude::{
+        EventFirer, ExitKind, Feedback, HasMetadata, Named, ObserversTuple, Testcase, UsesInput,
+    },
+    state::HasClientPerfMonitor,
+};
+
+use crate::{
+    adb_device::AdbDevice,
+    crash_layout::{execution_crash_log, java_exception_signature},
+    intent_input::IntentInput,
+    socket_coverage_observer::SocketCoverageObserver,
+    triage::Severity,
+};
+
+/// Feedback rejecting the solutions with an ignored exception. It belongs
+/// first in a `feedback_and_fast!` with the objective, so an ignored
+/// exception short-circuits the other feedbacks before they record it, and
+/// the escalation is in place before they classify the solution.
+#[derive(Debug)]
+pub struct ExceptionFilterFeedback {
+    adb_device: AdbDevice,
+    app_name: String,
+    ignored: Vec<String>,
+    escalated: Vec<String>,
+    escalate_last: bool,
+}
+
+impl ExceptionFilterFeedback {
+    pub fn new(
+        adb_device: AdbDevice,
+        app_name: String,
+        ignored: Vec<String>,
+        escalated: Vec<String>,
+    ) -> Self {
+        Self {
+            adb_device,
+            app_name,
+            ignored,
+            escalated,
+            escalate_last: false,
+        }
+    }
+}
+
+impl Named for ExceptionFilterFeedback {
+    fn name(&self) -> &str {
+        "ExceptionFilterFeedback"
+    }
+}
+
+impl<S> Feedback<S> for ExceptionFilterFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.escalate_last = false;
+        if self.ignored.is_empty() && self.escalated.is_empty() {
+            return Ok(true);
+        }
+
+        // Prefer the exception reported by the agent, and only search the
+        // crash log after a crash.
+        let signature = observers
+            .match_name::<SocketCoverageObserver>("SocketCoverageObserver")
+            .and_then(|observer| {
+                observer
+                    .exception_reports()
+                    .iter()
+                    .find(|report| report.uncaught)
+            })
+            .map(|report| report.signature())
+            .or_else(|| {
+                (*exit_kind == ExitKind::Crash)
+                    .then(|| execution_crash_log(&self.adb_device, observers))
+                    .flatten()
+                    .and_then(|log| java_exception_signature(&log, &self.app_name))
+            });
+        let Some(signature) = signature else {
+            return Ok(true);
+        };
+
+        if self.ignored.iter().any(|p| signature.contains(p.as_str())) {
+            println!("Ignoring the exception {}", signature);
+            return Ok(false);
+        }
+        self.escalate_last = self
+            .escalated
+            .iter()
+            .any(|p| signature.contains(p.as_str()));
+        Ok(true)
+    }
+
+    fn append_metadata<OT>(
+        &mut self,
+        _state: &mut S,
+        _observers: &OT,
+        testcase: &mut Testcase<IntentInput>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        if std::mem::take(&mut self.escalate_last) {
+            testcase.add_metadata(Severity::Escalated);
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(
+        &mut self,
+        _state: &mut S,
+        _input: &IntentInput,
+    ) -> Result<(), libafl::Error> {
+        self.escalate_last = false;
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+//! Exceptions reported by the coverage agent.
+//!
+//! Finding the exception of a crash in logcat is slow and can pick up
+//! exceptions of other processes or earlier executions. Agents with the
+//! `exceptions` capability instead report the uncaught and logged exceptions
+//! of the app process during every execution over the coverage socket. The
+//! [ExceptionReportFeedback] makes inputs with a new uncaught exception
+//! solutions and attaches the [ExceptionReport] to them, which the crash
+//! layout buckets them by. The [ExceptionNoveltyFeedback] keeps inputs
+//! throwing an exception with a new signature in the corpus, even if the app
+//! handled it, as the error handling paths they reach often don't show up as
+//! new coverage.
+
+use std::collections::HashSet;
+
+use libafl::{
+    impl_serdeany,
+    prelude::{
+        EventFirer, ExitKind, Feedback, HasMetadata, Named, ObserversTuple, Testcase, UsesInput,
+    },
+    state::{HasClientPerfMonitor, HasNamedMetadata},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{intent_input::IntentInput, socket_coverage_observer::SocketCoverageObserver};
+
+/// Number of frames kept of every exception.
+const MAX_FRAMES: usize = 10;
+
+/// An exception the agent saw in the app process during an execution.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ExceptionReport {
+    /// The pid of the app process the exception was thrown in.
+    pub pid: u32,
+    /// Whether the exception was uncaught, rather than only logged.
+    pub uncaught: bool,
+    /// The class name of the exception, e.g. `java.lang.NullPointerException`.
+    pub class_name: String,
+    /// The top frames, e.g. `com.example.Foo.bar(Foo.java:12)`.
+    pub frames: Vec<String>,
+}
+
+impl_serdeany!(ExceptionReport);
+
+impl ExceptionReport {
+    /// The class name and top frame, in the same format as the signatures
+    /// parsed from the crash log.
+    pub fn signature(&self) -> String {
+        match self.frames.first() {
+            Some(frame) => format!("{} at {}", self.class_name, frame),
+            None => self.class_name.clone(),
+        }
+    }
+}
+
+/// Parses the exception report message of the agent. Reports are separated
+/// by empty lines; the first line of a report is
+/// `<pid> <uncaught|logged> <class name>`, followed by one frame per line.
+pub fn parse_exception_reports(message: &str) -> Vec<ExceptionReport> {
+    message
+        .split("\n\n")
+        .filter_map(|report| {
+            let mut lines = report
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty());
+            let mut header = lines.next()?.split_whitespace();
+            let pid = header.next()?.parse().ok()?;
+            let uncaught = match header.next()? {
+                "uncaught" => true,
+                "logged" => false,
+                _ => return None,
+            };
+            let class_name = header.next()?.to_owned();
+            let frames = lines
+                .take(MAX_FRAMES)
+                .map(|frame| frame.strip_prefix("at ").unwrap_or(frame).to_owned())
+                .collect();
+            Some(ExceptionReport {
+                pid,
+                uncaught,
+                class_name,
+                frames,
+            })
+        })
+        .collect()
+}
+
+/// The signatures of all uncaught exceptions reported so far.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ExceptionSignatures {
+    pub signatures: HashSet<String>,
+}
+
+impl_serdeany!(ExceptionSignatures);
+
+/// Objective feedback reporting inputs whose execution threw an uncaught
+/// exception with a signature not seen before.
+#[derive(Debug, Default)]
+pub struct ExceptionReportFeedback {
+    last_report: Option<ExceptionReport>,
+}
+
+impl ExceptionReportFeedback {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Named for ExceptionReportFeedback {
+    fn name(&self) -> &str {
+        "ExceptionReportFeedback"
+    }
+}
+
+impl<S> Feedback<S> for ExceptionReportFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor + HasNamedMetadata,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let Some(observer) =
+            observers.match_name::<SocketCoverageObserver>("SocketCoverageObserver")
+        else {
+            return Ok(false);
+        };
+        let reports = observer.exception_reports();
+        // Describe a solution by its uncaught exception, or the first logged
+        // one if it was found otherwise.
+        self.last_report = reports
+            .iter()
+            .find(|report| report.uncaught)
+            .or_else(|| reports.first())
+            .cloned();
+        let Some(report) = self.last_report.as_ref().filter(|report| report.uncaught) else {
+            return Ok(false);
+        };
+
+        if !state.has_named_metadata::<ExceptionSignatures>("exception_signatures") {
+            state.add_named_metadata(ExceptionSignatures::default(), "exception_signatures");
+        }
+        let seen = state.named_metadata_mut::<ExceptionSignatures>("exception_signatures")?;
+        let signature = report.signature();
+        if !seen.signatures.insert(signature.clone()) {
+            return Ok(false);
+        }
+
+        println!("New uncaught exception (pid {}): {}", report.pid, signature);
+        Ok(true)
+    }
+
+    fn append_metadata<OT>(
+        &mut self,
+        _state: &mut S,
+        _observers: &OT,
+        testcase: &mut Testcase<IntentInput>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        if let Some(report) = self.last_report.take() {
+            testcase.add_metadata(report);
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(
+        &mut self,
+        _state: &mut S,
+        _input: &IntentInput,
+    ) -> Result<(), libafl::Error> {
+        self.last_report = None;
+        Ok(())
+    }
+}
+
+/// The signatures of all exceptions thrown by the corpus entries.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CorpusExceptionSignatures {
+    pub signatures: HashSet<String>,
+}
+
+impl_serdeany!(CorpusExceptionSignatures);
+
+/// Feedback marking inputs that throw an exception, uncaught or logged, with
+/// a signature not seen before in the corpus as interesting.
+#[derive(Debug, Default)]
+pub struct ExceptionNoveltyFeedback;
+
+impl ExceptionNoveltyFeedback {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Named for ExceptionNoveltyFeedback {
+    fn name(&self) -> &str {
+        "ExceptionNoveltyFeedback"
+    }
+}
+
+impl<S> Feedback<S> for ExceptionNoveltyFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor + HasNamedMetadata,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let Some(observer) =
+            observers.match_name::<SocketCoverageObserver>("SocketCoverageObserver")
+        else {
+            return Ok(false);
+        };
+        if observer.exception_reports().is_empty() {
+            return Ok(false);
+        }
+
+        if !state.has_named_metadata::<CorpusExceptionSignatures>("corpus_exception_signatures") {
+            state.add_named_metadata(
+                CorpusExceptionSignatures::default(),
+                "corpus_exception_signatures",
+            );
+        }
+        let seen =
+            state.named_metadata_mut::<CorpusExceptionSignatures>("corpus_exception_signatures")?;
+        let mut interesting = false;
+        for report in observer.exception_reports() {
+            let signature = report.signature();
+            if seen.signatures.insert(signature.clone()) {
+                println!("New exception signature {}", signature);
+                interesting = true;
+            }
+        }
+        Ok(interesting)
+    }
+}
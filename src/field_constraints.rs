@@ -0,0 +1,272 @@
+//! Field-level mutation constraints declared in the intent templates.
+//!
+//! Domain knowledge about a component often rules out whole classes of
+//! mutations, e.g. the component ignores every intent whose type is not
+//! `image/png`, or a debug extra short-circuits all interesting code. A
+//! template declares these in `constraints`:
+//!
+//! ```json
+//! "constraints": {
+//!     "pinned": ["action", "mime_type"],
+//!     "forbidden_extras": ["com.example.app.DEBUG"],
+//!     "allowed_values": {
+//!         "data": ["https://example.com/a", "https://example.com/b"],
+//!         "extra.com.example.app.MODE": ["list", "grid"]
+//!     }
+//! }
+//! ```
+//!
+//! Pinned fields keep the value of the mutated input, forbidden extras are
+//! never added, and the fields with allowed values only take one of them
+//! (`mime_type`, `data` and String extras as `extra.<key>`). The
+//! [ConstrainedMutator] enforces the constraints of the component of an
+//! input on every mutation of it: of the component it was mutated to, unless
+//! the component of the original input is pinned.
+
+use std::collections::HashMap;
+
+use libafl::{
+    impl_serdeany,
+    prelude::{BytesInput, CorpusId, HasBytesVec, MutationResult, Mutator, Named, Rand},
+    state::{HasNamedMetadata, HasRand},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+use crate::intent_input::{ExtraType, IntentInput, MimeType, URIInput};
+
+/// The fields of an intent that can be pinned.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IntentField {
+    Action,
+    Category,
+    Component,
+    Data,
+    MimeType,
+    Flags,
+    Extras,
+    UriGrant,
+}
+
+/// The mutation constraints of a template.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FieldConstraints {
+    /// The fields the mutators never change.
+    #[serde(default)]
+    pub pinned: Vec<IntentField>,
+    /// The keys of the extras the mutators never add.
+    #[serde(default)]
+    pub forbidden_extras: Vec<String>,
+    /// The values the mutators choose from for a field, keyed by `mime_type`,
+    /// `data` or `extra.<key>`.
+    #[serde(default)]
+    pub allowed_values: HashMap<String, Vec<String>>,
+}
+
+impl FieldConstraints {
+    /// Whether there is anything to enforce.
+    pub fn is_empty(&self) -> bool {
+        self.pinned.is_empty() && self.forbidden_extras.is_empty() && self.allowed_values.is_empty()
+    }
+
+    /// Checks that all fields with allowed values exist and that the allowed
+    /// mime types are known.
+    pub fn validate(&self) -> Result<(), libafl::Error> {
+        for (field, values) in &self.allowed_values {
+            match field.as_str() {
+                "data" => {}
+                "mime_type" => {
+                    if let Some(unknown) =
+                        values.iter().find(|value| parse_mime_type(value).is_none())
+                    {
+                        return Err(libafl::Error::illegal_argument(format!(
+                            "Unknown allowed mime type {}",
+                            unknown
+                        )));
+                    }
+                }
+                field if extra_key(field).is_some() => {}
+                field => {
+                    return Err(libafl::Error::illegal_argument(format!(
+                        "Values can only be restricted for mime_type, data and extra.<key>, not {}",
+                        field
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Changes the mutated input to satisfy the constraints, taking the
+    /// pinned fields from the original input if it is of the same component.
+    /// An input mutated to another component has no values to keep.
+    fn enforce<S>(&self, state: &mut S, original: Option<&IntentInput>, input: &mut IntentInput)
+    where
+        S: HasRand,
+    {
+        if let Some(original) = original {
+            for field in &self.pinned {
+                match field {
+                    IntentField::Action => input.action = original.action.clone(),
+                    IntentField::Category => input.category = original.category.clone(),
+                    IntentField::Component => copy_component(original, input),
+                    IntentField::Data => input.data = original.data.clone(),
+                    IntentField::MimeType => input.mime_type = original.mime_type,
+                    IntentField::Flags => input.flags = original.flags,
+                    IntentField::Extras => input.extras = original.extras.clone(),
+                    IntentField::UriGrant => input.uri_grant = original.uri_grant,
+                }
+            }
+        }
+
+        input
+            .extras
+            .retain(|extra| !self.forbidden_extras.contains(&extra.key));
+
+        for (field, values) in self.allowed_values.iter().filter(|(_, v)| !v.is_empty()) {
+            match field.as_str() {
+                "mime_type" => {
+                    if !values.contains(&input.mime_type.to_string()) {
+                        let value = state.rand_mut().choose(values);
+                        if let Some(mime_type) = parse_mime_type(value) {
+                            input.mime_type = mime_type;
+                        }
+                    }
+                }
+                "data" => {
+                    let data = input
+                        .data
+                        .as_ref()
+                        .map_or(String::new(), |data| data.raw_identifier(0));
+                    if !values.contains(&data) {
+                        input.data = match state.rand_mut().choose(values).as_str() {
+                            "" => None,
+                            value => Some(URIInput::raw(value)),
+                        };
+                    }
+                }
+                field => {
+                    let Some(key) = extra_key(field) else {
+                        continue;
+                    };
+                    for extra in input.extras.iter_mut().filter(|extra| extra.key == key) {
+                        let ExtraType::String(d_input) = &mut extra.value else {
+                            continue;
+                        };
+                        let value = String::from_utf8_lossy(d_input.buffer.bytes()).into_owned();
+                        if !values.contains(&value) {
+                            let value = state.rand_mut().choose(values);
+                            d_input.buffer = BytesInput::new(value.as_bytes().to_vec());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Sets the component of the input, and the permission it requires, to the
+/// ones of the original.
+fn copy_component(original: &IntentInput, input: &mut IntentInput) {
+    input.receiver_type = original.receiver_type;
+    input.component_package = original.component_package.clone();
+    input.component_class = original.component_class.clone();
+    input.permission = original.permission.clone();
+}
+
+/// The key of the extra an `extra.<key>` field stands for.
+fn extra_key(field: &str) -> Option<&str> {
+    field.strip_prefix("extra.").filter(|key| !key.is_empty())
+}
+
+/// The mime type with the name, the empty name standing for no type.
+fn parse_mime_type(name: &str) -> Option<MimeType> {
+    MimeType::iter().find(|mime_type| mime_type.to_string() == name)
+}
+
+/// The mutation constraints of the components of all templates.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MutationConstraints {
+    pub constraints: HashMap<String, FieldConstraints>,
+}
+
+impl_serdeany!(MutationConstraints);
+
+/// Mutator enforcing the constraints of the component of the input on the
+/// mutations of the inner mutator. Mutations the constraints undo entirely
+/// are skipped.
+pub struct ConstrainedMutator<M> {
+    inner: M,
+}
+
+impl<M> ConstrainedMutator<M> {
+    pub fn new(inner: M) -> Self {
+        Self { inner }
+    }
+}
+
+impl<M> Named for ConstrainedMutator<M> {
+    fn name(&self) -> &str {
+        "ConstrainedMutator"
+    }
+}
+
+impl<M, S> Mutator<IntentInput, S> for ConstrainedMutator<M>
+where
+    M: Mutator<IntentInput, S>,
+    S: HasRand + HasNamedMetadata,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut IntentInput,
+        stage_idx: i32,
+    ) -> Result<MutationResult, libafl::Error> {
+        let original = input.clone();
+        let result = self.inner.mutate(state, input, stage_idx)?;
+        if result != MutationResult::Mutated {
+            return Ok(result);
+        }
+
+        let constraints_of = |state: &S, component: String| {
+            state
+                .named_metadata::<MutationConstraints>("mutation_constraints")
+                .ok()
+                .and_then(|metadata| metadata.constraints.get(&component))
+                .cloned()
+        };
+
+        // A pinned component keeps the input at the one of the original.
+        let component_pinned = constraints_of(state, original.component())
+            .map_or(false, |constraints| {
+                constraints.pinned.contains(&IntentField::Component)
+            });
+        if component_pinned {
+            copy_component(&original, input);
+        }
+        let constraints = constraints_of(state, input.component());
+        if !component_pinned && constraints.is_none() {
+            return Ok(result);
+        }
+        if let Some(constraints) = constraints {
+            let same_component = input.component() == original.component();
+            constraints.enforce(state, same_component.then_some(&original), input);
+        }
+        if input.hash() == original.hash() {
+            return Ok(MutationResult::Skipped);
+        }
+        Ok(result)
+    }
+
+    fn post_exec(
+        &mut self,
+        state: &mut S,
+        stage_idx: i32,
+        corpus_idx: Option<CorpusId>,
+    ) -> Result<(), libafl::Error> {
+        self.inner.post_exec(state, stage_idx, corpus_idx)
+    }
+}
@@ -0,0 +1,119 @@
+//! Seed files for the content of URIs, per format.
+//!
+//! The magic bytes of a format only get a parser past its first check. With
+//! `--format-seeds <suffix or mime type>=<dir>`, every file in the directory
+//! becomes a seed for the format, e.g. `png=seeds/png` or
+//! `application/pdf=seeds/pdf`. When a mutator switches the suffix of a URI,
+//! [FormatSeeds] swaps in one of the seeds of the new format instead of
+//! carrying over the bytes of the previous one. The seeds are exempt from the
+//! max size of the intent data, which would cut most files short of being
+//! valid, and only capped at [MAX_SEED_SIZE].
+
+use std::{collections::HashMap, path::Path};
+
+use libafl::{
+    prelude::{BytesInput, Rand},
+    state::HasRand,
+};
+use strum::IntoEnumIterator;
+
+use crate::intent_input::{URIInput, URIScheme, URISuffix};
+
+/// The maximum size in bytes of a seed, larger files are truncated.
+const MAX_SEED_SIZE: usize = 16 * 1024 * 1024;
+
+/// The seed files of every configured format.
+#[derive(Clone, Debug, Default)]
+pub struct FormatSeeds {
+    seeds: HashMap<URISuffix, Vec<Vec<u8>>>,
+}
+
+impl FormatSeeds {
+    /// Reads the seeds of the `<suffix or mime type>=<dir>` mappings.
+    pub fn load(mappings: &[String]) -> Result<Self, libafl::Error> {
+        let mut seeds: HashMap<URISuffix, Vec<Vec<u8>>> = HashMap::new();
+        for mapping in mappings {
+            let (format, dir) = mapping.split_once('=').ok_or_else(|| {
+                libafl::Error::illegal_argument(format!(
+                    "Format seeds {} are not of the form <suffix or mime type>=<dir>",
+                    mapping
+                ))
+            })?;
+            let suffix = parse_format(format).ok_or_else(|| {
+                libafl::Error::illegal_argument(format!("Unknown URI format {}", format))
+            })?;
+
+            let files = seeds.entry(suffix).or_default();
+            for entry in std::fs::read_dir(Path::new(dir))? {
+                let path = entry?.path();
+                if path.is_file() {
+                    let mut seed = std::fs::read(&path)?;
+                    if seed.len() > MAX_SEED_SIZE {
+                        println!("Truncating seed {:?} to {} bytes", path, MAX_SEED_SIZE);
+                        seed.truncate(MAX_SEED_SIZE);
+                    }
+                    files.push(seed);
+                }
+            }
+            println!("Loaded {} seeds for {} from {}", files.len(), format, dir);
+        }
+        Ok(Self { seeds })
+    }
+
+    /// Changes the suffix of the URI like [URIInput::set_suffix], but starts
+    /// the content of the new format from one of its seeds if it has any.
+    pub fn set_suffix<S>(&self, state: &mut S, uri: &mut URIInput, suffix: URISuffix)
+    where
+        S: HasRand,
+    {
+        let switched = suffix != uri.suffix;
+        uri.set_suffix(suffix);
+        if switched {
+            self.seed_content(state, uri);
+        }
+    }
+
+    /// Replaces the staged content of the URI with a random seed of its
+    /// format. Raw URIs and formats without seeds are left as they are.
+    pub fn seed_content<S>(&self, state: &mut S, uri: &mut URIInput)
+    where
+        S: HasRand,
+    {
+        if uri.scheme == URIScheme::Other {
+            return;
+        }
+        let Some(seeds) = self.seeds.get(&uri.suffix).filter(|seeds| !seeds.is_empty()) else {
+            return;
+        };
+        uri.content = BytesInput::new(state.rand_mut().choose(seeds).clone());
+    }
+}
+
+/// The suffix of a format given as suffix (`png`, `.png`) or mime type
+/// (`image/png`).
+fn parse_format(format: &str) -> Option<URISuffix> {
+    let format = format.to_lowercase();
+    let suffix = match format.as_str() {
+        "application/pdf" => URISuffix::PDF,
+        "application/vnd.android.package-archive" => URISuffix::APK,
+        "application/xml" | "text/xml" => URISuffix::XML,
+        "audio/aac" => URISuffix::AAC,
+        "audio/midi" => URISuffix::MIDI,
+        "audio/mpeg" => URISuffix::MP3,
+        "audio/ogg" => URISuffix::OGG,
+        "audio/wav" => URISuffix::WAV,
+        "audio/x-ms-wma" => URISuffix::WMA,
+        "image/gif" => URISuffix::GIF,
+        "image/jpeg" => URISuffix::JPG,
+        "image/png" => URISuffix::PNG,
+        "text/html" => URISuffix::HTML,
+        "text/plain" => URISuffix::TXT,
+        "video/mp4" => URISuffix::MP4,
+        "video/x-ms-wmv" => URISuffix::WMV,
+        _ => {
+            let extension = format.trim_start_matches('.');
+            return URISuffix::iter().find(|suffix| suffix.to_string()[1..] == *extension);
+        }
+    };
+    Some(suffix)
+}
@@ -0,0 +1,210 @@
+//! Versioned serialization of the inputs.
+//!
+//! The input model keeps growing (structured URIs, bundles, ClipData), and
+//! corpora and solutions outlive the fuzzer version that wrote them. Inputs
+//! are therefore stored as JSON carrying a `schema_version`. Reading an input
+//! of an older version applies the migrations of every later version to its
+//! JSON before deserializing it, so old corpora keep loading. The
+//! `migrate-corpus` subcommand rewrites them in the current format once and
+//! for all.
+//!
+//! Inputs stored before the versioning (version 0) are either libAFL's
+//! default postcard encoding or plain JSON. As postcard is not
+//! self-describing, the unversioned binary entries are decoded with the
+//! frozen model of version 0 ([IntentInputV0]) and migrated from its JSON.
+
+use std::path::{Path, PathBuf};
+
+use libafl::bolts::fs::write_file_atomic;
+use serde_json::{json, Value};
+
+use crate::{intent_input::IntentInput, intent_input_v0::IntentInputV0};
+
+/// The version of the current input model.
+pub const SCHEMA_VERSION: u64 = 1;
+
+/// The key of the version in the JSON of an input.
+const VERSION_KEY: &str = "schema_version";
+
+/// Migrates the JSON of an input of the version at the index to the next
+/// version.
+const MIGRATIONS: [fn(&mut Value); SCHEMA_VERSION as usize] = [unversioned_to_v1];
+
+/// Version 1 split String arrays, stored as one buffer separating the
+/// elements by zero bytes, into their elements. The fields added since then
+/// have defaults.
+fn unversioned_to_v1(input: &mut Value) {
+    let Some(extras) = input.get_mut("extras").and_then(Value::as_array_mut) else {
+        return;
+    };
+    for value in extras.iter_mut().filter_map(|extra| extra.get_mut("value")) {
+        for array_type in ["StringArray", "StringArrayList"] {
+            let Some(array) = value.get_mut(array_type) else {
+                continue;
+            };
+            let Some(bytes) = array.pointer("/buffer/bytes").and_then(Value::as_array) else {
+                continue;
+            };
+            let bytes: Vec<u64> = bytes.iter().filter_map(Value::as_u64).collect();
+            // An empty buffer is an empty array, not one empty element.
+            let elements: Vec<Value> = if bytes.is_empty() {
+                Vec::new()
+            } else {
+                bytes
+                    .split(|byte| *byte == 0)
+                    .map(|element| json!({ "bytes": element }))
+                    .collect()
+            };
+            *array = json!({ "elements": elements });
+        }
+    }
+}
+
+/// The JSON of the input in the current version.
+pub fn to_json(input: &IntentInput) -> Result<String, libafl::Error> {
+    let mut value =
+        serde_json::to_value(input).map_err(|err| libafl::Error::serialize(err.to_string()))?;
+    if let Value::Object(object) = &mut value {
+        object.insert(VERSION_KEY.to_owned(), SCHEMA_VERSION.into());
+    }
+    serde_json::to_string_pretty(&value).map_err(|err| libafl::Error::serialize(err.to_string()))
+}
+
+/// Reads an input of any version, returning it with the version it was
+/// stored in.
+pub fn from_bytes(bytes: &[u8]) -> Result<(IntentInput, u64), libafl::Error> {
+    let mut value: Value = match bytes.first() {
+        Some(b'{') => serde_json::from_slice(bytes)
+            .map_err(|err| libafl::Error::serialize(err.to_string()))?,
+        _ => legacy_json(bytes)?,
+    };
+    let version = match &mut value {
+        Value::Object(object) => object
+            .remove(VERSION_KEY)
+            .map_or(Some(0), |version| version.as_u64()),
+        _ => None,
+    }
+    .ok_or_else(|| libafl::Error::serialize("The input has no valid schema version"))?;
+    if version > SCHEMA_VERSION {
+        return Err(libafl::Error::serialize(format!(
+            "The input is of schema version {}, newer than the supported version {}",
+            version, SCHEMA_VERSION
+        )));
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut value);
+    }
+    let input =
+        serde_json::from_value(value).map_err(|err| libafl::Error::serialize(err.to_string()))?;
+    Ok((input, version))
+}
+
+/// The JSON of an unversioned postcard input.
+fn legacy_json(bytes: &[u8]) -> Result<Value, libafl::Error> {
+    let (input, rest) = postcard::take_from_bytes::<IntentInputV0>(bytes)?;
+    if !rest.is_empty() {
+        return Err(libafl::Error::serialize(
+            "The input is not of the unversioned binary format",
+        ));
+    }
+    serde_json::to_value(input).map_err(|err| libafl::Error::serialize(err.to_string()))
+}
+
+/// How many inputs [migrate_files] upgraded, found current or failed to
+/// read.
+#[derive(Debug, Default)]
+pub struct MigrationSummary {
+    pub migrated: usize,
+    pub current: usize,
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+/// Rewrites the input files of older versions in the current version. Files
+/// that fail to read are left as they are.
+pub fn migrate_files(files: &[PathBuf]) -> MigrationSummary {
+    let mut summary = MigrationSummary::default();
+    for file in files {
+        match migrate_file(file) {
+            Ok(true) => summary.migrated += 1,
+            Ok(false) => summary.current += 1,
+            Err(err) => summary.failed.push((file.clone(), err.to_string())),
+        }
+    }
+    summary
+}
+
+/// Rewrites the input file in the current version, if it is of an older
+/// one. Returns whether it was rewritten.
+fn migrate_file(file: &Path) -> Result<bool, libafl::Error> {
+    let (input, version) = from_bytes(&std::fs::read(file)?)?;
+    if version == SCHEMA_VERSION {
+        return Ok(false);
+    }
+    write_file_atomic(file, to_json(&input)?.as_bytes())?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::prelude::HasBytesVec;
+
+    use super::*;
+    use crate::intent_input::{ExtraType, MimeType, URIGrant, URIScheme};
+
+    /// An input written by the first release of the fuzzer, in libAFL's
+    /// postcard encoding.
+    const V0_POSTCARD: &[u8] = include_bytes!("../tests/fixtures/input_v0.postcard");
+    /// The same input as JSON.
+    const V0_JSON: &[u8] = include_bytes!("../tests/fixtures/input_v0.json");
+
+    fn check_v0_input(input: &IntentInput) {
+        assert_eq!(
+            input.component(),
+            "com.example.app/com.example.app.MainActivity"
+        );
+        assert_eq!(input.action, "android.intent.action.VIEW");
+        assert!(matches!(input.mime_type, MimeType::ImagePng));
+        assert_eq!(input.flags, 0x1000_0000);
+        let data = input.data.as_ref().unwrap();
+        assert!(matches!(data.scheme, URIScheme::Content));
+        assert_eq!(data.content.bytes(), b"\x89PNG");
+        assert_eq!(input.uri_grant, URIGrant::default());
+        assert!(input.pending_intent.is_none());
+        assert!(input.permission.is_none());
+
+        let keys: Vec<&str> = input.extras.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(keys, ["name", "items", "empty", "count", "link"]);
+        let ExtraType::StringArray(items) = &input.extras[1].value else {
+            panic!("items is not a String array");
+        };
+        let items: Vec<&[u8]> = items.elements.iter().map(|e| e.bytes()).collect();
+        assert_eq!(items, [b"a", b"b", b"c"]);
+        let ExtraType::StringArrayList(empty) = &input.extras[2].value else {
+            panic!("empty is not a String array list");
+        };
+        assert!(empty.elements.is_empty());
+    }
+
+    #[test]
+    fn reads_v0_postcard() {
+        let (input, version) = from_bytes(V0_POSTCARD).unwrap();
+        assert_eq!(version, 0);
+        check_v0_input(&input);
+    }
+
+    #[test]
+    fn reads_v0_json() {
+        let (input, version) = from_bytes(V0_JSON).unwrap();
+        assert_eq!(version, 0);
+        check_v0_input(&input);
+    }
+
+    #[test]
+    fn round_trips_current_version() {
+        let (input, _) = from_bytes(V0_POSTCARD).unwrap();
+        let (read, version) = from_bytes(to_json(&input).unwrap().as_bytes()).unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+        assert_eq!(read.hash(), input.hash());
+    }
+}
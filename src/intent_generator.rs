@@ -2,25 +2,94 @@
 //!
 //! This module implements logic for creating an initial [IntentInput] for
 //! fuzzing.
-use std::{cmp::max, collections::HashMap, fs::File};
+//!
+//! A template may name a base template in `extends`, relative to its own
+//! file. The template inherits every field it does not set from the base,
+//! and the `known_extras_keys` of both are merged, so the extras inventory
+//! shared by many components is declared once. Base templates may extend
+//! other base templates, and files without a `component` are only used as
+//! base templates. Any other template without a `component`, e.g. one that
+//! forgot it, is rejected.
+//!
+//! A file may also hold an array of templates, e.g. all components of an
+//! app, which is handled like a directory with a file per template.
+//!
+//! Templates are parsed strictly: unknown fields, e.g. a misspelled
+//! `know_extras_keys`, fail the loading instead of silently falling back to
+//! the defaults. The `template-schema` subcommand writes the JSON Schema of
+//! the template files, for editors and template generators to validate
+//! against.
+use std::{
+    cmp::max,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 
 use libafl::{impl_serdeany, prelude::Generator, state::HasNamedMetadata};
+use schemars::{
+    schema::{
+        ArrayValidation, InstanceType, Metadata, RootSchema, Schema, SchemaObject,
+        SubschemaValidation,
+    },
+    JsonSchema,
+};
 use serde::{Deserialize, Serialize};
 
-use crate::intent_input::{IntentInput, MimeType, ReceiverType};
+use crate::{
+    data_filter::{self, DataBoundaryURIs, DataFilter},
+    field_constraints::{FieldConstraints, MutationConstraints},
+    intent_input::{IntentInput, MimeType, ReceiverType, URIGrant, URIInput},
+    intent_mutator::EXTRA_TYPES,
+};
 
 /// A template for an intent to start mutating, loaded from intent_template.json
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct IntentTemplate {
     receiver_type: ReceiverType,
+    /// The component receiving the intents, e.g.
+    /// `com.example.app/.ExampleActivity`
     component: String,
     actions: Vec<String>,
     categories: Vec<String>,
+    /// The keys of the extras the component reads, with their types, e.g.
+    /// `String` or `IntArray`
     pub known_extras_keys: HashMap<String, String>,
+    /// The `<data>` constraints of the intent filters of the component.
+    #[serde(default)]
+    pub data_filters: Vec<DataFilter>,
+    /// The activity-aliases routing to the same activity as the component,
+    /// e.g. `com.example.app/.ExampleAlias`
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// The permission the component requires of the sender, e.g.
+    /// `com.example.app.permission.RECEIVE`
+    #[serde(default)]
+    pub permission: Option<String>,
+    /// The fields the mutators must leave alone or restrict.
+    #[serde(default)]
+    pub constraints: FieldConstraints,
 }
 
 impl_serdeany!(IntentTemplate);
 
+/// The alias sets of the components, keyed by every member of the set.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ComponentAliases {
+    pub aliases: HashMap<String, Vec<String>>,
+}
+
+impl_serdeany!(ComponentAliases);
+
+/// The components of all templates with their receiver types and the
+/// permissions they require of the sender.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ExportedComponents {
+    pub components: Vec<(String, ReceiverType, Option<String>)>,
+}
+
+impl_serdeany!(ExportedComponents);
+
 impl IntentTemplate {
     /// Get the package name from the component attribute.
     pub fn package_name(&self) -> String {
@@ -32,11 +101,84 @@ impl IntentTemplate {
         return self.component.split("/").collect::<Vec<&str>>()[1].to_string();
     }
 
+    /// The number of intents of this template. A template without actions
+    /// has no intents, as the data is sent with the base intents.
     pub fn number_of_intents(&self) -> usize {
-        return self.actions.len() * max(1, self.categories.len());
+        match self.number_of_base_intents() {
+            0 => 0,
+            base_intents => base_intents + self.matching_data().len() + self.boundary_uris().len(),
+        }
+    }
+
+    /// The number of intents combining the actions and categories.
+    fn number_of_base_intents(&self) -> usize {
+        self.actions.len() * max(1, self.categories.len())
+    }
+
+    /// The intents combining the actions and categories, without any data.
+    pub fn base_intents(&self) -> Vec<IntentInput> {
+        (0..self.number_of_base_intents())
+            .map(|i| self.get_intent_input_for_index(i))
+            .collect()
+    }
+
+    /// The data and types matching the data filters, one per distinct
+    /// combination of scheme, host and type of an intent filter.
+    pub fn matching_data(&self) -> Vec<(URIInput, MimeType)> {
+        self.combined_data_filters()
+            .iter()
+            .filter_map(DataFilter::matching_data)
+            .collect()
+    }
+
+    /// The URIs at the match boundary of all data filters, combined like for
+    /// [IntentTemplate::matching_data].
+    pub fn boundary_uris(&self) -> Vec<String> {
+        let mut uris = Vec::new();
+        for uri in self
+            .combined_data_filters()
+            .iter()
+            .flat_map(DataFilter::boundary_uris)
+        {
+            if !uris.contains(&uri) {
+                uris.push(uri);
+            }
+        }
+        uris
     }
 
+    /// The distinct data filters of the intent filters, with the `<data>`
+    /// elements of each intent filter combined.
+    fn combined_data_filters(&self) -> Vec<DataFilter> {
+        let mut filters: Vec<DataFilter> = Vec::new();
+        for filter in data_filter::combined_filters(&self.data_filters) {
+            if !filters.contains(&filter) {
+                filters.push(filter);
+            }
+        }
+        filters
+    }
+
+    /// Get the intent for the index. The intents after the base intents carry
+    /// the data and type matching each data filter, followed by the boundary
+    /// URIs of the data filters as their data.
     pub fn get_intent_input_for_index(&self, index: usize) -> IntentInput {
+        let base_intents = self.number_of_base_intents();
+        let matching_data = self.matching_data();
+        if index >= base_intents + matching_data.len() {
+            let uri_index = index - base_intents - matching_data.len();
+            let mut input = self.get_intent_input_for_index(uri_index % base_intents);
+            input.data = Some(URIInput::raw(&self.boundary_uris()[uri_index]));
+            return input;
+        } else if index >= base_intents {
+            let data_index = index - base_intents;
+            let mut input = self.get_intent_input_for_index(data_index % base_intents);
+            let (data, mime_type) = matching_data[data_index].clone();
+            input.data = Some(data);
+            input.mime_type = mime_type;
+            return input;
+        }
+
         let action_index = index % self.actions.len();
         let category_index = index / max(1, self.actions.len());
 
@@ -52,6 +194,9 @@ impl IntentTemplate {
             flags: 0,
 
             extras: Vec::new(),
+            uri_grant: URIGrant::default(),
+            pending_intent: None,
+            permission: self.permission.clone(),
         }
     }
 }
@@ -59,7 +204,10 @@ impl IntentTemplate {
 /// Generates some starting intents based on the data from intent_template.json
 pub struct IntentGenerator {
     templates: Vec<IntentTemplate>,
-    read_count: u32,
+    /// The template and intent index of every intent, interleaving the
+    /// templates round-robin.
+    order: Vec<(usize, usize)>,
+    read_count: usize,
 }
 
 impl IntentGenerator {
@@ -69,34 +217,118 @@ impl IntentGenerator {
         if let Ok(dir) = std::fs::read_dir(config) {
             // If str is a directory, read all the files in the directory and parse the JSON
             let mut templates: Vec<IntentTemplate> = Vec::new();
-            for entry in dir {
-                if let Ok(entry) = entry {
-                    let file = File::open(entry.path()).expect("Failed to open intent template file");
-                    let template: IntentTemplate =
-                        serde_json::from_reader(file).expect("Failed to parse intent template file");
-                    if template.receiver_type == ReceiverType::Activity {
-                        templates.push(template);
-                    }
-                }
+            let paths: Vec<PathBuf> = dir.flatten().map(|entry| entry.path()).collect();
+            let bases: HashSet<PathBuf> = paths
+                .iter()
+                .flat_map(|path| extended_files(path, &read_template_json(path)))
+                .collect();
+            for path in &paths {
+                let (values, _) = load_template_values(path);
+                let is_base = bases.contains(&canonical_path(path));
+                templates.extend(activity_templates(path, values, is_base));
             }
             if templates.is_empty() {
                 panic!("No intent templates found in directory");
             }
-            return Self { templates, read_count: 0 };
-        } else if let Ok(file) = File::open(config) {
-            let template: IntentTemplate =
-                serde_json::from_reader(file).expect("Failed to parse intent template file");
-            return Self { templates: vec![template], read_count: 0 };
+            return Self::with_templates(templates);
+        } else if Path::new(config).is_file() {
+            let path = Path::new(config);
+            let (mut values, multiple) = load_template_values(path);
+            if multiple {
+                // Like a directory of templates.
+                let templates = activity_templates(path, values, false);
+                if templates.is_empty() {
+                    panic!("No intent templates found in the array");
+                }
+                return Self::with_templates(templates);
+            }
+            let template = parse_template(path, values.remove(0));
+            return Self::with_templates(vec![template]);
         }
 
         // If str is not a file or directory, panic
         panic!("Failed to open intent template file");
     }
 
+    fn with_templates(templates: Vec<IntentTemplate>) -> Self {
+        // Take the n-th intent of every template in round n, so that the
+        // first intents cover all templates, however many intents each has.
+        let counts: Vec<usize> = templates.iter().map(|t| t.number_of_intents()).collect();
+        let rounds = counts.iter().copied().max().unwrap_or(0);
+        let order = (0..rounds)
+            .flat_map(|round| {
+                counts
+                    .iter()
+                    .enumerate()
+                    .filter(move |(_, count)| round < **count)
+                    .map(move |(template, _)| (template, round))
+            })
+            .collect();
+
+        for template in &templates {
+            if let Err(err) = template.constraints.validate() {
+                panic!(
+                    "Invalid constraints in the intent template of {}: {}",
+                    template.component, err
+                );
+            }
+            for (key, extra_type) in &template.known_extras_keys {
+                if !EXTRA_TYPES.contains(&extra_type.as_str()) {
+                    panic!(
+                        "Unknown type {} of the extras key {} in the intent template of {}, \
+                        expected one of {:?}",
+                        extra_type, key, template.component, EXTRA_TYPES
+                    );
+                }
+            }
+        }
+
+        for template in templates.iter().filter(|t| t.number_of_intents() == 0) {
+            println!(
+                "Intent template of {} has no actions, no intents are generated for it",
+                template.component
+            );
+        }
+
+        Self {
+            templates,
+            order,
+            read_count: 0,
+        }
+    }
+
     /// Get the total number of base intents, a combination of all the actions
-    /// and categories.
+    /// and categories, plus one per boundary URI of the data filters.
     pub fn number_of_intents(&self) -> usize {
-        return self.templates.iter().map(|t| t.number_of_intents()).sum();
+        self.order.len()
+    }
+
+    /// The intents combining the actions and categories of all templates.
+    pub fn base_intents(&self) -> Vec<IntentInput> {
+        self.templates
+            .iter()
+            .flat_map(|t| t.base_intents())
+            .collect()
+    }
+
+    /// Adds the extras keys no template declares (e.g. mined from the code of
+    /// the app) to the keys the mutators draw from. Returns how many keys
+    /// were added.
+    pub fn add_known_extras_keys(&mut self, keys: HashMap<String, String>) -> usize {
+        let declared: HashSet<String> = self
+            .templates
+            .iter()
+            .flat_map(|t| t.known_extras_keys.keys().cloned())
+            .collect();
+        let mut added = 0;
+        // The mutators draw the keys of all templates, see Generator::generate.
+        for (key, extra_type) in keys {
+            if !declared.contains(&key) {
+                self.templates[0].known_extras_keys.insert(key, extra_type);
+                added += 1;
+            }
+        }
+        added
     }
 
     pub fn package_name(&self) -> String {
@@ -104,6 +336,46 @@ impl IntentGenerator {
         return self.templates[0].package_name();
     }
 
+    /// The components of all templates, in template order.
+    pub fn components(&self) -> Vec<String> {
+        self.templates.iter().map(|t| t.component.clone()).collect()
+    }
+
+    /// Targets the input at the first component whose template declares its
+    /// action and, if the input has data, a data filter matching its scheme
+    /// and host. Returns whether there is such a component.
+    pub fn resolve_target(&self, input: &mut IntentInput) -> bool {
+        let authority = input
+            .data
+            .as_ref()
+            .map(|data| uri_authority(&data.raw_identifier(0)));
+        let Some(template) = self.templates.iter().find(|template| {
+            (input.component_package.is_empty()
+                || template.package_name() == input.component_package)
+                && template.actions.contains(&input.action)
+                && authority.as_ref().map_or(true, |(scheme, host)| {
+                    data_filter::combined_filters(&template.data_filters)
+                        .iter()
+                        .any(|filter| filter.matches_authority(scheme, host))
+                })
+        }) else {
+            return false;
+        };
+
+        input.receiver_type = template.receiver_type;
+        input.component_package = template.package_name();
+        input.component_class = template.class_name();
+        input.permission = template.permission.clone();
+        true
+    }
+
+    /// Whether any template targets an activity.
+    pub fn has_activities(&self) -> bool {
+        self.templates
+            .iter()
+            .any(|t| t.receiver_type == ReceiverType::Activity)
+    }
+
     pub fn enable_synchronization(&self) -> bool {
         self.templates[0].receiver_type == ReceiverType::Activity
     }
@@ -115,20 +387,287 @@ impl IntentGenerator {
     }
 }
 
+/// The scheme and host of the URI, empty if it has none.
+fn uri_authority(uri: &str) -> (String, String) {
+    let Some((scheme, rest)) = uri.split_once(':') else {
+        return (String::new(), String::new());
+    };
+    let host = rest
+        .strip_prefix("//")
+        .map(|rest| {
+            let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+            let host = authority.rsplit('@').next().unwrap_or_default();
+            host.split(':').next().unwrap_or_default()
+        })
+        .unwrap_or_default();
+    (scheme.to_owned(), host.to_owned())
+}
+
+/// Parses the templates of activities in the file. Templates without a
+/// `component` are left out if the file is the base template of another one,
+/// and rejected otherwise.
+fn activity_templates(
+    path: &Path,
+    values: Vec<serde_json::Value>,
+    is_base: bool,
+) -> Vec<IntentTemplate> {
+    values
+        .into_iter()
+        .filter(|value| {
+            if value.get("component").is_some() {
+                return true;
+            }
+            if !is_base {
+                panic!(
+                    "Intent template {:?} has no component, and no other template extends it",
+                    path
+                );
+            }
+            false
+        })
+        .map(|value| parse_template(path, value))
+        .filter(|template| template.receiver_type == ReceiverType::Activity)
+        .collect()
+}
+
+/// The files the templates in the JSON of the file extend, as canonical
+/// paths.
+fn extended_files(path: &Path, value: &serde_json::Value) -> Vec<PathBuf> {
+    let values = match value {
+        serde_json::Value::Array(values) => values.iter().collect(),
+        value => vec![value],
+    };
+    values
+        .into_iter()
+        .filter_map(|value| value.get("extends")?.as_str())
+        .map(|base| canonical_path(&path.parent().unwrap_or(Path::new(".")).join(base)))
+        .collect()
+}
+
+/// Parses the JSON of the template in the file, rejecting unknown fields.
+fn parse_template(path: &Path, value: serde_json::Value) -> IntentTemplate {
+    serde_json::from_value(value)
+        .unwrap_or_else(|err| panic!("Invalid intent template {:?}: {}", path, err))
+}
+
+/// The JSON Schema of the template files, a template or an array of them,
+/// with the `extends` and `$schema` of a template that are resolved or
+/// dropped before parsing it.
+pub fn template_schema() -> RootSchema {
+    let string_property = |description: &str| {
+        Schema::Object(SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            metadata: Some(Box::new(Metadata {
+                description: Some(description.to_owned()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        })
+    };
+
+    let mut schema = schemars::schema_for!(IntentTemplate);
+    let properties = &mut schema.schema.object().properties;
+    if let Some(Schema::Object(known_extras_keys)) = properties.get_mut("known_extras_keys") {
+        known_extras_keys.object().additional_properties =
+            Some(Box::new(Schema::Object(SchemaObject {
+                instance_type: Some(InstanceType::String.into()),
+                enum_values: Some(EXTRA_TYPES.iter().map(|&t| t.into()).collect()),
+                ..Default::default()
+            })));
+    }
+    properties.insert(
+        "extends".to_owned(),
+        string_property("The file of the base template, relative to this one"),
+    );
+    properties.insert(
+        "$schema".to_owned(),
+        string_property("The JSON Schema of the template, ignored by the fuzzer"),
+    );
+
+    let template = std::mem::take(&mut schema.schema);
+    let metadata = template.metadata.clone();
+    schema
+        .definitions
+        .insert("IntentTemplate".to_owned(), Schema::Object(template));
+    let reference = Schema::new_ref("#/definitions/IntentTemplate".to_owned());
+    let array = SchemaObject {
+        instance_type: Some(InstanceType::Array.into()),
+        array: Some(Box::new(ArrayValidation {
+            items: Some(reference.clone().into()),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    schema.schema = SchemaObject {
+        metadata,
+        subschemas: Some(Box::new(SubschemaValidation {
+            any_of: Some(vec![reference, Schema::Object(array)]),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    schema
+}
+
+/// Reads the JSON of the templates in the file, a single template or an
+/// array of them, with the fields of the templates they extend filled in.
+/// Returns whether the file holds an array.
+fn load_template_values(path: &Path) -> (Vec<serde_json::Value>, bool) {
+    match read_template_json(path) {
+        serde_json::Value::Array(values) => {
+            let values = values
+                .into_iter()
+                .map(|value| resolve_base_template(path, value, &mut Vec::new()))
+                .collect();
+            (values, true)
+        }
+        value => (
+            vec![resolve_base_template(path, value, &mut Vec::new())],
+            false,
+        ),
+    }
+}
+
+/// Reads the JSON of the file, as is.
+fn read_template_json(path: &Path) -> serde_json::Value {
+    let file = std::fs::File::open(path).expect("Failed to open intent template file");
+    serde_json::from_reader(file).expect("Failed to parse intent template file")
+}
+
+/// Reads the JSON of the template in the file, with the fields of the
+/// template it extends filled in. `chain` holds the files extending it.
+fn load_template_value(path: &Path, chain: &mut Vec<PathBuf>) -> serde_json::Value {
+    resolve_base_template(path, read_template_json(path), chain)
+}
+
+/// The path with `.`, `..` and symbolic links resolved, so that different
+/// spellings of the same template file compare equal.
+fn canonical_path(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path)
+        .unwrap_or_else(|err| panic!("Failed to open intent template {:?}: {}", path, err))
+}
+
+/// Fills in the fields of the template the template in the file extends.
+fn resolve_base_template(
+    path: &Path,
+    mut value: serde_json::Value,
+    chain: &mut Vec<PathBuf>,
+) -> serde_json::Value {
+    if let Some(object) = value.as_object_mut() {
+        object.remove("$schema");
+    }
+    let Some(base) = value.as_object_mut().and_then(|object| object.remove("extends")) else {
+        return value;
+    };
+
+    let base = base
+        .as_str()
+        .expect("The extended template must be a file name");
+    let base_path = canonical_path(&path.parent().unwrap_or(Path::new(".")).join(base));
+    chain.push(canonical_path(path));
+    if chain.contains(&base_path) {
+        panic!("Intent template {:?} extends itself", base_path);
+    }
+    let mut merged = load_template_value(&base_path, chain);
+    chain.pop();
+
+    let (Some(merged_object), serde_json::Value::Object(object)) =
+        (merged.as_object_mut(), value)
+    else {
+        panic!("Intent template {:?} is not an object", path);
+    };
+    for (key, value) in object {
+        match (merged_object.get_mut(&key), value) {
+            (Some(serde_json::Value::Object(base)), serde_json::Value::Object(value))
+                if key == "known_extras_keys" =>
+            {
+                base.extend(value);
+            }
+            (_, value) => {
+                merged_object.insert(key, value);
+            }
+        }
+    }
+    merged
+}
+
 impl<S> Generator<IntentInput, S> for IntentGenerator
 where
     S: HasNamedMetadata,
 {
     fn generate(&mut self, state: &mut S) -> Result<IntentInput, libafl::Error> {
-        // Go through all the templates and generate the intent inputs for each template.
-        // Keep in mind that every template generates one or more intent inputs.
-        let input = self.templates.iter().flat_map(|t| {
-            (0..t.number_of_intents()).map(move |i| t.get_intent_input_for_index(i))
-        }).nth(self.read_count as usize).unwrap();
+        if self.order.is_empty() {
+            return Err(libafl::Error::illegal_state(
+                "The intent templates do not have any actions",
+            ));
+        }
+
+        // Once all intents were generated, start over with the first ones
+        // rather than failing, for callers asking for more inputs.
+        if self.read_count == self.order.len() {
+            println!(
+                "All {} intents of the templates were generated, repeating them",
+                self.order.len()
+            );
+        }
+        let (template, index) = self.order[self.read_count % self.order.len()];
+        let input = self.templates[template].get_intent_input_for_index(index);
 
         if !state.has_named_metadata::<IntentTemplate>("intent_template") {
-            // Save the template to the state so that we can use it later.
-            state.add_named_metadata(self.templates[0].clone(), "intent_template");
+            // Save the template to the state so that we can use it later. The
+            // mutators draw the extras keys from it, so it holds the keys of
+            // all templates.
+            let mut template = self.templates[0].clone();
+            for other in &self.templates[1..] {
+                for (key, extra_type) in &other.known_extras_keys {
+                    template
+                        .known_extras_keys
+                        .entry(key.clone())
+                        .or_insert_with(|| extra_type.clone());
+                }
+            }
+            state.add_named_metadata(template, "intent_template");
+        }
+
+        if !state.has_named_metadata::<DataBoundaryURIs>("data_boundary_uris") {
+            let uris = self
+                .templates
+                .iter()
+                .map(|t| (t.component.clone(), t.boundary_uris()))
+                .filter(|(_, uris)| !uris.is_empty())
+                .collect();
+            state.add_named_metadata(DataBoundaryURIs { uris }, "data_boundary_uris");
+        }
+
+        if !state.has_named_metadata::<ExportedComponents>("exported_components") {
+            let components = self
+                .templates
+                .iter()
+                .map(|t| (t.component.clone(), t.receiver_type, t.permission.clone()))
+                .collect();
+            state.add_named_metadata(ExportedComponents { components }, "exported_components");
+        }
+
+        if !state.has_named_metadata::<ComponentAliases>("component_aliases") {
+            let mut aliases = HashMap::new();
+            for template in self.templates.iter().filter(|t| !t.aliases.is_empty()) {
+                let mut alias_set = template.aliases.clone();
+                alias_set.push(template.component.clone());
+                for member in &alias_set {
+                    aliases.insert(member.clone(), alias_set.clone());
+                }
+            }
+            state.add_named_metadata(ComponentAliases { aliases }, "component_aliases");
+        }
+
+        if !state.has_named_metadata::<MutationConstraints>("mutation_constraints") {
+            let constraints = self
+                .templates
+                .iter()
+                .filter(|t| !t.constraints.is_empty())
+                .map(|t| (t.component.clone(), t.constraints.clone()))
+                .collect();
+            state.add_named_metadata(MutationConstraints { constraints }, "mutation_constraints");
         }
 
         self.read_count += 1;
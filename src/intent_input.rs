@@ -1,15 +1,68 @@
 //! A libafl [Input] representing a single intent.
 
 use std::fmt;
+use std::path::Path;
+use std::sync::OnceLock;
 use std::{fmt::Write, hash::Hasher};
 use strum_macros::EnumIter;
 
-use libafl::prelude::{BytesInput, HasBytesVec, Input};
+use libafl::{
+    bolts::fs::write_file_atomic,
+    prelude::{BytesInput, HasBytesVec, Input},
+};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use fasthash::{farm::Hasher128, FastHasher, HasherExt};
 
-use crate::util::encode_hex;
+use crate::{
+    input_schema,
+    util::{encode_hex, encode_uri_component, shell_quote},
+};
+
+/// The activity of the content provider app forwarding intents to an activity
+/// started for a result.
+const RESULT_FORWARDER_COMPONENT: &str =
+    "org.gts3.jnifuzz.contentprovider/.ResultForwarderActivity";
+/// The extra telling the result forwarder which activity to start.
+const EXTRA_TARGET_COMPONENT: &str = "org.gts3.jnifuzz.contentprovider.TARGET_COMPONENT";
+/// The broadcast asking the delivery instrumentation to deliver an intent.
+const ACTION_DELIVER_INTENT: &str = "org.gts3.jnifuzz.contentprovider.DELIVER_INTENT";
+/// The extra carrying the intent URI of the intent to deliver.
+const EXTRA_INTENT: &str = "org.gts3.jnifuzz.contentprovider.INTENT";
+/// The extra telling the delivery instrumentation how to deliver the intent.
+const EXTRA_RECEIVER_TYPE: &str = "org.gts3.jnifuzz.contentprovider.RECEIVER_TYPE";
+/// The broadcast asking the broadcast forwarder of the content provider app
+/// to send a broadcast with its permissions.
+const ACTION_FORWARD_BROADCAST: &str = "org.gts3.jnifuzz.contentprovider.FORWARD_BROADCAST";
+
+/// The package of the content provider app.
+const CONTENT_PROVIDER_PACKAGE: &str = "org.gts3.jnifuzz.contentprovider";
+/// The broadcast asking the notification listener of the content provider
+/// app to send a PendingIntent.
+const ACTION_FIRE_PENDING_INTENT: &str = "org.gts3.jnifuzz.contentprovider.FIRE_PENDING_INTENT";
+/// The extra carrying the (URI-encoded) key of the notification.
+const EXTRA_NOTIFICATION_KEY: &str = "org.gts3.jnifuzz.contentprovider.NOTIFICATION_KEY";
+/// The extra carrying the index of the notification action.
+const EXTRA_ACTION_INDEX: &str = "org.gts3.jnifuzz.contentprovider.ACTION_INDEX";
+
+/// The directory file URIs are staged in by default.
+const DEFAULT_FILE_DIR: &str = "/data/local/tmp";
+/// The directory file URIs are staged in, if not the default one.
+static FILE_DIR: OnceLock<String> = OnceLock::new();
+
+/// Stages the content of file URIs in the directory instead of
+/// `/data/local/tmp`, e.g. in the sandbox of the app in root mode. Only takes
+/// effect before the first input is staged.
+pub fn set_file_dir(dir: String) {
+    let _ = FILE_DIR.set(dir);
+}
+
+/// Longest part of the summary in the generated input names.
+const NAME_PART_LEN: usize = 24;
+
+/// Paths or URIs on the device with the content to stage for them.
+pub type UriPayloads = Vec<(String, Vec<u8>)>;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct IntentInput {
@@ -35,11 +88,232 @@ pub struct IntentInput {
     pub flags: u32,
     // The `extras` for the intent.
     pub extras: Vec<ExtraInput>,
+    /// The URI permissions granted with the intent.
+    #[serde(default)]
+    pub uri_grant: URIGrant,
+    /// The PendingIntent of a notification to send with this intent as
+    /// fill-in intent, instead of sending this intent to the component.
+    #[serde(default)]
+    pub pending_intent: Option<PendingIntentTarget>,
+    /// The permission the receiver requires of the sender, e.g.
+    /// `com.example.app.permission.RECEIVE`.
+    #[serde(default)]
+    pub permission: Option<String>,
 }
 
 impl IntentInput {
     /// Command to send this intent via adb shell.
     pub fn shell_command(&self) -> String {
+        match &self.pending_intent {
+            Some(target) => self.pending_intent_shell_command(target),
+            None => self.shell_command_for(&self.component()),
+        }
+    }
+
+    /// Returns the shell command asking the notification listener of the
+    /// content provider app to send the PendingIntent with this intent as
+    /// fill-in intent. The outcome is the result of the broadcast.
+    fn pending_intent_shell_command(&self, target: &PendingIntentTarget) -> String {
+        format!(
+            "am broadcast -a {} -p {} --es {} '{}' --ei {} {} --es {} {}",
+            ACTION_FIRE_PENDING_INTENT,
+            CONTENT_PROVIDER_PACKAGE,
+            EXTRA_NOTIFICATION_KEY,
+            encode_uri_component(&target.notification_key),
+            EXTRA_ACTION_INDEX,
+            target.action.map_or(-1, |action| action as i64),
+            EXTRA_INTENT,
+            shell_quote(&self.intent_uri())
+        )
+    }
+
+    /// Returns the shell command asking the broadcast forwarder of the
+    /// content provider app to send this broadcast. The forwarder passes on
+    /// the result of the broadcast.
+    pub fn forwarded_shell_command(&self) -> String {
+        format!(
+            "am broadcast -a {} -p {} --es {} {}",
+            ACTION_FORWARD_BROADCAST,
+            CONTENT_PROVIDER_PACKAGE,
+            EXTRA_INTENT,
+            shell_quote(&self.intent_uri())
+        )
+    }
+
+    /// Returns the shell command delivering this (activity) intent through
+    /// the result forwarder of the content provider app, which starts the
+    /// target activity for a result and logs the result it returns.
+    pub fn result_forwarding_shell_command(&self) -> String {
+        format!(
+            "{} --es {} '{}'",
+            self.shell_command_for(RESULT_FORWARDER_COMPONENT),
+            EXTRA_TARGET_COMPONENT,
+            self.component()
+        )
+    }
+
+    /// Returns the shell command asking the delivery instrumentation running
+    /// in the app to deliver this intent. The outcome of the delivery is the
+    /// result of the broadcast.
+    pub fn instrumented_shell_command(&self) -> String {
+        let receiver_type = match self.receiver_type {
+            ReceiverType::BroadcastReceiver => "broadcast",
+            _ => "activity",
+        };
+        format!(
+            "am broadcast -a {} -p '{}' --es {} {} --es {} {}",
+            ACTION_DELIVER_INTENT,
+            self.component_package,
+            EXTRA_INTENT,
+            shell_quote(&self.intent_uri()),
+            EXTRA_RECEIVER_TYPE,
+            receiver_type
+        )
+    }
+
+    /// This intent in the intent URI format of `Intent.toUri`. The extras the
+    /// format has no type for (URIs, component names and arrays) are passed
+    /// as Strings with a key prefix the content provider app converts back.
+    /// The data is not escaped, so the URI has to be quoted with
+    /// [shell_quote] on the command line.
+    pub fn intent_uri(&self) -> String {
+        let mut uri = String::new();
+        if let Some(data) = &self.data {
+            uri.push_str(&data.raw_identifier(0).replace('#', "%23"));
+        }
+
+        uri.push_str("#Intent;");
+        self.write_intent_uri_fields(&mut uri);
+        uri
+    }
+
+    /// This intent as `intent:` URI (`Intent.toUri(Intent.URI_INTENT_SCHEME)`),
+    /// which browsers start when following a link to it. Browsers drop the
+    /// component, so the link relies on the package and the intent filters.
+    pub fn browsable_intent_uri(&self) -> String {
+        let data = self
+            .data
+            .as_ref()
+            .map(|data| data.raw_identifier(0).replace('#', "%23"))
+            .unwrap_or_default();
+
+        // e.g. "intent://example.com/path#Intent;scheme=https;...;end"
+        let mut uri = String::from("intent:");
+        match data.split_once(':') {
+            Some((scheme, rest)) if is_uri_scheme(scheme) => {
+                write!(
+                    &mut uri,
+                    "{}#Intent;scheme={};",
+                    rest,
+                    encode_uri_component(scheme)
+                )
+                .unwrap();
+            }
+            _ => write!(&mut uri, "{}#Intent;", data).unwrap(),
+        }
+        write!(
+            &mut uri,
+            "package={};",
+            encode_uri_component(&self.component_package)
+        )
+        .unwrap();
+        self.write_intent_uri_fields(&mut uri);
+        uri
+    }
+
+    /// Writes the fields and extras of the intent URI format, up to the
+    /// final `end`.
+    fn write_intent_uri_fields(&self, uri: &mut String) {
+        write!(uri, "action={};", encode_uri_component(&self.action)).unwrap();
+        if self.mime_type != MimeType::Unset {
+            write!(
+                uri,
+                "type={};",
+                encode_uri_component(&self.mime_type.to_string())
+            )
+            .unwrap();
+        }
+        write!(
+            uri,
+            "launchFlags={:#x};component={}",
+            self.flags,
+            encode_uri_component(&self.component())
+        )
+        .unwrap();
+        if !self.category.is_empty() {
+            write!(uri, ";category={}", encode_uri_component(&self.category)).unwrap();
+        }
+
+        for (index, extra) in self.extras.iter().enumerate() {
+            let (prefix, key, value) = match &extra.value {
+                ExtraType::String(d_input) => ("S", extra.key.clone(), lossy(&d_input.buffer)),
+                ExtraType::URI(uri_input) => (
+                    "S",
+                    format!("malintent.uri:{}", extra.key),
+                    uri_input.raw_identifier(index + 1),
+                ),
+                ExtraType::ComponentName(d_input) => (
+                    "S",
+                    format!("malintent.component:{}", extra.key),
+                    lossy(&d_input.buffer),
+                ),
+                ExtraType::Boolean(_) => {
+                    ("B", extra.key.clone(), extra.value_arg(index + 1).unwrap())
+                }
+                ExtraType::Int(_) | ExtraType::Long(_) | ExtraType::Float(_) => {
+                    let Some(value) = extra.value_arg(index + 1) else {
+                        continue;
+                    };
+                    let prefix = match extra.value {
+                        ExtraType::Int(_) => "i",
+                        ExtraType::Long(_) => "l",
+                        _ => "f",
+                    };
+                    (prefix, extra.key.clone(), value)
+                }
+                // Arrays are passed as `am` takes them, marked with the `am`
+                // option of their type, e.g. `malintent.ia:` for `--eia`.
+                ExtraType::StringArray(array) | ExtraType::StringArrayList(array) => {
+                    let value = String::from_utf8_lossy(&array.am_value()).into_owned();
+                    if value.is_empty() {
+                        continue;
+                    }
+                    (
+                        "S",
+                        format!("malintent.{}:{}", extra.value, extra.key),
+                        value,
+                    )
+                }
+                ExtraType::IntArray(_)
+                | ExtraType::IntArrayList(_)
+                | ExtraType::LongArray(_)
+                | ExtraType::LongArrayList(_)
+                | ExtraType::FloatArray(_)
+                | ExtraType::FloatArrayList(_) => {
+                    let Some(value) = extra.value_arg(index + 1) else {
+                        continue;
+                    };
+                    (
+                        "S",
+                        format!("malintent.{}:{}", extra.value, extra.key),
+                        value,
+                    )
+                }
+            };
+            write!(
+                uri,
+                ";{}.{}={}",
+                prefix,
+                encode_uri_component(&key),
+                encode_uri_component(&value)
+            )
+            .unwrap();
+        }
+
+        uri.push_str(";end");
+    }
+
+    fn shell_command_for(&self, component: &str) -> String {
         // The way adb shell handles commands is documented here:
         //   https://developer.android.com/studio/command-line/adb#shellcommands
         // but basically we need to generate the command we want to run as
@@ -53,17 +327,15 @@ impl IntentInput {
             _ => panic!("Unsupported receiver type"),
         };
 
-        let mut command = format!(
-            "am {} -n '{}' -a '{}' -t '{}' --grant-read-uri-permission ",
-            am_command,
-            self.component(),
-            self.action,
-            self.mime_type
-        );
+        let mut command = format!("am {} -n '{}' -a '{}' ", am_command, component, self.action);
+        if self.mime_type != MimeType::Unset {
+            write!(&mut command, "-t '{}' ", self.mime_type).unwrap();
+        }
+        write!(&mut command, "{} ", self.uri_grant).unwrap();
 
         // Append data to the shell_command if it exists.
         if let Some(data) = &self.data {
-            write!(&mut command, " -d '{}'", data.identifier(0)).unwrap();
+            write!(&mut command, " -d $'{}'", data.identifier(0)).unwrap();
         }
 
         // Append category to the shell_command if it exists.
@@ -96,6 +368,22 @@ impl IntentInput {
         hasher.write(&serde_json::to_vec(&self.data).unwrap());
         hasher.write(self.mime_type.to_string().as_bytes());
         hasher.write(&self.flags.to_le_bytes());
+        // Only hash non-default grants to keep the hashes of existing inputs.
+        if self.uri_grant != URIGrant::default() {
+            hasher.write(self.uri_grant.to_string().as_bytes());
+        }
+        if let Some(target) = &self.pending_intent {
+            hasher.write(target.notification_key.as_bytes());
+            hasher.write(
+                &target
+                    .action
+                    .map_or(-1, |action| action as i64)
+                    .to_le_bytes(),
+            );
+        }
+        if let Some(permission) = &self.permission {
+            hasher.write(permission.as_bytes());
+        }
 
         for extra in &self.extras {
             hasher.write(extra.key.as_bytes());
@@ -105,28 +393,119 @@ impl IntentInput {
         format!("{:032x}", hasher.finish_ext())
     }
 
+    /// The files and content provider URIs (with their content) that have to
+    /// be staged on the device before sending the intent.
+    pub fn uri_payloads(&self) -> (UriPayloads, UriPayloads) {
+        let mut files = Vec::new();
+        let mut contents = Vec::new();
+        self.extras
+            .iter()
+            .enumerate()
+            .filter_map(|(index, extra)| match &extra.value {
+                ExtraType::URI(uri) => Some((index + 1, uri)),
+                _ => None,
+            })
+            .chain(self.data.iter().map(|uri| (0, uri)))
+            .for_each(|(id, uri)| {
+                let identifier = uri.identifier(id);
+                let content_bytes = uri.content.bytes().to_vec();
+
+                // Depending on the scheme, create the file or register the content on the adb device
+                // Note that we need to skip the "file://" prefix for the identifier if it is a file
+                match uri.scheme {
+                    URIScheme::Content => contents.push((identifier, content_bytes)),
+                    URIScheme::File => files.push((identifier[7..].to_owned(), content_bytes)),
+                    URIScheme::Other => {}
+                }
+            });
+        (files, contents)
+    }
+
     /// The component that receives the intent, e.g
     /// `com.example.app/.ExampleActivity`
     pub fn component(&self) -> String {
         format!("{}/{}", self.component_package, self.component_class)
     }
+
+    /// A short summary of the input for its file name: the class name, the
+    /// last segment of the action and the number and first keys of the
+    /// extras, e.g. `MainActivity_VIEW_e2-url-id`.
+    fn name_summary(&self) -> String {
+        let last_segment = |name: &str| {
+            name.rsplit('.')
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+                .take(NAME_PART_LEN)
+                .collect::<String>()
+        };
+
+        let mut extras = format!("e{}", self.extras.len());
+        for extra in self.extras.iter().take(2) {
+            write!(&mut extras, "-{}", last_segment(&extra.key)).unwrap();
+        }
+
+        let action = match last_segment(&self.action) {
+            action if action.is_empty() => "noaction".to_owned(),
+            action => action,
+        };
+        let component = match &self.pending_intent {
+            Some(target) => match target.action {
+                Some(index) => format!("notification-action{}", index),
+                None => "notification-content".to_owned(),
+            },
+            None => last_segment(&self.component_class),
+        };
+        format!("{}_{}_{}", component, action, extras)
+    }
 }
 
 impl Input for IntentInput {
+    /// Writes this input as versioned JSON, see [input_schema].
+    fn to_file<P>(&self, path: P) -> Result<(), libafl::Error>
+    where
+        P: AsRef<Path>,
+    {
+        write_file_atomic(path, input_schema::to_json(self)?.as_bytes())
+    }
+
+    /// Reads an input of any schema version, see [input_schema].
+    fn from_file<P>(path: P) -> Result<Self, libafl::Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(input_schema::from_bytes(&std::fs::read(path)?)?.0)
+    }
+
     /// Generate a name for this input
     #[must_use]
     fn generate_name(&self, idx: usize) -> String {
-        format!("id_{idx}_{hash}", idx = idx, hash = self.hash())
+        format!(
+            "id_{idx}_{summary}_{hash}",
+            idx = idx,
+            summary = self.name_summary(),
+            hash = self.hash()
+        )
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, EnumIter, Copy, PartialEq)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, EnumIter, Copy, PartialEq)]
 pub enum ReceiverType {
     Activity,
     Service,
     BroadcastReceiver,
 }
 
+/// A PendingIntent of a notification posted by the app.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PendingIntentTarget {
+    /// The key of the notification.
+    pub notification_key: String,
+    /// The index of the notification action, None for the content intent.
+    pub action: Option<usize>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ExtraInput {
     // The `key` of the extra input.
@@ -138,7 +517,13 @@ pub struct ExtraInput {
 impl ExtraInput {
     /// The command line arguments for this extra input.
     pub fn command_args(&self, index: usize) -> Option<String> {
-        let arg_string = match &self.value {
+        self.value_arg(index)
+            .map(|v| format!(" --e{} {} $'{}'", self.value, shell_quote(&self.key), v))
+    }
+
+    /// The value of this extra input as it is passed on the command line.
+    pub fn value_arg(&self, index: usize) -> Option<String> {
+        match &self.value {
             ExtraType::URI(uri_input) => Some(uri_input.identifier(index)),
             ExtraType::String(d_input) => Some(encode_hex(d_input.buffer.bytes())),
             ExtraType::Boolean(d_input) => {
@@ -154,20 +539,9 @@ impl ExtraInput {
             ExtraType::Long(d_input) => {
                 Some(i64::from_le_bytes(d_input.buffer.bytes().try_into().ok()?).to_string())
             }
-            ExtraType::Float(d_input) => {
-                let value_f32 = f32::from_le_bytes(d_input.buffer.bytes().try_into().ok()?);
-                if value_f32.is_infinite() {
-                    Some(if value_f32.is_sign_positive() {
-                        "Infinity".to_string()
-                    } else {
-                        "-Infinity".to_string()
-                    })
-                } else if value_f32.is_nan() {
-                    Some("NaN".to_string())
-                } else {
-                    Some(value_f32.to_string())
-                }
-            }
+            ExtraType::Float(d_input) => Some(float_arg(f32::from_le_bytes(
+                d_input.buffer.bytes().try_into().ok()?,
+            ))),
             ExtraType::IntArray(d_input) | ExtraType::IntArrayList(d_input) => {
                 let values: Vec<i32> = d_input
                     .buffer
@@ -222,25 +596,32 @@ impl ExtraInput {
 
                 let output = values
                     .iter()
-                    .map(ToString::to_string)
+                    .map(|value| float_arg(*value))
                     .collect::<Vec<_>>()
                     .join(",");
 
                 Some(output).filter(|output| !output.is_empty())
             }
-            ExtraType::StringArray(d_input) | ExtraType::StringArrayList(d_input) => {
-                let result = d_input
-                    .buffer
-                    .bytes()
-                    .iter()
-                    .map(|byte| if *byte == 0 { b',' } else { *byte })
-                    .collect::<Vec<u8>>();
-                Some(encode_hex(&result)).filter(|output| !output.is_empty())
+            ExtraType::StringArray(array) | ExtraType::StringArrayList(array) => {
+                Some(encode_hex(&array.am_value())).filter(|output| !output.is_empty())
             }
             _ => None,
-        };
+        }
+    }
+}
 
-        arg_string.map(|v| format!(" --e{} '{}' $'{}'", self.value, self.key, v))
+/// A float as Java's `Float.parseFloat` accepts it.
+fn float_arg(value: f32) -> String {
+    if value.is_infinite() {
+        if value.is_sign_positive() {
+            "Infinity".to_string()
+        } else {
+            "-Infinity".to_string()
+        }
+    } else if value.is_nan() {
+        "NaN".to_string()
+    } else {
+        value.to_string()
     }
 }
 
@@ -254,7 +635,61 @@ pub struct URIInput {
     pub content: BytesInput,
 }
 
+/// Whether the value is a valid URI scheme (RFC 3986).
+pub fn is_uri_scheme(value: &str) -> bool {
+    value.starts_with(|c: char| c.is_ascii_alphabetic())
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+}
+
+/// The bytes as a (lossy) UTF-8 string.
+fn lossy(buffer: &BytesInput) -> String {
+    String::from_utf8_lossy(buffer.bytes()).into_owned()
+}
+
 impl URIInput {
+    /// The URI itself, unlike [URIInput::identifier] not escaped for the
+    /// shell.
+    pub fn raw_identifier(&self, id: usize) -> String {
+        match &self.scheme {
+            URIScheme::Other => lossy(&self.content),
+            _ => self.identifier(id),
+        }
+    }
+
+    /// A URI with staged content, starting with the magic bytes of the
+    /// suffix.
+    pub fn staged(scheme: URIScheme, suffix: URISuffix) -> Self {
+        let content = match scheme {
+            URIScheme::Other => Vec::new(),
+            _ => suffix.magic_bytes().to_vec(),
+        };
+        Self {
+            scheme,
+            suffix,
+            content: BytesInput::new(content),
+        }
+    }
+
+    /// Changes the suffix, replacing staged content with the magic bytes of
+    /// the new format, as content of the previous format is of no use.
+    pub fn set_suffix(&mut self, suffix: URISuffix) {
+        if suffix != self.suffix && self.scheme != URIScheme::Other {
+            self.content = BytesInput::new(suffix.magic_bytes().to_vec());
+        }
+        self.suffix = suffix;
+    }
+
+    /// A URI passed to the app as is, without staging any content for it.
+    pub fn raw(uri: &str) -> Self {
+        Self {
+            scheme: URIScheme::Other,
+            suffix: URISuffix::TXT,
+            content: BytesInput::new(uri.as_bytes().to_vec()),
+        }
+    }
+
     pub fn identifier(&self, id: usize) -> String {
         match &self.scheme {
             URIScheme::Other => encode_hex(self.content.bytes()),
@@ -263,7 +698,7 @@ impl URIInput {
                     URIScheme::Content => {
                         "org.gts3.jnifuzz.contentprovider.provider/external_files"
                     }
-                    URIScheme::File => "/data/local/tmp",
+                    URIScheme::File => FILE_DIR.get().map_or(DEFAULT_FILE_DIR, String::as_str),
                     _ => unreachable!(),
                 };
 
@@ -282,8 +717,40 @@ pub struct DirectInput {
     pub buffer: BytesInput,
 }
 
+/// The elements of a String array extra, so mutations keep the element
+/// boundaries intact.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct StringArrayInput {
+    pub elements: Vec<BytesInput>,
+}
+
+impl StringArrayInput {
+    /// The elements as the comma-separated value `am` splits on commas not
+    /// preceded by a backslash. Commas in elements are escaped, which `am`
+    /// keeps in the element. Zero bytes cannot be passed on the command line
+    /// and trailing backslashes would escape the separator, so both are left
+    /// out.
+    fn am_value(&self) -> Vec<u8> {
+        let mut value = Vec::new();
+        for (index, element) in self.elements.iter().enumerate() {
+            if index > 0 {
+                value.push(b',');
+            }
+            let bytes = element.bytes();
+            let end = bytes.len() - bytes.iter().rev().take_while(|b| **b == b'\\').count();
+            for byte in bytes[..end].iter().filter(|b| **b != 0) {
+                if *byte == b',' {
+                    value.push(b'\\');
+                }
+                value.push(*byte);
+            }
+        }
+        value
+    }
+}
+
 // Enum for the different types of URI schemes.
-#[derive(Serialize, Deserialize, Clone, Debug, EnumIter)]
+#[derive(Serialize, Deserialize, Clone, Debug, EnumIter, PartialEq, Eq)]
 pub enum URIScheme {
     Content,
     File,
@@ -300,8 +767,37 @@ impl fmt::Display for URIScheme {
     }
 }
 
+/// The URI permissions granted to the receiver of the intent. Inputs without
+/// any (or with unusual) grants exercise how the app handles URIs it is not
+/// allowed to open.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, EnumIter, PartialEq)]
+pub enum URIGrant {
+    None,
+    #[default]
+    Read,
+    ReadWrite,
+    Persistable,
+}
+
+impl fmt::Display for URIGrant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            URIGrant::None => Ok(()),
+            URIGrant::Read => write!(f, "--grant-read-uri-permission"),
+            URIGrant::ReadWrite => write!(
+                f,
+                "--grant-read-uri-permission --grant-write-uri-permission"
+            ),
+            URIGrant::Persistable => write!(
+                f,
+                "--grant-read-uri-permission --grant-persistable-uri-permission"
+            ),
+        }
+    }
+}
+
 // Enum for the different suffixes of URI inputs.
-#[derive(Serialize, Deserialize, Clone, Debug, EnumIter)]
+#[derive(Serialize, Deserialize, Clone, Debug, EnumIter, PartialEq, Eq, Hash)]
 pub enum URISuffix {
     AAC,
     APK,
@@ -321,6 +817,51 @@ pub enum URISuffix {
     XML,
 }
 
+impl URISuffix {
+    /// The magic bytes or minimal valid header of the format, so parsers of
+    /// the format accept the content before looking at the mutated rest.
+    pub fn magic_bytes(&self) -> &'static [u8] {
+        match self {
+            // ADTS frame header
+            URISuffix::AAC => b"\xff\xf1\x50\x80\x02\x1f\xfc",
+            // ZIP local file header
+            URISuffix::APK => b"PK\x03\x04\x14\x00\x00\x00\x08\x00",
+            // Header and screen descriptor of a 1x1 image
+            URISuffix::GIF => b"GIF89a\x01\x00\x01\x00\x00\x00\x00",
+            URISuffix::HTML => b"<!DOCTYPE html><html><body></body></html>",
+            // SOI and JFIF APP0 segment
+            URISuffix::JPG => {
+                b"\xff\xd8\xff\xe0\x00\x10JFIF\x00\x01\x01\x00\x00\x01\x00\x01\x00\x00"
+            }
+            // Header chunk of a single track file
+            URISuffix::MIDI => b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60",
+            // ID3v2 tag header
+            URISuffix::MP3 => b"ID3\x03\x00\x00\x00\x00\x00\x00",
+            // ftyp box
+            URISuffix::MP4 => b"\x00\x00\x00\x18ftypisom\x00\x00\x02\x00isommp42",
+            // Page header of the first page
+            URISuffix::OGG => {
+                b"OggS\x00\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00"
+            }
+            URISuffix::PDF => b"%PDF-1.4\n",
+            // Signature and IHDR chunk of a 1x1 RGB image
+            URISuffix::PNG => {
+                b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR\x00\x00\x00\x01\x00\x00\x00\x01\x08\x02\x00\x00\x00\x90\x77\x53\xde"
+            }
+            URISuffix::TXT => b"",
+            // RIFF header with a PCM format chunk and an empty data chunk
+            URISuffix::WAV => {
+                b"RIFF\x24\x00\x00\x00WAVEfmt \x10\x00\x00\x00\x01\x00\x01\x00\x44\xac\x00\x00\x88\x58\x01\x00\x02\x00\x10\x00data\x00\x00\x00\x00"
+            }
+            // ASF header object GUID
+            URISuffix::WMA | URISuffix::WMV => {
+                b"\x30\x26\xb2\x75\x8e\x66\xcf\x11\xa6\xd9\x00\xaa\x00\x62\xce\x6c"
+            }
+            URISuffix::XML => b"<?xml version=\"1.0\"?>\n<a/>",
+        }
+    }
+}
+
 impl fmt::Display for URISuffix {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -360,13 +901,20 @@ pub enum ExtraType {
     LongArrayList(DirectInput),
     FloatArray(DirectInput),
     FloatArrayList(DirectInput),
-    StringArray(DirectInput),
-    StringArrayList(DirectInput),
+    StringArray(StringArrayInput),
+    StringArrayList(StringArrayInput),
 }
 
 impl ExtraType {
-    pub fn content_buffer(&mut self) -> &mut BytesInput {
-        match self {
+    /// Whether this extra is a URI backed by a file or content provider.
+    pub fn is_uri(&self) -> bool {
+        matches!(self, ExtraType::URI(_))
+    }
+
+    /// The buffer holding the value of this extra. None for String arrays,
+    /// whose elements are mutated one by one.
+    pub fn content_buffer(&mut self) -> Option<&mut BytesInput> {
+        Some(match self {
             ExtraType::URI(uri_input) => &mut uri_input.content,
             ExtraType::String(d_input) => &mut d_input.buffer,
             ExtraType::Boolean(d_input) => &mut d_input.buffer,
@@ -380,9 +928,8 @@ impl ExtraType {
             ExtraType::LongArrayList(d_input) => &mut d_input.buffer,
             ExtraType::FloatArray(d_input) => &mut d_input.buffer,
             ExtraType::FloatArrayList(d_input) => &mut d_input.buffer,
-            ExtraType::StringArray(d_input) => &mut d_input.buffer,
-            ExtraType::StringArrayList(d_input) => &mut d_input.buffer,
-        }
+            ExtraType::StringArray(_) | ExtraType::StringArrayList(_) => return None,
+        })
     }
 }
 
@@ -409,7 +956,7 @@ impl fmt::Display for ExtraType {
 }
 
 // Enum for the following mime types:
-#[derive(Serialize, Deserialize, Clone, Debug, EnumIter, Copy)]
+#[derive(Serialize, Deserialize, Clone, Debug, EnumIter, Copy, PartialEq, Eq)]
 pub enum MimeType {
     ApplicationPdf,
     ApplicationVndAndroidPackageArchive,
@@ -429,6 +976,34 @@ pub enum MimeType {
     VideoMp4,
     VideoXMsVideo,
     VideoXMsWmv,
+    /// No type at all, `-t` is left out of the command.
+    Unset,
+}
+
+impl MimeType {
+    /// The suffix of staged content of this type, if there is one.
+    pub fn suffix(&self) -> Option<URISuffix> {
+        match self {
+            MimeType::ApplicationPdf => Some(URISuffix::PDF),
+            MimeType::ApplicationVndAndroidPackageArchive => Some(URISuffix::APK),
+            MimeType::AudioAac => Some(URISuffix::AAC),
+            MimeType::AudioMidi => Some(URISuffix::MIDI),
+            MimeType::AudioMpeg => Some(URISuffix::MP3),
+            MimeType::AudioMpeg4Generic => Some(URISuffix::MP4),
+            MimeType::AudioOgg => Some(URISuffix::OGG),
+            MimeType::AudioWav => Some(URISuffix::WAV),
+            MimeType::AudioXMsWma => Some(URISuffix::WMA),
+            MimeType::ImageGif => Some(URISuffix::GIF),
+            MimeType::ImageJpeg => Some(URISuffix::JPG),
+            MimeType::ImagePng => Some(URISuffix::PNG),
+            MimeType::TextHtml => Some(URISuffix::HTML),
+            MimeType::TextPlain => Some(URISuffix::TXT),
+            MimeType::TextXml => Some(URISuffix::XML),
+            MimeType::VideoMp4 => Some(URISuffix::MP4),
+            MimeType::VideoXMsWmv => Some(URISuffix::WMV),
+            MimeType::VideoXMsVideo | MimeType::Unset => None,
+        }
+    }
 }
 
 impl fmt::Display for MimeType {
@@ -454,6 +1029,7 @@ impl fmt::Display for MimeType {
             MimeType::VideoMp4 => write!(f, "video/mp4"),
             MimeType::VideoXMsVideo => write!(f, "video/x-msvideo"),
             MimeType::VideoXMsWmv => write!(f, "video/x-ms-wmv"),
+            MimeType::Unset => Ok(()),
         }
     }
 }
@@ -0,0 +1,122 @@
+//! The input model of schema version 0, frozen.
+//!
+//! Inputs stored before the versioning used libAFL's default postcard
+//! encoding, which is not self-describing: it can only be decoded with the
+//! exact model that wrote it. This is that model, as of the first release of
+//! the fuzzer. [input_schema](crate::input_schema) decodes unversioned binary
+//! inputs with it and migrates their JSON to the current model. Never change
+//! these types.
+
+// The names of the types and variants are part of the JSON they migrate from.
+#![allow(clippy::upper_case_acronyms)]
+
+use libafl::prelude::BytesInput;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct IntentInputV0 {
+    pub receiver_type: ReceiverType,
+    pub component_package: String,
+    pub component_class: String,
+    pub action: String,
+    pub category: String,
+    pub data: Option<URIInput>,
+    pub mime_type: MimeType,
+    pub flags: u32,
+    pub extras: Vec<ExtraInput>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ReceiverType {
+    Activity,
+    Service,
+    BroadcastReceiver,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExtraInput {
+    pub key: String,
+    pub value: ExtraType,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct URIInput {
+    pub scheme: URIScheme,
+    pub suffix: URISuffix,
+    pub content: BytesInput,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DirectInput {
+    pub buffer: BytesInput,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum URIScheme {
+    Content,
+    File,
+    Other,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum URISuffix {
+    AAC,
+    APK,
+    GIF,
+    HTML,
+    JPG,
+    MIDI,
+    MP3,
+    MP4,
+    OGG,
+    PDF,
+    PNG,
+    TXT,
+    WAV,
+    WMA,
+    WMV,
+    XML,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ExtraType {
+    String(DirectInput),
+    Boolean(DirectInput),
+    Int(DirectInput),
+    Long(DirectInput),
+    Float(DirectInput),
+    URI(URIInput),
+    ComponentName(DirectInput),
+    IntArray(DirectInput),
+    IntArrayList(DirectInput),
+    LongArray(DirectInput),
+    LongArrayList(DirectInput),
+    FloatArray(DirectInput),
+    FloatArrayList(DirectInput),
+    /// The elements separated by zero bytes.
+    StringArray(DirectInput),
+    /// The elements separated by zero bytes.
+    StringArrayList(DirectInput),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum MimeType {
+    ApplicationPdf,
+    ApplicationVndAndroidPackageArchive,
+    AudioAac,
+    AudioMidi,
+    AudioMpeg,
+    AudioMpeg4Generic,
+    AudioOgg,
+    AudioWav,
+    AudioXMsWma,
+    ImageGif,
+    ImageJpeg,
+    ImagePng,
+    TextHtml,
+    TextPlain,
+    TextXml,
+    VideoMp4,
+    VideoXMsVideo,
+    VideoXMsWmv,
+}
@@ -4,18 +4,23 @@ use std::marker::PhantomData;
 
 use libafl::{
     prelude::{
-        tuple_list, tuple_list_type, BytesInput, HasBytesVec, MutationResult, Mutator, Named, Rand,
-        StdScheduledMutator,
+        tuple_list, tuple_list_type, BytesInput, CorpusId, HasBytesVec, MutationResult, Mutator,
+        Named, Rand, StdScheduledMutator,
     },
-    state::{HasCorpus, HasMaxSize, HasNamedMetadata, HasRand},
+    state::{HasCorpus, HasMaxSize, HasMetadata, HasNamedMetadata, HasRand},
 };
 use strum::IntoEnumIterator;
 
 use crate::{
-    intent_generator::IntentTemplate,
+    app_links::AppLinks,
+    data_filter::DataBoundaryURIs,
+    format_seeds::FormatSeeds,
+    intent_generator::{ComponentAliases, ExportedComponents, IntentTemplate},
     intent_input::{
-        DirectInput, ExtraInput, ExtraType, IntentInput, MimeType, URIInput, URIScheme, URISuffix,
+        DirectInput, ExtraInput, ExtraType, IntentInput, MimeType, ReceiverType, StringArrayInput,
+        URIGrant, URIInput, URIScheme, URISuffix,
     },
+    observed_values::ObservedValues,
     util::COMMON_EXTRA_KEYS,
 };
 
@@ -69,15 +74,17 @@ where
     S: HasRand + HasCorpus + HasMaxSize,
 {
     backing_byte_mutator: StdScheduledMutator<BytesInput, BaseByteMutationsType, S>,
+    format_seeds: FormatSeeds,
 }
 
 impl<S> IntentRandomDataMutator<S>
 where
     S: HasRand + HasCorpus + HasMaxSize,
 {
-    pub fn new() -> Self {
+    pub fn new(format_seeds: FormatSeeds) -> Self {
         Self {
             backing_byte_mutator: StdScheduledMutator::new(base_byte_mutations()),
+            format_seeds,
         }
     }
 }
@@ -110,23 +117,27 @@ where
                 }
                 2 => {
                     // Mutate the suffix
-                    uri_input.suffix = state.rand_mut().choose(URISuffix::iter());
+                    let suffix = state.rand_mut().choose(URISuffix::iter());
+                    self.format_seeds.set_suffix(state, uri_input, suffix);
                 }
                 _ => {
-                    // Mutate the content
-                    return self.backing_byte_mutator.mutate(
-                        state,
-                        &mut uri_input.content,
-                        stage_idx,
-                    );
+                    // Mutate the content, which only exceeds the max size
+                    // if it started from a format seed, and never grows
+                    // beyond it.
+                    let max_len = uri_input.content.bytes().len().max(state.max_size());
+                    let result =
+                        self.backing_byte_mutator
+                            .mutate(state, &mut uri_input.content, stage_idx);
+                    uri_input.content.bytes_mut().truncate(max_len);
+                    return result;
                 }
             },
             None => {
-                let mut uri_input = URIInput {
-                    scheme: state.rand_mut().choose(URIScheme::iter()),
-                    suffix: state.rand_mut().choose(URISuffix::iter()),
-                    content: BytesInput::new(Vec::new()),
-                };
+                let mut uri_input = URIInput::staged(
+                    state.rand_mut().choose(URIScheme::iter()),
+                    state.rand_mut().choose(URISuffix::iter()),
+                );
+                self.format_seeds.seed_content(state, &mut uri_input);
 
                 let result =
                     self.backing_byte_mutator
@@ -186,11 +197,351 @@ where
     }
 }
 
-// Mutator that randomly modifies the key attribute of the extra.
+/// Mutator that removes the data of the intent. Once added, the data
+/// can otherwise never be removed again.
+pub struct IntentRemoveDataMutator<S>
+where
+    S: HasRand,
+{
+    phantom: PhantomData<S>,
+}
+
+impl<S> IntentRemoveDataMutator<S>
+where
+    S: HasRand,
+{
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Named for IntentRemoveDataMutator<S>
+where
+    S: HasRand,
+{
+    fn name(&self) -> &str {
+        "IntentRemoveDataMutator"
+    }
+}
+
+impl<S> Mutator<IntentInput, S> for IntentRemoveDataMutator<S>
+where
+    S: HasRand,
+{
+    fn mutate(
+        &mut self,
+        _state: &mut S,
+        input: &mut IntentInput,
+        _stage_idx: i32,
+    ) -> Result<libafl::prelude::MutationResult, libafl::Error> {
+        if input.data.take().is_none() {
+            return Ok(MutationResult::Skipped);
+        }
+        Ok(MutationResult::Mutated)
+    }
+}
+
+/// Mutator that removes the type of the intent.
+pub struct IntentUnsetMimeTypeMutator<S>
+where
+    S: HasRand,
+{
+    phantom: PhantomData<S>,
+}
+
+impl<S> IntentUnsetMimeTypeMutator<S>
+where
+    S: HasRand,
+{
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Named for IntentUnsetMimeTypeMutator<S>
+where
+    S: HasRand,
+{
+    fn name(&self) -> &str {
+        "IntentUnsetMimeTypeMutator"
+    }
+}
+
+impl<S> Mutator<IntentInput, S> for IntentUnsetMimeTypeMutator<S>
+where
+    S: HasRand,
+{
+    fn mutate(
+        &mut self,
+        _state: &mut S,
+        input: &mut IntentInput,
+        _stage_idx: i32,
+    ) -> Result<libafl::prelude::MutationResult, libafl::Error> {
+        if input.mime_type == MimeType::Unset {
+            return Ok(MutationResult::Skipped);
+        }
+        input.mime_type = MimeType::Unset;
+        Ok(MutationResult::Mutated)
+    }
+}
+
+/// Mutator that changes the URI permissions granted with the intent.
+pub struct IntentRandomURIGrantMutator<S>
+where
+    S: HasRand,
+{
+    phantom: PhantomData<S>,
+}
+
+impl<S> IntentRandomURIGrantMutator<S>
+where
+    S: HasRand,
+{
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Named for IntentRandomURIGrantMutator<S>
+where
+    S: HasRand,
+{
+    fn name(&self) -> &str {
+        "IntentRandomURIGrantMutator"
+    }
+}
+
+impl<S> Mutator<IntentInput, S> for IntentRandomURIGrantMutator<S>
+where
+    S: HasRand,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut IntentInput,
+        _stage_idx: i32,
+    ) -> Result<libafl::prelude::MutationResult, libafl::Error> {
+        input.uri_grant = state.rand_mut().choose(URIGrant::iter());
+        Ok(MutationResult::Mutated)
+    }
+}
+
+/// Mutator that replaces the data of the intent with a URI at the match
+/// boundary of the data filters of its component.
+pub struct IntentDataBoundaryMutator<S>
+where
+    S: HasRand + HasNamedMetadata,
+{
+    phantom: PhantomData<S>,
+}
+
+impl<S> IntentDataBoundaryMutator<S>
+where
+    S: HasRand + HasNamedMetadata,
+{
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Named for IntentDataBoundaryMutator<S>
+where
+    S: HasRand + HasNamedMetadata,
+{
+    fn name(&self) -> &str {
+        "IntentDataBoundaryMutator"
+    }
+}
+
+impl<S> Mutator<IntentInput, S> for IntentDataBoundaryMutator<S>
+where
+    S: HasRand + HasNamedMetadata,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut IntentInput,
+        _stage_idx: i32,
+    ) -> Result<libafl::prelude::MutationResult, libafl::Error> {
+        let uris = match state
+            .named_metadata::<DataBoundaryURIs>("data_boundary_uris")
+            .ok()
+            .and_then(|boundaries| boundaries.uris.get(&input.component()))
+        {
+            Some(uris) => uris.clone(),
+            None => return Ok(MutationResult::Skipped),
+        };
+
+        // Mostly pick URIs a web link would deliver to the app, but keep
+        // trying the others.
+        let resolving: Vec<String> = state
+            .named_metadata::<AppLinks>("app_links")
+            .map(|app_links| {
+                uris.iter()
+                    .filter(|uri| app_links.resolves(uri))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        let uris = if !resolving.is_empty() && state.rand_mut().below(4) != 0 {
+            resolving
+        } else {
+            uris
+        };
+
+        let uri = state.rand_mut().choose(uris);
+        input.data = Some(URIInput::raw(&uri));
+        Ok(MutationResult::Mutated)
+    }
+}
+
+/// Mutator that retargets the intent at another activity-alias of the same
+/// activity.
+pub struct IntentRandomAliasMutator<S>
+where
+    S: HasRand + HasNamedMetadata,
+{
+    phantom: PhantomData<S>,
+}
+
+impl<S> IntentRandomAliasMutator<S>
+where
+    S: HasRand + HasNamedMetadata,
+{
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Named for IntentRandomAliasMutator<S>
+where
+    S: HasRand + HasNamedMetadata,
+{
+    fn name(&self) -> &str {
+        "IntentRandomAliasMutator"
+    }
+}
+
+impl<S> Mutator<IntentInput, S> for IntentRandomAliasMutator<S>
+where
+    S: HasRand + HasNamedMetadata,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut IntentInput,
+        _stage_idx: i32,
+    ) -> Result<libafl::prelude::MutationResult, libafl::Error> {
+        let alias_set = match state
+            .named_metadata::<ComponentAliases>("component_aliases")
+            .ok()
+            .and_then(|aliases| aliases.aliases.get(&input.component()))
+        {
+            Some(alias_set) => alias_set.clone(),
+            None => return Ok(MutationResult::Skipped),
+        };
+
+        let component = state.rand_mut().choose(alias_set);
+        match component.split_once('/') {
+            Some((_, class)) if class != input.component_class => {
+                input.component_class = class.to_owned();
+                Ok(MutationResult::Mutated)
+            }
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+/// Mutator that retargets the intent, with its data and extras, at another
+/// component of the campaign, if enabled.
+pub struct IntentRandomComponentMutator<S>
+where
+    S: HasRand + HasNamedMetadata,
+{
+    enabled: bool,
+    phantom: PhantomData<S>,
+}
+
+impl<S> IntentRandomComponentMutator<S>
+where
+    S: HasRand + HasNamedMetadata,
+{
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Named for IntentRandomComponentMutator<S>
+where
+    S: HasRand + HasNamedMetadata,
+{
+    fn name(&self) -> &str {
+        "IntentRandomComponentMutator"
+    }
+}
+
+impl<S> Mutator<IntentInput, S> for IntentRandomComponentMutator<S>
+where
+    S: HasRand + HasNamedMetadata,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut IntentInput,
+        _stage_idx: i32,
+    ) -> Result<libafl::prelude::MutationResult, libafl::Error> {
+        // The component of a PendingIntent is fixed.
+        if !self.enabled || input.pending_intent.is_some() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let current = input.component();
+        let others: Vec<(String, ReceiverType, Option<String>)> = state
+            .named_metadata::<ExportedComponents>("exported_components")
+            .map(|exported| {
+                exported
+                    .components
+                    .iter()
+                    .filter(|(component, _, _)| *component != current)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        if others.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let (component, receiver_type, permission) = state.rand_mut().choose(others);
+        let Some((package, class)) = component.split_once('/') else {
+            return Ok(MutationResult::Skipped);
+        };
+        input.component_package = package.to_owned();
+        input.component_class = class.to_owned();
+        input.receiver_type = receiver_type;
+        input.permission = permission;
+        Ok(MutationResult::Mutated)
+    }
+}
+
+// Mutator that adds a random extra, up to the given number of extras.
 pub struct IntentRandomAddExtraMutator<S>
 where
     S: HasRand + HasCorpus + HasMaxSize + HasNamedMetadata,
 {
+    max_extras: usize,
     backing_byte_mutator: StdScheduledMutator<BytesInput, BaseByteMutationsType, S>,
 }
 
@@ -207,8 +558,9 @@ impl<S> IntentRandomAddExtraMutator<S>
 where
     S: HasRand + HasCorpus + HasMaxSize + HasNamedMetadata,
 {
-    pub fn new() -> Self {
+    pub fn new(max_extras: usize) -> Self {
         Self {
+            max_extras,
             backing_byte_mutator: StdScheduledMutator::new(base_byte_mutations()),
         }
     }
@@ -224,7 +576,7 @@ where
         input: &mut IntentInput,
         stage_idx: i32,
     ) -> Result<libafl::prelude::MutationResult, libafl::Error> {
-        if input.extras.len() >= 10 {
+        if input.extras.len() >= self.max_extras {
             return Ok(MutationResult::Skipped);
         }
 
@@ -408,6 +760,7 @@ pub struct IntentRandomExtraSuffixMutator<S>
 where
     S: HasRand + HasCorpus + HasMaxSize + HasNamedMetadata,
 {
+    format_seeds: FormatSeeds,
     phantom: PhantomData<S>,
 }
 
@@ -424,8 +777,9 @@ impl<S> IntentRandomExtraSuffixMutator<S>
 where
     S: HasRand + HasCorpus + HasMaxSize + HasNamedMetadata,
 {
-    pub fn new() -> Self {
+    pub fn new(format_seeds: FormatSeeds) -> Self {
         Self {
+            format_seeds,
             phantom: PhantomData,
         }
     }
@@ -449,7 +803,8 @@ where
         // Mutate the suffix
         Ok(match &mut extra.value {
             ExtraType::URI(uri) => {
-                uri.suffix = state.rand_mut().choose(URISuffix::iter());
+                let suffix = state.rand_mut().choose(URISuffix::iter());
+                self.format_seeds.set_suffix(state, uri, suffix);
                 MutationResult::Mutated
             }
             _ => MutationResult::Skipped,
@@ -457,32 +812,776 @@ where
     }
 }
 
-// -----------------------------------------
+/// How the caller identity is encoded in an identity extra.
+#[derive(Clone, Copy)]
+enum IdentityValue {
+    /// The package name as String
+    PackageName,
+    /// An `android-app://<package>` URI as String
+    AppUriString,
+    /// An `android-app://<package>` URI
+    AppUri,
+    /// The uid of the package as Int
+    Uid,
+}
 
-/// Helper function to get an ExtraInput to mutate. Creates a new one if there
-/// are no extras yet.
-fn get_extra_to_mutate<'a, S>(
-    state: &mut S,
-    input: &'a mut IntentInput,
-) -> Result<&'a mut ExtraInput, libafl::Error>
+/// Extras apps use to learn who sent an intent, e.g. to check the caller
+/// against an allowlist.
+const IDENTITY_EXTRAS: [(&str, IdentityValue); 7] = [
+    (
+        "android.intent.extra.PACKAGE_NAME",
+        IdentityValue::PackageName,
+    ),
+    (
+        "android.intent.extra.CALLING_PACKAGE",
+        IdentityValue::PackageName,
+    ),
+    (
+        "android.intent.extra.INSTALLER_PACKAGE_NAME",
+        IdentityValue::PackageName,
+    ),
+    ("android.intent.extra.REFERRER", IdentityValue::AppUri),
+    (
+        "android.intent.extra.REFERRER_NAME",
+        IdentityValue::AppUriString,
+    ),
+    ("android.intent.extra.UID", IdentityValue::Uid),
+    ("android.intent.extra.ORIGINATING_UID", IdentityValue::Uid),
+];
+
+/// Mutator that sets a caller identity extra to another installed package,
+/// probing components that trust these values for authorization decisions.
+pub struct IntentIdentitySpoofMutator<S>
 where
-    S: HasRand + HasCorpus + HasMaxSize + HasNamedMetadata,
+    S: HasRand,
 {
-    if input.extras.is_empty() {
-        // Add a new extra.
-        return Err(libafl::Error::unknown("No extras to mutate"));
-    }
-
-    // Mutate one extra value.
-    let index = state.rand_mut().between(0, (input.extras.len() - 1) as u64) as usize;
+    /// Names and uids of the installed packages.
+    packages: Vec<(String, u32)>,
+    max_extras: usize,
+    phantom: PhantomData<S>,
+}
 
-    Ok(input.extras.get_mut(index).unwrap())
+impl<S> IntentIdentitySpoofMutator<S>
+where
+    S: HasRand,
+{
+    pub fn new(packages: Vec<(String, u32)>, max_extras: usize) -> Self {
+        Self {
+            packages,
+            max_extras,
+            phantom: PhantomData,
+        }
+    }
 }
 
-/// Helper function to get a random ExtraInput.
-fn generate_random_extra<S>(state: &mut S) -> ExtraInput
+impl<S> Named for IntentIdentitySpoofMutator<S>
 where
-    S: HasRand + HasCorpus + HasMaxSize + HasNamedMetadata,
+    S: HasRand,
+{
+    fn name(&self) -> &str {
+        "IntentIdentitySpoofMutator"
+    }
+}
+
+impl<S> Mutator<IntentInput, S> for IntentIdentitySpoofMutator<S>
+where
+    S: HasRand,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut IntentInput,
+        _stage_idx: i32,
+    ) -> Result<libafl::prelude::MutationResult, libafl::Error> {
+        if self.packages.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let (key, identity) = state.rand_mut().choose(IDENTITY_EXTRAS);
+        let (package, uid) = state.rand_mut().choose(&self.packages);
+        let string = |value: String| {
+            ExtraType::String(DirectInput {
+                buffer: BytesInput::new(value.into_bytes()),
+            })
+        };
+        let value = match identity {
+            IdentityValue::PackageName => string(package.clone()),
+            IdentityValue::AppUriString => string(format!("android-app://{}", package)),
+            IdentityValue::AppUri => {
+                ExtraType::URI(URIInput::raw(&format!("android-app://{}", package)))
+            }
+            IdentityValue::Uid => ExtraType::Int(DirectInput {
+                buffer: BytesInput::new(uid.to_le_bytes().to_vec()),
+            }),
+        };
+
+        match input.extras.iter().position(|extra| extra.key == key) {
+            Some(index) => input.extras[index].value = value,
+            None if input.extras.len() < self.max_extras => input.extras.push(ExtraInput {
+                key: key.to_owned(),
+                value,
+            }),
+            None => return Ok(MutationResult::Skipped),
+        }
+        Ok(MutationResult::Mutated)
+    }
+}
+
+/// Float values numeric parsing and aggregation code often mishandles: NaNs
+/// (quiet, signaling and negative), infinities, the smallest and largest
+/// subnormals, negative zero and the extremes of the normal range.
+const SPECIAL_FLOAT_BITS: [u32; 11] = [
+    0x7fc0_0000,
+    0x7f80_0001,
+    0xffc0_0000,
+    0x7f80_0000,
+    0xff80_0000,
+    0x0000_0001,
+    0x007f_ffff,
+    0x8000_0001,
+    0x8000_0000,
+    0x0080_0000,
+    0x7f7f_ffff,
+];
+
+/// Mutator that injects special float values at random positions of a
+/// FloatArray or FloatArrayList extra.
+pub struct IntentFloatArraySpecialValueMutator<S>
+where
+    S: HasRand + HasMaxSize,
+{
+    phantom: PhantomData<S>,
+}
+
+impl<S> IntentFloatArraySpecialValueMutator<S>
+where
+    S: HasRand + HasMaxSize,
+{
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Named for IntentFloatArraySpecialValueMutator<S>
+where
+    S: HasRand + HasMaxSize,
+{
+    fn name(&self) -> &str {
+        "IntentFloatArraySpecialValueMutator"
+    }
+}
+
+impl<S> Mutator<IntentInput, S> for IntentFloatArraySpecialValueMutator<S>
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut IntentInput,
+        _stage_idx: i32,
+    ) -> Result<libafl::prelude::MutationResult, libafl::Error> {
+        let arrays: Vec<&mut BytesInput> = input
+            .extras
+            .iter_mut()
+            .filter_map(|extra| match &mut extra.value {
+                ExtraType::FloatArray(value) | ExtraType::FloatArrayList(value) => {
+                    Some(&mut value.buffer)
+                }
+                _ => None,
+            })
+            .collect();
+        if arrays.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let max_size = state.max_size();
+        let buffer = state.rand_mut().choose(arrays).bytes_mut();
+        let bits = state.rand_mut().choose(SPECIAL_FLOAT_BITS).to_le_bytes();
+        let elements = buffer.len() / 4;
+        // Either overwrite an element or insert a new one.
+        let position = state.rand_mut().below(elements as u64 + 1) as usize * 4;
+        let overwrite = buffer.len() + 4 > max_size || state.rand_mut().below(2) == 0;
+        if position + 4 <= buffer.len() && overwrite {
+            buffer[position..position + 4].copy_from_slice(&bits);
+        } else if buffer.len() + 4 <= max_size {
+            buffer.splice(position..position, bits);
+        } else {
+            return Ok(MutationResult::Skipped);
+        }
+        Ok(MutationResult::Mutated)
+    }
+}
+
+/// Element counts at which array handling code often breaks, besides the
+/// largest count fitting into the max size.
+const BOUNDARY_ARRAY_LENGTHS: [usize; 7] = [0, 1, 2, 127, 128, 255, 256];
+
+/// The elements of a numeric array extra, None for other extras. A trailing
+/// partial element is padded like when it is sent.
+fn numeric_array_elements(value: &mut ExtraType) -> Option<Vec<Vec<u8>>> {
+    let element_size = match value {
+        ExtraType::IntArray(_)
+        | ExtraType::IntArrayList(_)
+        | ExtraType::FloatArray(_)
+        | ExtraType::FloatArrayList(_) => 4,
+        ExtraType::LongArray(_) | ExtraType::LongArrayList(_) => 8,
+        _ => return None,
+    };
+    Some(
+        value
+            .content_buffer()?
+            .bytes()
+            .chunks(element_size)
+            .map(|chunk| {
+                let mut element = chunk.to_vec();
+                element.resize(element_size, 0);
+                element
+            })
+            .collect(),
+    )
+}
+
+/// The elements of an array extra, None for other extras.
+fn array_elements(value: &mut ExtraType) -> Option<Vec<Vec<u8>>> {
+    match value {
+        ExtraType::StringArray(array) | ExtraType::StringArrayList(array) => Some(
+            array
+                .elements
+                .iter()
+                .map(|element| element.bytes().to_vec())
+                .collect(),
+        ),
+        _ => numeric_array_elements(value),
+    }
+}
+
+/// Mutator that resizes an array extra to a boundary length: empty, a single
+/// element, a few lengths around powers of two, the length of another array
+/// of the intent (apps often expect arrays of equal length, and templates do
+/// not record expected lengths), or as many elements as fit into the max size.
+/// Existing elements are repeated to fill the array.
+pub struct IntentArrayLengthMutator<S>
+where
+    S: HasRand + HasMaxSize,
+{
+    phantom: PhantomData<S>,
+}
+
+impl<S> IntentArrayLengthMutator<S>
+where
+    S: HasRand + HasMaxSize,
+{
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Named for IntentArrayLengthMutator<S>
+where
+    S: HasRand + HasMaxSize,
+{
+    fn name(&self) -> &str {
+        "IntentArrayLengthMutator"
+    }
+}
+
+impl<S> Mutator<IntentInput, S> for IntentArrayLengthMutator<S>
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut IntentInput,
+        _stage_idx: i32,
+    ) -> Result<libafl::prelude::MutationResult, libafl::Error> {
+        let arrays: Vec<(usize, usize)> = input
+            .extras
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, extra)| Some((index, array_elements(&mut extra.value)?.len())))
+            .collect();
+        if arrays.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let (index, length) = state.rand_mut().choose(arrays.clone());
+        let value = &mut input.extras[index].value;
+        let mut elements = array_elements(value).unwrap_or_default();
+        if elements.is_empty() {
+            elements.push(match value {
+                ExtraType::LongArray(_) | ExtraType::LongArrayList(_) => vec![0; 8],
+                ExtraType::StringArray(_) | ExtraType::StringArrayList(_) => b"a".to_vec(),
+                _ => vec![0; 4],
+            });
+        }
+
+        let max_size = state.max_size();
+        let average_size = elements.iter().map(Vec::len).sum::<usize>() / elements.len();
+        let mut lengths: Vec<usize> = BOUNDARY_ARRAY_LENGTHS
+            .into_iter()
+            .chain(arrays.iter().map(|(_, length)| *length))
+            .chain([max_size / average_size.max(1)])
+            .filter(|new_length| *new_length != length)
+            .collect();
+        lengths.sort_unstable();
+        lengths.dedup();
+        if lengths.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+        let new_length = state.rand_mut().choose(lengths);
+
+        let elements = elements.into_iter().cycle().take(new_length);
+        match value {
+            ExtraType::StringArray(array) | ExtraType::StringArrayList(array) => {
+                array.elements = elements.map(BytesInput::new).collect();
+            }
+            value => {
+                if let Some(buffer) = value.content_buffer() {
+                    *buffer.bytes_mut() = elements.flatten().collect();
+                    buffer.bytes_mut().truncate(max_size);
+                }
+            }
+        }
+        Ok(MutationResult::Mutated)
+    }
+}
+
+/// Characters string handling code often trips over: separators and quotes,
+/// whitespace and control characters, multi-byte and astral characters,
+/// combining marks, bidi overrides, zero-width characters and the BOM.
+const INTERESTING_CHARS: [char; 24] = [
+    '/',
+    '.',
+    ':',
+    '%',
+    '\'',
+    '"',
+    '\\',
+    '<',
+    '>',
+    '&',
+    '=',
+    '?',
+    '#',
+    ' ',
+    '\n',
+    '\t',
+    '\u{7f}',
+    '\u{e9}',
+    '\u{4e2d}',
+    '\u{1f600}',
+    '\u{301}',
+    '\u{202e}',
+    '\u{200b}',
+    '\u{feff}',
+];
+
+/// Mutator that edits a String or an element of a String array by
+/// characters instead of bytes: inserting, deleting, replacing or repeating
+/// them. Unlike the byte mutations, the result is always valid UTF-8, which
+/// Java would otherwise decode with replacement characters before the app
+/// sees it.
+pub struct IntentStringMutator<S>
+where
+    S: HasRand + HasMaxSize,
+{
+    phantom: PhantomData<S>,
+}
+
+impl<S> IntentStringMutator<S>
+where
+    S: HasRand + HasMaxSize,
+{
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Named for IntentStringMutator<S>
+where
+    S: HasRand + HasMaxSize,
+{
+    fn name(&self) -> &str {
+        "IntentStringMutator"
+    }
+}
+
+impl<S> Mutator<IntentInput, S> for IntentStringMutator<S>
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut IntentInput,
+        _stage_idx: i32,
+    ) -> Result<libafl::prelude::MutationResult, libafl::Error> {
+        let strings: Vec<&mut BytesInput> = input
+            .extras
+            .iter_mut()
+            .flat_map(|extra| match &mut extra.value {
+                ExtraType::String(value) => vec![&mut value.buffer],
+                ExtraType::StringArray(array) | ExtraType::StringArrayList(array) => {
+                    array.elements.iter_mut().collect()
+                }
+                _ => Vec::new(),
+            })
+            .collect();
+        if strings.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let buffer = state.rand_mut().choose(strings);
+        let mut chars: Vec<char> = String::from_utf8_lossy(buffer.bytes()).chars().collect();
+        let len = chars.len() as u64;
+        match state.rand_mut().below(4) {
+            // Insert a run of an interesting or printable character
+            0 => {
+                let position = state.rand_mut().below(len + 1) as usize;
+                let c = if state.rand_mut().below(2) == 0 {
+                    state.rand_mut().choose(INTERESTING_CHARS)
+                } else {
+                    char::from(state.rand_mut().between(0x20, 0x7e) as u8)
+                };
+                let count = 1 + state.rand_mut().below(8) as usize;
+                chars.splice(position..position, std::iter::repeat(c).take(count));
+            }
+            // Delete a range
+            1 if len > 0 => {
+                let start = state.rand_mut().below(len) as usize;
+                let end = start + 1 + state.rand_mut().below(len - start as u64) as usize;
+                chars.drain(start..end);
+            }
+            // Replace a character
+            2 if len > 0 => {
+                let position = state.rand_mut().below(len) as usize;
+                chars[position] = state.rand_mut().choose(INTERESTING_CHARS);
+            }
+            // Repeat a range
+            3 if len > 0 => {
+                let start = state.rand_mut().below(len) as usize;
+                let end = start + 1 + state.rand_mut().below((len - start as u64).min(16)) as usize;
+                let count = 1 + state.rand_mut().below(16) as usize;
+                let range: Vec<char> = chars[start..end].to_vec();
+                chars.splice(
+                    end..end,
+                    range.iter().copied().cycle().take(range.len() * count),
+                );
+            }
+            _ => return Ok(MutationResult::Skipped),
+        }
+
+        // Keep within the max size without splitting a character.
+        let mut string: String = chars.into_iter().collect();
+        let max_size = state.max_size();
+        if string.len() > max_size {
+            let end = (0..=max_size)
+                .rev()
+                .find(|end| string.is_char_boundary(*end))
+                .unwrap_or(0);
+            string.truncate(end);
+        }
+        *buffer.bytes_mut() = string.into_bytes();
+        Ok(MutationResult::Mutated)
+    }
+}
+
+/// Where the [IntentObservedValueMutator] puts an observed value.
+enum ValueSlot {
+    Key(usize),
+    Value(usize),
+    Element(usize, usize),
+    Data,
+}
+
+/// Mutator that copies a string value observed in the corpus into the key or
+/// String value of an extra, a String array element or the data, so the same
+/// identifier shows up in several places of an intent.
+pub struct IntentObservedValueMutator<S>
+where
+    S: HasRand + HasMetadata,
+{
+    phantom: PhantomData<S>,
+}
+
+impl<S> IntentObservedValueMutator<S>
+where
+    S: HasRand + HasMetadata,
+{
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Named for IntentObservedValueMutator<S>
+where
+    S: HasRand + HasMetadata,
+{
+    fn name(&self) -> &str {
+        "IntentObservedValueMutator"
+    }
+}
+
+impl<S> Mutator<IntentInput, S> for IntentObservedValueMutator<S>
+where
+    S: HasRand + HasMetadata,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut IntentInput,
+        _stage_idx: i32,
+    ) -> Result<libafl::prelude::MutationResult, libafl::Error> {
+        let count = match state.metadata::<ObservedValues>() {
+            Ok(observed) if !observed.values.is_empty() => observed.values.len() as u64,
+            _ => return Ok(MutationResult::Skipped),
+        };
+        let index = state.rand_mut().below(count) as usize;
+        let value = state.metadata::<ObservedValues>()?.values[index].clone();
+
+        let mut slots = vec![ValueSlot::Data];
+        for (i, extra) in input.extras.iter().enumerate() {
+            slots.push(ValueSlot::Key(i));
+            match &extra.value {
+                ExtraType::String(_) | ExtraType::ComponentName(_) => {
+                    slots.push(ValueSlot::Value(i))
+                }
+                ExtraType::StringArray(array) | ExtraType::StringArrayList(array) => {
+                    slots.extend((0..array.elements.len()).map(|j| ValueSlot::Element(i, j)))
+                }
+                _ => {}
+            }
+        }
+
+        let bytes = BytesInput::new(value.as_bytes().to_vec());
+        match state.rand_mut().choose(slots) {
+            ValueSlot::Key(i) => input.extras[i].key = value,
+            ValueSlot::Value(i) => match &mut input.extras[i].value {
+                ExtraType::String(direct) | ExtraType::ComponentName(direct) => {
+                    direct.buffer = bytes
+                }
+                _ => unreachable!(),
+            },
+            ValueSlot::Element(i, j) => match &mut input.extras[i].value {
+                ExtraType::StringArray(array) | ExtraType::StringArrayList(array) => {
+                    array.elements[j] = bytes
+                }
+                _ => unreachable!(),
+            },
+            ValueSlot::Data => input.data = Some(URIInput::raw(&value)),
+        }
+        Ok(MutationResult::Mutated)
+    }
+}
+
+/// Mutator that shuffles the order of the extras. The order decides which of
+/// several extras with the same key ends up in the Bundle, and the iteration
+/// order of its keySet().
+pub struct IntentExtrasOrderMutator<S>
+where
+    S: HasRand,
+{
+    phantom: PhantomData<S>,
+}
+
+impl<S> IntentExtrasOrderMutator<S>
+where
+    S: HasRand,
+{
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Named for IntentExtrasOrderMutator<S>
+where
+    S: HasRand,
+{
+    fn name(&self) -> &str {
+        "IntentExtrasOrderMutator"
+    }
+}
+
+impl<S> Mutator<IntentInput, S> for IntentExtrasOrderMutator<S>
+where
+    S: HasRand,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut IntentInput,
+        _stage_idx: i32,
+    ) -> Result<libafl::prelude::MutationResult, libafl::Error> {
+        if input.extras.len() < 2 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        // Fisher-Yates shuffle
+        for i in (1..input.extras.len()).rev() {
+            let j = state.rand_mut().below(i as u64 + 1) as usize;
+            input.extras.swap(i, j);
+        }
+        Ok(MutationResult::Mutated)
+    }
+}
+
+/// Mutator applying the inner mutator only if the mutated intent still fits
+/// into a shell command of the given length. Longer commands fail to deliver,
+/// so the mutations would only waste executions.
+pub struct CommandLengthLimit<M> {
+    inner: M,
+    max_len: usize,
+}
+
+impl<M> CommandLengthLimit<M> {
+    pub fn new(inner: M, max_len: usize) -> Self {
+        Self { inner, max_len }
+    }
+}
+
+impl<M> Named for CommandLengthLimit<M> {
+    fn name(&self) -> &str {
+        "CommandLengthLimit"
+    }
+}
+
+impl<M, S> Mutator<IntentInput, S> for CommandLengthLimit<M>
+where
+    M: Mutator<IntentInput, S>,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut IntentInput,
+        stage_idx: i32,
+    ) -> Result<libafl::prelude::MutationResult, libafl::Error> {
+        let original = input.clone();
+        let result = self.inner.mutate(state, input, stage_idx)?;
+        if result == MutationResult::Mutated && input.shell_command().len() > self.max_len {
+            *input = original;
+            return Ok(MutationResult::Skipped);
+        }
+        Ok(result)
+    }
+
+    fn post_exec(
+        &mut self,
+        state: &mut S,
+        stage_idx: i32,
+        corpus_idx: Option<CorpusId>,
+    ) -> Result<(), libafl::Error> {
+        self.inner.post_exec(state, stage_idx, corpus_idx)
+    }
+}
+
+/// One of two mutators of different types, chosen when the campaign starts,
+/// e.g. scheduled mutators with and without an optional mutation, so disabled
+/// mutations don't take up a slot of the schedule.
+pub enum EitherMutator<A, B> {
+    Left(A),
+    Right(B),
+}
+
+impl<A, B> Named for EitherMutator<A, B>
+where
+    A: Named,
+    B: Named,
+{
+    fn name(&self) -> &str {
+        match self {
+            EitherMutator::Left(mutator) => mutator.name(),
+            EitherMutator::Right(mutator) => mutator.name(),
+        }
+    }
+}
+
+impl<A, B, S> Mutator<IntentInput, S> for EitherMutator<A, B>
+where
+    A: Mutator<IntentInput, S>,
+    B: Mutator<IntentInput, S>,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut IntentInput,
+        stage_idx: i32,
+    ) -> Result<libafl::prelude::MutationResult, libafl::Error> {
+        match self {
+            EitherMutator::Left(mutator) => mutator.mutate(state, input, stage_idx),
+            EitherMutator::Right(mutator) => mutator.mutate(state, input, stage_idx),
+        }
+    }
+
+    fn post_exec(
+        &mut self,
+        state: &mut S,
+        stage_idx: i32,
+        corpus_idx: Option<CorpusId>,
+    ) -> Result<(), libafl::Error> {
+        match self {
+            EitherMutator::Left(mutator) => mutator.post_exec(state, stage_idx, corpus_idx),
+            EitherMutator::Right(mutator) => mutator.post_exec(state, stage_idx, corpus_idx),
+        }
+    }
+}
+
+// -----------------------------------------
+
+/// Helper function to get an ExtraInput to mutate. Creates a new one if there
+/// are no extras yet.
+fn get_extra_to_mutate<'a, S>(
+    state: &mut S,
+    input: &'a mut IntentInput,
+) -> Result<&'a mut ExtraInput, libafl::Error>
+where
+    S: HasRand + HasCorpus + HasMaxSize + HasNamedMetadata,
+{
+    if input.extras.is_empty() {
+        // Add a new extra.
+        return Err(libafl::Error::unknown("No extras to mutate"));
+    }
+
+    // Mutate one extra value.
+    let index = state.rand_mut().between(0, (input.extras.len() - 1) as u64) as usize;
+
+    Ok(input.extras.get_mut(index).unwrap())
+}
+
+/// The extra types of the templates [generate_random_extra] can create.
+pub const EXTRA_TYPES: [&str; 15] = [
+    "Boolean",
+    "Float",
+    "Int",
+    "Long",
+    "String",
+    "URI",
+    "ComponentName",
+    "IntArray",
+    "IntArrayList",
+    "LongArray",
+    "LongArrayList",
+    "FloatArray",
+    "FloatArrayList",
+    "StringArray",
+    "StringArrayList",
+];
+
+/// Helper function to get a random ExtraInput.
+fn generate_random_extra<S>(state: &mut S) -> ExtraInput
+where
+    S: HasRand + HasCorpus + HasMaxSize + HasNamedMetadata,
 {
     // Choose an extra from the template in the state.
     let intent_template = state
@@ -519,11 +1618,10 @@ where
         "String" => ExtraType::String(DirectInput {
             buffer: BytesInput::new(Vec::new()),
         }),
-        "URI" => ExtraType::URI(URIInput {
-            scheme: state.rand_mut().choose(URIScheme::iter()),
-            suffix: state.rand_mut().choose(URISuffix::iter()),
-            content: BytesInput::new(Vec::new()),
-        }),
+        "URI" => ExtraType::URI(URIInput::staged(
+            state.rand_mut().choose(URIScheme::iter()),
+            state.rand_mut().choose(URISuffix::iter()),
+        )),
         "ComponentName" => ExtraType::ComponentName(DirectInput {
             buffer: BytesInput::new(Vec::new()),
         }),
@@ -545,12 +1643,8 @@ where
         "FloatArrayList" => ExtraType::FloatArrayList(DirectInput {
             buffer: BytesInput::new(Vec::new()),
         }),
-        "StringArray" => ExtraType::StringArray(DirectInput {
-            buffer: BytesInput::new(Vec::new()),
-        }),
-        "StringArrayList" => ExtraType::StringArrayList(DirectInput {
-            buffer: BytesInput::new(Vec::new()),
-        }),
+        "StringArray" => ExtraType::StringArray(StringArrayInput::default()),
+        "StringArrayList" => ExtraType::StringArrayList(StringArrayInput::default()),
         _ => ExtraType::Boolean(DirectInput {
             // TODO: Implement me
             buffer: BytesInput::new(Vec::new()),
@@ -572,7 +1666,27 @@ fn mutate_content<S>(
 where
     S: HasRand + HasCorpus + HasMaxSize + HasNamedMetadata,
 {
-    let result = mutator.mutate(state, &mut extra.value.content_buffer(), stage_idx);
+    // URI contents only exceed the max size if they started from a format
+    // seed, as for the data.
+    let uri_len = match &extra.value {
+        ExtraType::URI(uri_input) => uri_input.content.bytes().len(),
+        _ => 0,
+    };
+
+    let result = match &mut extra.value {
+        // Mutate a single element, adding the first one if there is none.
+        ExtraType::StringArray(array) | ExtraType::StringArrayList(array) => {
+            if array.elements.is_empty() {
+                array.elements.push(BytesInput::new(Vec::new()));
+            }
+            let index = state.rand_mut().below(array.elements.len() as u64) as usize;
+            mutator.mutate(state, &mut array.elements[index], stage_idx)
+        }
+        value => match value.content_buffer() {
+            Some(buffer) => mutator.mutate(state, buffer, stage_idx),
+            None => Ok(MutationResult::Skipped),
+        },
+    };
 
     // If the mutation was successful, resize the extra value to the correct size.
     if let Ok(MutationResult::Mutated) = result {
@@ -584,6 +1698,30 @@ where
             ExtraType::Long(value) => value.buffer.bytes_mut().resize(8, 0),
             _ => {}
         }
+
+        // Keep variable-size values within the max size, and arrays at whole
+        // elements. Values of seeds may have been larger to begin with.
+        let max_size = state.max_size();
+        let max_len = match &extra.value {
+            ExtraType::URI(_) => uri_len.max(max_size),
+            ExtraType::IntArray(_)
+            | ExtraType::IntArrayList(_)
+            | ExtraType::FloatArray(_)
+            | ExtraType::FloatArrayList(_) => max_size - max_size % 4,
+            ExtraType::LongArray(_) | ExtraType::LongArrayList(_) => max_size - max_size % 8,
+            _ => max_size,
+        };
+        match &mut extra.value {
+            ExtraType::StringArray(array) | ExtraType::StringArrayList(array) => array
+                .elements
+                .iter_mut()
+                .for_each(|element| element.bytes_mut().truncate(max_len)),
+            value => {
+                if let Some(buffer) = value.content_buffer() {
+                    buffer.bytes_mut().truncate(max_len);
+                }
+            }
+        }
     }
 
     result
@@ -0,0 +1,234 @@
+//! Detection of intent redirection.
+//!
+//! An app redirects an intent when it launches another component with data
+//! taken from an intent it received, e.g. a `next_intent` extra or a URL it
+//! opens. Done without validation, this lets any app reach non-exported
+//! components or borrow the URI grants of the target. The
+//! [IntentRedirectionFeedback] reads the activity starts the app requested
+//! after every execution from the activity manager log and flags the input if
+//! one of them carries data the input controlled.
+//!
+//! The activity manager does not log the extras of a start. To catch data
+//! passed on in extras, the
+//! [REDIRECTION_PROBE](crate::adb_device::REDIRECTION_PROBE) of the content
+//! provider app logs the full intents it is started with, and the fuzzer
+//! offers its component as a value for the String and ComponentName extras
+//! and the data.
+//! An app redirecting an input to the probe reveals the extras it passed on.
+
+use std::time::SystemTime;
+
+use libafl::{
+    impl_serdeany,
+    prelude::{
+        EventFirer, ExitKind, Feedback, HasBytesVec, HasMetadata, Named, ObserversTuple, Testcase,
+        UsesInput,
+    },
+    state::HasClientPerfMonitor,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    adb_device::AdbDevice,
+    intent_input::{ExtraType, IntentInput},
+};
+
+/// Controlled values shorter than this are too likely to show up in any
+/// intent by chance.
+const MIN_TOKEN_LEN: usize = 6;
+
+/// The activity starts of the app that carried data of the input, attached
+/// to the solutions found by the [IntentRedirectionFeedback].
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct IntentRedirection {
+    /// The started intents as logged, e.g. `act=... dat=... cmp=...`, or as
+    /// `intent:` URIs with the extras if the app started the
+    /// [REDIRECTION_PROBE](crate::adb_device::REDIRECTION_PROBE).
+    pub starts: Vec<String>,
+}
+
+impl_serdeany!(IntentRedirection);
+
+/// Objective feedback for inputs the app redirects into new activity starts.
+#[derive(Debug)]
+pub struct IntentRedirectionFeedback {
+    adb_device: AdbDevice,
+    app_name: String,
+    enabled: bool,
+    /// The uid of the app, looked up on the first check.
+    app_uid: Option<u32>,
+    last_check: SystemTime,
+    starts: Vec<String>,
+}
+
+impl IntentRedirectionFeedback {
+    /// Creates the feedback. A disabled feedback never reads the log and
+    /// never reports any inputs.
+    pub fn new(adb_device: AdbDevice, app_name: String, enabled: bool) -> Self {
+        Self {
+            adb_device,
+            app_name,
+            enabled,
+            app_uid: None,
+            last_check: SystemTime::now(),
+            starts: Vec::new(),
+        }
+    }
+
+    /// The values of the input that can end up in a logged intent. The
+    /// activity manager only logs the scheme and authority of the data URI,
+    /// so URIs are reduced to those (which the full URIs logged by the probe
+    /// contain as well).
+    fn controlled_tokens(&self, input: &IntentInput) -> Vec<String> {
+        let mut tokens = Vec::new();
+        if let Some(data) = &input.data {
+            tokens.push(uri_token(&data.raw_identifier(0)));
+        }
+        for (index, extra) in input.extras.iter().enumerate() {
+            match &extra.value {
+                ExtraType::URI(uri_input) => {
+                    tokens.push(uri_token(&uri_input.raw_identifier(index + 1)))
+                }
+                ExtraType::String(d_input) => {
+                    tokens.push(String::from_utf8_lossy(d_input.buffer.bytes()).into_owned())
+                }
+                ExtraType::ComponentName(d_input) => {
+                    let component = String::from_utf8_lossy(d_input.buffer.bytes()).into_owned();
+                    // The log may abbreviate the package of the class.
+                    if let Some((_, class)) = component.split_once('/') {
+                        tokens.push(class.trim_start_matches('.').to_owned());
+                    }
+                    tokens.push(component);
+                }
+                _ => {}
+            }
+        }
+
+        tokens.retain(|token| {
+            token.len() >= MIN_TOKEN_LEN
+                && !token.contains('\n')
+                && !self.app_name.contains(token.as_str())
+        });
+        tokens
+    }
+}
+
+/// The part of the URI the activity manager logs: the scheme and the
+/// authority for hierarchical URIs, the whole URI otherwise.
+fn uri_token(uri: &str) -> String {
+    let Some((scheme, rest)) = uri.split_once("://") else {
+        return uri.to_owned();
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    format!("{}://{}", scheme, authority)
+}
+
+/// Whether the logged intent starts the fuzzed component itself, as the
+/// delivery instrumentation does from the uid of the app.
+fn starts_target(start: &str, input: &IntentInput) -> bool {
+    let Some(cmp) = start
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("cmp="))
+    else {
+        return false;
+    };
+    let Some((package, class)) = cmp.split_once('/') else {
+        return false;
+    };
+    let class = match class.strip_prefix('.') {
+        Some(_) => format!("{}{}", package, class),
+        None => class.to_owned(),
+    };
+
+    package == input.component_package && class == input.component_class
+}
+
+impl Named for IntentRedirectionFeedback {
+    fn name(&self) -> &str {
+        "IntentRedirectionFeedback"
+    }
+}
+
+impl<S> Feedback<S> for IntentRedirectionFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        input: &IntentInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        if !self.enabled {
+            return Ok(false);
+        }
+
+        let app_uid = match self.app_uid {
+            Some(uid) => uid,
+            None => match self.adb_device.app_uid(&self.app_name) {
+                Ok(uid) => *self.app_uid.insert(uid),
+                Err(err) => {
+                    println!("{}", err);
+                    return Ok(false);
+                }
+            },
+        };
+
+        // Only check the log since the last execution, so a start is
+        // attributed to a single input.
+        let now = SystemTime::now();
+        let starts = self
+            .adb_device
+            .activity_starts_since(app_uid, self.last_check);
+        let probe_intents = self
+            .adb_device
+            .redirection_probe_intents(&self.app_name, self.last_check);
+        self.last_check = now;
+
+        let tokens = self.controlled_tokens(input);
+        self.starts = starts
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|start| !starts_target(start, input))
+            .chain(probe_intents.unwrap_or_default())
+            .filter(|start| tokens.iter().any(|token| start.contains(token.as_str())))
+            .collect();
+
+        for start in &self.starts {
+            println!("App {} redirected input into {{{}}}", self.app_name, start);
+        }
+        Ok(!self.starts.is_empty())
+    }
+
+    fn append_metadata<OT>(
+        &mut self,
+        _state: &mut S,
+        _observers: &OT,
+        testcase: &mut Testcase<IntentInput>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        if !self.starts.is_empty() {
+            testcase.add_metadata(IntentRedirection {
+                starts: std::mem::take(&mut self.starts),
+            });
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(
+        &mut self,
+        _state: &mut S,
+        _input: &IntentInput,
+    ) -> Result<(), libafl::Error> {
+        self.starts.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+//! Feedback on the results the app returns for an intent.
+//!
+//! Activities started for a result report a result code (and optionally result
+//! data) back to their caller, as do receivers of ordered broadcasts calling
+//! `setResult()`. These results often differentiate handling
+//! paths that coverage alone may not distinguish, e.g. an activity rejecting a
+//! malformed intent with `RESULT_CANCELED` from the same code that accepts it.
+//! The executor stores the result of an execution in the
+//! [IntentResultObserver], and the [IntentResultFeedback] treats inputs
+//! producing a result not seen before for their component as interesting.
+
+use std::collections::HashSet;
+
+use libafl::{
+    impl_serdeany,
+    prelude::{
+        EventFirer, ExitKind, Feedback, HasMetadata, Named, Observer, ObserversTuple, Testcase,
+        UsesInput,
+    },
+    state::{HasClientPerfMonitor, HasNamedMetadata},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::intent_input::IntentInput;
+
+/// The result the app returned for an intent.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IntentResult {
+    /// The result code, e.g. `-1` for `RESULT_OK`.
+    pub code: i32,
+    /// The result data, if any.
+    pub data: Option<String>,
+    /// The result extras (as printed by `am broadcast`), if any.
+    pub extras: Option<String>,
+}
+
+impl IntentResult {
+    /// Parses the result of an ordered broadcast from the output of
+    /// `am broadcast`, e.g.
+    /// `Broadcast completed: result=1, data="foo", extras: Bundle[{a=b}]`.
+    pub fn from_broadcast_output(output: &str) -> Option<Self> {
+        let line = output
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("Broadcast completed: result="))?;
+
+        let (code, rest) = line.split_once(", ").unwrap_or((line, ""));
+        let (data, extras) = match rest.strip_prefix("data=\"") {
+            Some(rest) => match rest.split_once("\", extras: ") {
+                Some((data, extras)) => (Some(data), Some(extras)),
+                None => (Some(rest.strip_suffix('"').unwrap_or(rest)), None),
+            },
+            None => (None, rest.strip_prefix("extras: ")),
+        };
+
+        Some(Self {
+            code: code.trim().parse().ok()?,
+            data: data.map(str::to_owned),
+            extras: extras.map(str::to_owned),
+        })
+    }
+
+    /// The key identifying results that handle an intent differently. Only
+    /// the presence of data and extras is considered, as their content
+    /// usually mirrors the (mutated) input.
+    fn key(&self, component: &str) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            component,
+            self.code,
+            self.data.is_some(),
+            self.extras.is_some()
+        )
+    }
+}
+
+impl_serdeany!(IntentResult);
+
+/// Observer of the result returned for the last execution, set by the
+/// executor.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct IntentResultObserver {
+    result: Option<IntentResult>,
+}
+
+impl IntentResultObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The result of the last execution, if the app returned one.
+    pub fn result(&self) -> Option<&IntentResult> {
+        self.result.as_ref()
+    }
+
+    pub fn set_result(&mut self, result: Option<IntentResult>) {
+        self.result = result;
+    }
+}
+
+impl Named for IntentResultObserver {
+    fn name(&self) -> &str {
+        "IntentResultObserver"
+    }
+}
+
+impl<S> Observer<S> for IntentResultObserver
+where
+    S: UsesInput<Input = IntentInput>,
+{
+    fn pre_exec(&mut self, _state: &mut S, _input: &IntentInput) -> Result<(), libafl::Error> {
+        self.result = None;
+        Ok(())
+    }
+}
+
+/// All results seen so far, stored in the state.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SeenIntentResults {
+    pub results: HashSet<String>,
+}
+
+impl_serdeany!(SeenIntentResults);
+
+/// Feedback that marks inputs producing a new result for their component as
+/// interesting, and stores the result with the testcase.
+#[derive(Debug, Default)]
+pub struct IntentResultFeedback {
+    last_result: Option<IntentResult>,
+}
+
+impl IntentResultFeedback {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Named for IntentResultFeedback {
+    fn name(&self) -> &str {
+        "IntentResultFeedback"
+    }
+}
+
+impl<S> Feedback<S> for IntentResultFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor + HasNamedMetadata,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        input: &IntentInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.last_result = observers
+            .match_name::<IntentResultObserver>("IntentResultObserver")
+            .and_then(|observer| observer.result().cloned());
+        let Some(result) = &self.last_result else {
+            return Ok(false);
+        };
+
+        if !state.has_named_metadata::<SeenIntentResults>("seen_intent_results") {
+            state.add_named_metadata(SeenIntentResults::default(), "seen_intent_results");
+        }
+        let seen = state.named_metadata_mut::<SeenIntentResults>("seen_intent_results")?;
+
+        let interesting = seen.results.insert(result.key(&input.component()));
+        if interesting {
+            println!(
+                "New result {} (data: {}, extras: {}) for {}",
+                result.code,
+                result.data.is_some(),
+                result.extras.is_some(),
+                input.component()
+            );
+        }
+        Ok(interesting)
+    }
+
+    fn append_metadata<OT>(
+        &mut self,
+        _state: &mut S,
+        _observers: &OT,
+        testcase: &mut Testcase<IntentInput>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        if let Some(result) = self.last_result.take() {
+            testcase.add_metadata(result);
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(
+        &mut self,
+        _state: &mut S,
+        _input: &IntentInput,
+    ) -> Result<(), libafl::Error> {
+        self.last_result = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+//! App-specific invariants enforced on every mutated input.
+//!
+//! Apps often reject intents long before reaching interesting code, e.g.
+//! because a user id extra is not a number or a deep link points at another
+//! host. Mutators know nothing about these checks, so most of their inputs
+//! would be trivially rejected. With `--invariant <rule>` the
+//! [InvariantFixup] repairs every mutated input to satisfy the rules:
+//!
+//! - `extra.<key>=numeric`: String extras with the key only contain digits
+//! - `data.host=<host>`: the data URI points at the host
+//! - `data.scheme=<scheme>`: the data URI has the scheme
+
+use std::str::FromStr;
+
+use libafl::prelude::{BytesInput, CorpusId, HasBytesVec, MutationResult, Mutator, Named};
+
+use crate::intent_input::{is_uri_scheme, ExtraType, IntentInput, URIScheme};
+
+/// An invariant of the inputs the app accepts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Invariant {
+    /// The String extras with the key are numeric.
+    NumericExtra(String),
+    /// The data URI has the host.
+    DataHost(String),
+    /// The data URI has the scheme.
+    DataScheme(String),
+}
+
+impl FromStr for Invariant {
+    type Err = libafl::Error;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            libafl::Error::illegal_argument(format!(
+                "Invariant {} is not one of extra.<key>=numeric, data.host=<host> or \
+                 data.scheme=<scheme>",
+                rule
+            ))
+        };
+        let (field, value) = rule.split_once('=').ok_or_else(invalid)?;
+        if value.is_empty() {
+            return Err(invalid());
+        }
+        match field {
+            "data.host" => Ok(Invariant::DataHost(value.to_owned())),
+            "data.scheme" => Ok(Invariant::DataScheme(value.to_owned())),
+            _ => match field.strip_prefix("extra.") {
+                Some(key) if !key.is_empty() && value == "numeric" => {
+                    Ok(Invariant::NumericExtra(key.to_owned()))
+                }
+                _ => Err(invalid()),
+            },
+        }
+    }
+}
+
+impl Invariant {
+    /// Changes the input to satisfy this invariant.
+    pub fn fix(&self, input: &mut IntentInput) {
+        match self {
+            Invariant::NumericExtra(key) => {
+                for extra in input.extras.iter_mut().filter(|extra| &extra.key == key) {
+                    match &mut extra.value {
+                        ExtraType::String(d_input) => make_numeric(&mut d_input.buffer),
+                        ExtraType::StringArray(array) | ExtraType::StringArrayList(array) => {
+                            array.elements.iter_mut().for_each(make_numeric)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Invariant::DataHost(host) => {
+                if let Some(uri) = raw_data_uri(input) {
+                    map_string(uri, |uri| with_host(uri, host));
+                }
+            }
+            Invariant::DataScheme(scheme) => {
+                if let Some(uri) = raw_data_uri(input) {
+                    map_string(uri, |uri| with_scheme(uri, scheme));
+                }
+            }
+        }
+    }
+}
+
+/// The URI of the data, unless it is staged by the fuzzer.
+fn raw_data_uri(input: &mut IntentInput) -> Option<&mut BytesInput> {
+    input
+        .data
+        .as_mut()
+        .filter(|data| data.scheme == URIScheme::Other)
+        .map(|data| &mut data.content)
+}
+
+/// Replaces the (UTF-8) content of the buffer with the result of the
+/// function.
+fn map_string(buffer: &mut BytesInput, f: impl Fn(&str) -> String) {
+    let value = f(&String::from_utf8_lossy(buffer.bytes()));
+    *buffer.bytes_mut() = value.into_bytes();
+}
+
+/// Drops everything but the digits (and a leading minus) from the buffer,
+/// leaving 0 if nothing remains.
+fn make_numeric(buffer: &mut BytesInput) {
+    map_string(buffer, |value| {
+        let negative = value.starts_with('-');
+        let digits: String = value.chars().filter(char::is_ascii_digit).collect();
+        match (negative, digits.is_empty()) {
+            (_, true) => "0".to_owned(),
+            (true, false) => format!("-{}", digits),
+            (false, false) => digits,
+        }
+    })
+}
+
+/// The URI with its authority replaced by the host. URIs without an
+/// authority, e.g. `tel:123`, are left as they are.
+fn with_host(uri: &str, host: &str) -> String {
+    let Some(start) = uri.find("://").map(|index| index + 3) else {
+        return uri.to_owned();
+    };
+    let end = uri[start..]
+        .find(|c| c == '/' || c == '?' || c == '#')
+        .map_or(uri.len(), |index| start + index);
+    format!("{}{}{}", &uri[..start], host, &uri[end..])
+}
+
+/// The URI with its scheme replaced, or the scheme prepended if it has none.
+fn with_scheme(uri: &str, scheme: &str) -> String {
+    let rest = match uri.split_once(':') {
+        Some((current, rest)) if is_uri_scheme(current) => rest,
+        _ => uri,
+    };
+    format!("{}:{}", scheme, rest)
+}
+
+/// Mutator repairing the inputs of the inner mutator to satisfy the
+/// invariants.
+pub struct InvariantFixup<M> {
+    inner: M,
+    invariants: Vec<Invariant>,
+}
+
+impl<M> InvariantFixup<M> {
+    pub fn new(inner: M, invariants: Vec<Invariant>) -> Self {
+        Self { inner, invariants }
+    }
+}
+
+impl<M> Named for InvariantFixup<M> {
+    fn name(&self) -> &str {
+        "InvariantFixup"
+    }
+}
+
+impl<M, S> Mutator<IntentInput, S> for InvariantFixup<M>
+where
+    M: Mutator<IntentInput, S>,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut IntentInput,
+        stage_idx: i32,
+    ) -> Result<MutationResult, libafl::Error> {
+        let result = self.inner.mutate(state, input, stage_idx)?;
+        if result == MutationResult::Mutated {
+            for invariant in &self.invariants {
+                invariant.fix(input);
+            }
+        }
+        Ok(result)
+    }
+
+    fn post_exec(
+        &mut self,
+        state: &mut S,
+        stage_idx: i32,
+        corpus_idx: Option<CorpusId>,
+    ) -> Result<(), libafl::Error> {
+        self.inner.post_exec(state, stage_idx, corpus_idx)
+    }
+}
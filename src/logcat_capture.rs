@@ -0,0 +1,503 @@
+//! Notable logcat lines of the target for every execution.
+//!
+//! The [LogcatObserver] snapshots the logcat of the app process after every
+//! execution, including the log of a process the execution killed, and keeps
+//! the lines matching one of the configured patterns (e.g. exceptions and
+//! warnings). The [LogcatFeedback] attaches these lines to the testcases as
+//! [NotableLogLines], so the corpus entries and solutions explain themselves
+//! without rerunning them.
+//!
+//! The observer can also collect the classes of the exceptions in the log.
+//! The [ExceptionClassFeedback] keeps inputs throwing an exception class not
+//! seen before in the corpus, as exception diversity correlates with
+//! exploring new error handling paths.
+//!
+//! Finally, the observer matches the log against the watched regular
+//! expressions (e.g. `W SQLiteLog` or `leaked`). A [LogcatWatchFeedback]
+//! turns the matches of its patterns into corpus entries or solutions, so
+//! domain knowledge about the target guides the campaign without code changes.
+//! Matches are told apart by the signature of the matching line, its message
+//! with numbers and addresses masked.
+
+use std::{collections::HashSet, sync::OnceLock, time::SystemTime};
+
+use libafl::{
+    impl_serdeany,
+    prelude::{
+        EventFirer, ExitKind, Feedback, HasMetadata, Named, Observer, ObserversTuple, Testcase,
+        UsesInput,
+    },
+    state::{HasClientPerfMonitor, HasNamedMetadata},
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{adb_device::AdbDevice, intent_input::IntentInput};
+
+/// Maximum number of lines kept per execution.
+const MAX_LINES: usize = 50;
+
+/// Observer of the notable logcat lines of the app during an execution.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogcatObserver {
+    adb_device: AdbDevice,
+    app_name: String,
+    /// Lines containing any of the patterns are notable. No patterns disable
+    /// the observer.
+    patterns: Vec<String>,
+    /// Whether to collect the exception classes in the log.
+    track_exception_classes: bool,
+    /// The watched regular expressions and their compiled form.
+    watches: Vec<String>,
+    #[serde(skip)]
+    watch_regexes: Vec<Regex>,
+    exec_start: SystemTime,
+    /// The pid of the app when the execution started, if it was running.
+    pid: Option<String>,
+    lines: Vec<String>,
+    exception_classes: Vec<String>,
+    watch_matches: Vec<WatchMatch>,
+}
+
+/// A match of a watched pattern in the log of an execution.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchMatch {
+    pub pattern: String,
+    /// The signature of the first matching line.
+    pub signature: String,
+}
+
+impl LogcatObserver {
+    pub fn new(adb_device: AdbDevice, app_name: String, patterns: Vec<String>) -> Self {
+        Self {
+            adb_device,
+            app_name,
+            patterns,
+            track_exception_classes: false,
+            watches: Vec::new(),
+            watch_regexes: Vec::new(),
+            exec_start: SystemTime::now(),
+            pid: None,
+            lines: Vec::new(),
+            exception_classes: Vec::new(),
+            watch_matches: Vec::new(),
+        }
+    }
+
+    /// Collects the exception classes in the log of every execution.
+    pub fn track_exception_classes(mut self, track_exception_classes: bool) -> Self {
+        self.track_exception_classes = track_exception_classes;
+        self
+    }
+
+    /// Matches the log of every execution against the regular expressions.
+    pub fn watch_patterns(mut self, patterns: Vec<String>) -> Result<Self, libafl::Error> {
+        self.watch_regexes = patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|err| {
+                    libafl::Error::illegal_argument(format!(
+                        "Invalid logcat pattern {:?}: {}",
+                        pattern, err
+                    ))
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        self.watches = patterns;
+        Ok(self)
+    }
+
+    /// Whether the observer reads the log at all.
+    fn is_enabled(&self) -> bool {
+        !self.patterns.is_empty() || self.track_exception_classes || !self.watches.is_empty()
+    }
+
+    /// The log of the processes of the app since the execution started: of
+    /// the one running when it started, which an input crashing the app
+    /// killed, and of the one running now, if the app was (re)started.
+    fn app_log(&self) -> Result<String, libafl::Error> {
+        let current = self
+            .adb_device
+            .pid_of(&self.app_name)
+            .ok()
+            .filter(|pid| self.pid.as_ref() != Some(pid));
+        let mut log = String::new();
+        for pid in self.pid.iter().chain(&current) {
+            log.push_str(&self.adb_device.process_log_since(pid, self.exec_start)?);
+        }
+        Ok(log)
+    }
+
+    /// When the last execution started.
+    pub fn exec_start(&self) -> SystemTime {
+        self.exec_start
+    }
+
+    /// The notable lines of the last execution.
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// The distinct exception classes in the log of the last execution.
+    pub fn exception_classes(&self) -> &[String] {
+        &self.exception_classes
+    }
+
+    /// The watched patterns that matched the log of the last execution.
+    pub fn watch_matches(&self) -> &[WatchMatch] {
+        &self.watch_matches
+    }
+}
+
+/// The class of the exception a log line starts, e.g. for
+/// `W System.err: Caused by: java.io.IOException: message`.
+fn exception_class(line: &str) -> Option<&str> {
+    let message = line
+        .split_once(": ")
+        .map_or(line, |(_, message)| message)
+        .trim();
+    let message = message.strip_prefix("Caused by: ").unwrap_or(message);
+    let class = message.split([':', ' ']).next()?;
+    (class.contains('.')
+        && (class.ends_with("Exception") || class.ends_with("Error"))
+        && class
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '.' || c == '$' || c == '_'))
+    .then_some(class)
+}
+
+/// The message of a log line with the numbers and addresses masked, e.g.
+/// `Activity com.example.Main has leaked IntentReceiver a.b.C@#` for
+/// `10-16 12:00:00.123  1234  1250 E ActivityThread: Activity com.example.Main
+/// has leaked IntentReceiver a.b.C@5e3a2f1`.
+fn line_signature(line: &str) -> String {
+    static VARIABLE: OnceLock<Regex> = OnceLock::new();
+    let variable = VARIABLE
+        .get_or_init(|| Regex::new(r"(?P<at>@)[0-9a-fA-F]+|0x[0-9a-fA-F]+|[0-9]+").unwrap());
+    let message = line.split_once(": ").map_or(line, |(_, message)| message);
+    variable.replace_all(message.trim(), "${at}#").into_owned()
+}
+
+impl Named for LogcatObserver {
+    fn name(&self) -> &str {
+        "LogcatObserver"
+    }
+}
+
+impl<S> Observer<S> for LogcatObserver
+where
+    S: UsesInput<Input = IntentInput>,
+{
+    fn pre_exec(&mut self, _state: &mut S, _input: &IntentInput) -> Result<(), libafl::Error> {
+        self.exec_start = SystemTime::now();
+        self.pid = if self.is_enabled() {
+            self.adb_device.pid_of(&self.app_name).ok()
+        } else {
+            None
+        };
+        self.lines.clear();
+        self.exception_classes.clear();
+        self.watch_matches.clear();
+        Ok(())
+    }
+
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _input: &IntentInput,
+        _exit_kind: &ExitKind,
+    ) -> Result<(), libafl::Error> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        match self.app_log() {
+            Ok(log) => {
+                self.lines = log
+                    .lines()
+                    .filter(|line| self.patterns.iter().any(|p| line.contains(p.as_str())))
+                    .take(MAX_LINES)
+                    .map(str::to_owned)
+                    .collect();
+                if self.track_exception_classes {
+                    for class in log.lines().filter_map(exception_class) {
+                        if !self.exception_classes.iter().any(|c| c == class) {
+                            self.exception_classes.push(class.to_owned());
+                        }
+                    }
+                }
+                for (pattern, regex) in self.watches.iter().zip(&self.watch_regexes) {
+                    if let Some(line) = log.lines().find(|line| regex.is_match(line)) {
+                        self.watch_matches.push(WatchMatch {
+                            pattern: pattern.clone(),
+                            signature: line_signature(line),
+                        });
+                    }
+                }
+            }
+            Err(err) => println!("Failed to read logcat: {:?}", err),
+        }
+        Ok(())
+    }
+}
+
+/// The notable logcat lines of a testcase.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NotableLogLines {
+    pub lines: Vec<String>,
+}
+
+impl_serdeany!(NotableLogLines);
+
+/// Feedback attaching the notable logcat lines to the testcases. It never
+/// considers an input interesting on its own.
+#[derive(Debug, Default)]
+pub struct LogcatFeedback;
+
+impl LogcatFeedback {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Named for LogcatFeedback {
+    fn name(&self) -> &str {
+        "LogcatFeedback"
+    }
+}
+
+impl<S> Feedback<S> for LogcatFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        Ok(false)
+    }
+
+    fn append_metadata<OT>(
+        &mut self,
+        _state: &mut S,
+        observers: &OT,
+        testcase: &mut Testcase<IntentInput>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        let Some(observer) = observers.match_name::<LogcatObserver>("LogcatObserver") else {
+            return Ok(());
+        };
+        if !observer.lines().is_empty() {
+            testcase.add_metadata(NotableLogLines {
+                lines: observer.lines().to_vec(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The exception classes thrown by the corpus entries.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SeenExceptionClasses {
+    pub classes: HashSet<String>,
+}
+
+impl_serdeany!(SeenExceptionClasses);
+
+/// Feedback marking inputs that throw an exception class not seen before in
+/// the corpus as interesting.
+#[derive(Debug, Default)]
+pub struct ExceptionClassFeedback;
+
+impl ExceptionClassFeedback {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Named for ExceptionClassFeedback {
+    fn name(&self) -> &str {
+        "ExceptionClassFeedback"
+    }
+}
+
+impl<S> Feedback<S> for ExceptionClassFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor + HasNamedMetadata,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let Some(observer) = observers.match_name::<LogcatObserver>("LogcatObserver") else {
+            return Ok(false);
+        };
+        if observer.exception_classes().is_empty() {
+            return Ok(false);
+        }
+
+        if !state.has_named_metadata::<SeenExceptionClasses>("seen_exception_classes") {
+            state.add_named_metadata(SeenExceptionClasses::default(), "seen_exception_classes");
+        }
+        let seen = state.named_metadata_mut::<SeenExceptionClasses>("seen_exception_classes")?;
+        let mut interesting = false;
+        for class in observer.exception_classes() {
+            if seen.classes.insert(class.clone()) {
+                println!("New exception class {}", class);
+                interesting = true;
+            }
+        }
+        Ok(interesting)
+    }
+}
+
+/// The watched logcat patterns that matched during the execution of a
+/// testcase.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LogcatWatchMatches {
+    pub patterns: Vec<String>,
+}
+
+impl_serdeany!(LogcatWatchMatches);
+
+/// The watched logcat patterns that matched for a corpus entry before, and the
+/// matches (pattern and line signature) of the solutions.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SeenLogcatWatches {
+    pub patterns: HashSet<String>,
+}
+
+impl_serdeany!(SeenLogcatWatches);
+
+/// Feedback on the matches of some of the watched logcat patterns. In the
+/// corpus feedback, only the first input matching each pattern is kept. As an
+/// objective, only the first input matching each pattern with a line of a new
+/// signature is a solution. Either way, a pattern the app logs all the time
+/// does not flood the corpus or the crashes.
+#[derive(Debug)]
+pub struct LogcatWatchFeedback {
+    patterns: Vec<String>,
+    objective: bool,
+    last_matches: Vec<String>,
+}
+
+impl LogcatWatchFeedback {
+    pub fn new(patterns: Vec<String>, objective: bool) -> Self {
+        Self {
+            patterns,
+            objective,
+            last_matches: Vec::new(),
+        }
+    }
+}
+
+impl Named for LogcatWatchFeedback {
+    fn name(&self) -> &str {
+        "LogcatWatchFeedback"
+    }
+}
+
+impl<S> Feedback<S> for LogcatWatchFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor + HasNamedMetadata,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.last_matches.clear();
+        let Some(observer) = observers.match_name::<LogcatObserver>("LogcatObserver") else {
+            return Ok(false);
+        };
+        let matches: Vec<&WatchMatch> = observer
+            .watch_matches()
+            .iter()
+            .filter(|watch_match| self.patterns.contains(&watch_match.pattern))
+            .collect();
+        if matches.is_empty() {
+            return Ok(false);
+        }
+        self.last_matches = matches
+            .iter()
+            .map(|watch_match| watch_match.pattern.clone())
+            .collect();
+
+        let name = if self.objective {
+            "seen_logcat_watch_solutions"
+        } else {
+            "seen_logcat_watches"
+        };
+        if !state.has_named_metadata::<SeenLogcatWatches>(name) {
+            state.add_named_metadata(SeenLogcatWatches::default(), name);
+        }
+        let seen = state.named_metadata_mut::<SeenLogcatWatches>(name)?;
+        let mut interesting = false;
+        for watch_match in matches {
+            let key = if self.objective {
+                format!("{} {}", watch_match.pattern, watch_match.signature)
+            } else {
+                watch_match.pattern.clone()
+            };
+            if seen.patterns.insert(key) {
+                println!(
+                    "Logcat matched {:?} for the first time: {}",
+                    watch_match.pattern, watch_match.signature
+                );
+                interesting = true;
+            }
+        }
+        Ok(interesting)
+    }
+
+    fn append_metadata<OT>(
+        &mut self,
+        _state: &mut S,
+        _observers: &OT,
+        testcase: &mut Testcase<IntentInput>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        if !self.last_matches.is_empty() {
+            testcase.add_metadata(LogcatWatchMatches {
+                patterns: std::mem::take(&mut self.last_matches),
+            });
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(
+        &mut self,
+        _state: &mut S,
+        _input: &IntentInput,
+    ) -> Result<(), libafl::Error> {
+        self.last_matches.clear();
+        Ok(())
+    }
+}
@@ -1,34 +1,129 @@
 mod adb_device;
 mod adb_executor;
+mod adb_shell;
+mod app_links;
+mod campaign_stats;
+mod ci_summary;
+mod component_launch;
+mod component_scheduler;
+mod control_api;
+mod corpus_metadata;
+mod corpus_replay;
+mod coverage_goals;
+mod coverage_mapping;
+mod coverage_report;
+mod crash_layout;
+mod crash_report;
+mod daemon;
+mod data_filter;
+mod delivery;
+mod device_health;
+mod dex_extras;
+mod disk_retention;
+mod edge_attribution;
+mod entry_points;
+mod exception_filter;
+mod exception_report;
+mod field_constraints;
+mod format_seeds;
+mod input_schema;
 mod intent_generator;
 mod intent_input;
+mod intent_input_v0;
 mod intent_mutator;
+mod intent_redirection;
+mod intent_result;
+mod invariants;
+mod logcat_capture;
+mod native_crash;
+mod notifier;
+mod observed_values;
+mod out_of_memory;
+mod pending_intents;
+mod pipelined_stage;
+mod reproduction;
+mod results_db;
+mod run_config;
+mod script_export;
+mod seed_allocation;
+mod seed_import;
+mod shortcuts;
 mod socket_coverage_observer;
+mod stability;
+mod timeout_culling;
+mod triage;
 mod util;
 
-use adb_device::AdbDevice;
-use clap::Parser;
+use adb_device::{
+    AdbDevice, AdbServer, AppSetup, DeliveryPolicy, EscalationPolicy, IdleDetection, IdleStrategy,
+    DEFAULT_APP_RESTART_PATTERNS,
+};
+use adb_executor::PermissionDelivery;
+use app_links::AppLinks;
+use campaign_stats::CampaignStats;
+use ci_summary::CiSummary;
+use clap::{parser::ValueSource, CommandFactory, FromArgMatches, Parser, Subcommand};
+use component_launch::{ComponentLaunchFeedback, ComponentLaunchObserver};
+use component_scheduler::ComponentScheduler;
+use control_api::ControlApi;
+use corpus_metadata::{CorpusMetadataFormat, SchedulingStatsPersistence};
+use corpus_replay::ReplayDevice;
+use coverage_goals::{CoverageGoalFeedback, CoverageGoalProgress};
+use coverage_mapping::CoverageMapping;
+use crash_layout::CrashLayoutFeedback;
+use crash_report::CrashReportFeedback;
+use delivery::{DeliveredFeedback, DeliveryObserver};
+use device_health::{HealthThresholds, HealthWatchdog};
+use disk_retention::{DiskRetention, RetentionPolicy};
+use edge_attribution::EdgeAttributionFeedback;
+use entry_points::{EntryPointFeedback, EntryPointReport};
+use exception_filter::ExceptionFilterFeedback;
+use exception_report::{ExceptionNoveltyFeedback, ExceptionReportFeedback};
+use field_constraints::ConstrainedMutator;
+use format_seeds::FormatSeeds;
 use intent_generator::IntentGenerator;
 use intent_input::IntentInput;
 use intent_mutator::{
-    IntentRandomAddExtraMutator, IntentRandomDataMutator, IntentRandomExtraContentMutator,
+    CommandLengthLimit, EitherMutator, IntentArrayLengthMutator, IntentDataBoundaryMutator,
+    IntentExtrasOrderMutator, IntentFloatArraySpecialValueMutator, IntentIdentitySpoofMutator,
+    IntentObservedValueMutator, IntentRandomAddExtraMutator, IntentRandomAliasMutator,
+    IntentRandomComponentMutator, IntentRandomDataMutator, IntentRandomExtraContentMutator,
     IntentRandomExtraKeyMutator, IntentRandomExtraSchemeMutator, IntentRandomExtraSuffixMutator,
-    IntentRandomFlagMutator, IntentRandomMimeTypeMutator,
+    IntentRandomFlagMutator, IntentRandomMimeTypeMutator, IntentRandomURIGrantMutator,
+    IntentRemoveDataMutator, IntentStringMutator, IntentUnsetMimeTypeMutator,
 };
-use socket_coverage_observer::SocketCoverageObserver;
+use intent_redirection::IntentRedirectionFeedback;
+use intent_result::{IntentResultFeedback, IntentResultObserver};
+use invariants::{Invariant, InvariantFixup};
+use logcat_capture::{ExceptionClassFeedback, LogcatFeedback, LogcatObserver, LogcatWatchFeedback};
+use native_crash::NativeCrashDedupFeedback;
+use observed_values::ObservedValueFeedback;
+use out_of_memory::OutOfMemoryFeedback;
+use pipelined_stage::{AppInstance, PipelinedMutationalStage};
+use results_db::{RecordKind, ResultsDbFeedback};
+use run_config::{CampaignInfo, CampaignInfoFeedback};
+use seed_import::SeedFormat;
+use socket_coverage_observer::{CoverageMode, SocketCoverageObserver};
+use stability::StabilityStage;
+use timeout_culling::TimeoutCullingFeedback;
 
-use std::{env, path::PathBuf};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 use libafl::{
+    bolts::{current_time, tuples::Merge},
+    feedback_and_fast, feedback_or,
     prelude::{
-        tuple_list, AflMapFeedback, CachedOnDiskCorpus, ConstFeedback, CrashFeedback,
-        InMemoryCorpus, OnDiskCorpus, SimpleEventManager, SimpleMonitor, StdRand,
-        StdScheduledMutator, OnDiskTOMLMonitor,
+        tuple_list, AflMapFeedback, ConstFeedback, Corpus, CrashFeedback, Evaluator, HasObservers,
+        InMemoryCorpus, MatchName, OnDiskTOMLMonitor, ProgressReporter, SimpleEventManager,
+        SimpleMonitor, StdRand, StdScheduledMutator, TimeFeedback, TimeObserver,
     },
     schedulers::QueueScheduler,
-    stages::StdMutationalStage,
-    state::StdState,
-    Fuzzer, StdFuzzer,
+    state::{HasCorpus, HasExecutions, HasMaxSize, HasNamedMetadata, HasSolutions, StdState},
+    Fuzzer, HasScheduler, StdFuzzer,
 };
 
 /// Executes through adb on a device or emulator receiving coverage feedback
@@ -36,27 +131,340 @@ use libafl::{
 #[derive(Parser, Debug)]
 #[command(version, about)]
 struct CommandLineArgs {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// The address of the coverage agent socket
     #[arg(short, long, default_value = "localhost:6249")]
     coverage_socket_address: String,
 
+    /// How many seconds to wait for a reply from the coverage agent
+    #[arg(long, default_value = "10")]
+    socket_timeout: u64,
+
     /// The adb command used to send intents and control the device, can also
     /// be set with the `ADB_COMMAND` environment variable
     #[arg(short, long, default_value = "adb")]
     adb_command: String,
 
-    /// The config file or directory from where to read the intent information
+    /// The adb server (`host:port`) the device is attached to, e.g. of a
+    /// device farm, instead of the local one; can also be set with the
+    /// `ADB_SERVER_SOCKET` environment variable (`tcp:host:port`). Forwarded
+    /// ports such as the coverage socket are then reached on its host, which
+    /// needs the server to listen on all interfaces (started with `adb -a`,
+    /// e.g. `adb -a -P 5037 nodaemon server`): otherwise its forwards only
+    /// accept connections from its own host
+    #[arg(long)]
+    adb_server: Option<String>,
+
+    /// Further options inserted into every adb command before the
+    /// subcommand, e.g. `-e` or `-t 3`
+    #[arg(long, allow_hyphen_values = true)]
+    adb_args: Option<String>,
+
+    /// The config file or directory from where to read the intent information.
+    /// A file may hold a single template or an array of them. Templates may
+    /// extend a base template named in their `extends` field
     #[arg(short, long, default_value = "intent_template.json")]
     intent_config: String,
 
+    /// The APK of the app, to add the extras keys its code reads (the
+    /// string constants passed to the Intent extra getters, hasExtra and the
+    /// getters of the extras Bundle) to the known extras keys of the
+    /// templates
+    #[arg(long)]
+    apk: Option<PathBuf>,
+
     /// Re-run corpus instead of fuzzing
     #[arg(short, long, default_value = "false")]
     run_corpus: bool,
 
+    /// Shard the corpus replay across this device, given as
+    /// `<serial>=<coverage socket address>`, and merge the coverage and traces
+    /// of all devices; can be repeated
+    #[arg(long = "replay-device", requires = "run_corpus")]
+    replay_devices: Vec<String>,
+
+    /// Spread the executions round-robin over the app instance of this
+    /// Android user (e.g. a work profile) as well, given as `<user>=<coverage
+    /// socket address>`; can be repeated. The content URIs of the next input
+    /// are staged for the next instance while waiting on the current one
+    #[arg(
+        long = "instance",
+        conflicts_with_all = ["run_corpus", "root", "instrumentation_runner", "fuzz_notifications"]
+    )]
+    instances: Vec<String>,
+
     /// Trace JNI calls instead of Java coverage
     #[arg(short, long, default_value = "false")]
     trace_native: bool,
 
+    /// How to interpret the coverage map: hitcount buckets, binary edges,
+    /// which keeps the corpus smaller for nondeterministic code, or binary
+    /// methods, for huge apps
+    #[arg(long, value_enum, default_value = "hitcounts")]
+    coverage_mode: CoverageMode,
+
+    /// Only count the coverage of the classes in this package (e.g.
+    /// com.example) and its subpackages; can be repeated
+    #[arg(long = "coverage-package")]
+    coverage_packages: Vec<String>,
+
+    /// Keep the inputs first reaching this class or method (e.g.
+    /// com.example.Foo#bar) and report when it is reached; can be repeated,
+    /// needs a coverage mapping
+    #[arg(long = "coverage-goal", requires = "coverage_mapping")]
+    coverage_goals: Vec<String>,
+
+    /// Stop fuzzing once all coverage goals are reached
+    #[arg(long, default_value = "false", requires = "coverage_goals")]
+    stop_at_goals: bool,
+
+    /// The mapping of the coverage map entries to source locations exported
+    /// by the agent, as lines <index> <class>#<method>:<line>, to report new
+    /// coverage as locations and to filter or fold the coverage on the host
+    /// if the agent can't
+    #[arg(long)]
+    coverage_mapping: Option<PathBuf>,
+
+    /// Track the indexes of the map entries covered by each corpus entry
+    #[arg(long, default_value = "false")]
+    track_indexes: bool,
+
+    /// Track the map entries that each corpus entry newly covered
+    #[arg(long, default_value = "false")]
+    track_novelties: bool,
+
+    /// Treat inputs that start a new activity or service of the app as
+    /// interesting, even if the coverage didn't change
+    #[arg(long, default_value = "false")]
+    track_component_launches: bool,
+
+    /// Deliver activity intents through the result forwarder of the content
+    /// provider app and treat inputs producing a new result code as
+    /// interesting
+    #[arg(long, default_value = "false")]
+    capture_activity_results: bool,
+
+    /// Capture the result code, data and extras receivers set on broadcasts
+    /// and treat inputs producing a new result as interesting
+    #[arg(long, default_value = "false")]
+    capture_broadcast_results: bool,
+
+    /// Re-execute inputs covering new edges and only count the new edges
+    /// covered again, to keep flaky coverage out of the corpus
+    #[arg(long, default_value = "false")]
+    confirm_new_coverage: bool,
+
+    /// Execute every new corpus entry this many times before fuzzing it to
+    /// measure the stability of the coverage, 0 disables the measurement
+    #[arg(long, default_value = "0")]
+    stability_runs: usize,
+
+    /// The number of extra initial inputs with prepopulated extras, allocated
+    /// to the action and category seeds by the coverage only they reach
+    #[arg(long, default_value = "0")]
+    seed_variants: usize,
+
+    /// Mutate the URI permissions granted with the intents, including sending
+    /// them without any grants, instead of granting the app read and write
+    /// access to all staged files up front
+    #[arg(long, default_value = "false")]
+    mutate_uri_grants: bool,
+
+    /// The maximum number of extras of an intent
+    #[arg(long, default_value = "10")]
+    max_extras: usize,
+
+    /// The maximum size in bytes of the data and every extra of an intent,
+    /// except for the URI contents started from format seeds
+    #[arg(long, default_value = "4096")]
+    max_extra_size: usize,
+
+    /// Seed files for the content of URIs of a format, as
+    /// <suffix or mime type>=<dir> (e.g. png=seeds/png); can be repeated
+    #[arg(long = "format-seeds")]
+    format_seeds: Vec<String>,
+
+    /// An invariant of the inputs the app accepts, enforced on every mutated
+    /// input, as extra.<key>=numeric, data.host=<host> or
+    /// data.scheme=<scheme>; can be repeated
+    #[arg(long = "invariant")]
+    invariants: Vec<String>,
+
+    /// The maximum length of the shell command delivering an intent, by
+    /// default the longest argument Linux accepts (`MAX_ARG_STRLEN`)
+    #[arg(long, default_value = "131072")]
+    max_command_length: usize,
+
+    /// Retarget inputs, keeping their data and extras, at the other components
+    /// of a multi-template campaign
+    #[arg(long, default_value = "false")]
+    retarget_components: bool,
+
+    /// Start the app under this instrumentation (e.g.
+    /// `org.gts3.jnifuzz.contentprovider/.IntentDeliveryInstrumentation`) and
+    /// deliver the intents through it from inside the app
+    #[arg(long)]
+    instrumentation_runner: Option<String>,
+
+    /// Deliver the intents as root (with adb root or su), which also reaches
+    /// non-exported components, and stage files in the sandbox of the app;
+    /// needs a rooted test device
+    #[arg(long, default_value = "false")]
+    root: bool,
+
+    /// How to send broadcasts to receivers whose template declares a
+    /// required permission
+    #[arg(long, value_enum, default_value = "shell")]
+    permission_delivery: PermissionDelivery,
+
+    /// Import the intents in this file as additional seeds
+    #[arg(long)]
+    import_seeds: Option<PathBuf>,
+
+    /// The format of the seeds to import
+    #[arg(long, value_enum, default_value = "drozer")]
+    seed_format: SeedFormat,
+
+    /// Also fuzz the PendingIntents of the notifications the app shows after
+    /// the initial corpus ran, sent with the inputs as fill-in intents
+    #[arg(long, default_value = "false")]
+    fuzz_notifications: bool,
+
+    /// Also fuzz the intents of the app's shortcuts (static, dynamic and
+    /// pinned), starting from the intents they declare
+    #[arg(long, default_value = "false")]
+    fuzz_shortcuts: bool,
+
+    /// Stop scheduling corpus entries whose executions time out more often
+    /// than this fraction (e.g. 0.9)
+    #[arg(long)]
+    cull_timeout_rate: Option<f64>,
+
+    /// Treat inputs causing an OutOfMemoryError in the app as solutions
+    #[arg(long, default_value = "false")]
+    detect_out_of_memory: bool,
+
+    /// The directory to store the heap dumps of the app in when it runs out
+    /// of memory. Only the heap of an app that survives the error can be
+    /// dumped
+    #[arg(long, requires = "detect_out_of_memory")]
+    heap_dumps_dir: Option<PathBuf>,
+
+    /// Treat inputs whose data the app passes on to a new activity start as
+    /// solutions (potential intent redirection). Needs the content provider
+    /// app to see the extras passed on
+    #[arg(long, default_value = "false")]
+    detect_intent_redirection: bool,
+
+    /// Attach the logcat lines of the app containing this pattern (e.g.
+    /// "Exception") to the testcases; can be given multiple times
+    #[arg(long = "logcat-pattern")]
+    logcat_patterns: Vec<String>,
+
+    /// Keep the first input for which a logcat line of the app matches this
+    /// regular expression (e.g. "W SQLiteLog") in the corpus; can be repeated
+    #[arg(long = "logcat-feedback-pattern")]
+    logcat_feedback_patterns: Vec<String>,
+
+    /// Treat inputs for which a logcat line of the app matches this regular
+    /// expression (e.g. "leaked IntentReceiver") as solutions; can be repeated
+    #[arg(long = "logcat-objective-pattern")]
+    logcat_objective_patterns: Vec<String>,
+
+    /// Do not keep crashes whose exception signature contains this (e.g.
+    /// com.thirdparty.sdk) as solutions; can be repeated
+    #[arg(long = "ignore-exception")]
+    ignored_exceptions: Vec<String>,
+
+    /// Classify crashes whose exception signature contains this as high
+    /// severity; can be repeated
+    #[arg(long = "escalate-exception")]
+    escalated_exceptions: Vec<String>,
+
+    /// Treat inputs throwing an exception class not seen before in the
+    /// logcat of the app as interesting
+    #[arg(long, default_value = "false")]
+    track_exception_classes: bool,
+
+    /// Spawn a new adb shell for every device command instead of running
+    /// them in a long-lived shell
+    #[arg(long, default_value = "false")]
+    no_persistent_shell: bool,
+
+    /// Number of attempts to deliver an intent before giving up
+    #[arg(long, default_value = "5", value_parser = clap::value_parser!(u32).range(1..))]
+    delivery_attempts: u32,
+
+    /// Milliseconds to wait after the first failed delivery attempt
+    #[arg(long, default_value = "2000")]
+    delivery_backoff: u64,
+
+    /// Factor by which the wait grows after every failed delivery attempt, at
+    /// least 1.0
+    #[arg(long, default_value = "1.0", value_parser = parse_backoff_factor)]
+    delivery_backoff_factor: f64,
+
+    /// Also restart the app when a failed delivery prints this on stderr,
+    /// besides on "OutOfResourcesException" and on "Activity not started, its
+    /// current task has been brought to the front"; can be given multiple
+    /// times
+    #[arg(long = "app-restart-pattern")]
+    app_restart_patterns: Vec<String>,
+
+    /// Restart the device when a failed delivery prints this on stderr; can be
+    /// given multiple times
+    #[arg(long = "device-restart-pattern")]
+    device_restart_patterns: Vec<String>,
+
+    /// Check the health of the device every this many seconds, pausing to
+    /// remediate when it is unhealthy [default: never]
+    #[arg(long)]
+    health_check_interval: Option<u64>,
+
+    /// Free storage (in MiB) below which the device is unhealthy
+    #[arg(long, default_value = "500")]
+    min_free_storage: u64,
+
+    /// Available memory (in MiB) below which the device is unhealthy
+    #[arg(long, default_value = "200")]
+    min_available_memory: u64,
+
+    /// Thermal status (0 = none, 6 = shutdown) above which the device is
+    /// unhealthy
+    #[arg(long, default_value = "3")]
+    max_thermal_status: u32,
+
+    /// Number of coverage socket reconnects before restarting the app
+    #[arg(long, default_value = "1")]
+    socket_reconnects: u64,
+
+    /// Number of consecutive failures to reach the app after which the
+    /// Android framework is restarted as well
+    #[arg(long, default_value = "2")]
+    framework_restart_after: u32,
+
+    /// Number of consecutive failures to reach the app after which the device
+    /// is rebooted [default: never]
+    #[arg(long)]
+    reboot_after: Option<u32>,
+
+    /// How to detect that the (re-)started app is ready to receive intents
+    /// [default: activity-idle when fuzzing activities, process-started
+    /// otherwise]
+    #[arg(long, value_enum)]
+    idle_strategy: Option<IdleStrategy>,
+
+    /// Seconds to wait after the app process started before detecting idle
+    /// [default: 5 when fuzzing activities, 0 otherwise]
+    #[arg(long)]
+    idle_settle_delay: Option<u64>,
+
+    /// Seconds to wait for the app to become idle
+    #[arg(long, default_value = "20")]
+    idle_timeout: u64,
+
     /// Switch to disable usage of coverage feedback
     #[arg(long, default_value = "false")]
     no_coverage: bool,
@@ -65,7 +473,13 @@ struct CommandLineArgs {
     #[arg(long, default_value = "corpus")]
     corpus_dir: PathBuf,
 
-    /// The directory to store the crashes in
+    /// The format to store the metadata (incl. execution time and how often
+    /// it was scheduled) of the corpus entries in
+    #[arg(long, value_enum, default_value = "json-pretty")]
+    corpus_metadata: CorpusMetadataFormat,
+
+    /// The directory to store the crashes in, as
+    /// `<bucket>/<hash>/{input,metadata}.json`
     #[arg(long, default_value = "crashes")]
     crashes_dir: PathBuf,
 
@@ -73,26 +487,339 @@ struct CommandLineArgs {
     #[arg(long, default_value = "traces")]
     traces_dir: PathBuf,
 
+    /// Compress the pulled trace files with gzip
+    #[arg(long, default_value = "false")]
+    compress_traces: bool,
+
+    /// Stop the campaign once the corpus directory uses this many MiB on disk
+    /// [default: no limit]
+    #[arg(long)]
+    max_corpus_size: Option<u64>,
+
+    /// Number of bugreports and heap dumps to keep, deleting the oldest ones
+    /// first [default: keep all]
+    #[arg(long)]
+    max_crash_bundles: Option<usize>,
+
+    /// The directory to store the Markdown crash reports in
+    #[arg(long, default_value = "crash_reports")]
+    crash_reports_dir: PathBuf,
+
+    /// Capture a device bugreport for native crashes and ANRs
+    #[arg(long, default_value = "false")]
+    capture_bugreport: bool,
+
+    /// The SQLite database to record campaign results in
+    #[arg(long)]
+    results_db: Option<PathBuf>,
+
+    /// The URL to POST JSON notifications of the first crash, new crash
+    /// buckets, coverage plateaus and device failures to, e.g. a Slack
+    /// incoming webhook
+    #[arg(long)]
+    webhook_url: Option<String>,
+
+    /// Notify a coverage plateau once the coverage did not grow for this many
+    /// minutes
+    #[arg(long, default_value = "60")]
+    webhook_plateau: u64,
+
+    /// The directory to record the edges first covered by each corpus entry in
+    #[arg(long)]
+    edge_attribution_dir: Option<PathBuf>,
+
     /// The file to store the fuzzer stats in
     #[arg(long, default_value = "fuzzer_stats.toml")]
     stats_file: PathBuf,
 
-    /// The file to store the overall edge count in
-    #[arg(long, default_value = "overall_coverage.txt")]
+    /// The CSV file to store the overall edge count over time in
+    #[arg(long, default_value = "overall_coverage.csv")]
     overall_coverage_file: PathBuf,
+
+    /// Stop fuzzing after the given number of seconds
+    #[arg(long)]
+    time_budget: Option<u64>,
+
+    /// Re-execute every new crash or timeout this many times and record how
+    /// often it reproduced in its metadata
+    #[arg(long)]
+    verify_solutions: Option<usize>,
+
+    /// The seed of the random number generator
+    #[arg(long, default_value = "0")]
+    seed: u64,
+
+    /// Fuzz the components of a multi-template campaign round-robin, each for
+    /// the given number of seconds at a time
+    #[arg(long)]
+    component_slice: Option<u64>,
+
+    /// Stop fuzzing a component once it found no new coverage for the given
+    /// number of minutes of its fuzzing time
+    #[arg(long, requires = "component_slice")]
+    component_stale_timeout: Option<u64>,
+
+    /// Stop fuzzing a component once it was fuzzed for the given number of
+    /// seconds in total
+    #[arg(long, requires = "component_slice")]
+    component_budget: Option<u64>,
+
+    /// The file to persist the progress of the components in, to resume a
+    /// multi-component campaign where it left off
+    #[arg(long, default_value = "component_progress.json")]
+    component_progress_file: PathBuf,
+
+    /// Resume the components where the previous run left off, according to
+    /// the component progress file
+    #[arg(long, requires = "component_slice")]
+    resume: bool,
+
+    /// How many seconds to wait between printing (and reporting as user
+    /// stats) the corpus entries, new edges and crashes by intent action and
+    /// category, 0 disables it
+    #[arg(long, default_value = "300")]
+    entry_point_report_interval: u64,
+
+    /// The file to periodically export the state of the scheduler queue to
+    /// as JSON (ids, scheduling counts, execution times and whether the
+    /// corpus entries are eligible for scheduling)
+    #[arg(long)]
+    queue_export_file: Option<PathBuf>,
+
+    /// How many seconds to wait between exports of the scheduler queue
+    #[arg(long, default_value = "60")]
+    queue_export_interval: u64,
+
+    /// CI mode: fuzz within the time budget, write a JSON summary and exit
+    /// with a non-zero code if crashes were found
+    #[arg(long, default_value = "false", requires = "time_budget")]
+    ci: bool,
+
+    /// The file to write the CI summary to
+    #[arg(long, default_value = "ci_summary.json")]
+    ci_summary_file: PathBuf,
+
+    /// The address to serve the HTTP control API on, e.g. `127.0.0.1:8080`
+    #[arg(long)]
+    control_address: Option<String>,
+
+    /// Daemon mode: run the campaign definitions in this directory one after
+    /// another until it is empty
+    #[arg(long)]
+    campaign_queue: Option<PathBuf>,
+
+    /// The directory to archive the outputs of the daemon's campaigns in
+    #[arg(long, default_value = "campaigns")]
+    archive_dir: PathBuf,
+
+    /// Export the crashes as verification scripts to this directory and exit
+    #[arg(long)]
+    export_scripts: Option<PathBuf>,
+}
+
+/// Tasks run instead of fuzzing.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write an HTML report of the covered and uncovered methods of every
+    /// class of a campaign
+    Report {
+        /// The overall coverage map of the campaign, written next to the
+        /// overall coverage file
+        #[arg(long, default_value = "overall_coverage.map")]
+        coverage_map: PathBuf,
+
+        /// The mapping of the coverage map entries to source locations
+        /// exported by the agent
+        #[arg(long)]
+        mapping: PathBuf,
+
+        /// The decompiled sources of the app (e.g. the output directory of
+        /// jadx), to show the covered lines
+        #[arg(long)]
+        sources: Option<PathBuf>,
+
+        /// The directory to write the report to
+        #[arg(long, default_value = "coverage_report")]
+        output: PathBuf,
+    },
+    /// Rewrite the corpus and the solutions of a campaign stored by an older
+    /// version of the fuzzer in the current input format
+    MigrateCorpus,
+    /// Write the JSON Schema of the intent template files
+    TemplateSchema {
+        /// The file to write the schema to, by default standard output
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Lets every option be set with a `MALINTENT_<OPTION>` environment
+/// variable as well, e.g. `MALINTENT_COVERAGE_SOCKET_ADDRESS`, so deployments
+/// can configure the fuzzer without templating command lines. The options of
+/// subcommands are prefixed with the subcommand, e.g.
+/// `MALINTENT_REPORT_OUTPUT`. Options given on the command line take
+/// precedence.
+fn with_env_overrides(command: clap::Command, prefix: &str) -> clap::Command {
+    let subcommands: Vec<String> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_owned())
+        .collect();
+    let command = subcommands.iter().fold(command, |command, name| {
+        let prefix = format!("{}{}_", prefix, name.replace('-', "_").to_uppercase());
+        command.mut_subcommand(name, |subcommand| with_env_overrides(subcommand, &prefix))
+    });
+    command.mut_args(|arg| {
+        if arg.is_positional() || ["help", "version"].contains(&arg.get_id().as_str()) {
+            return arg;
+        }
+        let name = format!("{}{}", prefix, arg.get_id().as_str().to_uppercase());
+        arg.env(name)
+    })
+}
+
+/// Parses a factor by which a wait grows, which must not shrink it.
+fn parse_backoff_factor(value: &str) -> Result<f64, String> {
+    let factor: f64 = value
+        .parse()
+        .map_err(|err: std::num::ParseFloatError| err.to_string())?;
+    if !factor.is_finite() || factor < 1.0 {
+        return Err(format!("{} is not a finite number of at least 1.0", factor));
+    }
+    Ok(factor)
 }
 
 fn main() {
-    let mut args = CommandLineArgs::parse();
+    let matches = with_env_overrides(CommandLineArgs::command(), "MALINTENT_").get_matches();
+    let mut args = CommandLineArgs::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+
+    // Set ADB_COMMAND from environment if present, unless the adb command was
+    // given explicitly.
+    if matches.value_source("adb_command") == Some(ValueSource::DefaultValue) {
+        if let Ok(command) = env::var("ADB_COMMAND") {
+            args.adb_command = command;
+        }
+    }
+
+    // Talk to a remote adb server, if any. Its forwarded ports listen on the
+    // host of the server.
+    let adb_server = args
+        .adb_server
+        .clone()
+        .or_else(|| env::var("ADB_SERVER_SOCKET").ok())
+        .map(|address| AdbServer::parse(&address))
+        .transpose()
+        .expect("Failed to parse the adb server address");
+    let mut instances: Vec<AppInstance> = args
+        .instances
+        .iter()
+        .map(|spec| AppInstance::parse(spec))
+        .collect::<Result<_, _>>()
+        .expect("Failed to parse the app instances");
+    if let Some(server) = &adb_server {
+        args.coverage_socket_address = server.forwarded_address(&args.coverage_socket_address);
+        for instance in &mut instances {
+            instance.coverage_socket_address =
+                server.forwarded_address(&instance.coverage_socket_address);
+        }
+        println!(
+            "Reaching the coverage socket at {}, which needs the adb server to be started with \
+            `adb -a`",
+            args.coverage_socket_address
+        );
+    }
+    let adb_args = match &args.adb_args {
+        Some(adb_args) => shlex::split(adb_args).expect("Failed to parse the adb arguments"),
+        None => Vec::new(),
+    };
 
-    // Set ADB_COMMAND from environment if present.
-    if let Ok(command) = env::var("ADB_COMMAND") {
-        args.adb_command = command;
+    if let Some(Command::Report {
+        coverage_map,
+        mapping,
+        sources,
+        output,
+    }) = &args.command
+    {
+        let mapping = CoverageMapping::load(mapping).expect("Failed to read the coverage mapping");
+        let (covered, methods) =
+            coverage_report::write_report(coverage_map, &mapping, sources.as_deref(), output)
+                .expect("Failed to write the coverage report");
+        println!(
+            "Wrote the coverage report to {:?} ({} of {} methods covered)",
+            output, covered, methods
+        );
+        return;
+    }
+
+    if let Some(Command::MigrateCorpus) = &args.command {
+        let mut files =
+            corpus_replay::corpus_files(&args.corpus_dir).expect("Failed to read the corpus");
+        files.extend(crash_layout::solution_inputs(&args.crashes_dir));
+        let summary = input_schema::migrate_files(&files);
+        for (file, err) in &summary.failed {
+            println!("Failed to migrate {:?}: {}", file, err);
+        }
+        println!(
+            "Migrated {} inputs to schema version {}, {} were current, {} failed",
+            summary.migrated,
+            input_schema::SCHEMA_VERSION,
+            summary.current,
+            summary.failed.len()
+        );
+        return;
+    }
+
+    if let Some(Command::TemplateSchema { output }) = &args.command {
+        let schema = crash_layout::to_pretty_json(&intent_generator::template_schema())
+            .expect("Failed to serialize the template schema");
+        match output {
+            Some(output) => {
+                std::fs::write(output, schema + "\n").expect("Failed to write the template schema")
+            }
+            None => println!("{}", schema),
+        }
+        return;
+    }
+
+    if let Some(campaign_queue) = &args.campaign_queue {
+        let options = daemon::DaemonOptions {
+            adb_command: args.adb_command.clone(),
+            adb_server,
+            adb_args: args.adb_args.clone(),
+            coverage_socket_address: args.coverage_socket_address.clone(),
+            no_coverage: args.no_coverage,
+            capture_bugreport: args.capture_bugreport,
+            results_db: args.results_db.clone(),
+        };
+        daemon::run(campaign_queue, &args.archive_dir, &options);
+        return;
+    }
+
+    if let Some(scripts_dir) = &args.export_scripts {
+        let count = script_export::export_scripts(&args.crashes_dir, scripts_dir)
+            .expect("Failed to export verification scripts");
+        println!(
+            "Exported {} verification scripts to {:?}",
+            count, scripts_dir
+        );
+        return;
     }
 
     // Generator of initial intents.
-    let generator = IntentGenerator::new(&args.intent_config);
+    let mut generator = IntentGenerator::new(&args.intent_config);
     let app_name = generator.package_name();
+    if let Some(apk) = &args.apk {
+        match dex_extras::mine_extras_keys(apk) {
+            Ok(keys) => {
+                let found = keys.len();
+                let added = generator.add_known_extras_keys(keys);
+                println!(
+                    "Found {} extras keys in the APK, {} not in the templates",
+                    found, added
+                );
+            }
+            Err(err) => println!("Failed to mine the extras keys of the APK: {:?}", err),
+        }
+    }
 
     // Check if the receiver type is supported
     if !generator.is_supported() {
@@ -100,14 +827,118 @@ fn main() {
         return;
     }
 
-    // Adb device to send intents to.
-    let adb_device = AdbDevice::new(&args.adb_command);
+    // Database to record the campaign results in, tagged with the package and
+    // the device the campaign runs on.
+    let device = env::var("ANDROID_SERIAL").unwrap_or_else(|_| "default".to_owned());
+    if let Some(results_db) = &args.results_db {
+        results_db::init(results_db, &format!("{}@{}", app_name, device))
+            .expect("Failed to open results database");
+    }
+
+    // Webhook to notify of the campaign events, tagged the same way.
+    if let Some(webhook_url) = &args.webhook_url {
+        notifier::init(
+            webhook_url,
+            &format!("{}@{}", app_name, device),
+            Duration::from_secs(args.webhook_plateau * 60),
+        )
+        .expect("Failed to set up the webhook notifications");
+    }
 
-    adb_device.grant_uri_permissions(&app_name);
-    adb_device.set_debug_app(&app_name);
+    // Adb device to send intents to.
+    let default_idle_detection = IdleDetection::for_targets(generator.has_activities());
+    let build_device = |serial: Option<String>| {
+        let adb_device = AdbDevice::new(&args.adb_command)
+            .with_server(adb_server.clone())
+            .with_serial(serial)
+            .with_extra_args(adb_args.clone())
+            .with_persistent_shell(!args.no_persistent_shell)
+            .with_instrumentation_runner(args.instrumentation_runner.clone())
+            .with_escalation_policy(EscalationPolicy {
+                socket_reconnects: args.socket_reconnects,
+                framework_restart_after: args.framework_restart_after,
+                reboot_after: args.reboot_after,
+            })
+            .with_delivery_policy(DeliveryPolicy {
+                max_attempts: args.delivery_attempts,
+                initial_backoff: Duration::from_millis(args.delivery_backoff),
+                backoff_factor: args.delivery_backoff_factor,
+                app_restart_patterns: DEFAULT_APP_RESTART_PATTERNS
+                    .iter()
+                    .map(|p| p.to_string())
+                    .chain(args.app_restart_patterns.iter().cloned())
+                    .collect(),
+                device_restart_patterns: args.device_restart_patterns.clone(),
+            })
+            .with_idle_detection(IdleDetection {
+                strategy: args
+                    .idle_strategy
+                    .unwrap_or(default_idle_detection.strategy),
+                settle_delay: args
+                    .idle_settle_delay
+                    .map_or(default_idle_detection.settle_delay, Duration::from_secs),
+                timeout: Duration::from_secs(args.idle_timeout),
+            })
+            .with_root(args.root, &app_name)
+            .expect("Failed to set up the root mode");
+        // The files are staged for all app instances at once, outside of the
+        // sandbox of any of them.
+        let adb_device = if instances.is_empty() {
+            adb_device.with_run_as(&app_name)
+        } else {
+            adb_device
+        };
+        adb_device.with_app_setup(AppSetup {
+            package: app_name.clone(),
+            revoke_uri_grants: args.mutate_uri_grants,
+        })
+    };
 
     let enable_synchronization = generator.enable_synchronization();
 
+    let coverage_mapping = args
+        .coverage_mapping
+        .as_deref()
+        .map(CoverageMapping::load)
+        .transpose()
+        .expect("Failed to read the coverage mapping");
+
+    // Shard the replay across the devices, without touching the default one.
+    if args.run_corpus && !args.replay_devices.is_empty() {
+        let corpus =
+            corpus_replay::corpus_files(&args.corpus_dir).expect("Failed to read the corpus");
+        replay_sharded(
+            &args,
+            &build_device,
+            &app_name,
+            coverage_mapping,
+            enable_synchronization,
+            corpus,
+        );
+        return;
+    }
+
+    let adb_device = build_device(None);
+    if adb_device.stages_in_sandbox() {
+        intent_input::set_file_dir(adb_device::sandbox_staging_dir(&app_name));
+    }
+
+    adb_device.set_up_app();
+
+    // Identify the campaign in all of its artifacts.
+    let campaign = CampaignInfo::new(
+        &adb_device,
+        &app_name,
+        &device,
+        &format!("{:?}", args),
+        Path::new(&args.intent_config),
+    )
+    .expect("Failed to identify the campaign");
+    println!("Campaign: {}", campaign.id);
+    run_config::init_campaign(campaign).expect("Failed to identify the campaign");
+    run_config::write_campaign_file(&args.overall_coverage_file)
+        .expect("Failed to write the campaign file");
+
     if args.run_corpus {
         // Create the ".hook_native" file to enable JNI tracing.
         if args.trace_native {
@@ -126,9 +957,17 @@ fn main() {
             enable_synchronization,
             !args.no_coverage,
             &args.overall_coverage_file,
-        );
+            Duration::from_secs(args.socket_timeout),
+            args.coverage_mode,
+        )
+        .with_coverage_mapping(coverage_mapping)
+        .with_coverage_filter(args.coverage_packages.clone())
+        .and_then(|observer| observer.with_method_mapping())
+        .expect("Failed to set up the coverage mode");
 
-        re_run(observer, adb_device.clone(), args.corpus_dir);
+        let corpus =
+            corpus_replay::corpus_files(&args.corpus_dir).expect("Failed to read the corpus");
+        re_run(observer, adb_device.clone(), &corpus);
 
         // Stop app to disable JNI tracing.
         adb_device.stop_app(&app_name).expect("Failed to stop app");
@@ -138,6 +977,11 @@ fn main() {
             adb_device
                 .pull_native_trace_files(&app_name, &args.traces_dir)
                 .expect("Failed to pull trace files");
+
+            if args.compress_traces {
+                disk_retention::compress_traces(&args.traces_dir)
+                    .expect("Failed to compress trace files");
+            }
         }
     } else {
         // Fuzzing with native hooking is not supported.
@@ -159,13 +1003,129 @@ fn main() {
             enable_synchronization,
             !args.no_coverage,
             &args.overall_coverage_file,
-        );
+            Duration::from_secs(args.socket_timeout),
+            args.coverage_mode,
+        )
+        .with_coverage_mapping(coverage_mapping)
+        .with_coverage_filter(args.coverage_packages.clone())
+        .and_then(|observer| observer.with_method_mapping())
+        .and_then(|observer| observer.with_instances(&instances))
+        .expect("Failed to set up the coverage mode");
 
-        fuzz(observer, adb_device, args, generator);
+        fuzz(observer, adb_device, app_name, args, generator, &instances);
     }
 }
 
-fn re_run(observer: SocketCoverageObserver, adb_device: AdbDevice, corpus_dir: PathBuf) {
+/// Replays the corpus sharded across the replay devices in parallel and
+/// merges their overall coverage and native traces.
+fn replay_sharded(
+    args: &CommandLineArgs,
+    build_device: &(dyn Fn(Option<String>) -> AdbDevice + Sync),
+    app_name: &str,
+    coverage_mapping: Option<CoverageMapping>,
+    enable_synchronization: bool,
+    corpus: Vec<PathBuf>,
+) {
+    let devices: Vec<ReplayDevice> = args
+        .replay_devices
+        .iter()
+        .map(|spec| ReplayDevice::parse(spec))
+        .collect::<Result<_, _>>()
+        .expect("Failed to parse the replay devices");
+    println!(
+        "Replaying {} corpus entries on {} devices",
+        corpus.len(),
+        devices.len()
+    );
+    let shards = corpus_replay::shard(corpus, devices.len());
+    let coverage_files: Vec<PathBuf> = (0..devices.len())
+        .map(|index| corpus_replay::device_file(&args.overall_coverage_file, index))
+        .collect();
+    let trace_dirs: Vec<PathBuf> = (0..devices.len())
+        .map(|index| args.traces_dir.join(format!("device{}", index)))
+        .collect();
+
+    // The staging directory is shared by all devices, so it has to be chosen
+    // before any of them stages an input.
+    let adb_devices: Vec<AdbDevice> = devices
+        .iter()
+        .map(|device| build_device(Some(device.serial.clone())))
+        .collect();
+    if adb_devices.iter().all(AdbDevice::stages_in_sandbox) {
+        intent_input::set_file_dir(adb_device::sandbox_staging_dir(app_name));
+    }
+
+    std::thread::scope(|scope| {
+        for (index, ((device, adb_device), shard)) in
+            devices.iter().zip(adb_devices).zip(shards).enumerate()
+        {
+            let coverage_mapping = coverage_mapping.clone();
+            let (coverage_file, trace_dir) = (&coverage_files[index], &trace_dirs[index]);
+            scope.spawn(move || {
+                adb_device.set_up_app();
+                if !args.no_coverage {
+                    if let Err(err) = adb_device.install_coverage_agent(app_name) {
+                        println!(
+                            "Failed to install the coverage agent on {}: {:?}",
+                            device.serial, err
+                        );
+                    }
+                }
+                if args.trace_native {
+                    adb_device.enable_native_hooking(app_name);
+                } else {
+                    adb_device.disable_native_hooking(app_name);
+                }
+                adb_device.restart_app(app_name);
+
+                let observer = socket_coverage_observer::create_coverage_map_observer(
+                    adb_device.clone(),
+                    app_name.to_owned(),
+                    &device.coverage_socket_address,
+                    true,
+                    enable_synchronization,
+                    !args.no_coverage,
+                    coverage_file,
+                    Duration::from_secs(args.socket_timeout),
+                    args.coverage_mode,
+                )
+                .with_coverage_mapping(coverage_mapping)
+                .with_coverage_filter(args.coverage_packages.clone())
+                .and_then(|observer| observer.with_method_mapping())
+                .expect("Failed to set up the coverage mode");
+
+                re_run(observer, adb_device.clone(), &shard);
+
+                // Stop app to disable JNI tracing.
+                adb_device.stop_app(app_name).expect("Failed to stop app");
+                if args.trace_native {
+                    adb_device
+                        .pull_native_trace_files(app_name, trace_dir)
+                        .expect("Failed to pull trace files");
+                }
+                println!(
+                    "Device {} replayed {} corpus entries",
+                    device.serial,
+                    shard.len()
+                );
+            });
+        }
+    });
+
+    let edges = corpus_replay::merge_overall_coverage(&coverage_files, &args.overall_coverage_file)
+        .expect("Failed to merge the overall coverage");
+    println!("Merged overall coverage: {} edges", edges);
+    if args.trace_native {
+        corpus_replay::merge_traces(&trace_dirs, &args.traces_dir)
+            .expect("Failed to merge the trace files");
+        if args.compress_traces {
+            disk_retention::compress_traces(&args.traces_dir)
+                .expect("Failed to compress trace files");
+        }
+    }
+}
+
+fn re_run(observer: SocketCoverageObserver, adb_device: AdbDevice, corpus: &[PathBuf]) {
     let mut feedback = ConstFeedback::new(true);
     let mut objective = ConstFeedback::new(false);
     // The Monitor trait defines how the fuzzer stats are displayed to the user
@@ -195,22 +1155,90 @@ fn re_run(observer: SocketCoverageObserver, adb_device: AdbDevice, corpus_dir: P
     let mut executor = adb_executor::AdbExecutor::new(adb_device, tuple_list!(observer));
 
     state
-        .load_initial_inputs_forced(
-            &mut fuzzer,
-            &mut executor,
-            &mut mgr,
-            &[PathBuf::from(corpus_dir)],
-        )
+        .load_initial_inputs_by_filenames_forced(&mut fuzzer, &mut executor, &mut mgr, corpus)
         .expect("Failed to load the corpus");
 }
 
 fn fuzz(
     observer: SocketCoverageObserver,
     adb_device: AdbDevice,
+    app_name: String,
     args: CommandLineArgs,
     mut generator: IntentGenerator,
+    instances: &[AppInstance],
 ) {
-    let mut feedback = AflMapFeedback::new(&observer);
+    // Observer of the execution time, recorded in the corpus entries.
+    let time_observer = TimeObserver::new("time");
+    // Observer of the components started by an intent.
+    let launch_observer = ComponentLaunchObserver::new(
+        adb_device.clone(),
+        app_name.clone(),
+        args.track_component_launches,
+    );
+    // Observer of the notable logcat lines of an execution.
+    let logcat_observer = LogcatObserver::new(
+        adb_device.clone(),
+        app_name.clone(),
+        args.logcat_patterns.clone(),
+    )
+    .track_exception_classes(args.track_exception_classes)
+    .watch_patterns(
+        args.logcat_feedback_patterns
+            .iter()
+            .chain(&args.logcat_objective_patterns)
+            .cloned()
+            .collect(),
+    )
+    .expect("Failed to set up the logcat patterns");
+    // Keeps the corpus and the crash bundles within their disk budget.
+    let mut disk_retention = DiskRetention::new(
+        RetentionPolicy {
+            max_corpus_size_mb: args.max_corpus_size,
+            max_crash_bundles: args.max_crash_bundles,
+        },
+        args.corpus_dir.clone(),
+        Duration::from_secs(60),
+    )
+    .with_bundle_dir(args.crash_reports_dir.clone(), "_bugreport.zip");
+    if let Some(heap_dumps_dir) = &args.heap_dumps_dir {
+        disk_retention = disk_retention.with_bundle_dir(heap_dumps_dir.clone(), ".hprof");
+    }
+
+    // Watchdog pausing the campaign while the device is unhealthy.
+    let health_watchdog = args.health_check_interval.map(|secs| {
+        HealthWatchdog::start(
+            adb_device.clone(),
+            app_name.clone(),
+            Duration::from_secs(secs),
+            HealthThresholds {
+                min_free_storage_mb: args.min_free_storage,
+                min_available_memory_mb: args.min_available_memory,
+                max_thermal_status: args.max_thermal_status,
+            },
+        )
+    });
+
+    // Intents that were not delivered are not evaluated at all.
+    let mut feedback = feedback_and_fast!(
+        DeliveredFeedback::new(),
+        feedback_or!(
+            AflMapFeedback::tracking(&observer, args.track_indexes, args.track_novelties),
+            ExceptionNoveltyFeedback::new(),
+            ExceptionClassFeedback::new(),
+            ComponentLaunchFeedback::new(),
+            IntentResultFeedback::new(),
+            ObservedValueFeedback::new(),
+            LogcatFeedback::new(),
+            LogcatWatchFeedback::new(args.logcat_feedback_patterns.clone(), false),
+            TimeoutCullingFeedback::new(args.cull_timeout_rate),
+            ResultsDbFeedback::new(RecordKind::CorpusEntry),
+            CampaignInfoFeedback::new(),
+            EdgeAttributionFeedback::new(args.edge_attribution_dir.clone()),
+            EntryPointFeedback::new(false),
+            CoverageGoalFeedback::new(args.coverage_goals.clone()),
+            TimeFeedback::with_observer(&time_observer)
+        )
+    );
     // The Monitor trait defines how the fuzzer stats are displayed to the user
     let simple_mon = SimpleMonitor::new(|s| println!("{s}"));
 
@@ -223,18 +1251,65 @@ fn fuzz(
     // such as the notification of the addition of a new item to the corpus
     let mut mgr = SimpleEventManager::new(mon);
 
-    // A feedback to choose if an input is a solution or not
-    let mut objective = CrashFeedback::new();
+    // A feedback to choose if an input is a solution or not, skipping native
+    // crashes with a known signature and writing a report for every new
+    // solution. Intents that were not delivered and crashes with an ignored
+    // exception signature don't reach the inner feedbacks, so they don't
+    // record or report anything for them.
+    let mut objective = feedback_and_fast!(
+        DeliveredFeedback::new(),
+        ExceptionFilterFeedback::new(
+            adb_device.clone(),
+            app_name.clone(),
+            args.ignored_exceptions.clone(),
+            args.escalated_exceptions.clone()
+        ),
+        feedback_or!(
+            feedback_and_fast!(
+                CrashFeedback::new(),
+                NativeCrashDedupFeedback::new(adb_device.clone(), app_name.clone())
+            ),
+            OutOfMemoryFeedback::new(
+                adb_device.clone(),
+                app_name.clone(),
+                args.detect_out_of_memory,
+                args.heap_dumps_dir.clone()
+            ),
+            IntentRedirectionFeedback::new(
+                adb_device.clone(),
+                app_name.clone(),
+                args.detect_intent_redirection
+            ),
+            ExceptionReportFeedback::new(),
+            CrashReportFeedback::new(
+                adb_device.clone(),
+                app_name.clone(),
+                args.crash_reports_dir,
+                args.capture_bugreport
+            ),
+            LogcatFeedback::new(),
+            LogcatWatchFeedback::new(args.logcat_objective_patterns.clone(), true),
+            ResultsDbFeedback::new(RecordKind::Crash),
+            CampaignInfoFeedback::new(),
+            EntryPointFeedback::new(true),
+            CrashLayoutFeedback::new(
+                adb_device.clone(),
+                app_name.clone(),
+                args.crashes_dir.clone()
+            )
+        )
+    );
 
     // create a State from scratch
     let mut state = StdState::new(
         // RNG
-        StdRand::with_seed(0),
+        StdRand::with_seed(args.seed),
         // Corpus that will be evolved.
-        CachedOnDiskCorpus::<IntentInput>::new(PathBuf::from(args.corpus_dir), 128).unwrap(),
-        // Corpus in which we store solutions (crashes in this example),
-        // on disk so the user can get them after stopping the fuzzer
-        OnDiskCorpus::<IntentInput>::new(PathBuf::from(args.crashes_dir)).unwrap(),
+        args.corpus_metadata.corpus(args.corpus_dir, 128).unwrap(),
+        // Corpus in which we store solutions (crashes in this example). The
+        // CrashLayoutFeedback writes them to the crashes directory and only
+        // their location stays in memory.
+        InMemoryCorpus::<IntentInput>::new(),
         // States of the feedbacks.
         // The feedbacks can report the data that should persist in the State.
         &mut feedback,
@@ -243,13 +1318,76 @@ fn fuzz(
     )
     .unwrap();
 
-    // A queue policy to get testcases from the corpus
-    let scheduler = QueueScheduler::new();
+    // Record the exact configuration of the campaign in the stats file.
+    run_config::report_run_config(
+        &mut mgr,
+        &mut state,
+        &adb_device,
+        &app_name,
+        args.seed,
+        Path::new(&args.intent_config),
+    )
+    .expect("Failed to report the run configuration");
+
+    // Web URIs only resolve to the app for the hosts it verified. Recorded
+    // right away, so it is there even if no seeds are generated.
+    if !state.has_named_metadata::<AppLinks>("app_links") {
+        match adb_device.app_links(&app_name) {
+            Ok(app_links)
+                if app_links.verified_hosts.is_empty() && app_links.unverified_hosts.is_empty() =>
+            {
+                println!("App declares no App Links");
+            }
+            Ok(app_links) => {
+                println!(
+                    "App Links: verified hosts {:?}, unverified hosts {:?}",
+                    app_links.verified_hosts, app_links.unverified_hosts
+                );
+                state.add_named_metadata(app_links, "app_links");
+            }
+            Err(err) => println!("Failed to read the App Links state: {}", err),
+        }
+    }
+
+    // A queue policy to get testcases from the corpus, optionally rotating
+    // through the components of the campaign
+    let scheduler = ComponentScheduler::new(
+        generator.components(),
+        args.component_slice.map(Duration::from_secs),
+        args.component_stale_timeout
+            .map(|minutes| Duration::from_secs(minutes * 60)),
+        args.component_budget.map(Duration::from_secs),
+        &args.component_progress_file,
+        args.resume,
+    );
 
     // A fuzzer with feedbacks and a corpus scheduler
     let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
 
-    let mut executor = adb_executor::AdbExecutor::new(adb_device, tuple_list!(observer));
+    let mut executor = adb_executor::AdbExecutor::new(
+        adb_device.clone(),
+        // The coverage observer comes last, so the coverage map requested by
+        // the executor arrives while the other observers query the device.
+        tuple_list!(
+            time_observer,
+            DeliveryObserver::new(),
+            launch_observer,
+            IntentResultObserver::new(),
+            logcat_observer,
+            observer
+        ),
+    )
+    .capture_activity_results(args.capture_activity_results)
+    .capture_broadcast_results(args.capture_broadcast_results)
+    .deliver_via_instrumentation(args.instrumentation_runner.is_some())
+    .permission_delivery(args.permission_delivery)
+    .confirm_new_coverage(args.confirm_new_coverage)
+    .with_instances(
+        &instances
+            .iter()
+            .map(|instance| instance.user)
+            .collect::<Vec<_>>(),
+    );
 
     let number_of_intents = generator.number_of_intents();
 
@@ -264,19 +1402,288 @@ fn fuzz(
         )
         .expect("Failed to generate the initial corpus");
 
-    let mutator = StdScheduledMutator::new(tuple_list!(
-        IntentRandomDataMutator::new(),
-        IntentRandomFlagMutator::new(),
-        IntentRandomMimeTypeMutator::new(),
-        IntentRandomAddExtraMutator::new(),
-        IntentRandomExtraKeyMutator::new(),
-        IntentRandomExtraContentMutator::new(),
-        IntentRandomExtraSchemeMutator::new(),
-        IntentRandomExtraSuffixMutator::new()
-    ));
-    let mut stages = tuple_list!(StdMutationalStage::new(mutator));
-
-    fuzzer
-        .fuzz_loop(&mut stages, &mut executor, &mut state, &mut mgr)
-        .expect("Error in the fuzzing loop");
+    seed_allocation::allocate_seed_variants::<_, _, SocketCoverageObserver, _>(
+        &mut fuzzer,
+        &mut executor,
+        &mut state,
+        &mut mgr,
+        "SocketCoverageObserver",
+        &generator.base_intents(),
+        args.seed_variants,
+        args.max_extras,
+    )
+    .expect("Failed to allocate the seed variants");
+
+    if let Some(seeds_file) = &args.import_seeds {
+        let seeds = seed_import::import_seeds(seeds_file, args.seed_format, &generator)
+            .expect("Failed to import seeds");
+        println!("Importing {} seeds from {:?}", seeds.len(), seeds_file);
+        for seed in seeds {
+            fuzzer
+                .add_input(&mut state, &mut executor, &mut mgr, seed)
+                .expect("Failed to add imported seed");
+        }
+    }
+
+    if args.fuzz_shortcuts {
+        match shortcuts::shortcut_seeds(&adb_device, &app_name) {
+            Ok(seeds) => {
+                println!("Adding {} shortcut intents as seeds", seeds.len());
+                for seed in seeds {
+                    fuzzer
+                        .add_input(&mut state, &mut executor, &mut mgr, seed)
+                        .expect("Failed to add shortcut seed");
+                }
+            }
+            Err(err) => println!("Failed to read the shortcuts of {}: {}", app_name, err),
+        }
+    }
+
+    if args.fuzz_notifications {
+        match pending_intents::notification_seeds(&adb_device, &app_name) {
+            Ok(seeds) => {
+                println!(
+                    "Adding {} notification PendingIntents as seeds",
+                    seeds.len()
+                );
+                for seed in seeds {
+                    fuzzer
+                        .add_input(&mut state, &mut executor, &mut mgr, seed)
+                        .expect("Failed to add notification seed");
+                }
+            }
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    // Other apps to impersonate in caller identity extras.
+    let installed_packages = adb_device.installed_packages().unwrap_or_else(|err| {
+        println!("Failed to list the installed packages: {}", err);
+        Vec::new()
+    });
+
+    let format_seeds =
+        FormatSeeds::load(&args.format_seeds).expect("Failed to load the format seeds");
+    let invariants = args
+        .invariants
+        .iter()
+        .map(|rule| rule.parse::<Invariant>())
+        .collect::<Result<Vec<_>, _>>()
+        .expect("Failed to parse the invariants");
+
+    // The byte mutations do not grow buffers beyond the max size.
+    state.set_max_size(args.max_extra_size);
+
+    // Offer the redirection probe as a target, so redirected extras show up
+    // in its log.
+    if args.detect_intent_redirection {
+        observed_values::offer_value(&mut state, adb_device::REDIRECTION_PROBE);
+    }
+    let mutations = move || {
+        tuple_list!(
+            IntentRandomDataMutator::new(format_seeds.clone()),
+            IntentRandomFlagMutator::new(),
+            IntentRandomMimeTypeMutator::new(),
+            IntentRemoveDataMutator::new(),
+            IntentUnsetMimeTypeMutator::new(),
+            IntentRandomAddExtraMutator::new(args.max_extras),
+            IntentRandomExtraKeyMutator::new(),
+            IntentRandomExtraContentMutator::new(),
+            IntentRandomExtraSchemeMutator::new(),
+            IntentRandomExtraSuffixMutator::new(format_seeds),
+            IntentFloatArraySpecialValueMutator::new(),
+            IntentArrayLengthMutator::new(),
+            IntentStringMutator::new(),
+            IntentObservedValueMutator::new(),
+            IntentExtrasOrderMutator::new(),
+            IntentIdentitySpoofMutator::new(installed_packages, args.max_extras),
+            IntentDataBoundaryMutator::new(),
+            IntentRandomAliasMutator::new(),
+            IntentRandomComponentMutator::new(args.retarget_components)
+        )
+    };
+    // The URI grants are only scheduled if they are mutated at all.
+    let scheduled = if args.mutate_uri_grants {
+        EitherMutator::Left(StdScheduledMutator::new(
+            mutations().merge(tuple_list!(IntentRandomURIGrantMutator::new())),
+        ))
+    } else {
+        EitherMutator::Right(StdScheduledMutator::new(mutations()))
+    };
+    let mutator = CommandLengthLimit::new(
+        ConstrainedMutator::new(InvariantFixup::new(scheduled, invariants)),
+        args.max_command_length,
+    );
+    let mut stages = tuple_list!(
+        StabilityStage::<SocketCoverageObserver, _>::new(
+            "SocketCoverageObserver",
+            args.stability_runs
+        ),
+        PipelinedMutationalStage::new(mutator)
+    );
+
+    let start_time = Instant::now();
+
+    let control_api = args.control_address.as_ref().map(|address| {
+        ControlApi::start(
+            address,
+            args.crashes_dir.clone(),
+            args.overall_coverage_file.clone(),
+        )
+        .expect("Failed to start the control API")
+    });
+
+    // Fuzz until the time budget (if any) is used up.
+    let budget = args.time_budget.map(Duration::from_secs);
+    let mut last_report = current_time();
+    let mut last_queue_export = Instant::now();
+    let mut campaign_stats = CampaignStats::new(Duration::from_secs(60));
+    let mut scheduling_stats = (args.corpus_metadata != CorpusMetadataFormat::None)
+        .then(|| SchedulingStatsPersistence::new(Duration::from_secs(60)));
+    let mut entry_point_report =
+        EntryPointReport::new(Duration::from_secs(args.entry_point_report_interval));
+    while budget.map_or(true, |budget| start_time.elapsed() < budget)
+        && !fuzzer.scheduler().all_finished()
+    {
+        if let Some(health_watchdog) = &health_watchdog {
+            health_watchdog.remediate_if_needed();
+        }
+
+        if let Some(control_api) = &control_api {
+            control_api.wait_while_paused();
+
+            for seed in control_api.take_seeds() {
+                fuzzer
+                    .add_input(&mut state, &mut executor, &mut mgr, seed)
+                    .expect("Failed to add injected seed");
+            }
+
+            if control_api.take_dump_request() {
+                if let Some(observer) = executor
+                    .observers()
+                    .match_name::<SocketCoverageObserver>("SocketCoverageObserver")
+                {
+                    match observer.dump_coverage() {
+                        Ok((map_file, edges_file)) => {
+                            println!("Dumped the coverage to {:?} and {:?}", map_file, edges_file)
+                        }
+                        Err(err) => println!("Failed to dump the coverage: {}", err),
+                    }
+                }
+            }
+        }
+
+        let known_solutions = state.solutions().count();
+        fuzzer
+            .fuzz_one(&mut stages, &mut executor, &mut state, &mut mgr)
+            .expect("Error in the fuzzing loop");
+        if let Some(runs) = args.verify_solutions.filter(|runs| *runs > 0) {
+            reproduction::verify_solutions(
+                &mut fuzzer,
+                &mut executor,
+                &mut state,
+                &mut mgr,
+                known_solutions,
+                runs,
+            )
+            .expect("Failed to verify the new solutions");
+        }
+        last_report = mgr
+            .maybe_report_progress(&mut state, last_report, Duration::from_secs(15))
+            .expect("Failed to report progress");
+        campaign_stats
+            .maybe_report(&mut mgr, &mut state, secs_since_new_coverage(&executor))
+            .expect("Failed to report campaign stats");
+        notifier::check_plateau(secs_since_new_coverage(&executor));
+        entry_point_report
+            .maybe_report(&mut mgr, &mut state)
+            .expect("Failed to report the entry points");
+        if let Some(scheduling_stats) = &mut scheduling_stats {
+            scheduling_stats.maybe_persist(state.corpus_mut());
+        }
+
+        if let Some(queue_export_file) = &args.queue_export_file {
+            if last_queue_export.elapsed() >= Duration::from_secs(args.queue_export_interval) {
+                if let Err(err) = fuzzer
+                    .scheduler()
+                    .export_queue(state.corpus(), queue_export_file)
+                {
+                    println!("Failed to export the scheduler queue: {}", err);
+                }
+                last_queue_export = Instant::now();
+            }
+        }
+
+        if disk_retention.maybe_enforce() {
+            break;
+        }
+
+        if args.stop_at_goals
+            && state
+                .named_metadata::<CoverageGoalProgress>("coverage_goal_progress")
+                .is_ok_and(|progress| progress.all_reached())
+        {
+            println!("Reached all coverage goals, stopping");
+            break;
+        }
+
+        if let Some(control_api) = &control_api {
+            control_api.update_status(control_api::Status {
+                paused: false,
+                execs: *state.executions(),
+                corpus_size: state.corpus().count(),
+                crashes: state.solutions().count(),
+                edges: overall_edge_count(&executor),
+                elapsed_secs: start_time.elapsed().as_secs(),
+            });
+        }
+    }
+
+    if args.corpus_metadata != CorpusMetadataFormat::None {
+        if let Err(err) = corpus_metadata::persist_scheduling_stats(state.corpus_mut()) {
+            println!("Failed to persist the scheduling stats: {}", err);
+        }
+    }
+
+    if args.ci {
+        let summary = CiSummary {
+            edges: overall_edge_count(&executor),
+            execs: *state.executions(),
+            crash_buckets: ci_summary::count_crash_buckets(state.solutions()),
+            elapsed_secs: start_time.elapsed().as_secs(),
+        };
+
+        summary
+            .write_to_file(&args.ci_summary_file)
+            .expect("Failed to write CI summary");
+        println!("CI summary: {:?}", summary);
+
+        std::process::exit(summary.exit_code());
+    }
+}
+
+/// Seconds since the overall coverage last grew, as seen by the executor's
+/// coverage observer.
+fn secs_since_new_coverage<E>(executor: &E) -> u64
+where
+    E: HasObservers,
+{
+    executor
+        .observers()
+        .match_name::<SocketCoverageObserver>("SocketCoverageObserver")
+        .map(|observer| observer.secs_since_new_coverage())
+        .unwrap_or(0)
+}
+
+/// Number of edges covered over the whole campaign, as seen by the executor's
+/// coverage observer.
+fn overall_edge_count<E>(executor: &E) -> usize
+where
+    E: HasObservers,
+{
+    executor
+        .observers()
+        .match_name::<SocketCoverageObserver>("SocketCoverageObserver")
+        .map(|observer| observer.overall_edge_count())
+        .unwrap_or(0)
 }
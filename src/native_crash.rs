@@ -0,0 +1,234 @@
+//! Parsing and deduplication of native crashes.
+//!
+//! Native crashes are parsed from the logcat crash buffer and bucketed by a
+//! signature made up of the signal, the page of the fault address and the top
+//! frames of the backtrace, so repeated hits of the same JNI bug only end up
+//! in the solutions corpus once.
+
+use std::{collections::HashSet, hash::Hasher};
+
+use fasthash::{farm::Hasher128, FastHasher, HasherExt};
+use libafl::{
+    impl_serdeany,
+    prelude::{
+        EventFirer, ExitKind, Feedback, HasMetadata, Named, ObserversTuple, Testcase, UsesInput,
+    },
+    state::{HasClientPerfMonitor, HasNamedMetadata},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{adb_device::AdbDevice, intent_input::IntentInput};
+
+/// Number of backtrace frames that make up the signature.
+const SIGNATURE_FRAMES: usize = 5;
+
+/// Mask to get the page of the fault address.
+const PAGE_MASK: u64 = !0xfff;
+
+/// A native crash of the app parsed from logcat.
+#[derive(Debug, Clone)]
+pub struct NativeCrash {
+    /// The signal name, e.g. `SIGSEGV`.
+    pub signal: String,
+    /// The fault address, if reported.
+    pub fault_addr: Option<u64>,
+    /// The backtrace frames as `library!symbol` (or `library+pc` without symbol).
+    pub frames: Vec<String>,
+    /// Whether the coverage agent shows up in the backtrace.
+    pub caused_by_coverage: bool,
+}
+
+impl NativeCrash {
+    /// Parses the first native crash of the given app from logcat output.
+    pub fn parse(log: &str, app_name: &str) -> Option<Self> {
+        let mut crash: Option<NativeCrash> = None;
+
+        for line in log.lines() {
+            if line.contains("Fatal signal") {
+                // Stop at the next crash once we found the one of the app.
+                if crash.is_some() {
+                    break;
+                }
+                if line.contains(&format!("({})", app_name)) {
+                    crash = Some(NativeCrash {
+                        signal: parse_signal(line).unwrap_or_default(),
+                        fault_addr: parse_fault_addr(line),
+                        frames: Vec::new(),
+                        caused_by_coverage: false,
+                    });
+                }
+                continue;
+            }
+
+            let Some(crash) = crash.as_mut() else {
+                continue;
+            };
+
+            if line.contains("libcoverage_instrumenting_agent.so") {
+                crash.caused_by_coverage = true;
+            }
+
+            if let Some(frame) = parse_frame(line) {
+                crash.frames.push(frame);
+            }
+        }
+
+        crash
+    }
+
+    /// The signature used to bucket this crash.
+    pub fn signature(&self) -> String {
+        let mut hasher = Hasher128::new();
+
+        hasher.write(self.signal.as_bytes());
+        if let Some(fault_addr) = self.fault_addr {
+            hasher.write(&(fault_addr & PAGE_MASK).to_le_bytes());
+        }
+        for frame in self.frames.iter().take(SIGNATURE_FRAMES) {
+            hasher.write(frame.as_bytes());
+        }
+
+        format!("{:032x}", hasher.finish_ext())
+    }
+}
+
+/// Parses the signal name from a "Fatal signal 11 (SIGSEGV), ..." line.
+fn parse_signal(line: &str) -> Option<String> {
+    let rest = &line[line.find("Fatal signal")?..];
+    let start = rest.find('(')? + 1;
+    let end = rest.find(')')?;
+    Some(rest[start..end].to_owned())
+}
+
+/// Parses the fault address from a "..., fault addr 0x1234 in tid ..." line.
+fn parse_fault_addr(line: &str) -> Option<u64> {
+    let rest = &line[line.find("fault addr ")? + "fault addr ".len()..];
+    let addr = rest.split_whitespace().next()?;
+    u64::from_str_radix(addr.trim_start_matches("0x"), 16).ok()
+}
+
+/// Parses a backtrace line like
+/// `#00 pc 0000000000012345  /data/app/.../libfoo.so (Java_foo+20) (BuildId: ...)`.
+fn parse_frame(line: &str) -> Option<String> {
+    let rest = &line[line.find('#')?..];
+    let mut tokens = rest.split_whitespace();
+
+    tokens.next()?;
+    if tokens.next()? != "pc" {
+        return None;
+    }
+    let pc = tokens.next()?;
+    let library = tokens.next()?.rsplit('/').next()?;
+
+    let symbol = tokens
+        .next()
+        .filter(|t| t.starts_with('(') && !t.starts_with("(BuildId"))
+        .map(|t| t.trim_start_matches('(').trim_end_matches(')'))
+        .map(|t| t.split('+').next().unwrap_or(t));
+
+    Some(match symbol {
+        Some(symbol) => format!("{}!{}", library, symbol),
+        None => format!("{}+{}", library, pc),
+    })
+}
+
+/// Signatures of all native crashes seen so far, stored in the state.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct NativeCrashBuckets {
+    pub signatures: HashSet<String>,
+}
+
+impl_serdeany!(NativeCrashBuckets);
+
+/// The crash signature of a solution, stored in the testcase metadata.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CrashSignature(pub String);
+
+impl_serdeany!(CrashSignature);
+
+/// Feedback that rejects native crashes whose signature was seen before.
+/// Non-native crashes are always reported as interesting, so this is meant to
+/// be combined with a crash feedback using `feedback_and_fast!`.
+#[derive(Debug)]
+pub struct NativeCrashDedupFeedback {
+    adb_device: AdbDevice,
+    app_name: String,
+    last_signature: Option<String>,
+}
+
+impl NativeCrashDedupFeedback {
+    pub fn new(adb_device: AdbDevice, app_name: String) -> Self {
+        Self {
+            adb_device,
+            app_name,
+            last_signature: None,
+        }
+    }
+}
+
+impl Named for NativeCrashDedupFeedback {
+    fn name(&self) -> &str {
+        "NativeCrashDedupFeedback"
+    }
+}
+
+impl<S> Feedback<S> for NativeCrashDedupFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor + HasNamedMetadata,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let Some(crash) = self.adb_device.report_native_crash(&self.app_name) else {
+            return Ok(true);
+        };
+
+        if !state.has_named_metadata::<NativeCrashBuckets>("native_crash_buckets") {
+            state.add_named_metadata(NativeCrashBuckets::default(), "native_crash_buckets");
+        }
+        let buckets = state.named_metadata_mut::<NativeCrashBuckets>("native_crash_buckets")?;
+
+        let signature = crash.signature();
+        if !buckets.signatures.insert(signature.clone()) {
+            println!("Native crash with known signature {}", signature);
+            return Ok(false);
+        }
+
+        println!("New native crash signature {}: {:?}", signature, crash);
+        self.last_signature = Some(signature);
+        Ok(true)
+    }
+
+    fn append_metadata<OT>(
+        &mut self,
+        _state: &mut S,
+        _observers: &OT,
+        testcase: &mut Testcase<IntentInput>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        if let Some(signature) = self.last_signature.take() {
+            testcase.add_metadata(CrashSignature(signature));
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(
+        &mut self,
+        _state: &mut S,
+        _input: &IntentInput,
+    ) -> Result<(), libafl::Error> {
+        self.last_signature = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+//! Webhook notifications of campaign events.
+//!
+//! Long unattended campaigns should alert their owner when something happens.
+//! Every event is POSTed as JSON to the configured URL (e.g. a Slack incoming
+//! webhook, which shows the `text` field): the first crash, every new crash
+//! bucket, the coverage not growing for a while, and device failures.
+//!
+//! The notifier is process-wide and optional: all `notify*` functions do
+//! nothing unless [init] was called. The requests are sent with `curl` in the
+//! background, so a slow endpoint never blocks the fuzzing loop.
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::json;
+
+struct Webhook {
+    url: String,
+    campaign: String,
+    plateau: Duration,
+}
+
+static WEBHOOK: OnceLock<Webhook> = OnceLock::new();
+/// Whether a crash was notified yet.
+static CRASH_NOTIFIED: AtomicBool = AtomicBool::new(false);
+/// Whether the current coverage plateau was notified yet.
+static PLATEAU_NOTIFIED: AtomicBool = AtomicBool::new(false);
+
+/// Sends the events of this process, tagged with the given campaign
+/// identifier, to the webhook at the URL. The coverage counts as plateaued
+/// once it did not grow for the given duration.
+pub fn init(url: &str, campaign: &str, plateau: Duration) -> Result<(), libafl::Error> {
+    let webhook = Webhook {
+        url: url.to_owned(),
+        campaign: campaign.to_owned(),
+        plateau,
+    };
+    if WEBHOOK.set(webhook).is_err() {
+        return Err(libafl::Error::illegal_state("Notifier already initialized"));
+    }
+    Ok(())
+}
+
+/// Sends the event with the given message and details to the webhook, if
+/// enabled.
+pub fn notify(event: &str, message: &str, details: serde_json::Value) {
+    let Some(webhook) = WEBHOOK.get() else {
+        return;
+    };
+
+    let payload = json!({
+        "text": format!("[{}] {}", webhook.campaign, message),
+        "event": event,
+        "campaign": webhook.campaign,
+        "time": SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        "details": details,
+    })
+    .to_string();
+    let url = webhook.url.clone();
+    let event = event.to_owned();
+    std::thread::spawn(move || {
+        if let Err(err) = post(&url, &payload) {
+            println!("Failed to send the {} notification: {}", event, err);
+        }
+    });
+}
+
+/// Notifies a new solution, if it is the first one of the campaign or the
+/// first one in its crash bucket.
+pub fn notify_crash(bucket: &str, new_bucket: bool, component: &str, signature: Option<&str>) {
+    let details = json!({
+        "bucket": bucket,
+        "component": component,
+        "signature": signature,
+    });
+    if !CRASH_NOTIFIED.swap(true, Ordering::Relaxed) {
+        notify(
+            "first_crash",
+            &format!("First crash found in {} ({})", component, bucket),
+            details,
+        );
+    } else if new_bucket {
+        notify(
+            "new_crash_bucket",
+            &format!("New crash bucket {} in {}", bucket, component),
+            details,
+        );
+    }
+}
+
+/// Notifies when the coverage stopped growing for the plateau duration, once
+/// per plateau.
+pub fn check_plateau(secs_since_new_coverage: u64) {
+    let Some(webhook) = WEBHOOK.get() else {
+        return;
+    };
+    if secs_since_new_coverage < webhook.plateau.as_secs() {
+        PLATEAU_NOTIFIED.store(false, Ordering::Relaxed);
+        return;
+    }
+    if !PLATEAU_NOTIFIED.swap(true, Ordering::Relaxed) {
+        notify(
+            "coverage_plateau",
+            &format!(
+                "Coverage did not grow for {} minutes",
+                secs_since_new_coverage / 60
+            ),
+            json!({ "secs_since_new_coverage": secs_since_new_coverage }),
+        );
+    }
+}
+
+/// Notifies a failure of the device the fuzzer recovers from with the given
+/// action, with an optional detail of the failure.
+pub fn notify_device_failure(action: &str, detail: &str) {
+    let message = match detail {
+        "" => format!("Device failure, {}", action),
+        detail => format!("Device failure, {}: {}", action, detail),
+    };
+    notify(
+        "device_failure",
+        &message,
+        json!({ "action": action, "detail": detail }),
+    );
+}
+
+/// POSTs the JSON payload to the URL.
+fn post(url: &str, payload: &str) -> Result<(), std::io::Error> {
+    let mut child = Command::new("curl")
+        .args(["-sS", "-f", "-m", "30", "-X", "POST"])
+        .args(["-H", "Content-Type: application/json"])
+        .args(["--data-binary", "@-", url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(payload.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("curl exited with {}", status),
+        ));
+    }
+    Ok(())
+}
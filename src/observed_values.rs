@@ -0,0 +1,150 @@
+//! Dictionary of the string values observed in the corpus.
+//!
+//! Intent handlers often expect the same identifier in several places, e.g.
+//! an account id in two extras or a package name both as extra and as data.
+//! The [ObservedValueFeedback] collects the String extras, String array
+//! elements, component names and raw data URIs of every input added to the
+//! corpus into the [ObservedValues] of the state, which the
+//! `IntentObservedValueMutator` copies into other extras, keys and the data.
+
+use std::collections::HashSet;
+
+use libafl::{
+    impl_serdeany,
+    prelude::{
+        EventFirer, ExitKind, Feedback, HasBytesVec, HasMetadata, Named, ObserversTuple, Testcase,
+        UsesInput,
+    },
+    state::HasClientPerfMonitor,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::intent_input::{ExtraType, IntentInput, URIScheme};
+
+/// The maximum number of values kept, further values are not recorded.
+const MAX_OBSERVED_VALUES: usize = 4096;
+
+/// Longer values are payloads rather than identifiers and not recorded.
+const MAX_OBSERVED_VALUE_LEN: usize = 256;
+
+/// The distinct string values of the corpus, in the order they were found.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ObservedValues {
+    pub values: Vec<String>,
+    #[serde(skip)]
+    known: HashSet<String>,
+}
+
+impl_serdeany!(ObservedValues);
+
+impl ObservedValues {
+    /// Records the value unless it is empty, too long or already known.
+    fn insert(&mut self, value: &[u8]) {
+        if self.known.len() < self.values.len() {
+            // Restore the set after deserialization.
+            self.known = self.values.iter().cloned().collect();
+        }
+        let Ok(value) = std::str::from_utf8(value) else {
+            return;
+        };
+        if value.is_empty()
+            || value.len() > MAX_OBSERVED_VALUE_LEN
+            || self.values.len() >= MAX_OBSERVED_VALUES
+            || !self.known.insert(value.to_owned())
+        {
+            return;
+        }
+        self.values.push(value.to_owned());
+    }
+}
+
+/// Offers the value to the `IntentObservedValueMutator` as if it had been
+/// observed in the corpus.
+pub fn offer_value<S>(state: &mut S, value: &str)
+where
+    S: HasMetadata,
+{
+    if !state.has_metadata::<ObservedValues>() {
+        state.add_metadata(ObservedValues::default());
+    }
+    if let Ok(observed) = state.metadata_mut::<ObservedValues>() {
+        observed.insert(value.as_bytes());
+    }
+}
+
+/// Feedback recording the string values of new corpus entries. It never
+/// reports any inputs itself.
+#[derive(Debug, Default)]
+pub struct ObservedValueFeedback;
+
+impl ObservedValueFeedback {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Named for ObservedValueFeedback {
+    fn name(&self) -> &str {
+        "ObservedValueFeedback"
+    }
+}
+
+impl<S> Feedback<S> for ObservedValueFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor + HasMetadata,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        Ok(false)
+    }
+
+    fn append_metadata<OT>(
+        &mut self,
+        state: &mut S,
+        _observers: &OT,
+        testcase: &mut Testcase<IntentInput>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        let Some(input) = testcase.input() else {
+            return Ok(());
+        };
+
+        if !state.has_metadata::<ObservedValues>() {
+            state.add_metadata(ObservedValues::default());
+        }
+        let observed = state.metadata_mut::<ObservedValues>()?;
+        if let Some(data) = input
+            .data
+            .as_ref()
+            .filter(|data| data.scheme == URIScheme::Other)
+        {
+            observed.insert(data.content.bytes());
+        }
+        for extra in &input.extras {
+            match &extra.value {
+                ExtraType::String(value) | ExtraType::ComponentName(value) => {
+                    observed.insert(value.buffer.bytes())
+                }
+                ExtraType::StringArray(array) | ExtraType::StringArrayList(array) => {
+                    for element in &array.elements {
+                        observed.insert(element.bytes());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
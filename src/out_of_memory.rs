@@ -0,0 +1,131 @@
+//! Detection of inputs driving the app out of memory.
+//!
+//! Intents amplifying the memory use of the app until it throws an
+//! `OutOfMemoryError` are a finding of their own, whether the app catches the
+//! error or dies from it. The [OutOfMemoryFeedback] checks the log of the app
+//! after every execution and, if configured, dumps the Java heap of the app
+//! for the offending input to analyze what filled it. Only the heap of a
+//! process that caught the error can be dumped: the heap of a process that
+//! died of it is gone by the time the error shows up in the log.
+
+use std::{path::PathBuf, time::SystemTime};
+
+use libafl::{
+    prelude::{EventFirer, ExitKind, Feedback, Named, ObserversTuple, Testcase, UsesInput},
+    state::HasClientPerfMonitor,
+};
+
+use crate::{
+    adb_device::{AdbDevice, OutOfMemoryError},
+    intent_input::IntentInput,
+};
+
+/// Objective feedback for inputs causing an `OutOfMemoryError` in the app.
+#[derive(Debug)]
+pub struct OutOfMemoryFeedback {
+    adb_device: AdbDevice,
+    app_name: String,
+    enabled: bool,
+    /// Where to store the heap dumps, if they should be captured.
+    heap_dumps_dir: Option<PathBuf>,
+    last_check: SystemTime,
+    found: Option<OutOfMemoryError>,
+}
+
+impl OutOfMemoryFeedback {
+    /// Creates the feedback. A disabled feedback never reads the log and
+    /// never reports any inputs.
+    pub fn new(
+        adb_device: AdbDevice,
+        app_name: String,
+        enabled: bool,
+        heap_dumps_dir: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            adb_device,
+            app_name,
+            enabled,
+            heap_dumps_dir,
+            last_check: SystemTime::now(),
+            found: None,
+        }
+    }
+}
+
+impl Named for OutOfMemoryFeedback {
+    fn name(&self) -> &str {
+        "OutOfMemoryFeedback"
+    }
+}
+
+impl<S> Feedback<S> for OutOfMemoryFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        if !self.enabled {
+            return Ok(false);
+        }
+
+        // Only check the log since the last execution, so an error is
+        // attributed to a single input.
+        let now = SystemTime::now();
+        self.found = self
+            .adb_device
+            .out_of_memory_error(&self.app_name, self.last_check);
+        self.last_check = now;
+
+        if self.found.is_some() {
+            println!("App {} ran out of memory", self.app_name);
+        }
+        Ok(self.found.is_some())
+    }
+
+    fn append_metadata<OT>(
+        &mut self,
+        _state: &mut S,
+        _observers: &OT,
+        testcase: &mut Testcase<IntentInput>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        let (Some(found), Some(dir), Some(input)) =
+            (self.found.take(), &self.heap_dumps_dir, testcase.input())
+        else {
+            return Ok(());
+        };
+        let OutOfMemoryError::Caught(pid) = found else {
+            println!("App {} died of the OutOfMemoryError, its heap can't be dumped", self.app_name);
+            return Ok(());
+        };
+
+        std::fs::create_dir_all(dir)?;
+        let dump_file = dir.join(format!("{}.hprof", input.hash()));
+        if let Err(err) = self.adb_device.dump_heap(&self.app_name, &pid, &dump_file) {
+            println!("Failed to capture heap dump: {}", err);
+        }
+
+        Ok(())
+    }
+
+    fn discard_metadata(
+        &mut self,
+        _state: &mut S,
+        _input: &IntentInput,
+    ) -> Result<(), libafl::Error> {
+        self.found = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+//! Fuzzing of the PendingIntents of the app's notifications.
+//!
+//! Notifications hand PendingIntents to the system, which any app listening
+//! to notifications can send. A mutable PendingIntent merges the fill-in
+//! intent of the sender into its base intent, so extras and data the app
+//! never expected reach the component it points to (PendingIntent
+//! hijacking). [notification_seeds] turns every content intent and action of
+//! the currently posted notifications into a seed, which the notification
+//! listener of the content provider app sends with the input as fill-in
+//! intent.
+//!
+//! Stopping the app cancels its notifications, and the keys of the ones it
+//! does not post again go stale. The executor therefore re-lists the posted
+//! notifications (see [posted_notification_keys]) after every app restart
+//! and skips the inputs of notifications that are gone.
+
+use std::collections::HashSet;
+
+use crate::{
+    adb_device::AdbDevice,
+    intent_input::{IntentInput, MimeType, PendingIntentTarget, ReceiverType, URIGrant},
+    util::decode_uri_component,
+};
+
+/// A PendingIntent of a posted notification, as listed by the notification
+/// listener.
+struct ListedPendingIntent {
+    receiver_type: ReceiverType,
+    /// The index of the notification action, the content intent if none.
+    action: Option<usize>,
+    notification_key: String,
+}
+
+/// Returns the PendingIntents of the notifications the app currently shows.
+fn list_pending_intents(
+    adb_device: &AdbDevice,
+    package: &str,
+) -> Result<Vec<ListedPendingIntent>, libafl::Error> {
+    let listing = adb_device.notification_pending_intents(package)?;
+
+    let mut pending_intents = Vec::new();
+    // e.g. "activity,-1,0%7Ccom.example%7C1%7Cnull%7C10123"
+    for entry in listing.split(';').filter(|entry| !entry.is_empty()) {
+        let mut fields = entry.splitn(3, ',');
+        let (Some(kind), Some(action), Some(key)) = (fields.next(), fields.next(), fields.next())
+        else {
+            println!("Skipping malformed PendingIntent entry {}", entry);
+            continue;
+        };
+        pending_intents.push(ListedPendingIntent {
+            receiver_type: match kind {
+                "broadcast" => ReceiverType::BroadcastReceiver,
+                "service" => ReceiverType::Service,
+                _ => ReceiverType::Activity,
+            },
+            action: action.parse::<usize>().ok(),
+            notification_key: decode_uri_component(key),
+        });
+    }
+
+    Ok(pending_intents)
+}
+
+/// Returns the keys of the notifications the app currently shows.
+pub fn posted_notification_keys(
+    adb_device: &AdbDevice,
+    package: &str,
+) -> Result<HashSet<String>, libafl::Error> {
+    Ok(list_pending_intents(adb_device, package)?
+        .into_iter()
+        .map(|pending_intent| pending_intent.notification_key)
+        .collect())
+}
+
+/// Returns a seed for every PendingIntent of the notifications the app
+/// currently shows.
+pub fn notification_seeds(
+    adb_device: &AdbDevice,
+    package: &str,
+) -> Result<Vec<IntentInput>, libafl::Error> {
+    let mut seeds = Vec::new();
+    for ListedPendingIntent {
+        receiver_type,
+        action,
+        notification_key,
+    } in list_pending_intents(adb_device, package)?
+    {
+        seeds.push(IntentInput {
+            receiver_type,
+            component_package: package.to_owned(),
+            // The PendingIntent fixes the component, which the notification
+            // listener cannot see.
+            component_class: String::new(),
+            action: String::new(),
+            category: String::new(),
+            data: None,
+            mime_type: MimeType::TextPlain,
+            flags: 0,
+            extras: Vec::new(),
+            uri_grant: URIGrant::default(),
+            pending_intent: Some(PendingIntentTarget {
+                notification_key,
+                action,
+            }),
+            permission: None,
+        });
+    }
+
+    Ok(seeds)
+}
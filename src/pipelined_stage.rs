@@ -0,0 +1,135 @@
+//! Mutational stage telling the executor the next input ahead of time.
+//!
+//! Delivering an intent takes seconds, most of which the fuzzer spends
+//! waiting on the device. With the executions spread over the app instances
+//! of several Android users, the executor can prepare the next input for the
+//! next instance while waiting on the current one. The
+//! [PipelinedMutationalStage] mutates every input one execution ahead and
+//! hands it to the executor through [PreparesNextInput].
+
+use std::marker::PhantomData;
+
+use libafl::{
+    bolts::rands::Rand,
+    prelude::{Corpus, CorpusId, Evaluator, MutationResult, Mutator, Stage, UsesInput, UsesState},
+    stages::mutational::DEFAULT_MUTATIONAL_MAX_ITERATIONS,
+    state::{HasCorpus, HasRand},
+};
+
+use crate::intent_input::IntentInput;
+
+/// An app instance in another Android user the executions are spread over.
+#[derive(Clone, Debug)]
+pub struct AppInstance {
+    /// The Android user of the instance, e.g. the one of a work profile.
+    pub user: u32,
+    /// The address of the coverage agent socket of the instance.
+    pub coverage_socket_address: String,
+}
+
+impl AppInstance {
+    /// Parses `<user>=<coverage socket address>`.
+    pub fn parse(spec: &str) -> Result<Self, libafl::Error> {
+        let invalid = || {
+            libafl::Error::illegal_argument(format!(
+                "Invalid app instance {:?}, expected <user>=<coverage socket address>",
+                spec
+            ))
+        };
+        let (user, address) = spec.split_once('=').ok_or_else(invalid)?;
+        Ok(Self {
+            user: user.parse().map_err(|_| invalid())?,
+            coverage_socket_address: address.to_owned(),
+        })
+    }
+}
+
+/// An executor that prepares the execution of the next input while running
+/// the current one.
+pub trait PreparesNextInput {
+    /// Sets the input executed after the one about to run, None if it is
+    /// not known yet.
+    fn prepare_next(&mut self, input: Option<&IntentInput>);
+}
+
+/// Stage mutating the corpus entry like the
+/// [libafl::prelude::StdMutationalStage], but one mutant ahead of the
+/// executions.
+#[derive(Debug)]
+pub struct PipelinedMutationalStage<E, EM, M, Z> {
+    mutator: M,
+    phantom: PhantomData<(E, EM, Z)>,
+}
+
+impl<E, EM, M, Z> PipelinedMutationalStage<E, EM, M, Z> {
+    pub fn new(mutator: M) -> Self {
+        Self {
+            mutator,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<E, EM, M, Z> PipelinedMutationalStage<E, EM, M, Z>
+where
+    Z: UsesState,
+    M: Mutator<IntentInput, Z::State>,
+{
+    /// The first mutant of the input from iteration `i` on that was not
+    /// skipped by the mutator, with its iteration.
+    fn next_mutant(
+        &mut self,
+        state: &mut Z::State,
+        input: &IntentInput,
+        mut i: u64,
+        iterations: u64,
+    ) -> Result<Option<(u64, IntentInput)>, libafl::Error> {
+        while i < iterations {
+            let mut mutant = input.clone();
+            if self.mutator.mutate(state, &mut mutant, i as i32)? == MutationResult::Mutated {
+                return Ok(Some((i, mutant)));
+            }
+            i += 1;
+        }
+        Ok(None)
+    }
+}
+
+impl<E, EM, M, Z> UsesState for PipelinedMutationalStage<E, EM, M, Z>
+where
+    Z: UsesState,
+{
+    type State = Z::State;
+}
+
+impl<E, EM, M, Z> Stage<E, EM, Z> for PipelinedMutationalStage<E, EM, M, Z>
+where
+    E: UsesState<State = Z::State> + PreparesNextInput,
+    EM: UsesState<State = Z::State>,
+    M: Mutator<IntentInput, Z::State>,
+    Z: Evaluator<E, EM>,
+    Z::State: HasCorpus + HasRand + UsesInput<Input = IntentInput>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Z::State,
+        manager: &mut EM,
+        corpus_idx: CorpusId,
+    ) -> Result<(), libafl::Error> {
+        let iterations = 1 + state.rand_mut().below(DEFAULT_MUTATIONAL_MAX_ITERATIONS);
+        let input = state.corpus().cloned_input_for_id(corpus_idx)?;
+
+        let mut next = self.next_mutant(state, &input, 0, iterations)?;
+        while let Some((i, mutant)) = next {
+            next = self.next_mutant(state, &input, i + 1, iterations)?;
+            executor.prepare_next(next.as_ref().map(|(_, mutant)| mutant));
+
+            let (_, new_corpus_idx) = fuzzer.evaluate_input(state, executor, manager, mutant)?;
+            self.mutator.post_exec(state, i as i32, new_corpus_idx)?;
+        }
+        executor.prepare_next(None);
+        Ok(())
+    }
+}
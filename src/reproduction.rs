@@ -0,0 +1,78 @@
+//! Reproducibility verification of new solutions.
+//!
+//! An unstable device makes executions fail for reasons unrelated to the
+//! input, which shows up as crashes and timeouts that never happen again.
+//! [verify_solutions] re-executes every new solution a number of times right
+//! after it was found and records how often its execution ended the same way
+//! in the `metadata.json` of the solution, so flaky findings can be told
+//! apart from real ones.
+
+use libafl::{
+    prelude::{Corpus, CorpusId, ExecutesInput, ExitKind, HasMetadata, Input, UsesState},
+    state::HasSolutions,
+};
+use serde_json::json;
+
+use crate::{
+    crash_layout::{to_pretty_json, SolutionLocation, INPUT_FILE, METADATA_FILE},
+    intent_input::IntentInput,
+};
+
+/// Re-executes the solutions added after the first `known` ones `runs` times
+/// each and records their reproduction rate. Solutions that did not end with
+/// a crash or timeout cannot be told apart from a normal execution by how
+/// they end, so they are not verified.
+pub fn verify_solutions<E, EM, S, Z>(
+    fuzzer: &mut Z,
+    executor: &mut E,
+    state: &mut S,
+    manager: &mut EM,
+    known: usize,
+    runs: usize,
+) -> Result<(), libafl::Error>
+where
+    E: UsesState<State = S>,
+    EM: UsesState<State = S>,
+    S: HasSolutions<Input = IntentInput>,
+    Z: ExecutesInput<E, EM, State = S>,
+{
+    let new_ids: Vec<CorpusId> = state.solutions().ids().skip(known).collect();
+    for id in new_ids {
+        // The solutions corpus only keeps where the solution was stored.
+        let location = {
+            let testcase = state.solutions().get(id)?.borrow();
+            let Ok(location) = testcase.metadata::<SolutionLocation>() else {
+                continue;
+            };
+            location.clone()
+        };
+        let input = IntentInput::from_file(location.dir.join(INPUT_FILE))?;
+        if location.exit_kind == ExitKind::Ok {
+            continue;
+        }
+
+        let mut reproduced = 0;
+        for _ in 0..runs {
+            if fuzzer.execute_input(state, executor, manager, &input)? == location.exit_kind {
+                reproduced += 1;
+            }
+        }
+        let rate = reproduced as f64 / runs as f64;
+        println!(
+            "Solution {:?} reproduced {}/{} times",
+            location.dir, reproduced, runs
+        );
+
+        let metadata_file = location.dir.join(METADATA_FILE);
+        let mut metadata: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&metadata_file)?)
+                .map_err(|err| libafl::Error::serialize(err.to_string()))?;
+        metadata["reproduction"] = json!({
+            "runs": runs,
+            "reproduced": reproduced,
+            "rate": rate,
+        });
+        std::fs::write(&metadata_file, to_pretty_json(&metadata)?)?;
+    }
+    Ok(())
+}
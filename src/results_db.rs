@@ -0,0 +1,233 @@
+//! SQLite database collecting the results of a campaign.
+//!
+//! Corpus additions, crashes, coverage milestones and device events are
+//! recorded together with a campaign identifier, so the results of many
+//! components and devices can be queried and aggregated after the campaign.
+//!
+//! The database is process-wide and optional: all `record_*` functions do
+//! nothing unless [init] was called.
+
+use std::{
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use libafl::prelude::{
+    EventFirer, ExitKind, Feedback, HasMetadata, Named, ObserversTuple, Testcase, UsesInput,
+};
+use libafl::state::HasClientPerfMonitor;
+use rusqlite::{params, Connection};
+
+use crate::{intent_input::IntentInput, native_crash::CrashSignature, triage::Severity};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS corpus_entries (
+    campaign TEXT NOT NULL,
+    time REAL NOT NULL,
+    component TEXT NOT NULL,
+    input_hash TEXT NOT NULL,
+    input_json TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS crashes (
+    campaign TEXT NOT NULL,
+    time REAL NOT NULL,
+    component TEXT NOT NULL,
+    input_hash TEXT NOT NULL,
+    severity TEXT,
+    signature TEXT,
+    input_json TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS coverage (
+    campaign TEXT NOT NULL,
+    time REAL NOT NULL,
+    elapsed_secs INTEGER NOT NULL,
+    edges INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS device_events (
+    campaign TEXT NOT NULL,
+    time REAL NOT NULL,
+    kind TEXT NOT NULL,
+    detail TEXT NOT NULL
+);
+";
+
+struct ResultsDb {
+    connection: Connection,
+    campaign: String,
+}
+
+static RESULTS_DB: OnceLock<Mutex<ResultsDb>> = OnceLock::new();
+
+/// Opens (or creates) the results database at the given path. All records of
+/// this process are tagged with the given campaign identifier.
+pub fn init(path: &Path, campaign: &str) -> Result<(), libafl::Error> {
+    let connection = Connection::open(path)
+        .map_err(|err| libafl::Error::unknown(format!("Failed to open results db: {}", err)))?;
+    connection
+        .execute_batch(SCHEMA)
+        .map_err(|err| libafl::Error::unknown(format!("Failed to create results db: {}", err)))?;
+
+    let db = ResultsDb {
+        connection,
+        campaign: campaign.to_owned(),
+    };
+    if RESULTS_DB.set(Mutex::new(db)).is_err() {
+        return Err(libafl::Error::illegal_state(
+            "Results db already initialized",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Runs the given insert on the database, if it is enabled.
+fn record<F>(insert: F)
+where
+    F: FnOnce(&Connection, &str, f64) -> rusqlite::Result<usize>,
+{
+    let Some(db) = RESULTS_DB.get() else {
+        return;
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64();
+
+    let db = db.lock().unwrap();
+    if let Err(err) = insert(&db.connection, &db.campaign, now) {
+        println!("Failed to write to results db: {}", err);
+    }
+}
+
+/// Records an input that was added to the corpus.
+pub fn record_corpus_entry(input: &IntentInput) {
+    record(|connection, campaign, now| {
+        connection.execute(
+            "INSERT INTO corpus_entries VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                campaign,
+                now,
+                input.component(),
+                input.hash(),
+                serde_json::to_string(input).unwrap()
+            ],
+        )
+    });
+}
+
+/// Records an input that was added to the solutions.
+pub fn record_crash(input: &IntentInput, severity: Option<Severity>, signature: Option<&str>) {
+    record(|connection, campaign, now| {
+        connection.execute(
+            "INSERT INTO crashes VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                campaign,
+                now,
+                input.component(),
+                input.hash(),
+                severity.map(|s| s.to_string()),
+                signature,
+                serde_json::to_string(input).unwrap()
+            ],
+        )
+    });
+}
+
+/// Records a new overall coverage milestone.
+pub fn record_coverage(elapsed_secs: u64, edges: usize) {
+    record(|connection, campaign, now| {
+        connection.execute(
+            "INSERT INTO coverage VALUES (?1, ?2, ?3, ?4)",
+            params![campaign, now, elapsed_secs, edges],
+        )
+    });
+}
+
+/// Records an event on the device, e.g. an app or device restart.
+pub fn record_device_event(kind: &str, detail: &str) {
+    record(|connection, campaign, now| {
+        connection.execute(
+            "INSERT INTO device_events VALUES (?1, ?2, ?3, ?4)",
+            params![campaign, now, kind, detail],
+        )
+    });
+}
+
+/// What a [ResultsDbFeedback] records.
+#[derive(Debug, Clone, Copy)]
+pub enum RecordKind {
+    CorpusEntry,
+    Crash,
+}
+
+/// Feedback that never marks an input as interesting, but records every
+/// testcase added to the corpus (or solutions) in the results database.
+#[derive(Debug)]
+pub struct ResultsDbFeedback {
+    kind: RecordKind,
+}
+
+impl ResultsDbFeedback {
+    pub fn new(kind: RecordKind) -> Self {
+        Self { kind }
+    }
+}
+
+impl Named for ResultsDbFeedback {
+    fn name(&self) -> &str {
+        match self.kind {
+            RecordKind::CorpusEntry => "ResultsDbCorpusFeedback",
+            RecordKind::Crash => "ResultsDbCrashFeedback",
+        }
+    }
+}
+
+impl<S> Feedback<S> for ResultsDbFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        Ok(false)
+    }
+
+    fn append_metadata<OT>(
+        &mut self,
+        _state: &mut S,
+        _observers: &OT,
+        testcase: &mut Testcase<IntentInput>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        let Some(input) = testcase.input() else {
+            return Ok(());
+        };
+
+        match self.kind {
+            RecordKind::CorpusEntry => record_corpus_entry(input),
+            RecordKind::Crash => record_crash(
+                input,
+                testcase.metadata::<Severity>().ok().copied(),
+                testcase
+                    .metadata::<CrashSignature>()
+                    .ok()
+                    .map(|s| s.0.as_str()),
+            ),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,234 @@
+//! The configuration a campaign ran with, reported to the monitor as user
+//! stats.
+//!
+//! The stats file only holds the numbers of a campaign. To attribute its
+//! results to an exact configuration, [report_run_config] adds the RNG seed,
+//! the fuzzer version, the hashes of the intent templates, the build
+//! fingerprint of the device and the versionCode of the target, which the
+//! monitor writes along with every other user stat.
+//!
+//! Artifacts outlive the stats file they belong to, so the [CampaignInfo]
+//! identifying the campaign is also stamped into the metadata of every corpus
+//! entry and solution (by the [CampaignInfoFeedback]), the metadata and
+//! reports of the solutions, and a sidecar of the overall coverage file.
+
+use std::{
+    hash::Hasher,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use fasthash::{farm::Hasher64, FastHasher};
+use libafl::{
+    impl_serdeany,
+    prelude::{
+        Event, EventFirer, ExitKind, Feedback, HasMetadata, Named, ObserversTuple, Testcase,
+        UserStats, UsesInput,
+    },
+    state::HasClientPerfMonitor,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    adb_device::AdbDevice, crash_layout::to_pretty_json, intent_input::IntentInput,
+    socket_coverage_observer::unix_time,
+};
+
+/// What identifies the campaign an artifact was produced by.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CampaignInfo {
+    /// `<package>@<device>-<start time>`
+    pub id: String,
+    /// Seconds since the UNIX epoch.
+    pub started_at: u64,
+    pub package: String,
+    pub version_code: String,
+    /// Hash of the command line options and the intent templates.
+    pub config_hash: String,
+}
+
+impl_serdeany!(CampaignInfo);
+
+static CAMPAIGN: OnceLock<CampaignInfo> = OnceLock::new();
+
+impl CampaignInfo {
+    /// Identifies the campaign starting now. `options` describes the command
+    /// line options of the campaign.
+    pub fn new(
+        adb_device: &AdbDevice,
+        app_name: &str,
+        device: &str,
+        options: &str,
+        intent_config: &Path,
+    ) -> Result<Self, libafl::Error> {
+        let started_at = unix_time();
+        let mut hasher = Hasher64::new();
+        hasher.write(options.as_bytes());
+        hasher.write(template_hashes(intent_config)?.as_bytes());
+        Ok(Self {
+            id: format!("{}@{}-{}", app_name, device, started_at),
+            started_at,
+            package: app_name.to_owned(),
+            version_code: adb_device
+                .package_version(app_name)
+                .map_or("unknown".to_owned(), |version| version.version_code),
+            config_hash: format!("{:016x}", hasher.finish()),
+        })
+    }
+}
+
+/// Sets the campaign of this process.
+pub fn init_campaign(info: CampaignInfo) -> Result<(), libafl::Error> {
+    CAMPAIGN
+        .set(info)
+        .map_err(|_| libafl::Error::illegal_state("Campaign already initialized"))
+}
+
+/// The campaign of this process, once initialized.
+pub fn campaign() -> Option<&'static CampaignInfo> {
+    CAMPAIGN.get()
+}
+
+/// The sidecar of the overall coverage file describing its campaign, e.g.
+/// `edgecount.campaign.json` for `edgecount.csv`.
+pub fn campaign_file(overall_coverage_file: &Path) -> PathBuf {
+    overall_coverage_file.with_extension("campaign.json")
+}
+
+/// Writes the campaign of this process next to the overall coverage file.
+pub fn write_campaign_file(overall_coverage_file: &Path) -> Result<(), libafl::Error> {
+    if let Some(info) = campaign() {
+        std::fs::write(campaign_file(overall_coverage_file), to_pretty_json(info)?)?;
+    }
+    Ok(())
+}
+
+/// Reports the configuration of the campaign to the monitor.
+pub fn report_run_config<EM, S>(
+    manager: &mut EM,
+    state: &mut S,
+    adb_device: &AdbDevice,
+    app_name: &str,
+    seed: u64,
+    intent_config: &Path,
+) -> Result<(), libafl::Error>
+where
+    EM: EventFirer<State = S>,
+    S: UsesInput<Input = IntentInput>,
+{
+    let unknown = |err: libafl::Error| {
+        println!("Failed to read the run configuration: {}", err);
+        "unknown".to_owned()
+    };
+
+    let config = [
+        (
+            "campaign_id",
+            campaign().map_or("unknown".to_owned(), |info| info.id.clone()),
+        ),
+        ("rng_seed", seed.to_string()),
+        ("fuzzer_version", env!("CARGO_PKG_VERSION").to_owned()),
+        ("template_hashes", template_hashes(intent_config)?),
+        (
+            "device_fingerprint",
+            adb_device.build_fingerprint().unwrap_or_else(unknown),
+        ),
+        (
+            "target_version_code",
+            adb_device
+                .package_version(app_name)
+                .map(|version| version.version_code)
+                .unwrap_or_else(unknown),
+        ),
+    ];
+    for (name, value) in config {
+        println!("{}: {}", name, value);
+        manager.fire(
+            state,
+            Event::UpdateUserStats {
+                name: name.to_owned(),
+                value: UserStats::String(value),
+                phantom: PhantomData,
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Feedback adding the [CampaignInfo] to every new corpus entry or solution.
+/// It never reports any inputs itself.
+#[derive(Debug, Default)]
+pub struct CampaignInfoFeedback;
+
+impl CampaignInfoFeedback {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Named for CampaignInfoFeedback {
+    fn name(&self) -> &str {
+        "CampaignInfoFeedback"
+    }
+}
+
+impl<S> Feedback<S> for CampaignInfoFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        Ok(false)
+    }
+
+    fn append_metadata<OT>(
+        &mut self,
+        _state: &mut S,
+        _observers: &OT,
+        testcase: &mut Testcase<IntentInput>,
+    ) -> Result<(), libafl::Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        if let Some(info) = campaign() {
+            testcase.add_metadata(info.clone());
+        }
+        Ok(())
+    }
+}
+
+/// The hashes of the intent template file, or of all files in the template
+/// directory, as `<file>:<hash>` separated by spaces.
+fn template_hashes(intent_config: &Path) -> Result<String, libafl::Error> {
+    let mut files = if intent_config.is_dir() {
+        std::fs::read_dir(intent_config)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect()
+    } else {
+        vec![intent_config.to_path_buf()]
+    };
+    files.sort();
+
+    let mut hashes = Vec::new();
+    for file in files {
+        let mut hasher = Hasher64::new();
+        hasher.write(&std::fs::read(&file)?);
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        hashes.push(format!("{}:{:016x}", name, hasher.finish()));
+    }
+    Ok(hashes.join(" "))
+}
@@ -0,0 +1,169 @@
+//! Export of the findings as a pack of verification scripts.
+//!
+//! Security reviewers want to confirm a crash on a clean device without
+//! setting up the fuzzer. For every solution in the crashes directory, a
+//! standalone shell script is written that stages the URI payloads of the
+//! input, sends the intent with plain adb and checks the crash buffer for a
+//! Java or native crash of the app. A `manifest.json` lists all scripts, and
+//! `links.html` links to the `intent:` URI of every activity input, to
+//! reproduce it by following the link in a browser on the device.
+
+use std::{fmt::Write, path::Path};
+
+use libafl::prelude::Input;
+use serde::Serialize;
+
+use crate::{
+    adb_device, crash_layout,
+    intent_input::{IntentInput, ReceiverType},
+};
+
+/// Seconds the scripts wait for the app to crash after sending the intent.
+const CRASH_WAIT_SECS: u32 = 5;
+
+/// An entry of the script pack manifest.
+#[derive(Serialize, Debug)]
+struct ManifestEntry {
+    hash: String,
+    script: String,
+    component: String,
+    receiver_type: String,
+    /// Whether the content provider app of the fuzzer has to be installed
+    /// to serve the content URIs of the input.
+    needs_content_provider: bool,
+    /// The `intent:` URI of the input, for activities only.
+    intent_link: Option<String>,
+}
+
+/// Writes a verification script for every solution in the crashes directory
+/// to the output directory. Returns the number of scripts written.
+pub fn export_scripts(crashes_dir: &Path, output_dir: &Path) -> Result<usize, libafl::Error> {
+    std::fs::create_dir_all(output_dir)?;
+
+    let solutions = crash_layout::solution_inputs(crashes_dir);
+
+    let mut manifest = Vec::new();
+    for solution in solutions {
+        let input = match IntentInput::from_file(&solution) {
+            Ok(input) => input,
+            Err(err) => {
+                println!("Skipping {:?}: {}", solution, err);
+                continue;
+            }
+        };
+
+        let hash = input.hash();
+        let script = format!("{}.sh", hash);
+        let (_, contents) = input.uri_payloads();
+        write_script(&output_dir.join(&script), &input)?;
+
+        manifest.push(ManifestEntry {
+            hash,
+            script,
+            component: input.component(),
+            receiver_type: format!("{:?}", input.receiver_type),
+            needs_content_provider: !contents.is_empty(),
+            intent_link: (input.receiver_type == ReceiverType::Activity)
+                .then(|| input.browsable_intent_uri()),
+        });
+    }
+
+    let manifest_json = serde_json::to_string_pretty(&manifest)
+        .map_err(|err| libafl::Error::serialize(err.to_string()))?;
+    std::fs::write(output_dir.join("manifest.json"), manifest_json)?;
+    write_links(&output_dir.join("links.html"), &manifest)?;
+
+    Ok(manifest.len())
+}
+
+/// Writes the verification script of a single input.
+fn write_script(path: &Path, input: &IntentInput) -> Result<(), libafl::Error> {
+    let mut script = String::new();
+    writeln!(script, "#!/bin/sh").unwrap();
+    writeln!(script, "# Verifies the crash of {}.", input.component()).unwrap();
+    writeln!(script, "# Exits with 0 if the app crashed, 1 otherwise.").unwrap();
+    writeln!(script, "ADB=\"${{ADB:-adb}}\"\n").unwrap();
+
+    // The commands are passed through quoted here-documents, so the host
+    // shell leaves them alone.
+    let (files, contents) = input.uri_payloads();
+    if let Some(staging) = adb_device::staging_command(&files, &contents, None) {
+        if !contents.is_empty() {
+            writeln!(
+                script,
+                "# Serving the content URIs needs the content provider app of MALintent."
+            )
+            .unwrap();
+        }
+        writeln!(
+            script,
+            "\"$ADB\" shell \"$(cat <<'EOF'\n{}\nEOF\n)\"\n",
+            staging
+        )
+        .unwrap();
+    }
+
+    writeln!(script, "\"$ADB\" logcat -c -b crash").unwrap();
+    writeln!(
+        script,
+        "\"$ADB\" shell \"$(cat <<'EOF'\n{}\nEOF\n)\"",
+        input.shell_command()
+    )
+    .unwrap();
+    writeln!(script, "sleep {}\n", CRASH_WAIT_SECS).unwrap();
+
+    writeln!(
+        script,
+        "if \"$ADB\" logcat -d -b crash | grep -qE 'Process: {0},|>>> {0} <<<'; then",
+        input.component_package
+    )
+    .unwrap();
+    writeln!(script, "    echo \"CONFIRMED: {}\"", input.hash()).unwrap();
+    writeln!(script, "    exit 0\nfi").unwrap();
+    writeln!(script, "echo \"NOT REPRODUCED: {}\"", input.hash()).unwrap();
+    writeln!(script, "exit 1").unwrap();
+
+    std::fs::write(path, script)?;
+    set_executable(path)?;
+    Ok(())
+}
+
+/// Writes a page linking to the `intent:` URIs of the entries.
+fn write_links(path: &Path, manifest: &[ManifestEntry]) -> Result<(), libafl::Error> {
+    let mut page = String::new();
+    writeln!(page, "<!DOCTYPE html>\n<html><body><ul>").unwrap();
+    for entry in manifest {
+        if let Some(link) = &entry.intent_link {
+            writeln!(
+                page,
+                "<li><a href=\"{}\">{}</a> {}</li>",
+                escape_html(link),
+                entry.hash,
+                escape_html(&entry.component)
+            )
+            .unwrap();
+        }
+    }
+    writeln!(page, "</ul></body></html>").unwrap();
+
+    std::fs::write(path, page)?;
+    Ok(())
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,118 @@
+//! Coverage-weighted allocation of initial seed variants.
+//!
+//! The generator creates one intent per action and category of a template,
+//! but some of these reach much more of the app than others. After the
+//! initial corpus is generated, [allocate_seed_variants] executes every base
+//! intent once, counts the map entries only it covers and spends a budget of
+//! extra initial inputs on variants with prepopulated extras, proportionally
+//! to these counts. Seeds reaching code nothing else reaches get the most
+//! variants, seeds covering nothing of their own get none.
+
+use std::collections::HashMap;
+
+use libafl::{
+    prelude::{Evaluator, ExecutesInput, HasObservers, MapObserver, MatchName, Mutator, UsesState},
+    state::{HasCorpus, HasMaxSize, HasNamedMetadata, HasRand},
+};
+
+use crate::{intent_input::IntentInput, intent_mutator::IntentRandomAddExtraMutator};
+
+/// The number of extras added to every variant.
+const VARIANT_EXTRAS: usize = 3;
+
+/// Executes every seed once, then adds `variants` variants of the seeds with
+/// random extras to the corpus, allocated by the coverage of the named
+/// observer only the respective seed reaches.
+#[allow(clippy::too_many_arguments)]
+pub fn allocate_seed_variants<E, EM, O, Z>(
+    fuzzer: &mut Z,
+    executor: &mut E,
+    state: &mut E::State,
+    manager: &mut EM,
+    observer_name: &str,
+    seeds: &[IntentInput],
+    variants: usize,
+    max_extras: usize,
+) -> Result<(), libafl::Error>
+where
+    E: HasObservers,
+    EM: UsesState<State = E::State>,
+    O: MapObserver<Entry = u8>,
+    E::State: HasRand + HasCorpus<Input = IntentInput> + HasMaxSize + HasNamedMetadata,
+    Z: ExecutesInput<E, EM, State = E::State> + Evaluator<E, EM, State = E::State>,
+{
+    if seeds.is_empty() || variants == 0 {
+        return Ok(());
+    }
+
+    // The covered map entries of every seed and how many seeds cover them.
+    let mut covered = Vec::with_capacity(seeds.len());
+    let mut coverers: HashMap<usize, usize> = HashMap::new();
+    for seed in seeds {
+        fuzzer.execute_input(state, executor, manager, seed)?;
+        let entries: Vec<usize> = executor
+            .observers()
+            .match_name::<O>(observer_name)
+            .ok_or_else(|| libafl::Error::key_not_found("Coverage observer not found"))?
+            .to_vec()
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b != 0)
+            .map(|(i, _)| i)
+            .collect();
+        for entry in &entries {
+            *coverers.entry(*entry).or_default() += 1;
+        }
+        covered.push(entries);
+    }
+    let distinct: Vec<usize> = covered
+        .iter()
+        .map(|entries| entries.iter().filter(|entry| coverers[entry] == 1).count())
+        .collect();
+
+    let allocation = allocate(&distinct, variants);
+    let mut mutator = IntentRandomAddExtraMutator::new(max_extras.min(VARIANT_EXTRAS));
+    for ((seed, distinct), count) in seeds.iter().zip(&distinct).zip(allocation) {
+        if count == 0 {
+            continue;
+        }
+        println!(
+            "Adding {} variants of {} {} ({} map entries only it covers)",
+            count,
+            seed.component(),
+            seed.action,
+            distinct
+        );
+        for _ in 0..count {
+            let mut variant = seed.clone();
+            for _ in 0..VARIANT_EXTRAS {
+                mutator.mutate(state, &mut variant, 0)?;
+            }
+            fuzzer.add_input(state, executor, manager, variant)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Splits the variants proportionally to the weights, giving what is left
+/// after rounding down to the heaviest seeds. Without any weights, the
+/// variants are split evenly.
+fn allocate(weights: &[usize], variants: usize) -> Vec<usize> {
+    let total: usize = weights.iter().sum();
+    let weights = if total == 0 {
+        vec![1; weights.len()]
+    } else {
+        weights.to_vec()
+    };
+    let total: usize = weights.iter().sum();
+
+    let mut allocation: Vec<usize> = weights.iter().map(|w| variants * w / total).collect();
+    let mut heaviest: Vec<usize> = (0..weights.len()).filter(|i| weights[*i] > 0).collect();
+    heaviest.sort_by_key(|i| std::cmp::Reverse(weights[*i]));
+    let remaining = variants - allocation.iter().sum::<usize>();
+    for i in heaviest.into_iter().cycle().take(remaining) {
+        allocation[i] += 1;
+    }
+    allocation
+}
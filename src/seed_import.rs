@@ -0,0 +1,332 @@
+//! Importers for the intents of other tools as seeds.
+//!
+//! Prior manual analysis often already produced intents that reach the
+//! interesting parts of an app. These importers turn them into [IntentInput]s
+//! to bootstrap the corpus with:
+//!
+//! - [SeedFormat::Drozer]: drozer `run app.activity.start` and
+//!   `run app.broadcast.send` command lines, one per line, e.g. from a drozer
+//!   session log.
+//! - [SeedFormat::IntentFuzzerXml]: IntentFuzzer-style XML test definitions,
+//!   `<intent>` elements with `component`, `action`, `category`, `data`,
+//!   `type` and `receiver` attributes and `<extra key type value>` children.
+//! - [SeedFormat::IntentUri]: intent URIs in the `Intent.toUri` format, like
+//!   the `intent://...#Intent;...;end` links of web pages, anywhere in the
+//!   file (e.g. a saved HTML page). Links without a component go to the
+//!   component the framework would resolve them to, according to the actions
+//!   and data filters of the templates.
+
+use std::path::Path;
+
+use libafl::prelude::BytesInput;
+use strum::IntoEnumIterator;
+
+use crate::{
+    intent_generator::IntentGenerator,
+    intent_input::{
+        DirectInput, ExtraInput, ExtraType, IntentInput, MimeType, ReceiverType, StringArrayInput,
+        URIGrant, URIInput,
+    },
+    util::decode_uri_component,
+};
+
+/// The format of the seeds to import.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeedFormat {
+    Drozer,
+    IntentFuzzerXml,
+    IntentUri,
+}
+
+/// Reads the intents in the file as seeds, resolving the targets of intent
+/// URIs without a component against the templates of the generator.
+pub fn import_seeds(
+    path: &Path,
+    format: SeedFormat,
+    generator: &IntentGenerator,
+) -> Result<Vec<IntentInput>, libafl::Error> {
+    let text = std::fs::read_to_string(path)?;
+    match format {
+        SeedFormat::Drozer => Ok(text.lines().filter_map(parse_drozer_command).collect()),
+        SeedFormat::IntentFuzzerXml => parse_intent_fuzzer_xml(&text),
+        SeedFormat::IntentUri => Ok(find_intent_uris(&text)
+            .filter_map(|uri| {
+                let mut input = parse_intent_uri(uri)?;
+                if input.component_class.is_empty() && !generator.resolve_target(&mut input) {
+                    println!("Skipping intent URI no template component handles: {}", uri);
+                    return None;
+                }
+                Some(input)
+            })
+            .collect()),
+    }
+}
+
+/// The intent URIs in the text, delimited by whitespace, quotes or tags.
+fn find_intent_uris(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>'))
+        .filter_map(|token| {
+            let start = token.find("#Intent;")?;
+            let end = token[start..].find(";end")? + start + ";end".len();
+            // Links start at the intent scheme, other URIs at the data.
+            let start = token.find("intent:").filter(|i| *i < start).unwrap_or(0);
+            Some(&token[start..end])
+        })
+}
+
+/// Parses an intent URI in the `Intent.toUri` format, either with the data
+/// before the fragment (`https://example.com#Intent;...;end`) or as `intent:`
+/// URI (`intent://example.com#Intent;scheme=https;...;end`). The component
+/// class is empty if the URI has none.
+pub fn parse_intent_uri(uri: &str) -> Option<IntentInput> {
+    // Links in HTML pages escape the ampersands.
+    let uri = uri.replace("&amp;", "&");
+    let (data, fields) = uri.split_once("#Intent;")?;
+
+    let mut input = empty_input(ReceiverType::Activity);
+    let mut scheme = None;
+    for field in fields.split(';').take_while(|field| *field != "end") {
+        let Some((name, value)) = field.split_once('=') else {
+            continue;
+        };
+        let value = decode_uri_component(value);
+        match name {
+            "action" => input.action = value,
+            "category" if input.category.is_empty() => input.category = value,
+            "type" => input.mime_type = parse_mime_type(&value),
+            "launchFlags" => {
+                input.flags = match value.strip_prefix("0x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => value.parse().ok()?,
+                }
+            }
+            "component" => {
+                let (package, class) = value.split_once('/')?;
+                input.component_package = package.to_owned();
+                input.component_class = match class.strip_prefix('.') {
+                    Some(_) => format!("{}{}", package, class),
+                    None => class.to_owned(),
+                };
+            }
+            "package" if input.component_package.is_empty() => input.component_package = value,
+            "scheme" => scheme = Some(value),
+            _ => {
+                let Some((prefix, key)) = name.split_once('.') else {
+                    continue;
+                };
+                let key = decode_uri_component(key);
+                let (extra_type, key) = match (prefix, key.split_once(':')) {
+                    ("S", Some(("malintent.uri", key))) => ("uri", key.to_owned()),
+                    ("S", Some(("malintent.component", key))) => ("componentname", key.to_owned()),
+                    // Arrays, marked with the `am` option of their type.
+                    ("S", Some((flag, key))) if flag.starts_with("malintent.") => {
+                        (flag.trim_start_matches("malintent."), key.to_owned())
+                    }
+                    ("S", _) => ("string", key),
+                    ("B", _) => ("boolean", key),
+                    ("i", _) => ("int", key),
+                    ("l", _) => ("long", key),
+                    ("f", _) => ("float", key),
+                    _ => {
+                        println!("Skipping extra {} of unsupported type {}", key, prefix);
+                        continue;
+                    }
+                };
+                input.extras.extend(parse_extra(&key, extra_type, &value));
+            }
+        }
+    }
+
+    // The framework parses `intent:` URIs without an action as views.
+    if input.action.is_empty() && data.starts_with("intent:") {
+        input.action = "android.intent.action.VIEW".to_owned();
+    }
+
+    let data = match (data.strip_prefix("intent:"), scheme) {
+        (Some(rest), Some(scheme)) => format!("{}:{}", scheme, rest),
+        (Some(rest), None) => rest.to_owned(),
+        (None, _) => data.to_owned(),
+    };
+    if !data.is_empty() {
+        input.data = Some(URIInput::raw(&data));
+    }
+
+    Some(input)
+}
+
+/// Parses a drozer command line starting an activity or sending a broadcast.
+fn parse_drozer_command(line: &str) -> Option<IntentInput> {
+    let args = shlex::split(line.trim().trim_start_matches("dz>").trim())?;
+    let module = args.iter().position(|arg| arg.starts_with("app."))?;
+    let receiver_type = match args[module].as_str() {
+        "app.activity.start" => ReceiverType::Activity,
+        "app.broadcast.send" => ReceiverType::BroadcastReceiver,
+        _ => return None,
+    };
+
+    let mut input = empty_input(receiver_type);
+    let mut args = args[module + 1..].iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--component" => {
+                input.component_package = args.next()?.clone();
+                input.component_class = args.next()?.clone();
+            }
+            "--action" => input.action = args.next()?.clone(),
+            "--category" => input.category = args.next()?.clone(),
+            "--data-uri" => input.data = Some(URIInput::raw(args.next()?)),
+            "--mimetype" => input.mime_type = parse_mime_type(args.next()?),
+            "--extra" => {
+                let (extra_type, key, value) = (args.next()?, args.next()?, args.next()?);
+                input.extras.extend(parse_extra(key, extra_type, value));
+            }
+            _ => {}
+        }
+    }
+
+    (!input.component_package.is_empty()).then_some(input)
+}
+
+/// Parses the `<intent>` elements of an IntentFuzzer-style XML file.
+fn parse_intent_fuzzer_xml(text: &str) -> Result<Vec<IntentInput>, libafl::Error> {
+    let document = roxmltree::Document::parse(text)
+        .map_err(|err| libafl::Error::illegal_argument(format!("Invalid XML: {}", err)))?;
+
+    let mut inputs = Vec::new();
+    for node in document
+        .descendants()
+        .filter(|node| node.has_tag_name("intent"))
+    {
+        let receiver_type = match node.attribute("receiver") {
+            Some("broadcast") | Some("receiver") => ReceiverType::BroadcastReceiver,
+            _ => ReceiverType::Activity,
+        };
+        let Some((package, class)) = node.attribute("component").and_then(|c| c.split_once('/'))
+        else {
+            println!("Skipping intent without a component");
+            continue;
+        };
+
+        let mut input = empty_input(receiver_type);
+        input.component_package = package.to_owned();
+        input.component_class = class.to_owned();
+        input.action = node.attribute("action").unwrap_or_default().to_owned();
+        input.category = node.attribute("category").unwrap_or_default().to_owned();
+        input.data = node.attribute("data").map(URIInput::raw);
+        if let Some(mime_type) = node.attribute("type") {
+            input.mime_type = parse_mime_type(mime_type);
+        }
+
+        for extra in node.children().filter(|child| child.has_tag_name("extra")) {
+            if let (Some(key), Some(extra_type), Some(value)) = (
+                extra.attribute("key"),
+                extra.attribute("type"),
+                extra.attribute("value"),
+            ) {
+                input.extras.extend(parse_extra(key, extra_type, value));
+            }
+        }
+        inputs.push(input);
+    }
+
+    Ok(inputs)
+}
+
+/// An intent to the given kind of receiver without any content.
+pub fn empty_input(receiver_type: ReceiverType) -> IntentInput {
+    IntentInput {
+        receiver_type,
+        component_package: String::new(),
+        component_class: String::new(),
+        action: String::new(),
+        category: String::new(),
+        data: None,
+        mime_type: MimeType::TextPlain,
+        flags: 0,
+        extras: Vec::new(),
+        uri_grant: URIGrant::default(),
+        pending_intent: None,
+        permission: None,
+    }
+}
+
+/// The mime type with the given name, `text/plain` for unknown types.
+pub fn parse_mime_type(name: &str) -> MimeType {
+    MimeType::iter()
+        .find(|mime_type| mime_type.to_string() == name)
+        .unwrap_or(MimeType::TextPlain)
+}
+
+/// Converts an extra to the representation of the fuzzer. Extras of types
+/// the fuzzer cannot send are dropped.
+fn parse_extra(key: &str, extra_type: &str, value: &str) -> Option<ExtraInput> {
+    let direct = |bytes: Vec<u8>| DirectInput {
+        buffer: BytesInput::new(bytes),
+    };
+
+    let value = match extra_type.to_lowercase().as_str() {
+        "string" | "s" => ExtraType::String(direct(value.as_bytes().to_vec())),
+        "boolean" | "bool" | "z" => {
+            ExtraType::Boolean(direct(vec![u8::from(value.parse::<bool>().ok()?)]))
+        }
+        "integer" | "int" | "i" => {
+            ExtraType::Int(direct(value.parse::<i32>().ok()?.to_le_bytes().to_vec()))
+        }
+        "long" | "l" => ExtraType::Long(direct(value.parse::<i64>().ok()?.to_le_bytes().to_vec())),
+        "float" | "double" | "f" => {
+            ExtraType::Float(direct(value.parse::<f32>().ok()?.to_le_bytes().to_vec()))
+        }
+        "uri" | "u" => ExtraType::URI(URIInput::raw(value)),
+        "componentname" => ExtraType::ComponentName(direct(value.as_bytes().to_vec())),
+        "ia" => ExtraType::IntArray(direct(parse_numbers(value, i32::to_le_bytes)?)),
+        "ial" => ExtraType::IntArrayList(direct(parse_numbers(value, i32::to_le_bytes)?)),
+        "la" => ExtraType::LongArray(direct(parse_numbers(value, i64::to_le_bytes)?)),
+        "lal" => ExtraType::LongArrayList(direct(parse_numbers(value, i64::to_le_bytes)?)),
+        "fa" => ExtraType::FloatArray(direct(parse_numbers(value, f32::to_le_bytes)?)),
+        "fal" => ExtraType::FloatArrayList(direct(parse_numbers(value, f32::to_le_bytes)?)),
+        "sa" => ExtraType::StringArray(parse_strings(value)),
+        "sal" => ExtraType::StringArrayList(parse_strings(value)),
+        _ => {
+            println!("Skipping extra {} of unsupported type {}", key, extra_type);
+            return None;
+        }
+    };
+
+    Some(ExtraInput {
+        key: key.to_owned(),
+        value,
+    })
+}
+
+/// Parses the comma-separated numbers of an array extra into its buffer.
+fn parse_numbers<T, const N: usize>(value: &str, to_bytes: fn(T) -> [u8; N]) -> Option<Vec<u8>>
+where
+    T: std::str::FromStr,
+{
+    value
+        .split(',')
+        .map(|number| number.trim().parse().ok().map(to_bytes))
+        .collect::<Option<Vec<_>>>()
+        .map(|numbers| numbers.concat())
+}
+
+/// Splits the value of a String array extra on the commas not preceded by a
+/// backslash, as `am` does, and unescapes the other commas, which the fuzzer
+/// escaped in the elements.
+fn parse_strings(value: &str) -> StringArrayInput {
+    let mut elements = vec![Vec::new()];
+    let mut bytes = value.bytes().peekable();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'\\' if bytes.peek() == Some(&b',') => {
+                elements.last_mut().unwrap().push(b',');
+                bytes.next();
+            }
+            b',' => elements.push(Vec::new()),
+            _ => elements.last_mut().unwrap().push(byte),
+        }
+    }
+    StringArrayInput {
+        elements: elements.into_iter().map(BytesInput::new).collect(),
+    }
+}
@@ -0,0 +1,102 @@
+//! Seeds from the app shortcuts of the target.
+//!
+//! App shortcuts (static ones from the manifest as well as dynamic and pinned
+//! ones published at runtime) declare intents the launcher sends on behalf of
+//! the user, usually carrying the extras and data the target component
+//! expects. [shortcut_seeds] reads them with `cmd shortcut` and turns every
+//! explicit shortcut intent into a seed, which the mutators then vary before
+//! it is delivered like any other input. Shortcuts to activities that are not
+//! exported can only be started by the launcher, so their seeds end up as
+//! setup errors.
+
+use libafl::prelude::BytesInput;
+
+use crate::{
+    adb_device::AdbDevice,
+    intent_input::{DirectInput, ExtraInput, ExtraType, IntentInput, ReceiverType, URIInput},
+    seed_import::{empty_input, parse_mime_type},
+};
+
+/// Returns a seed for every explicit intent of the shortcuts of the package.
+pub fn shortcut_seeds(
+    adb_device: &AdbDevice,
+    package: &str,
+) -> Result<Vec<IntentInput>, libafl::Error> {
+    let output = adb_device.shortcuts(package)?;
+
+    let mut seeds = Vec::new();
+    // e.g. "ShortcutInfo {id=compose, ..., intents=[Intent { act=... cmp=... }
+    // /PersistableBundle[{draft=true}]], ...}"
+    for line in output
+        .lines()
+        .filter(|line| line.contains("ShortcutInfo {"))
+    {
+        let Some(mut rest) = line.split(", intents=[").nth(1) else {
+            continue;
+        };
+        while let Some(start) = rest.find("Intent { ") {
+            rest = &rest[start + "Intent { ".len()..];
+            let Some(end) = rest.find(" }") else {
+                break;
+            };
+            let (description, after) = (&rest[..end], &rest[end + " }".len()..]);
+            let extras = after
+                .strip_prefix("/PersistableBundle[{")
+                .and_then(|bundle| bundle.split_once("}]"))
+                .map(|(extras, _)| extras);
+
+            match parse_intent(description, extras) {
+                Some(seed) => seeds.push(seed),
+                None => println!("Skipping implicit shortcut intent {{{}}}", description),
+            }
+            rest = after;
+        }
+    }
+
+    Ok(seeds)
+}
+
+/// Parses the fields of an `Intent.toString()` and the extras of its
+/// shortcut. Returns None for intents without a component.
+fn parse_intent(description: &str, extras: Option<&str>) -> Option<IntentInput> {
+    let mut input = empty_input(ReceiverType::Activity);
+    for field in description.split_whitespace() {
+        let Some((name, value)) = field.split_once('=') else {
+            continue;
+        };
+        match name {
+            "act" => input.action = value.to_owned(),
+            "cat" => {
+                let categories = value.trim_start_matches('[').trim_end_matches(']');
+                input.category = categories.split(',').next()?.to_owned();
+            }
+            "dat" => input.data = Some(URIInput::raw(value)),
+            "typ" => input.mime_type = parse_mime_type(value),
+            "flg" => input.flags = u32::from_str_radix(value.trim_start_matches("0x"), 16).ok()?,
+            "cmp" => {
+                let (package, class) = value.split_once('/')?;
+                input.component_package = package.to_owned();
+                input.component_class = match class.strip_prefix('.') {
+                    Some(_) => format!("{}{}", package, class),
+                    None => class.to_owned(),
+                };
+            }
+            _ => {}
+        }
+    }
+
+    // The bundle does not tell the types of its values, so they are all
+    // sent as strings.
+    for pair in extras.unwrap_or_default().split(", ") {
+        if let Some((key, value)) = pair.split_once('=') {
+            input.extras.push(ExtraInput {
+                key: key.to_owned(),
+                value: ExtraType::String(DirectInput {
+                    buffer: BytesInput::new(value.as_bytes().to_vec()),
+                }),
+            });
+        }
+    }
+
+    (!input.component_package.is_empty()).then_some(input)
+}
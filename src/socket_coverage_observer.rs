@@ -1,20 +1,296 @@
 //! Handles getting the coverage map from CoverageAgent over a socket.
 
 use std::{
-    io::{BufReader, Read, Write},
-    net::TcpStream, time::Duration, path::PathBuf,
+    collections::{hash_map::Entry, HashMap, HashSet},
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    path::PathBuf,
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
 use libafl::prelude::{
-    AsIter, AsSlice, AsMutSlice, ConstMapObserver, HasLen, HitcountsMapObserver, MapObserver, Named,
-    Observer, UsesInput,
+    AsIter, AsMutSlice, AsSlice, ConstMapObserver, HasLen, HitcountsMapObserver, MapObserver,
+    Named, Observer, UsesInput,
+};
+use libafl::{
+    prelude::Corpus,
+    state::{HasCorpus, HasExecutions, HasNamedMetadata},
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{adb_device::AdbDevice, intent_input::IntentInput};
+use crate::{
+    adb_device::AdbDevice,
+    campaign_stats,
+    coverage_mapping::{CoverageMapping, SourceLocation},
+    coverage_report::overall_map_file,
+    exception_report::{parse_exception_reports, ExceptionReport},
+    intent_generator::IntentTemplate,
+    intent_input::IntentInput,
+    intent_mutator::EXTRA_TYPES,
+    pipelined_stage::AppInstance,
+    results_db,
+};
 
 const COVERAGE_MAP_SIZE: usize = 1024 * 1024;
 
+/// Columns of the overall coverage CSV file: the unix time, the seconds since
+/// the start of the campaign, the total executions, the corpus size and the
+/// number of edges covered.
+pub const OVERALL_COVERAGE_HEADER: &str = "wall_time,elapsed_secs,execs,corpus_size,edges\n";
+
+/// How the coverage map received from the agent is interpreted.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CoverageMode {
+    /// AFL-style hitcount buckets of every edge
+    Hitcounts,
+    /// Only whether an edge was hit, ignoring how often
+    Edges,
+    /// Only whether a method was entered, one map entry per method
+    Methods,
+}
+
+/// Version of the coverage socket protocol spoken by the fuzzer. Agents of
+/// an older version are still supported (see [connect]), newer ones are
+/// rejected during the handshake.
+const PROTOCOL_VERSION: u32 = 2;
+
+/// Version 1 of the protocol sends the coverage map without a length prefix.
+const UNFRAMED_PROTOCOL_VERSION: u32 = 1;
+
+/// Agents predating the handshake speak version 0: they only support the
+/// synchronization (`ss` or `se` right after connecting), resetting (`r`),
+/// native tracing (`ts`) and the unframed coverage map (`d`).
+const LEGACY_PROTOCOL_VERSION: u32 = 0;
+
+/// Magic at the start of both handshake lines.
+const PROTOCOL_MAGIC: &str = "MALINTENT";
+
+/// Capability to synchronize on the start of the activity under test. Further
+/// capabilities (e.g. delta coverage or compression) are negotiated the same
+/// way once the fuzzer supports them.
+const CAPABILITY_SYNC: &str = "sync";
+/// Capability to answer heartbeats (`p`) with `p`.
+const CAPABILITY_KEEPALIVE: &str = "keepalive";
+/// Capability to report the extra keys the app queried during the execution,
+/// see [SocketCoverageObserver::read_queried_extra_keys].
+const CAPABILITY_EXTRA_KEYS: &str = "extra_keys";
+/// Capability to report the exceptions of the app during the execution, see
+/// [SocketCoverageObserver::read_exception_reports].
+const CAPABILITY_EXCEPTIONS: &str = "exceptions";
+/// Capability to only record the coverage of the classes in given packages,
+/// see [SocketCoverageObserver::negotiate_filter].
+const CAPABILITY_FILTER: &str = "filter";
+/// Capability to record one map entry per method instead of per edge.
+const CAPABILITY_METHODS: &str = "methods";
+
+/// Number of new source locations logged per execution.
+const NEW_LOCATIONS_LOGGED: usize = 5;
+
+/// Marks the map entries without a method in [SocketCoverageObserver::method_entries].
+const NO_METHOD: u32 = u32::MAX;
+
+/// The maximum length of the queried extra keys message.
+const MAX_EXTRA_KEYS_MESSAGE_LEN: usize = 64 * 1024;
+/// The maximum length of the exception report message.
+const MAX_EXCEPTIONS_MESSAGE_LEN: usize = 64 * 1024;
+
+/// Idle time after which the connection is checked with a heartbeat before
+/// it is used again.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Number of attempts to reconnect to the agent, and the delay before the
+/// second attempt. The delay doubles after every failed attempt.
+const RECONNECT_ATTEMPTS: u32 = 5;
+const RECONNECT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// An established connection to the coverage agent.
+struct Connection {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    protocol_version: u32,
+    capabilities: Vec<String>,
+}
+
+/// The connection to the coverage agent of an app instance the executions
+/// are spread over, parked while another instance is executed, see
+/// [SocketCoverageObserver::with_instances].
+#[derive(Debug)]
+struct AgentInstance {
+    user: Option<u32>,
+    address: String,
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    protocol_version: u32,
+    capabilities: Vec<String>,
+    last_activity: Instant,
+}
+
+/// Connects to the coverage agent and performs the protocol handshake.
+///
+/// The fuzzer sends `h` followed by the line `MALINTENT <version> <caps>`,
+/// where `<caps>` is the comma-separated list of requested capabilities. The
+/// agent answers with a line in the same format, listing the capabilities it
+/// enabled. Since version 2, the coverage map is sent as a length-prefixed
+/// message (see [read_message_from]).
+///
+/// An agent that does not answer the handshake predates it, so the fuzzer
+/// reconnects and speaks the [LEGACY_PROTOCOL_VERSION] with it, as it does
+/// right away without `handshake` (when reconnecting to such an agent).
+fn connect(
+    address: &str,
+    capabilities: &[&str],
+    timeout: Duration,
+    handshake: bool,
+) -> Result<Connection, libafl::Error> {
+    let mut stream = TcpStream::connect(address)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    if !handshake {
+        // Set up the socket for synchronization if requested.
+        let sync = capabilities.contains(&CAPABILITY_SYNC);
+        stream.write_all(if sync { b"ss" } else { b"se" })?;
+        return Ok(Connection {
+            stream,
+            reader,
+            protocol_version: LEGACY_PROTOCOL_VERSION,
+            capabilities: sync
+                .then(|| vec![CAPABILITY_SYNC.to_owned()])
+                .unwrap_or_default(),
+        });
+    }
+
+    stream.write_all(
+        format!(
+            "h{} {} {}\n",
+            PROTOCOL_MAGIC,
+            PROTOCOL_VERSION,
+            capabilities.join(",")
+        )
+        .as_bytes(),
+    )?;
+
+    let mut reply = String::new();
+    if let Err(err) = reader.read_line(&mut reply) {
+        println!(
+            "Coverage agent did not answer the protocol handshake ({}), assuming an agent \
+            predating it",
+            err
+        );
+        return connect(address, capabilities, timeout, false);
+    }
+    if reply.is_empty() {
+        println!(
+            "Coverage agent closed the connection on the protocol handshake, assuming an agent \
+            predating it"
+        );
+        return connect(address, capabilities, timeout, false);
+    }
+
+    let mut fields = reply.split_whitespace();
+    if fields.next() != Some(PROTOCOL_MAGIC) {
+        return Err(libafl::Error::unknown(format!(
+            "Coverage agent sent an invalid handshake reply ({:?}). \
+            The agent build is incompatible with this fuzzer.",
+            reply
+        )));
+    }
+
+    let version = fields.next().and_then(|v| v.parse::<u32>().ok());
+    let protocol_version = match version {
+        Some(version) if (UNFRAMED_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version) => {
+            version
+        }
+        _ => {
+            return Err(libafl::Error::unknown(format!(
+                "Coverage agent speaks protocol version {:?}, but the fuzzer speaks versions up \
+                to {}. Please update the fuzzer.",
+                version, PROTOCOL_VERSION
+            )))
+        }
+    };
+    if protocol_version < PROTOCOL_VERSION {
+        println!(
+            "Coverage agent speaks the older protocol version {}",
+            protocol_version
+        );
+    }
+
+    let enabled = fields
+        .next()
+        .map(|caps| {
+            caps.split(',')
+                .filter(|c| !c.is_empty())
+                .map(str::to_owned)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(Connection {
+        stream,
+        reader,
+        protocol_version,
+        capabilities: enabled,
+    })
+}
+
+/// Reads a message from the agent: a little-endian `u32` length followed by
+/// that many bytes. A message of the wrong length or a partial read is an
+/// error, as the connection can't be trusted afterwards.
+fn read_message_from<R: Read>(
+    reader: &mut R,
+    expected_len: usize,
+) -> Result<Vec<u8>, libafl::Error> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len != expected_len {
+        return Err(libafl::Error::unknown(format!(
+            "Unexpected message length {} (expected {})",
+            len, expected_len
+        )));
+    }
+
+    let mut buffer = vec![0; len];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Reads a message of variable length from the agent, like
+/// [read_message_from] but accepting any length up to `max_len`.
+fn read_message_up_to<R: Read>(reader: &mut R, max_len: usize) -> Result<Vec<u8>, libafl::Error> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > max_len {
+        return Err(libafl::Error::unknown(format!(
+            "Message length {} exceeds the maximum of {}",
+            len, max_len
+        )));
+    }
+
+    let mut buffer = vec![0; len];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Reads the coverage map from the agent, which is a message since protocol
+/// version 2 and the raw map before.
+fn read_coverage_map_from<R: Read>(
+    reader: &mut R,
+    protocol_version: u32,
+) -> Result<Vec<u8>, libafl::Error> {
+    if protocol_version > UNFRAMED_PROTOCOL_VERSION {
+        return read_message_from(reader, COVERAGE_MAP_SIZE);
+    }
+    let mut buffer = vec![0; COVERAGE_MAP_SIZE];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn create_coverage_map_observer<'a>(
     adb_device: AdbDevice,
     app_name: String,
@@ -23,6 +299,8 @@ pub fn create_coverage_map_observer<'a>(
     enable_synchronization: bool,
     use_coverage: bool,
     overall_coverage_file: &PathBuf,
+    socket_timeout: Duration,
+    coverage_mode: CoverageMode,
 ) -> SocketCoverageObserver<'a> {
     return SocketCoverageObserver::new(
         adb_device,
@@ -32,6 +310,8 @@ pub fn create_coverage_map_observer<'a>(
         enable_synchronization,
         use_coverage,
         overall_coverage_file,
+        socket_timeout,
+        coverage_mode,
     );
 }
 
@@ -43,11 +323,55 @@ pub struct SocketCoverageObserver<'a> {
     trace_native: bool,
     enable_synchronization: bool,
     use_coverage: bool,
+    coverage_mode: CoverageMode,
+    // How long to wait for a reply from the agent
+    socket_timeout: Duration,
 
     #[serde(skip, default = "default_stream")]
     stream: TcpStream,
     #[serde(skip, default = "default_reader")]
     reader: BufReader<TcpStream>,
+    // Protocol version spoken by the agent
+    protocol_version: u32,
+    // Capabilities enabled by the agent in the handshake
+    capabilities: Vec<String>,
+    // Last time the connection was used successfully
+    #[serde(skip, default = "Instant::now")]
+    last_activity: Instant,
+    // Android user of the app instance of the connection, the current user
+    // if None
+    user: Option<u32>,
+    // Connections to the agents of the other app instances, in the order
+    // they are executed next
+    #[serde(skip)]
+    instances: Vec<AgentInstance>,
+    // Last time the overall coverage grew
+    #[serde(skip, default = "Instant::now")]
+    last_new_coverage: Instant,
+    // Edges covered for the first time by the last execution
+    new_edges: Vec<usize>,
+    // Exceptions the agent reported for the last execution
+    exception_reports: Vec<ExceptionReport>,
+    // Extra keys the agent reported with a type the mutators can't create
+    ignored_extra_keys: HashSet<String>,
+    // Locations of the map entries of the agent
+    #[serde(skip)]
+    coverage_mapping: Option<CoverageMapping>,
+    // Packages the coverage is restricted to, all if empty
+    coverage_packages: Vec<String>,
+    // Map entries of classes outside the packages, if the agent can't filter
+    excluded_entries: Vec<bool>,
+    // Method of every map entry, to fold the edge coverage into method
+    // coverage if the agent can't record it
+    method_entries: Vec<u32>,
+    // Locations of the methods the edge coverage is folded into
+    method_locations: Vec<SourceLocation>,
+    // Coverage map requested by the executor and read in the background
+    #[serde(skip)]
+    pending_coverage: Option<JoinHandle<Result<Vec<u8>, libafl::Error>>>,
+    // Coverage map of the current execution retrieved before post_exec
+    #[serde(skip)]
+    retrieved_coverage: Option<Result<Vec<u8>, libafl::Error>>,
 
     base_observer: HitcountsMapObserver<ConstMapObserver<'a, u8, COVERAGE_MAP_SIZE>>,
     // array to keep track of which edges have been covered
@@ -60,6 +384,7 @@ pub struct SocketCoverageObserver<'a> {
 }
 
 impl<'a> SocketCoverageObserver<'a> {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         adb_device: AdbDevice,
         app_name: String,
@@ -68,37 +393,54 @@ impl<'a> SocketCoverageObserver<'a> {
         enable_synchronization: bool,
         use_coverage: bool,
         overall_coverage_file: &PathBuf,
+        socket_timeout: Duration,
+        coverage_mode: CoverageMode,
     ) -> Self {
-        let mut stream = TcpStream::connect(address).expect("Failed to connect to socket");
-        stream.set_read_timeout(Some(Duration::from_secs(10))).expect("Failed to set read timeout");
-        let reader = BufReader::new(stream.try_clone().expect("Failed to clone tcp stream"));
-
-        stream
-            .set_nodelay(true)
-            .expect("Failed to set nodelay on socket");
-
-        // Set up the socket for synchronization if requested.
-        stream
-            .write(if enable_synchronization { b"ss" } else { b"se" })
-            .expect("Failed to write to socket");
+        let connection = connect(
+            address,
+            &requested_capabilities(enable_synchronization, coverage_mode),
+            socket_timeout,
+            true,
+        )
+        .expect("Failed to connect to coverage agent");
 
         // Delete coverage file if it exists
         if overall_coverage_file.exists() {
             std::fs::remove_file(overall_coverage_file).unwrap();
         }
-        // Write first entry to coverage file
+        // Write the CSV header and first record to coverage file
         let mut file = std::fs::File::create(overall_coverage_file).unwrap();
-        file.write_all(b"0: 0\n").unwrap();
+        file.write_all(OVERALL_COVERAGE_HEADER.as_bytes()).unwrap();
+        file.write_all(format!("{},0,0,0,0\n", unix_time()).as_bytes())
+            .unwrap();
 
-        Self {
+        let observer = Self {
             adb_device,
             app_name,
             address: address.to_owned(),
             trace_native,
             enable_synchronization,
             use_coverage,
-            stream,
-            reader,
+            coverage_mode,
+            socket_timeout,
+            stream: connection.stream,
+            reader: connection.reader,
+            protocol_version: connection.protocol_version,
+            capabilities: connection.capabilities,
+            last_activity: Instant::now(),
+            user: None,
+            instances: Vec::new(),
+            last_new_coverage: Instant::now(),
+            new_edges: Vec::new(),
+            ignored_extra_keys: HashSet::new(),
+            exception_reports: Vec::new(),
+            coverage_mapping: None,
+            coverage_packages: Vec::new(),
+            excluded_entries: Vec::new(),
+            method_entries: Vec::new(),
+            method_locations: Vec::new(),
+            pending_coverage: None,
+            retrieved_coverage: None,
             base_observer: HitcountsMapObserver::new(ConstMapObserver::owned(
                 "edges_from_socket",
                 vec![0; COVERAGE_MAP_SIZE],
@@ -110,27 +452,426 @@ impl<'a> SocketCoverageObserver<'a> {
             overall_coverage_file: overall_coverage_file.to_owned(),
             start_time: std::time::SystemTime::now(),
             last_overall_coverage: 0,
+        };
+        observer.check_capabilities();
+        observer
+    }
+
+    /// Sets the locations of the map entries, used to report new coverage as
+    /// source locations and to filter or fold the coverage on the host.
+    pub fn with_coverage_mapping(mut self, coverage_mapping: Option<CoverageMapping>) -> Self {
+        self.coverage_mapping = coverage_mapping;
+        self
+    }
+
+    /// Restricts the coverage to the classes in the given packages (e.g.
+    /// `com.example`), leaving out the framework and library code that
+    /// otherwise dominates the map. The agent filters the coverage if it
+    /// supports it, otherwise the map entries of other classes are cleared on
+    /// the host, for which the coverage mapping is needed.
+    pub fn with_coverage_filter(mut self, packages: Vec<String>) -> Result<Self, libafl::Error> {
+        self.coverage_packages = packages;
+        if self.coverage_packages.is_empty() {
+            return Ok(self);
+        }
+        if self.negotiate_filter()? {
+            println!(
+                "Coverage agent filters the coverage to {:?}",
+                self.coverage_packages
+            );
+            return Ok(self);
+        }
+
+        let Some(coverage_mapping) = &self.coverage_mapping else {
+            return Err(libafl::Error::illegal_argument(
+                "Coverage agent can't filter the coverage, a coverage mapping file is needed \
+                to filter it on the host",
+            ));
+        };
+        self.excluded_entries = vec![false; COVERAGE_MAP_SIZE];
+        for (index, location) in coverage_mapping.locations() {
+            if let Some(excluded) = self.excluded_entries.get_mut(index) {
+                *excluded = !in_packages(&location.class, &self.coverage_packages);
+            }
         }
+        println!(
+            "Filtering the coverage on the host to {:?}, excluding {} map entries",
+            self.coverage_packages,
+            self.excluded_entries
+                .iter()
+                .filter(|&&excluded| excluded)
+                .count()
+        );
+        Ok(self)
     }
 
-    fn init(&mut self) {
-        self.stream =
-            TcpStream::connect(self.address.clone()).expect("Failed to connect to socket");
-        self.stream.set_read_timeout(Some(Duration::from_secs(10))).expect("Failed to set read timeout");
-        self.reader = BufReader::new(self.stream.try_clone().expect("Failed to clone tcp stream"));
+    /// Sets up the [CoverageMode::Methods] mode. If the agent can't record the
+    /// coverage of the methods itself, the edge coverage is folded into one
+    /// map entry per method on the host, for which the mapping file of the
+    /// instrumentation is needed.
+    pub fn with_method_mapping(mut self) -> Result<Self, libafl::Error> {
+        if self.coverage_mode != CoverageMode::Methods || self.has_capability(CAPABILITY_METHODS) {
+            return Ok(self);
+        }
 
-        self.stream
-            .set_nodelay(true)
-            .expect("Failed to set nodelay on socket");
+        let Some(coverage_mapping) = &self.coverage_mapping else {
+            return Err(libafl::Error::illegal_argument(
+                "Coverage agent can't record method coverage, a coverage mapping file is needed \
+                to fold the edge coverage on the host",
+            ));
+        };
+        let mut methods = HashMap::new();
+        self.method_entries = vec![NO_METHOD; COVERAGE_MAP_SIZE];
+        for (index, location) in coverage_mapping.locations() {
+            if location.method.is_none() || index >= COVERAGE_MAP_SIZE {
+                continue;
+            }
+            let method = location.method_location();
+            let next = methods.len() as u32;
+            self.method_entries[index] = *methods.entry(method).or_insert(next);
+        }
+        if methods.len() > COVERAGE_MAP_SIZE {
+            return Err(libafl::Error::illegal_argument(format!(
+                "The coverage mapping has {} methods, more than the {} map entries",
+                methods.len(),
+                COVERAGE_MAP_SIZE
+            )));
+        }
+        let mut method_locations: Vec<_> = methods.into_iter().collect();
+        method_locations.sort_by_key(|(_, id)| *id);
+        self.method_locations = method_locations
+            .into_iter()
+            .map(|(method, _)| method)
+            .collect();
+        println!(
+            "Folding the edge coverage on the host into {} methods",
+            self.method_locations.len()
+        );
+        Ok(self)
+    }
 
-        // Set up the socket for synchronization if requested.
+    /// Spreads the executions round-robin over the app instances of the given
+    /// Android users (e.g. work profiles) besides the one of the current user,
+    /// with the addresses of their coverage agents. Every agent has to record
+    /// the coverage the same way, as the maps of all instances are merged.
+    pub fn with_instances(mut self, instances: &[AppInstance]) -> Result<Self, libafl::Error> {
+        let filtered_by_agent =
+            !self.coverage_packages.is_empty() && self.excluded_entries.is_empty();
+        let methods_by_agent = self.has_capability(CAPABILITY_METHODS);
+        for AppInstance {
+            user,
+            coverage_socket_address: address,
+        } in instances
+        {
+            let connection = connect(
+                address,
+                &requested_capabilities(self.enable_synchronization, self.coverage_mode),
+                self.socket_timeout,
+                true,
+            )?;
+            self.instances.push(AgentInstance {
+                user: Some(*user),
+                address: address.clone(),
+                stream: connection.stream,
+                reader: connection.reader,
+                protocol_version: connection.protocol_version,
+                capabilities: connection.capabilities,
+                last_activity: Instant::now(),
+            });
+
+            let index = self.instances.len() - 1;
+            self.swap_instance(index);
+            self.check_capabilities();
+            let consistent = self.negotiate_filter().map(|filtered| {
+                filtered == filtered_by_agent
+                    && self.has_capability(CAPABILITY_METHODS) == methods_by_agent
+            });
+            self.swap_instance(index);
+            if !consistent? {
+                return Err(libafl::Error::illegal_argument(format!(
+                    "Coverage agent of user {} at {} does not record the coverage like the one \
+                    of the current user",
+                    user, address
+                )));
+            }
+            println!(
+                "Spreading the executions to the app instance of user {} (coverage agent at {})",
+                user, address
+            );
+        }
+        Ok(self)
+    }
+
+    /// Exchanges the connection in use with the parked one of the instance at
+    /// the index.
+    fn swap_instance(&mut self, index: usize) {
+        let instance = &mut self.instances[index];
+        std::mem::swap(&mut self.user, &mut instance.user);
+        std::mem::swap(&mut self.address, &mut instance.address);
+        std::mem::swap(&mut self.stream, &mut instance.stream);
+        std::mem::swap(&mut self.reader, &mut instance.reader);
+        std::mem::swap(&mut self.protocol_version, &mut instance.protocol_version);
+        std::mem::swap(&mut self.capabilities, &mut instance.capabilities);
+        std::mem::swap(&mut self.last_activity, &mut instance.last_activity);
+    }
+
+    /// The Android user of the app instance of the current execution, the
+    /// current user if None.
+    pub fn active_user(&self) -> Option<u32> {
+        self.user
+    }
+
+    /// The Android user of the app instance of the next execution, None if
+    /// the executions are not spread over several instances.
+    pub fn next_user(&self) -> Option<Option<u32>> {
+        self.instances.first().map(|instance| instance.user)
+    }
+
+    /// Asks the agent to only record the coverage of the classes in the
+    /// filtered packages by sending `f` followed by the comma-separated
+    /// packages and a newline, which the agent acknowledges with `d`. Returns
+    /// whether the agent filters the coverage.
+    fn negotiate_filter(&mut self) -> Result<bool, libafl::Error> {
+        if self.coverage_packages.is_empty() || !self.has_capability(CAPABILITY_FILTER) {
+            return Ok(false);
+        }
+
+        let mut buffer = [0; 1];
         self.stream
-            .write(if self.enable_synchronization {
-                b"ss"
-            } else {
-                b"se"
-            })
-            .expect("Failed to write to socket");
+            .write_all(format!("f{}\n", self.coverage_packages.join(",")).as_bytes())?;
+        self.reader.read_exact(&mut buffer)?;
+        if buffer[0] != b'd' {
+            return Err(libafl::Error::unknown(format!(
+                "Failed to set the coverage filter (got {:?})",
+                buffer
+            )));
+        }
+        Ok(true)
+    }
+
+    /// Reconnects to the agent with exponential backoff, re-establishing the
+    /// adb forward of the coverage port before every attempt.
+    fn init(&mut self) -> Result<(), libafl::Error> {
+        let port = self
+            .address
+            .rsplit(':')
+            .next()
+            .and_then(|port| port.parse::<u16>().ok());
+        let mut backoff = RECONNECT_INITIAL_BACKOFF;
+
+        for attempt in 1..=RECONNECT_ATTEMPTS {
+            if let Some(port) = port {
+                if let Err(err) = self.adb_device.refresh_forward(port) {
+                    println!("Failed to refresh adb forward: {}", err);
+                }
+            }
+
+            match connect(
+                &self.address,
+                &requested_capabilities(self.enable_synchronization, self.coverage_mode),
+                self.socket_timeout,
+                self.protocol_version != LEGACY_PROTOCOL_VERSION,
+            ) {
+                Ok(connection) => {
+                    self.stream = connection.stream;
+                    self.reader = connection.reader;
+                    self.protocol_version = connection.protocol_version;
+                    self.capabilities = connection.capabilities;
+                    self.last_activity = Instant::now();
+                    self.check_capabilities();
+                    if let Err(err) = self.negotiate_filter() {
+                        println!("Failed to set the coverage filter: {:?}", err);
+                    }
+                    campaign_stats::count(&campaign_stats::SOCKET_RECONNECTS);
+                    return Ok(());
+                }
+                Err(err) => println!(
+                    "Failed to reconnect to coverage agent (attempt {}/{}): {:?}",
+                    attempt, RECONNECT_ATTEMPTS, err
+                ),
+            }
+
+            std::thread::sleep(backoff);
+            backoff *= 2;
+        }
+
+        Err(libafl::Error::unknown(
+            "Failed to reconnect to coverage agent",
+        ))
+    }
+
+    /// Checks that the agent is still alive if the connection was idle for a
+    /// while and the agent supports heartbeats.
+    fn heartbeat(&mut self) -> Result<(), libafl::Error> {
+        if !self.has_capability(CAPABILITY_KEEPALIVE)
+            || self.last_activity.elapsed() < HEARTBEAT_INTERVAL
+        {
+            return Ok(());
+        }
+
+        let mut buffer = [0; 1];
+        self.stream.write_all(b"p")?;
+        self.reader.read_exact(&mut buffer)?;
+        if buffer[0] != b'p' {
+            return Err(libafl::Error::unknown(format!(
+                "Invalid heartbeat reply (got {:?})",
+                buffer
+            )));
+        }
+
+        self.last_activity = Instant::now();
+        Ok(())
+    }
+
+    /// Requests the coverage map of the current execution from the agent and
+    /// reads it on a background thread, so the 1 MiB transfer overlaps with
+    /// the remaining device work of the execution (reading results and logs
+    /// in the other observers). [Observer::post_exec] picks up the map.
+    pub fn request_coverage(&mut self) {
+        // Reading from a clone of the stream bypasses the buffered reader, so
+        // it must not hold any bytes of a previous message.
+        if self.pending_coverage.is_some() || !self.reader.buffer().is_empty() {
+            return;
+        }
+        let Ok(mut stream) = self.stream.try_clone() else {
+            return;
+        };
+        if self.stream.write_all(b"d").is_err() {
+            return;
+        }
+
+        let protocol_version = self.protocol_version;
+        self.pending_coverage = Some(std::thread::spawn(move || {
+            read_coverage_map_from(&mut stream, protocol_version)
+        }));
+    }
+
+    /// Retrieves the coverage map of the current execution from the agent,
+    /// unless the executor already requested it, and clears the entries
+    /// excluded by the coverage filter.
+    fn retrieve_coverage(&mut self) -> Result<Vec<u8>, libafl::Error> {
+        let retrieval_start = Instant::now();
+        let buffer = match self.pending_coverage.take() {
+            Some(pending) => pending
+                .join()
+                .unwrap_or_else(|_| Err(libafl::Error::unknown("Coverage reader thread panicked"))),
+            None => self
+                .stream
+                .write_all(b"d")
+                .map_err(libafl::Error::from)
+                .and_then(|()| read_coverage_map_from(&mut self.reader, self.protocol_version)),
+        };
+        campaign_stats::add_time(
+            &campaign_stats::COVERAGE_RETRIEVAL_TIME,
+            retrieval_start.elapsed(),
+        );
+        let mut buffer = buffer?;
+        for (b, &excluded) in buffer.iter_mut().zip(&self.excluded_entries) {
+            if excluded {
+                *b = 0;
+            }
+        }
+        if !self.method_entries.is_empty() {
+            let mut methods = vec![0; COVERAGE_MAP_SIZE];
+            for (&b, &method) in buffer.iter().zip(&self.method_entries) {
+                if b != 0 && method != NO_METHOD {
+                    methods[method as usize] = 1;
+                }
+            }
+            buffer = methods;
+        }
+        Ok(buffer)
+    }
+
+    /// If the current execution covered edges never seen before, re-executes
+    /// the input with `rerun` and drops the new edges the re-execution did not
+    /// cover again, so only new coverage that persists makes an input
+    /// interesting.
+    pub fn confirm_new_coverage(&mut self, input: &IntentInput, rerun: impl FnOnce()) {
+        let first = match self.retrieve_coverage() {
+            Ok(first) => first,
+            Err(err) => {
+                self.retrieved_coverage = Some(Err(err));
+                return;
+            }
+        };
+        let overall_buffer = self.overall_coverage.as_slice();
+        if !first
+            .iter()
+            .zip(overall_buffer)
+            .any(|(&b, &overall)| b != 0 && overall == 0)
+        {
+            self.retrieved_coverage = Some(Ok(first));
+            return;
+        }
+
+        if let Err(err) = self
+            .heartbeat()
+            .and_then(|()| self.reset_coverage(input.hash()))
+        {
+            println!("Failed to reset coverage map for re-execution: {:?}", err);
+            self.retrieved_coverage = Some(Ok(first));
+            return;
+        }
+        rerun();
+
+        self.retrieved_coverage = Some(self.retrieve_coverage().map(|second| {
+            let overall_buffer = self.overall_coverage.as_slice();
+            let mut confirmed = first;
+            let mut flaky_edges = 0;
+            for (i, b) in confirmed.iter_mut().enumerate() {
+                if *b != 0 && overall_buffer[i] == 0 && second[i] == 0 {
+                    *b = 0;
+                    flaky_edges += 1;
+                }
+            }
+            if flaky_edges > 0 {
+                println!("Dropped {} new edges not covered again", flaky_edges);
+                campaign_stats::count(&campaign_stats::FLAKY_COVERAGE);
+            }
+            confirmed
+        }));
+    }
+
+    /// Asks the agent for the extra keys the app queried during the execution
+    /// (`k`), by hooking the getters of Bundle. The agent answers with a
+    /// length-prefixed message of lines `<type> <key>`, where `<type>` is the
+    /// extra type of the getter as in the intent templates (e.g. `String`,
+    /// `IntArray`).
+    fn read_queried_extra_keys(&mut self) -> Result<Vec<(String, String)>, libafl::Error> {
+        self.stream.write_all(b"k")?;
+        let message = read_message_up_to(&mut self.reader, MAX_EXTRA_KEYS_MESSAGE_LEN)?;
+        Ok(String::from_utf8_lossy(&message)
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .filter(|(_, key)| !key.is_empty())
+            .map(|(extra_type, key)| (key.to_owned(), extra_type.to_owned()))
+            .collect())
+    }
+
+    /// Asks the agent for the exceptions of the app during the execution
+    /// (`e`), see [parse_exception_reports] for the format of the
+    /// length-prefixed reply.
+    fn read_exception_reports(&mut self) -> Result<Vec<ExceptionReport>, libafl::Error> {
+        self.stream.write_all(b"e")?;
+        let message = read_message_up_to(&mut self.reader, MAX_EXCEPTIONS_MESSAGE_LEN)?;
+        Ok(parse_exception_reports(&String::from_utf8_lossy(&message)))
+    }
+
+    /// Whether the agent enabled the given capability in the handshake.
+    fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+
+    /// Warns about requested capabilities the agent did not enable.
+    fn check_capabilities(&self) {
+        println!("Coverage agent capabilities: {:?}", self.capabilities);
+        if self.coverage_mode == CoverageMode::Methods && !self.has_capability(CAPABILITY_METHODS) {
+            println!("Coverage agent does not support method coverage");
+        }
+        if self.enable_synchronization && !self.has_capability(CAPABILITY_SYNC) {
+            println!("Coverage agent does not support synchronization, continuing without it");
+        }
     }
 
     fn reset_coverage(&mut self, hash: String) -> Result<(), libafl::Error> {
@@ -157,14 +898,98 @@ impl<'a> SocketCoverageObserver<'a> {
         Ok(())
     }
 
-    pub fn save_overall_edge_count(&self) {
+    /// Number of edges covered over all executions so far.
+    pub fn overall_edge_count(&self) -> usize {
+        self.overall_coverage
+            .as_slice()
+            .iter()
+            .filter(|&b| *b != 0)
+            .count()
+    }
+
+    /// Indices of the edges the last execution covered for the first time.
+    pub fn new_edges(&self) -> &[usize] {
+        &self.new_edges
+    }
+
+    /// Exceptions the agent reported for the last execution.
+    pub fn exception_reports(&self) -> &[ExceptionReport] {
+        &self.exception_reports
+    }
+
+    /// The source location of the map entry, if known. The entries of an
+    /// agent recording the method coverage itself are method ids of the
+    /// agent, which the mapping of the edges does not describe.
+    pub fn location(&self, index: usize) -> Option<&SourceLocation> {
+        match self.coverage_mode {
+            CoverageMode::Methods if self.method_entries.is_empty() => None,
+            CoverageMode::Methods => self.method_locations.get(index),
+            CoverageMode::Hitcounts | CoverageMode::Edges => {
+                self.coverage_mapping.as_ref()?.location(index)
+            }
+        }
+    }
+
+    /// The source locations of the edges the last execution covered for the
+    /// first time, or nothing without a coverage mapping.
+    pub fn new_locations(&self) -> Vec<String> {
+        self.new_edges
+            .iter()
+            .filter_map(|&index| self.location(index))
+            .map(|location| location.to_string())
+            .collect()
+    }
+
+    /// Writes the overall coverage map and the covered map entries (one per
+    /// line, with their source location if known) next to the overall
+    /// coverage file, named after the current time, e.g.
+    /// `edgecount.1700000000.map` and `edgecount.1700000000.edges`. Returns
+    /// the paths of both files.
+    pub fn dump_coverage(&self) -> Result<(PathBuf, PathBuf), libafl::Error> {
+        let time = unix_time();
+        let map_file = self
+            .overall_coverage_file
+            .with_extension(format!("{}.map", time));
+        let edges_file = self
+            .overall_coverage_file
+            .with_extension(format!("{}.edges", time));
+
+        std::fs::write(&map_file, self.overall_coverage.as_slice())?;
+        let mut edges = String::new();
+        for (index, _) in self
+            .overall_coverage
+            .as_slice()
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b != 0)
+        {
+            match self.location(index) {
+                Some(location) => edges.push_str(&format!("{} {}\n", index, location)),
+                None => edges.push_str(&format!("{}\n", index)),
+            }
+        }
+        std::fs::write(&edges_file, edges)?;
+        Ok((map_file, edges_file))
+    }
+
+    /// Seconds since the overall coverage last grew.
+    pub fn secs_since_new_coverage(&self) -> u64 {
+        self.last_new_coverage.elapsed().as_secs()
+    }
+
+    /// Appends a record to the overall coverage file if the overall coverage
+    /// grew, see [OVERALL_COVERAGE_HEADER] for the columns, and writes the
+    /// overall coverage map next to it.
+    pub fn save_overall_edge_count(&mut self, execs: usize, corpus_size: usize) {
         // Number of bytes not 0 in the overall coverage.
-        let overall_coverage = self.overall_coverage.as_slice().iter().filter(|&b| *b != 0).count();
+        let overall_coverage = self.overall_edge_count();
 
         // Do nothing if the overall coverage hasn't changed.
         if overall_coverage <= self.last_overall_coverage as usize {
             return;
         }
+        self.last_overall_coverage = overall_coverage as u64;
+        self.last_new_coverage = Instant::now();
 
         // Create the directory if it doesn't exist
         let mut dir = self.overall_coverage_file.clone();
@@ -180,14 +1005,35 @@ impl<'a> SocketCoverageObserver<'a> {
             .append(true)
             .open(&self.overall_coverage_file)
             .unwrap();
-        file.write_all(format!("{}: {}\n", elapsed.as_secs(), overall_coverage).as_bytes())
-            .unwrap();
+        file.write_all(
+            format!(
+                "{},{},{},{},{}\n",
+                unix_time(),
+                elapsed.as_secs(),
+                execs,
+                corpus_size,
+                overall_coverage
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+        // Keep the overall coverage map for the coverage report.
+        let map_file = overall_map_file(&self.overall_coverage_file);
+        if let Err(err) = std::fs::write(&map_file, self.overall_coverage.as_slice()) {
+            println!(
+                "Failed to write the overall coverage map to {:?}: {}",
+                map_file, err
+            );
+        }
+
+        results_db::record_coverage(elapsed.as_secs(), overall_coverage);
     }
 }
 
 impl<S> Observer<S> for SocketCoverageObserver<'_>
 where
-    S: UsesInput<Input = IntentInput>,
+    S: UsesInput<Input = IntentInput> + HasExecutions + HasCorpus + HasNamedMetadata,
 {
     #[inline]
     fn pre_exec(
@@ -195,32 +1041,54 @@ where
         state: &mut S,
         input: &<S as UsesInput>::Input,
     ) -> Result<(), libafl::Error> {
-        for i in 0..5 {
-            if let Err(err) = self.reset_coverage(input.hash()) {
+        // Every execution goes to the next app instance, whose coverage is
+        // reset below.
+        if !self.instances.is_empty() {
+            self.swap_instance(0);
+            self.instances.rotate_left(1);
+        }
+
+        let reconnects = self.adb_device.escalation_policy().socket_reconnects;
+        for i in 0..reconnects + 4 {
+            let result = self
+                .heartbeat()
+                .and_then(|()| self.reset_coverage(input.hash()));
+            let Err(err) = result else {
+                self.last_activity = Instant::now();
+                // Reset the local coverage map.
+                return self.base_observer.pre_exec(state, input);
+            };
+
+            // A momentary agent restart only needs a new connection.
+            if i < reconnects {
                 println!(
-                    "Failed to write reset message to socket. Restarting app. Error: {:?}",
+                    "Failed to write reset message to socket. Reconnecting. Error: {:?}",
                     err
                 );
-
-                if self.trace_native {
-                    self.adb_device.report_native_crash(&self.app_name);
+                if self.init().is_ok() {
+                    continue;
                 }
+            }
 
-                if i > 1 {
-                    self.adb_device.restart_device();
-                }
+            println!(
+                "Failed to write reset message to socket. Restarting app. Error: {:?}",
+                err
+            );
 
-                self.adb_device.restart_app(&self.app_name);
+            if self.trace_native {
+                self.adb_device.report_native_crash(&self.app_name);
+            }
 
-                std::thread::sleep(std::time::Duration::from_secs(1 + i));
+            let failures = (i + 1).saturating_sub(reconnects).max(1);
+            self.adb_device.recover(&self.app_name, failures as u32);
 
-                self.init();
+            std::thread::sleep(std::time::Duration::from_secs(1 + i));
 
-                std::thread::sleep(std::time::Duration::from_secs(1 + i));
-            } else {
-                // Reset the local coverage map.
-                return self.base_observer.pre_exec(state, input);
+            if let Err(err) = self.init() {
+                println!("{:?}", err);
             }
+
+            std::thread::sleep(std::time::Duration::from_secs(1 + i));
         }
 
         Err(libafl::Error::unknown(
@@ -235,33 +1103,119 @@ where
         input: &<S as UsesInput>::Input,
         exit_kind: &libafl::prelude::ExitKind,
     ) -> Result<(), libafl::Error> {
-        // Retrieve the coverage from the socket into the observer.
-        self.stream
-            .write(b"d")
-            .expect("Failed to write send-coverage message to socket");
+        self.new_edges.clear();
+        self.exception_reports.clear();
 
-        let mut buffer = vec![0; COVERAGE_MAP_SIZE];
-        if let Err(_err) = self.reader.read_exact(&mut buffer) {
-            println!("Failed to read entire coverage from socket.");
-            return Ok(());
+        // Retrieve the coverage from the socket into the observer, unless the
+        // executor already retrieved it.
+        let buffer = match self.retrieved_coverage.take() {
+            Some(buffer) => buffer,
+            None => self.retrieve_coverage(),
+        };
+
+        let buffer = match buffer {
+            Ok(buffer) => buffer,
+            Err(err) => {
+                // The rest of the message may still arrive, so start over with
+                // a new connection to stay in sync with the agent.
+                println!("Failed to read coverage from socket: {:?}", err);
+                if let Err(err) = self.init() {
+                    println!("{:?}", err);
+                }
+                return Ok(());
+            }
+        };
+        self.last_activity = Instant::now();
+
+        // Learn the extra keys the app looked for, so the mutators add them.
+        if self.has_capability(CAPABILITY_EXTRA_KEYS) {
+            match self.read_queried_extra_keys() {
+                Ok(keys) => {
+                    if let Ok(template) =
+                        state.named_metadata_mut::<IntentTemplate>("intent_template")
+                    {
+                        for (key, extra_type) in keys {
+                            if !EXTRA_TYPES.contains(&extra_type.as_str()) {
+                                if self.ignored_extra_keys.insert(key.clone()) {
+                                    println!(
+                                        "Ignoring extra key {} of unknown type {} from the app",
+                                        key, extra_type
+                                    );
+                                }
+                                continue;
+                            }
+                            if let Entry::Vacant(entry) = template.known_extras_keys.entry(key) {
+                                println!(
+                                    "Learned extra key {} ({}) from the app",
+                                    entry.key(),
+                                    extra_type
+                                );
+                                entry.insert(extra_type);
+                            }
+                        }
+                    }
+                }
+                Err(err) => {
+                    println!("Failed to read queried extra keys from socket: {:?}", err);
+                    if let Err(err) = self.init() {
+                        println!("{:?}", err);
+                    }
+                }
+            }
+        }
+
+        if self.has_capability(CAPABILITY_EXCEPTIONS) {
+            match self.read_exception_reports() {
+                Ok(reports) => self.exception_reports = reports,
+                Err(err) => {
+                    println!("Failed to read exception reports from socket: {:?}", err);
+                    if let Err(err) = self.init() {
+                        println!("{:?}", err);
+                    }
+                }
+            }
         }
 
         if self.use_coverage {
             let observer_buffer = self.base_observer.as_mut_slice();
             // Copy into the observer buffer
-            observer_buffer.copy_from_slice(&buffer);
+            match self.coverage_mode {
+                CoverageMode::Hitcounts => observer_buffer.copy_from_slice(&buffer),
+                CoverageMode::Edges | CoverageMode::Methods => {
+                    for (dst, &b) in observer_buffer.iter_mut().zip(buffer.iter()) {
+                        *dst = (b != 0) as u8;
+                    }
+                }
+            }
         }
 
-        // Update the overall coverage.
+        // Update the overall coverage, remembering the edges never seen before.
         let overall_buffer = self.overall_coverage.as_mut_slice();
         for (i, &b) in buffer.iter().enumerate() {
             if b != 0 {
+                if overall_buffer[i] == 0 {
+                    self.new_edges.push(i);
+                }
                 overall_buffer[i] = b;
             }
         }
 
+        if !self.new_edges.is_empty() {
+            let locations = self.new_locations();
+            if !locations.is_empty() {
+                println!(
+                    "New coverage: {}{}",
+                    locations[..locations.len().min(NEW_LOCATIONS_LOGGED)].join(", "),
+                    match locations.len().checked_sub(NEW_LOCATIONS_LOGGED) {
+                        Some(more) if more > 0 => format!(" and {} more", more),
+                        _ => String::new(),
+                    }
+                );
+            }
+        }
+
         // Save the overall edge count to a file
-        self.save_overall_edge_count();
+        self.save_overall_edge_count(*state.executions(), state.corpus().count());
 
         self.base_observer.post_exec(state, input, exit_kind)
     }
@@ -333,6 +1287,44 @@ impl MapObserver for SocketCoverageObserver<'_> {
     }
 }
 
+/// Seconds since the unix epoch.
+pub fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// The capabilities requested from the agent. Delta coverage and compression
+/// are not requested yet, as the fuzzer can't decode them.
+fn requested_capabilities(
+    enable_synchronization: bool,
+    coverage_mode: CoverageMode,
+) -> Vec<&'static str> {
+    let mut capabilities = vec![
+        CAPABILITY_KEEPALIVE,
+        CAPABILITY_EXTRA_KEYS,
+        CAPABILITY_EXCEPTIONS,
+        CAPABILITY_FILTER,
+    ];
+    if enable_synchronization {
+        capabilities.insert(0, CAPABILITY_SYNC);
+    }
+    if coverage_mode == CoverageMode::Methods {
+        capabilities.push(CAPABILITY_METHODS);
+    }
+    capabilities
+}
+
+/// Whether the class is in one of the packages or their subpackages.
+fn in_packages(class: &str, packages: &[String]) -> bool {
+    packages.iter().any(|package| {
+        class
+            .strip_prefix(package.as_str())
+            .is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
 // For some reason MapObserver requires the struct to implement Serialize/Deserialize.
 //
 // As far as I can tell it's not really used but since TcpStream and BufReader
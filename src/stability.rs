@@ -0,0 +1,137 @@
+//! Stability of the coverage of the target.
+//!
+//! Background threads of the app and the framework make the coverage of an
+//! intent vary between executions. The [StabilityStage] executes every new
+//! corpus entry a few times before it is fuzzed and reports the fraction of
+//! the covered map entries that did not change across these executions as
+//! `stability` (like AFL). A low stability means the coverage is too noisy
+//! for the feedback to tell interesting inputs apart.
+
+use std::{collections::HashSet, marker::PhantomData};
+
+use libafl::{
+    impl_serdeany,
+    prelude::{
+        Corpus, CorpusId, Event, EventFirer, ExecutesInput, HasObservers, MapObserver, MatchName,
+        Stage, UserStats, UsesInput, UsesState,
+    },
+    state::{HasCorpus, HasMetadata},
+};
+use serde::{Deserialize, Serialize};
+
+/// The map entries covered and those that changed across the executions of
+/// the same input, over all corpus entries.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct StabilityMetadata {
+    filled_entries: HashSet<usize>,
+    unstable_entries: HashSet<usize>,
+}
+
+impl_serdeany!(StabilityMetadata);
+
+/// Stage executing every new corpus entry `runs` times to measure the
+/// stability of the coverage map observed by the named observer.
+#[derive(Debug)]
+pub struct StabilityStage<O, S> {
+    observer_name: String,
+    runs: usize,
+    phantom: PhantomData<(O, S)>,
+}
+
+impl<O, S> StabilityStage<O, S> {
+    pub fn new(observer_name: &str, runs: usize) -> Self {
+        Self {
+            observer_name: observer_name.to_owned(),
+            runs,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<O, S> UsesState for StabilityStage<O, S>
+where
+    S: UsesInput,
+{
+    type State = S;
+}
+
+impl<E, EM, O, Z> Stage<E, EM, Z> for StabilityStage<O, E::State>
+where
+    E: HasObservers,
+    EM: EventFirer<State = E::State>,
+    O: MapObserver<Entry = u8>,
+    E::State: HasCorpus + HasMetadata,
+    Z: ExecutesInput<E, EM, State = E::State>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut E::State,
+        manager: &mut EM,
+        corpus_idx: CorpusId,
+    ) -> Result<(), libafl::Error> {
+        // Only measure entries that were never fuzzed before.
+        if self.runs < 2 || state.corpus().get(corpus_idx)?.borrow().scheduled_count() > 0 {
+            return Ok(());
+        }
+
+        let input = state.corpus().cloned_input_for_id(corpus_idx)?;
+        let mut first_map: Option<Vec<u8>> = None;
+        let mut filled_entries = HashSet::new();
+        let mut unstable_entries = HashSet::new();
+        for _ in 0..self.runs {
+            fuzzer.execute_input(state, executor, manager, &input)?;
+            let map = executor
+                .observers()
+                .match_name::<O>(&self.observer_name)
+                .ok_or_else(|| libafl::Error::key_not_found("Coverage observer not found"))?
+                .to_vec();
+
+            filled_entries.extend(
+                map.iter()
+                    .enumerate()
+                    .filter(|(_, b)| **b != 0)
+                    .map(|(i, _)| i),
+            );
+            match &first_map {
+                Some(first_map) => unstable_entries.extend(
+                    first_map
+                        .iter()
+                        .zip(map.iter())
+                        .enumerate()
+                        .filter(|(_, (first, b))| first != b)
+                        .map(|(i, _)| i),
+                ),
+                None => first_map = Some(map),
+            }
+        }
+
+        if !state.has_metadata::<StabilityMetadata>() {
+            state.add_metadata(StabilityMetadata::default());
+        }
+        let metadata = state.metadata_mut::<StabilityMetadata>()?;
+        metadata.filled_entries.extend(filled_entries);
+        metadata.unstable_entries.extend(unstable_entries);
+        let filled = metadata.filled_entries.len() as u64;
+        let stable = filled - metadata.unstable_entries.len() as u64;
+        if filled == 0 {
+            return Ok(());
+        }
+
+        println!(
+            "Stability: {:.2}% ({}/{} map entries)",
+            stable as f64 * 100.0 / filled as f64,
+            stable,
+            filled
+        );
+        manager.fire(
+            state,
+            Event::UpdateUserStats {
+                name: "stability".to_owned(),
+                value: UserStats::Ratio(stable, filled),
+                phantom: PhantomData,
+            },
+        )
+    }
+}
@@ -0,0 +1,119 @@
+//! Retirement of corpus entries that nearly always time out.
+//!
+//! Some corpus entries (and their mutations) hit the execution timeout almost
+//! every time, spending the full 5-20s budget on every schedule without any
+//! coverage payoff. The [TimeoutCullingFeedback] counts the executions and
+//! timeouts of the entry being fuzzed in its [TimeoutStats], and retires the
+//! entry once its timeout rate exceeds the configured threshold. The
+//! scheduler skips retired entries.
+
+use libafl::{
+    impl_serdeany,
+    prelude::{
+        Corpus, CorpusId, EventFirer, ExitKind, Feedback, HasMetadata, Named, ObserversTuple,
+        UsesInput,
+    },
+    state::{HasClientPerfMonitor, HasCorpus},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::intent_input::IntentInput;
+
+/// Minimum number of executions of an entry before it can be retired.
+const MIN_EXECUTIONS: u64 = 20;
+
+/// Executions and timeouts of the inputs derived from a corpus entry.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TimeoutStats {
+    pub executions: u64,
+    pub timeouts: u64,
+    /// Whether the entry is no longer scheduled.
+    pub retired: bool,
+}
+
+impl_serdeany!(TimeoutStats);
+
+impl TimeoutStats {
+    fn timeout_rate(&self) -> f64 {
+        self.timeouts as f64 / self.executions as f64
+    }
+}
+
+/// Feedback tracking the timeout rate of the corpus entries. It never
+/// considers an input interesting on its own.
+#[derive(Debug)]
+pub struct TimeoutCullingFeedback {
+    /// Timeout rate above which an entry is retired, None to never retire.
+    max_timeout_rate: Option<f64>,
+}
+
+impl TimeoutCullingFeedback {
+    pub fn new(max_timeout_rate: Option<f64>) -> Self {
+        Self { max_timeout_rate }
+    }
+}
+
+impl Named for TimeoutCullingFeedback {
+    fn name(&self) -> &str {
+        "TimeoutCullingFeedback"
+    }
+}
+
+impl<S> Feedback<S> for TimeoutCullingFeedback
+where
+    S: UsesInput<Input = IntentInput> + HasClientPerfMonitor + HasCorpus,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &IntentInput,
+        _observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let Some(max_timeout_rate) = self.max_timeout_rate else {
+            return Ok(false);
+        };
+        let Some(id) = *state.corpus().current() else {
+            return Ok(false);
+        };
+
+        let mut testcase = state.corpus().get(id)?.borrow_mut();
+        if !testcase.has_metadata::<TimeoutStats>() {
+            testcase.add_metadata(TimeoutStats::default());
+        }
+        let stats = testcase.metadata_mut::<TimeoutStats>()?;
+
+        stats.executions += 1;
+        if *exit_kind == ExitKind::Timeout {
+            stats.timeouts += 1;
+        }
+
+        if !stats.retired
+            && stats.executions >= MIN_EXECUTIONS
+            && stats.timeout_rate() > max_timeout_rate
+        {
+            println!(
+                "Retiring corpus entry {} ({} of {} executions timed out)",
+                id, stats.timeouts, stats.executions
+            );
+            stats.retired = true;
+        }
+
+        Ok(false)
+    }
+}
+
+/// Whether the corpus entry was retired for timing out too often.
+pub fn is_retired<C: Corpus>(corpus: &C, id: CorpusId) -> bool {
+    corpus.get(id).map_or(false, |testcase| {
+        testcase
+            .borrow()
+            .metadata::<TimeoutStats>()
+            .map_or(false, |stats| stats.retired)
+    })
+}
@@ -0,0 +1,89 @@
+//! Heuristic triage of the findings saved to the solutions corpus.
+//!
+//! Every solution is classified by [Severity] based on what the device logged
+//! around the time of the crash, so findings can be sorted and reported by
+//! importance instead of being treated identically.
+
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use libafl::impl_serdeany;
+use serde::{Deserialize, Serialize};
+
+use crate::{adb_device::AdbDevice, native_crash::NativeCrash};
+
+/// How far back the device logs are searched when classifying a finding.
+const TRIAGE_LOG_DURATION: Duration = Duration::from_secs(10);
+
+/// Severity of a finding, ordered from least to most severe.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Nothing was found in the logs to classify the finding.
+    Unknown,
+    /// A StrictMode policy violation of the app.
+    StrictMode,
+    /// The app stopped responding.
+    Anr,
+    /// An uncaught Java exception in the app.
+    UncaughtException,
+    /// The app ran out of memory.
+    OutOfMemory,
+    /// A native crash (fatal signal) in the app.
+    NativeCrash,
+    /// An exception configured to be escalated.
+    Escalated,
+}
+
+impl_serdeany!(Severity);
+
+impl Severity {
+    /// Whether this finding warrants a deeper post-mortem analysis.
+    pub fn is_high(&self) -> bool {
+        matches!(
+            self,
+            Severity::NativeCrash | Severity::Anr | Severity::Escalated
+        )
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Unknown => write!(f, "unknown"),
+            Severity::StrictMode => write!(f, "StrictMode violation"),
+            Severity::Anr => write!(f, "ANR"),
+            Severity::UncaughtException => write!(f, "uncaught exception"),
+            Severity::OutOfMemory => write!(f, "out of memory"),
+            Severity::NativeCrash => write!(f, "native crash"),
+            Severity::Escalated => write!(f, "escalated exception"),
+        }
+    }
+}
+
+/// Classifies the finding that just happened in the given app.
+pub fn classify(adb_device: &AdbDevice, app_name: &str) -> Severity {
+    let crash_log = adb_device
+        .recent_crash_log(TRIAGE_LOG_DURATION)
+        .unwrap_or_default();
+
+    if NativeCrash::parse(&crash_log, app_name).is_some() {
+        Severity::NativeCrash
+    } else if adb_device
+        .out_of_memory_error(app_name, SystemTime::now() - TRIAGE_LOG_DURATION)
+        .is_some()
+    {
+        Severity::OutOfMemory
+    } else if crash_log.contains("FATAL EXCEPTION")
+        && crash_log.contains(&format!("Process: {},", app_name))
+    {
+        Severity::UncaughtException
+    } else if adb_device.has_recent_anr(app_name, TRIAGE_LOG_DURATION) {
+        Severity::Anr
+    } else if adb_device.has_recent_strict_mode_violation(app_name, TRIAGE_LOG_DURATION) {
+        Severity::StrictMode
+    } else {
+        Severity::Unknown
+    }
+}
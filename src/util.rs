@@ -3,6 +3,59 @@ pub fn encode_hex(bytes: &[u8]) -> String {
     bytes.iter().map(|b| format!("\\x{:02x}", b)).collect()
 }
 
+/// Quotes the value as a single shell argument: in single quotes if that is
+/// safe, otherwise hex-encoded in `$'...'`, e.g. for values containing
+/// quotes or newlines.
+pub fn shell_quote(value: &str) -> String {
+    if value
+        .chars()
+        .all(|c| c == ' ' || (c.is_ascii_graphic() && c != '\''))
+    {
+        format!("'{}'", value)
+    } else {
+        format!("$'{}'", encode_hex(value.as_bytes()))
+    }
+}
+
+/// Percent-encodes everything but unreserved characters, like Android's
+/// `Uri.encode`.
+pub fn encode_uri_component(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Decodes the percent-encoded characters of the value, the inverse of
+/// [encode_uri_component].
+pub fn decode_uri_component(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
 /// Array that contains common extra keys and types.
 pub const COMMON_EXTRA_KEYS: [(&str, &str); 14] = [
     ("android.intent.extra.CC", "StringArray"),
@@ -1,8 +1,12 @@
 import socketserver
 import random
+import struct
 
 
-COVERAGE_MAP_SIZE = 64 * 1024
+COVERAGE_MAP_SIZE = 1024 * 1024
+PROTOCOL_VERSION = 2
+SUPPORTED_CAPABILITIES = {"sync", "keepalive", "extra_keys", "exceptions", "filter", "methods"}
+fake_extra_keys = b"String test_key\nIntArray test_ids\n"
 fake_map = bytearray([0] * COVERAGE_MAP_SIZE)
 
 
@@ -11,14 +15,41 @@ class MyTCPHandler(socketserver.BaseRequestHandler):
         # self.request is the TCP socket connected to the client
         while True:
             command = self.request.recv(1)
-            if command == b'r':
-                continue
+            if command == b'h':
+                # handshake: "MALINTENT <version> <capabilities>\n"
+                line = b''
+                while not line.endswith(b'\n'):
+                    line += self.request.recv(1)
+                fields = line.decode().split()
+                requested = set(fields[2].split(',')) if len(fields) > 2 else set()
+                enabled = ','.join(sorted(requested & SUPPORTED_CAPABILITIES))
+                print(f"[+] Handshake: {fields}, enabling {enabled!r}")
+                self.request.sendall(f"MALINTENT {PROTOCOL_VERSION} {enabled}\n".encode())
+            elif command == b'f':
+                # coverage filter: comma-separated packages and a newline.
+                line = b''
+                while not line.endswith(b'\n'):
+                    line += self.request.recv(1)
+                print(f"[+] Coverage filter: {line.decode().strip().split(',')}")
+                self.request.sendall(b'd')
+            elif command == b'p':
+                # answer the heartbeat.
+                self.request.sendall(b'p')
+            elif command == b'r':
+                # acknowledge the reset.
+                self.request.sendall(b'd')
             elif command == b'd':
-                # send the coverage map.
+                # send the coverage map, prefixed with its length.
                 if random.choice([False, False, False, True]):
                     print("[!] Changing fake map")
                     fake_map[23] = fake_map[23] + 1
-                self.request.sendall(fake_map)
+                self.request.sendall(struct.pack('<I', len(fake_map)) + fake_map)
+            elif command == b'k':
+                # send the queried extra keys as "<type> <key>" lines.
+                self.request.sendall(struct.pack('<I', len(fake_extra_keys)) + fake_extra_keys)
+            elif command == b'e':
+                # no exceptions to report.
+                self.request.sendall(struct.pack('<I', 0))
             elif command == b'':
                 print("[-] Client disconnected")
                 break